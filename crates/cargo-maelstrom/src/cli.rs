@@ -35,11 +35,18 @@ pub struct ListOptions {
             have been run."
     )]
     pub packages: bool,
+
+    #[arg(
+        long = "report-metadata",
+        help = "Instead of running tests, print the metadata that would have been used for each \
+            test as a line of JSON. May require building test binaries."
+    )]
+    pub metadata: bool,
 }
 
 impl ListOptions {
     pub fn any(&self) -> bool {
-        self.tests || self.binaries || self.packages
+        self.tests || self.binaries || self.packages || self.metadata
     }
 }
 
@@ -12,8 +12,12 @@ use maelstrom_client::{
     ContainerImageDepotDir, ProjectDir, StateDir,
 };
 use maelstrom_test_runner::{
-    metadata::TestMetadata, run_app_with_ui_multithreaded, ui::Ui, ui::UiSender, BuildDir,
-    CollectTests, ListAction, LoggingOutput, MainAppDeps, MainAppState, NoCaseMetadata,
+    golden::{FsGoldenStore, GoldenFiles},
+    metadata::TestMetadata,
+    run_app_with_ui_multithreaded,
+    ui::Ui,
+    ui::UiSender,
+    BuildDir, CollectTests, ListAction, LoggingOutput, MainAppDeps, MainAppState, NoCaseMetadata,
     TestArtifact, TestArtifactKey, TestFilter, TestLayers, TestPackage, TestPackageId, Wait,
 };
 use maelstrom_util::{
@@ -203,6 +207,7 @@ impl TestFilter for pattern::Pattern {
     }
 }
 
+#[derive(Debug)]
 struct CargoOptions {
     feature_selection_options: cargo::FeatureSelectionOptions,
     compilation_options: cargo::CompilationOptions,
@@ -528,7 +533,11 @@ pub fn main(
         let logging_output = LoggingOutput::default();
         let log = logger.build(logging_output.clone());
 
-        let list_action = extra_options.list.tests.then_some(ListAction::ListTests);
+        let list_action = if extra_options.list.metadata {
+            Some(ListAction::ReportMetadata)
+        } else {
+            extra_options.list.tests.then_some(ListAction::ListTests)
+        };
         let target_dir = Root::<BuildDir>::new(cargo_metadata.target_directory.as_std_path());
         let maelstrom_target_dir = target_dir.join::<MaelstromTargetDir>("maelstrom");
         let state_dir = maelstrom_target_dir.join::<StateDir>("state");
@@ -561,6 +570,7 @@ pub fn main(
             deps,
             extra_options.parent.include,
             extra_options.parent.exclude,
+            extra_options.parent.shard,
             list_action,
             stderr_is_tty,
             workspace_dir,
@@ -570,10 +580,27 @@ pub fn main(
                 .map(|p| CargoPackage(p.clone()))
                 .collect::<Vec<_>>(),
             &state_dir,
+            config.parent.test_listing_read_retries,
             cargo_options,
             logging_output,
             log,
-        )?;
+        )?
+        .include_quarantined(extra_options.parent.include_quarantined)
+        .junit_output(extra_options.parent.junit_output)
+        .golden_files(extra_options.parent.golden_dir.map(|dir| {
+            GoldenFiles::new(FsGoldenStore::new(dir))
+                .create_missing(extra_options.parent.create_missing_golden_files)
+        }))
+        .retries(extra_options.parent.retries)
+        .ordering(extra_options.parent.order)
+        .max_concurrent_packages(extra_options.parent.max_concurrent_packages)
+        .package_timeouts(
+            extra_options
+                .parent
+                .timeout_per_package
+                .into_iter()
+                .map(|package_timeout| (package_timeout.package, package_timeout.timeout)),
+        );
 
         let res = run_app_with_ui_multithreaded(state, config.parent.timeout.map(Timeout::new), ui);
         maybe_print_build_error(res)
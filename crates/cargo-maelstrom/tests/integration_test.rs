@@ -59,6 +59,7 @@ fn do_cargo_maelstrom_test(source_contents: &str) -> String {
             slots: Slots::default(),
             accept_invalid_remote_container_tls_certs: true.into(),
             ui: ui::UiKind::Simple,
+            test_listing_read_retries: 0,
         },
         cargo_feature_selection_options: FeatureSelectionOptions::default(),
         cargo_compilation_options: CompilationOptions::default(),
@@ -76,6 +77,7 @@ fn do_cargo_maelstrom_test(source_contents: &str) -> String {
             tests: false,
             binaries: false,
             packages: false,
+            metadata: false,
         },
     };
     let term = InMemoryTerm::new(50, 50);
@@ -5,6 +5,8 @@ pub mod manifest;
 pub mod proto;
 pub mod ring_buffer;
 pub mod stats;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 pub mod tty;
 
 pub use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
@@ -15,11 +17,16 @@ use derive_more::{Constructor, Display, From};
 use enumset::EnumSetType;
 use hex::{self, FromHexError};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
 use std::{
+    borrow::Cow,
+    collections::HashSet,
     error::Error,
     fmt::{self, Debug, Formatter},
     hash::Hash,
-    num::NonZeroU32,
+    mem,
+    num::NonZeroU64,
+    ops::RangeInclusive,
     result::Result,
     str::{self, FromStr},
     time::Duration,
@@ -120,6 +127,8 @@ pub enum JobMountForTomlAndJson {
         local_path: Utf8PathBuf,
         #[serde(default)]
         read_only: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        security_label: Option<String>,
     },
     Devices {
         devices: EnumSet<JobDeviceForTomlAndJson>,
@@ -130,6 +139,12 @@ pub enum JobMountForTomlAndJson {
     Mqueue {
         mount_point: Utf8PathBuf,
     },
+    Overlay {
+        mount_point: Utf8PathBuf,
+        lower: Utf8PathBuf,
+        upper: Utf8PathBuf,
+        work: Utf8PathBuf,
+    },
     Proc {
         mount_point: Utf8PathBuf,
     },
@@ -147,6 +162,9 @@ pub enum JobMount {
         mount_point: Utf8PathBuf,
         local_path: Utf8PathBuf,
         read_only: bool,
+        /// The SELinux security context to label the mount point with, if any.
+        #[serde(default)]
+        security_label: Option<String>,
     },
     Devices {
         devices: EnumSet<JobDevice>,
@@ -157,6 +175,15 @@ pub enum JobMount {
     Mqueue {
         mount_point: Utf8PathBuf,
     },
+    /// A writable overlay file system at `mount_point`. The `lower`, `upper`, and `work`
+    /// directories are local paths on the worker, like a bind mount's `local_path`. The `upper`
+    /// and `work` directories must already exist and be on the same file system.
+    Overlay {
+        mount_point: Utf8PathBuf,
+        lower: Utf8PathBuf,
+        upper: Utf8PathBuf,
+        work: Utf8PathBuf,
+    },
     Proc {
         mount_point: Utf8PathBuf,
     },
@@ -175,16 +202,29 @@ impl From<JobMountForTomlAndJson> for JobMount {
                 mount_point,
                 local_path,
                 read_only,
+                security_label,
             } => JobMount::Bind {
                 mount_point,
                 local_path,
                 read_only,
+                security_label,
             },
             JobMountForTomlAndJson::Devices { devices } => JobMount::Devices {
                 devices: devices.into_iter().map(JobDevice::from).collect(),
             },
             JobMountForTomlAndJson::Devpts { mount_point } => JobMount::Devpts { mount_point },
             JobMountForTomlAndJson::Mqueue { mount_point } => JobMount::Mqueue { mount_point },
+            JobMountForTomlAndJson::Overlay {
+                mount_point,
+                lower,
+                upper,
+                work,
+            } => JobMount::Overlay {
+                mount_point,
+                lower,
+                upper,
+                work,
+            },
             JobMountForTomlAndJson::Proc { mount_point } => JobMount::Proc { mount_point },
             JobMountForTomlAndJson::Sys { mount_point } => JobMount::Sys { mount_point },
             JobMountForTomlAndJson::Tmp { mount_point } => JobMount::Tmp { mount_point },
@@ -195,12 +235,26 @@ impl From<JobMountForTomlAndJson> for JobMount {
 #[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum JobNetwork {
+    /// The job runs in its own network namespace with no usable interfaces. The namespace's
+    /// loopback interface exists but is left down.
     #[default]
     Disabled,
+    /// The job runs in its own network namespace with only the loopback interface brought up.
     Loopback,
+    /// The job runs in the worker's network namespace. This makes the job local-only.
     Local,
 }
 
+impl JobNetwork {
+    /// Whether the job can communicate on `localhost`.
+    pub fn allows_loopback(&self) -> bool {
+        match self {
+            Self::Disabled => false,
+            Self::Loopback | Self::Local => true,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum JobRootOverlay {
@@ -213,6 +267,76 @@ pub enum JobRootOverlay {
     },
 }
 
+/// Directories that are commonly their own mount, so paths under different ones are on different
+/// file systems.
+const WELL_KNOWN_MOUNT_ROOTS: [&str; 6] = ["/dev", "/dev/shm", "/proc", "/run", "/sys", "/tmp"];
+
+fn well_known_mount_root(path: &Utf8Path) -> Option<&'static str> {
+    WELL_KNOWN_MOUNT_ROOTS
+        .into_iter()
+        .filter(|root| path.starts_with(root))
+        .max_by_key(|root| root.len())
+}
+
+impl JobRootOverlay {
+    /// Check that the `upper` and `work` directories of a [`JobRootOverlay::Local`] could be on the
+    /// same file system, which overlayfs requires. This only looks at the paths, so it can't catch
+    /// every problem, but it lets the client reject obviously bad specs before the job is enqueued.
+    pub fn validate(&self) -> Result<(), JobRootOverlayError> {
+        let Self::Local { upper, work } = self else {
+            return Ok(());
+        };
+        if upper.is_absolute() != work.is_absolute() {
+            return Err(JobRootOverlayError::MixedAbsoluteAndRelative {
+                upper: upper.clone(),
+                work: work.clone(),
+            });
+        }
+        let upper_root = well_known_mount_root(upper);
+        let work_root = well_known_mount_root(work);
+        if upper_root != work_root {
+            return Err(JobRootOverlayError::DifferentMountRoots {
+                upper: upper.clone(),
+                work: work.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// An error returned from [`JobRootOverlay::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobRootOverlayError {
+    /// One of `upper` and `work` is absolute and the other is relative.
+    MixedAbsoluteAndRelative {
+        upper: Utf8PathBuf,
+        work: Utf8PathBuf,
+    },
+    /// `upper` and `work` are under different mount roots, like `/tmp` and `/home`.
+    DifferentMountRoots {
+        upper: Utf8PathBuf,
+        work: Utf8PathBuf,
+    },
+}
+
+impl fmt::Display for JobRootOverlayError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MixedAbsoluteAndRelative { upper, work } => write!(
+                f,
+                "local root overlay upper {upper} and work {work} must both be absolute \
+                or both be relative"
+            ),
+            Self::DifferentMountRoots { upper, work } => write!(
+                f,
+                "local root overlay upper {upper} and work {work} must be on the same file system"
+            ),
+        }
+    }
+}
+
+impl Error for JobRootOverlayError {}
+
 /// ID of a user. This should be compatible with uid_t.
 #[derive(
     Copy, Clone, Debug, Deserialize, Display, Eq, From, Hash, Ord, PartialEq, PartialOrd, Serialize,
@@ -245,23 +369,94 @@ impl GroupId {
     }
 }
 
-/// A count of seconds.
-#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
-pub struct Timeout(NonZeroU32);
+/// A job timeout, with millisecond resolution.
+///
+/// Human-readable formats serialize it as a count of seconds: an integer if it is a whole number
+/// of seconds, and a fraction otherwise. That way, specs saved before sub-second timeouts existed
+/// still load. Binary formats serialize it as a count of milliseconds.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Timeout(NonZeroU64);
 
 impl Timeout {
+    /// A timeout of `timeout` seconds, or `None` if `timeout` is zero.
     pub fn new(timeout: u32) -> Option<Self> {
-        NonZeroU32::new(timeout).map(Timeout)
+        Self::from_millis(u64::from(timeout) * 1000)
+    }
+
+    /// A timeout of `millis` milliseconds, or `None` if `millis` is zero.
+    pub fn from_millis(millis: u64) -> Option<Self> {
+        NonZeroU64::new(millis).map(Timeout)
     }
 
+    /// The timeout in whole seconds, rounded up.
     pub fn as_u32(&self) -> u32 {
-        self.0.into()
+        self.0.get().div_ceil(1000).try_into().unwrap_or(u32::MAX)
+    }
+
+    pub fn as_millis(&self) -> u64 {
+        self.0.get()
     }
 }
 
 impl From<Timeout> for Duration {
     fn from(timeout: Timeout) -> Duration {
-        Duration::from_secs(timeout.0.get().into())
+        Duration::from_millis(timeout.0.get())
+    }
+}
+
+impl Serialize for Timeout {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let millis = self.0.get();
+        if !serializer.is_human_readable() {
+            serializer.serialize_u64(millis)
+        } else if millis % 1000 == 0 {
+            serializer.serialize_u64(millis / 1000)
+        } else {
+            serializer.serialize_f64(millis as f64 / 1000.)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Timeout {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Timeout;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                write!(f, "a positive number of seconds")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                v.checked_mul(1000)
+                    .and_then(Timeout::from_millis)
+                    .ok_or_else(|| E::custom(format!("invalid timeout of {v} seconds")))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                let v = u64::try_from(v)
+                    .map_err(|_| E::custom(format!("invalid timeout of {v} seconds")))?;
+                self.visit_u64(v)
+            }
+
+            fn visit_f64<E: serde::de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                let millis = (v * 1000.).round();
+                if !(1. ..=u64::MAX as f64).contains(&millis) {
+                    return Err(E::custom(format!("invalid timeout of {v} seconds")));
+                }
+                Ok(Timeout::from_millis(millis as u64).unwrap())
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(Visitor)
+        } else {
+            let millis = u64::deserialize(deserializer)?;
+            Timeout::from_millis(millis).ok_or_else(|| D::Error::custom("zero timeout"))
+        }
     }
 }
 
@@ -278,25 +473,87 @@ impl WindowSize {
     }
 }
 
-/// The parameters for a TTY for a job.
-#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
-pub struct JobTty {
+/// The Unix domain socket the worker connects to in order to relay a job's TTY.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum TtySocket {
     /// A Unix domain socket abstract address. We use exactly 6 bytes because that's how many bytes
     /// the autobind feature in Linux uses. The first byte will always be 0.
-    pub socket_address: [u8; 6],
+    Abstract([u8; 6]),
+
+    /// A Unix domain socket bound to a path in the file system. This is for environments where
+    /// abstract sockets aren't available.
+    Path(Utf8PathBuf),
+}
+
+/// The parameters for a TTY for a job.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct JobTty {
+    /// The socket to connect to.
+    pub socket: TtySocket,
 
     /// The initial window size of the TTY. Window size updates may follow.
     pub window_size: WindowSize,
+
+    /// A bound on how much of the job's output the worker buffers while it waits for the socket.
+    /// If `None`, the worker doesn't read more from the TTY until the last read has been sent.
+    #[serde(default)]
+    pub output_limit: Option<TtyOutputLimit>,
+
+    /// Whether the worker frames the job's output the same way the client frames its input, so
+    /// that it can acknowledge window size changes in the same stream. See [`tty::Reader`]. If
+    /// `false`, the worker sends the job's output as is.
+    #[serde(default)]
+    pub framed_output: bool,
 }
 
 impl JobTty {
     pub fn new(socket_address: &[u8; 6], window_size: WindowSize) -> Self {
-        let socket_address = *socket_address;
+        Self::with_socket(TtySocket::Abstract(*socket_address), window_size)
+    }
+
+    pub fn with_socket(socket: TtySocket, window_size: WindowSize) -> Self {
         Self {
-            socket_address,
+            socket,
             window_size,
+            output_limit: None,
+            framed_output: false,
         }
     }
+
+    pub fn output_limit(mut self, output_limit: Option<TtyOutputLimit>) -> Self {
+        self.output_limit = output_limit;
+        self
+    }
+
+    pub fn framed_output(mut self, framed_output: bool) -> Self {
+        self.framed_output = framed_output;
+        self
+    }
+}
+
+/// A bound on how much of a TTY job's output the worker buffers when the other end of the socket
+/// isn't keeping up.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct TtyOutputLimit {
+    /// The number of bytes of output the worker buffers before applying `overflow`.
+    pub high_water_mark: u64,
+    #[serde(default)]
+    pub overflow: TtyOutputOverflow,
+}
+
+/// What the worker does with a TTY job's output once it has buffered
+/// [`high_water_mark`](TtyOutputLimit::high_water_mark) bytes.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum TtyOutputOverflow {
+    /// Stop reading from the TTY until the buffer drains. The job blocks writing its output.
+    #[default]
+    Backpressure,
+    /// Keep reading from the TTY, but throw away output that doesn't fit in the buffer. A marker
+    /// made by [`tty::encode_dropped_output`] is sent in place of the dropped output.
+    Drop,
 }
 
 /// All necessary information for the worker to execute a job.
@@ -315,6 +572,200 @@ pub struct JobSpec {
     pub timeout: Option<Timeout>,
     pub estimated_duration: Option<Duration>,
     pub allocate_tty: Option<JobTty>,
+    /// The value the worker writes to the job's `/proc/self/oom_score_adj`. Higher values make the
+    /// job more likely to be killed when the worker runs out of memory. Must be in
+    /// [`OOM_SCORE_ADJ_RANGE`]. Values below the worker's own adjustment, which is usually zero,
+    /// only work if the worker has `CAP_SYS_RESOURCE`. Otherwise, the job fails with an execution
+    /// error.
+    #[serde(default)]
+    pub oom_score_adj: Option<i16>,
+    /// Whether the job's result may be served from, and stored in, a result cache. Even when set,
+    /// jobs that aren't deterministic are never cached. See [`JobSpec::cache_key`].
+    #[serde(default)]
+    pub cacheable: bool,
+    /// An opaque ID used to correlate the job across the client, broker, and worker, for
+    /// distributed tracing. It has no effect on how the job is run, and doesn't contribute to the
+    /// job's [`JobSpec::content_hash`].
+    #[serde(default)]
+    pub trace_id: Option<String>,
+    /// A human-readable name for the job, for UIs to show instead of its program. See
+    /// [`JobSpec::display_name_or_program`]. Like the [`trace_id`](JobSpec::trace_id), it has no
+    /// effect on how the job is run, and doesn't contribute to the job's
+    /// [`JobSpec::content_hash`].
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// The indices of the CPUs the job is allowed to run on. If `None`, the job may run on any CPU
+    /// available to the worker. If provided, it must be non-empty, and all indices must be less
+    /// than [`MAX_CPUS`].
+    #[serde(default)]
+    pub cpu_set: Option<Vec<u16>>,
+    /// Require the job's root file system to be read-only. This can't be combined with a
+    /// [`JobRootOverlay::Tmp`] or [`JobRootOverlay::Local`] overlay, which would make the root
+    /// writable: [`JobSpec::validate`] rejects specs that ask for both.
+    #[serde(default)]
+    pub readonly_root: bool,
+    /// Jobs that must complete before this one may run. These are IDs of jobs submitted by the
+    /// same client: dependencies on other clients' jobs can't be expressed. This is currently
+    /// advisory, as the broker doesn't enforce it yet.
+    #[serde(default)]
+    pub depends_on: Vec<ClientJobId>,
+    /// Workers start available jobs with higher priorities first, regardless of their estimated
+    /// durations. Jobs with the same priority are started longest estimated duration first.
+    #[serde(default)]
+    pub priority: i32,
+    /// The most memory, in bytes, the job is expected to use. This is used to decide which
+    /// workers the job may be placed on (see [`JobSpec::fits`]), but it isn't enforced when the
+    /// job runs.
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+}
+
+/// The range of values Linux accepts for `/proc/<pid>/oom_score_adj`.
+pub const OOM_SCORE_ADJ_RANGE: RangeInclusive<i16> = -1000..=1000;
+
+/// One more than the largest CPU index allowed in [`JobSpec::cpu_set`]. This matches glibc's
+/// `CPU_SETSIZE`.
+pub const MAX_CPUS: u16 = 1024;
+
+/// The resources a worker has available for new jobs. See [`JobSpec::fits`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkerCapacity {
+    /// Memory, in bytes, not already claimed by running jobs.
+    pub free_memory: u64,
+    /// Job slots not already occupied by running jobs.
+    pub free_slots: usize,
+    /// The number of CPUs the worker has. CPU indices in [`JobSpec::cpu_set`] must be less than
+    /// this.
+    pub cpus: u16,
+}
+
+/// An error returned from [`JobSpec::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JobSpecValidationError {
+    OomScoreAdjOutOfRange(i16),
+    EmptyCpuSet,
+    CpuIndexOutOfRange(u16),
+    ConflictingMounts(usize, usize),
+    ReadonlyRootWithOverlay,
+}
+
+impl fmt::Display for JobSpecValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OomScoreAdjOutOfRange(value) => write!(
+                f,
+                "oom_score_adj value {value} is outside of the range {}..={}",
+                OOM_SCORE_ADJ_RANGE.start(),
+                OOM_SCORE_ADJ_RANGE.end()
+            ),
+            Self::EmptyCpuSet => write!(f, "cpu_set must not be empty"),
+            Self::CpuIndexOutOfRange(index) => write!(
+                f,
+                "cpu_set index {index} is out of range, it must be less than {MAX_CPUS}"
+            ),
+            Self::ConflictingMounts(first, second) => {
+                write!(f, "mounts {first} and {second} have the same mount point")
+            }
+            Self::ReadonlyRootWithOverlay => write!(
+                f,
+                "readonly_root can't be used with a root overlay, which makes the root writable"
+            ),
+        }
+    }
+}
+
+impl Error for JobSpecValidationError {}
+
+/// An error returned from [`JobSpec::check_mount_network_coherence`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CoherenceError {
+    /// The mount at the given index is a [`JobMount::Sys`], which Linux disallows when using
+    /// [`JobNetwork::Local`].
+    SysMountWithLocalNetwork(usize),
+}
+
+impl fmt::Display for CoherenceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SysMountWithLocalNetwork(index) => write!(
+                f,
+                "mount {index} is a sys mount, which can't be used with local networking"
+            ),
+        }
+    }
+}
+
+impl Error for CoherenceError {}
+
+/// An error returned from [`JobSpec::from_canonical_json`].
+#[derive(Debug)]
+pub enum JobSpecFromJsonError {
+    Json(serde_json::Error),
+    Invalid(JobSpecValidationError),
+}
+
+impl fmt::Display for JobSpecFromJsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Json(err) => write!(f, "malformed job spec: {err}"),
+            Self::Invalid(err) => write!(f, "invalid job spec: {err}"),
+        }
+    }
+}
+
+impl Error for JobSpecFromJsonError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Json(err) => Some(err),
+            Self::Invalid(err) => Some(err),
+        }
+    }
+}
+
+/// An error returned from [`safe_join`] when a path would escape its root.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathTraversalError {
+    pub root: Utf8PathBuf,
+    pub path: Utf8PathBuf,
+}
+
+impl fmt::Display for PathTraversalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "path {} escapes root {}", self.path, self.root)
+    }
+}
+
+impl Error for PathTraversalError {}
+
+/// Join `path` onto `root`, making sure the result stays within `root`. An absolute `path` is
+/// treated as relative to `root`. The result is normalized lexically: `.` components are dropped
+/// and `..` components remove the preceding component, with an error if there is nothing left to
+/// remove. Symlinks aren't considered.
+///
+/// ```
+/// # use maelstrom_base::safe_join;
+/// assert_eq!(safe_join("/jail".into(), "/a/./b/../c".into()).unwrap(), "/jail/a/c");
+/// assert!(safe_join("/jail".into(), "a/../../b".into()).is_err());
+/// ```
+pub fn safe_join(root: &Utf8Path, path: &Utf8Path) -> Result<Utf8PathBuf, PathTraversalError> {
+    let mut components = vec![];
+    for component in path.components() {
+        match component {
+            Utf8Component::Prefix(_) | Utf8Component::RootDir | Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                if components.pop().is_none() {
+                    return Err(PathTraversalError {
+                        root: root.to_owned(),
+                        path: path.to_owned(),
+                    });
+                }
+            }
+            Utf8Component::Normal(name) => components.push(name),
+        }
+    }
+    let mut joined = root.to_owned();
+    joined.extend(components);
+    Ok(joined)
 }
 
 impl JobSpec {
@@ -336,6 +787,15 @@ impl JobSpec {
             timeout: None,
             estimated_duration: None,
             allocate_tty: None,
+            oom_score_adj: None,
+            cacheable: false,
+            trace_id: None,
+            display_name: None,
+            cpu_set: None,
+            readonly_root: false,
+            depends_on: Default::default(),
+            priority: 0,
+            memory_limit: None,
         }
     }
 
@@ -362,6 +822,17 @@ impl JobSpec {
         self
     }
 
+    /// Append `mount` to the spec's mounts, keeping the ones already there.
+    pub fn add_mount(mut self, mount: JobMount) -> Self {
+        self.mounts.push(mount);
+        self
+    }
+
+    /// Append `layer` to the spec's layers in place, keeping the ones already there.
+    pub fn push_layer(&mut self, layer: (Sha256Digest, ArtifactType)) {
+        self.layers.push(layer);
+    }
+
     pub fn network(mut self, network: JobNetwork) -> Self {
         self.network = network;
         self
@@ -402,15 +873,333 @@ impl JobSpec {
         self
     }
 
+    pub fn oom_score_adj(mut self, oom_score_adj: Option<i16>) -> Self {
+        self.oom_score_adj = oom_score_adj;
+        self
+    }
+
+    pub fn cacheable(mut self, cacheable: bool) -> Self {
+        self.cacheable = cacheable;
+        self
+    }
+
+    pub fn trace_id(mut self, trace_id: Option<impl Into<String>>) -> Self {
+        self.trace_id = trace_id.map(Into::into);
+        self
+    }
+
+    pub fn display_name(mut self, display_name: Option<impl Into<String>>) -> Self {
+        self.display_name = display_name.map(Into::into);
+        self
+    }
+
+    pub fn cpu_set(mut self, cpu_set: Option<impl IntoIterator<Item = u16>>) -> Self {
+        self.cpu_set = cpu_set.map(|cpu_set| cpu_set.into_iter().collect());
+        self
+    }
+
+    pub fn readonly_root(mut self, readonly_root: bool) -> Self {
+        self.readonly_root = readonly_root;
+        self
+    }
+
+    pub fn depends_on(mut self, depends_on: impl IntoIterator<Item = ClientJobId>) -> Self {
+        self.depends_on = depends_on.into_iter().collect();
+        self
+    }
+
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn memory_limit(mut self, memory_limit: Option<u64>) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
+
+    /// Whether a worker with the given capacity could start the job now. The worker needs a free
+    /// slot, enough free memory for the job's [`memory_limit`](JobSpec::memory_limit), and every
+    /// CPU in the job's [`cpu_set`](JobSpec::cpu_set). Requirements the job doesn't declare are
+    /// always met.
+    pub fn fits(&self, capacity: &WorkerCapacity) -> bool {
+        capacity.free_slots > 0
+            && self
+                .memory_limit
+                .map_or(true, |limit| limit <= capacity.free_memory)
+            && self.cpu_set.as_ref().map_or(true, |cpu_set| {
+                cpu_set.iter().all(|&index| index < capacity.cpus)
+            })
+    }
+
+    /// Whether the job has to wait for other jobs to complete before it may run. See
+    /// [`depends_on`](JobSpec::depends_on).
+    pub fn has_dependencies(&self) -> bool {
+        !self.depends_on.is_empty()
+    }
+
+    /// All of the devices the job requests, across all of its
+    /// [`Devices`](JobMount::Devices) mounts.
+    pub fn requested_devices(&self) -> EnumSet<JobDevice> {
+        self.mounts
+            .iter()
+            .filter_map(|mount| match mount {
+                JobMount::Devices { devices } => Some(*devices),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the job's root file system will be writable. This is decided by
+    /// [`root_overlay`](JobSpec::root_overlay) alone: an overlay makes the root writable, and
+    /// without one the root is read-only.
+    pub fn root_is_writable(&self) -> bool {
+        match self.root_overlay {
+            JobRootOverlay::None => false,
+            JobRootOverlay::Tmp | JobRootOverlay::Local { .. } => true,
+        }
+    }
+
+    /// The name UIs should show for the job: its [`display_name`](JobSpec::display_name) if it has
+    /// one, and its program otherwise.
+    pub fn display_name_or_program(&self) -> &str {
+        self.display_name
+            .as_deref()
+            .unwrap_or(self.program.as_str())
+    }
+
+    /// A digest of the parts of the spec that affect what the job does. This leaves out the
+    /// [`trace_id`](JobSpec::trace_id) and [`display_name`](JobSpec::display_name), as well as the
+    /// fields that only affect when and where the job is scheduled: the
+    /// [`estimated_duration`](JobSpec::estimated_duration),
+    /// [`depends_on`](JobSpec::depends_on), [`priority`](JobSpec::priority), and
+    /// [`memory_limit`](JobSpec::memory_limit). Two specs have the same content hash if and only if
+    /// they are equal, ignoring those fields.
+    pub fn content_hash(&self) -> Sha256Digest {
+        let spec = Self {
+            estimated_duration: None,
+            trace_id: None,
+            display_name: None,
+            depends_on: vec![],
+            priority: 0,
+            memory_limit: None,
+            ..self.clone()
+        };
+        let mut hasher = Sha256::new();
+        proto::serialize_into(&mut hasher, &spec).unwrap();
+        Sha256Digest(hasher.finalize().into())
+    }
+
+    /// The key under which the job's result can be cached, or `None` if the job's result must not
+    /// be cached. Only jobs that are marked [`cacheable`](JobSpec::cacheable) and that don't
+    /// depend on anything outside of the spec itself, like the local network, bind mounts, or a
+    /// TTY, get a key.
+    pub fn cache_key(&self) -> Option<Sha256Digest> {
+        (self.cacheable && !self.must_be_run_locally()).then(|| self.content_hash())
+    }
+
+    /// Serialize the spec as compact JSON. Fields are always written in the same order, so equal
+    /// specs produce identical strings.
+    pub fn to_canonical_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    /// Parse a spec written by [`JobSpec::to_canonical_json`], then [`validate`](JobSpec::validate)
+    /// it.
+    pub fn from_canonical_json(s: &str) -> Result<Self, JobSpecFromJsonError> {
+        let spec: Self = serde_json::from_str(s).map_err(JobSpecFromJsonError::Json)?;
+        spec.validate().map_err(JobSpecFromJsonError::Invalid)?;
+        Ok(spec)
+    }
+
+    /// Check the parts of the spec that can't be enforced by the type system.
+    pub fn validate(&self) -> Result<(), JobSpecValidationError> {
+        if let Some(oom_score_adj) = self.oom_score_adj {
+            if !OOM_SCORE_ADJ_RANGE.contains(&oom_score_adj) {
+                return Err(JobSpecValidationError::OomScoreAdjOutOfRange(oom_score_adj));
+            }
+        }
+        if let Some(cpu_set) = &self.cpu_set {
+            if cpu_set.is_empty() {
+                return Err(JobSpecValidationError::EmptyCpuSet);
+            }
+            if let Some(&index) = cpu_set.iter().find(|&&index| index >= MAX_CPUS) {
+                return Err(JobSpecValidationError::CpuIndexOutOfRange(index));
+            }
+        }
+        if let Some(&(first, second)) = self.conflicting_mounts().first() {
+            return Err(JobSpecValidationError::ConflictingMounts(first, second));
+        }
+        if self.readonly_root && self.root_is_writable() {
+            return Err(JobSpecValidationError::ReadonlyRootWithOverlay);
+        }
+        Ok(())
+    }
+
+    /// Check that the spec's mounts can all be used with its network mode. Incoherent specs would
+    /// otherwise only fail when the worker tries to start the job.
+    pub fn check_mount_network_coherence(&self) -> Result<(), CoherenceError> {
+        for (index, mount) in self.mounts.iter().enumerate() {
+            let error = match (mount, self.network) {
+                (JobMount::Sys { .. }, JobNetwork::Local) => {
+                    Some(CoherenceError::SysMountWithLocalNetwork(index))
+                }
+                _ => None,
+            };
+            if let Some(error) = error {
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// The mount points of all of the spec's mounts, in order. [`JobMount::Devices`] mounts don't
+    /// have a single mount point, so they contribute nothing.
+    pub fn mount_points(&self) -> Vec<&Utf8Path> {
+        self.indexed_mount_points()
+            .map(|(_, mount_point)| mount_point)
+            .collect()
+    }
+
+    /// Pairs of indices into [`Self::mounts`] of mounts that have the same mount point. The first
+    /// index of each pair is always less than the second.
+    pub fn conflicting_mounts(&self) -> Vec<(usize, usize)> {
+        let mount_points = Vec::from_iter(self.indexed_mount_points());
+        mount_points
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &(first, first_mount_point))| {
+                mount_points[i + 1..]
+                    .iter()
+                    .filter(move |&&(_, second_mount_point)| {
+                        second_mount_point == first_mount_point
+                    })
+                    .map(move |&(second, _)| (first, second))
+            })
+            .collect()
+    }
+
+    fn indexed_mount_points(&self) -> impl Iterator<Item = (usize, &Utf8Path)> {
+        self.mounts
+            .iter()
+            .enumerate()
+            .filter_map(|(index, mount)| match mount {
+                JobMount::Bind { mount_point, .. }
+                | JobMount::Overlay { mount_point, .. }
+                | JobMount::Devpts { mount_point }
+                | JobMount::Mqueue { mount_point }
+                | JobMount::Proc { mount_point }
+                | JobMount::Sys { mount_point }
+                | JobMount::Tmp { mount_point } => Some((index, mount_point.as_path())),
+                JobMount::Devices { .. } => None,
+            })
+    }
+
+    /// Rebase every mount point, the local path of every bind mount, the working directory, and
+    /// the paths of a local root overlay under `root`, using [`safe_join`]. If any of them would
+    /// escape `root`, an error is returned and the spec is left unchanged.
+    pub fn rebase_paths(&mut self, root: &Utf8Path) -> Result<(), PathTraversalError> {
+        let mut rebased = self.clone();
+        for mount in &mut rebased.mounts {
+            match mount {
+                JobMount::Bind {
+                    mount_point,
+                    local_path,
+                    ..
+                } => {
+                    *mount_point = safe_join(root, mount_point)?;
+                    *local_path = safe_join(root, local_path)?;
+                }
+                JobMount::Overlay {
+                    mount_point,
+                    lower,
+                    upper,
+                    work,
+                } => {
+                    *mount_point = safe_join(root, mount_point)?;
+                    *lower = safe_join(root, lower)?;
+                    *upper = safe_join(root, upper)?;
+                    *work = safe_join(root, work)?;
+                }
+                JobMount::Devpts { mount_point }
+                | JobMount::Mqueue { mount_point }
+                | JobMount::Proc { mount_point }
+                | JobMount::Sys { mount_point }
+                | JobMount::Tmp { mount_point } => *mount_point = safe_join(root, mount_point)?,
+                JobMount::Devices { .. } => {}
+            }
+        }
+        if let Some(working_directory) = &mut rebased.working_directory {
+            *working_directory = safe_join(root, working_directory)?;
+        }
+        if let JobRootOverlay::Local { upper, work } = &mut rebased.root_overlay {
+            *upper = safe_join(root, upper)?;
+            *work = safe_join(root, work)?;
+        }
+        *self = rebased;
+        Ok(())
+    }
+
     pub fn must_be_run_locally(&self) -> bool {
         self.network == JobNetwork::Local
             || self
                 .mounts
                 .iter()
-                .any(|mount| matches!(mount, JobMount::Bind { .. }))
+                .any(|mount| matches!(mount, JobMount::Bind { .. } | JobMount::Overlay { .. }))
             || matches!(&self.root_overlay, JobRootOverlay::Local { .. })
             || self.allocate_tty.is_some()
     }
+
+    /// The digests of the spec's layers for which `present` returns false, without duplicates.
+    /// The digests are returned in the order they first appear in [`Self::layers`]. A client can
+    /// use this to upload only the artifacts a cache doesn't already have.
+    pub fn missing_layers(&self, present: impl Fn(&Sha256Digest) -> bool) -> Vec<Sha256Digest> {
+        let mut seen = HashSet::new();
+        self.layers
+            .iter()
+            .map(|(digest, _)| digest)
+            .filter(|digest| seen.insert(*digest) && !present(digest))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Collect the layers used by any of `specs`, without duplicates. Layers are considered the same if
+/// they have the same digest. The layers are returned in the order they were first seen.
+pub fn distinct_layers(specs: &[JobSpec]) -> Vec<(Sha256Digest, ArtifactType)> {
+    let mut seen = HashSet::new();
+    specs
+        .iter()
+        .flat_map(|spec| spec.layers.iter())
+        .filter(|(digest, _)| seen.insert(digest))
+        .cloned()
+        .collect()
+}
+
+/// Greedily group `layers`, in order, into batches whose total size, as reported by `sizes`, is no
+/// more than `max_batch_bytes`. A layer that is larger than `max_batch_bytes` by itself is put in
+/// its own batch.
+pub fn batch_layers(
+    layers: &[(Sha256Digest, ArtifactType)],
+    sizes: impl Fn(&Sha256Digest) -> u64,
+    max_batch_bytes: u64,
+) -> Vec<Vec<(Sha256Digest, ArtifactType)>> {
+    let mut batches = vec![];
+    let mut batch = vec![];
+    let mut batch_bytes = 0u64;
+    for layer in layers {
+        let size = sizes(&layer.0);
+        if !batch.is_empty() && batch_bytes.saturating_add(size) > max_batch_bytes {
+            batches.push(mem::take(&mut batch));
+            batch_bytes = 0;
+        }
+        batch.push(layer.clone());
+        batch_bytes = batch_bytes.saturating_add(size);
+    }
+    if !batch.is_empty() {
+        batches.push(batch);
+    }
+    batches
 }
 
 /// How a job's process terminated. A process can either exit of its own accord or be killed by a
@@ -421,6 +1210,37 @@ pub enum JobStatus {
     Signaled(u8),
 }
 
+/// A compression algorithm for job output. Every algorithm must be usable from WASM.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompressionAlgorithm {
+    /// Raw DEFLATE, as described in RFC 1951.
+    #[default]
+    Deflate,
+}
+
+impl CompressionAlgorithm {
+    pub fn compress(&self, data: &[u8]) -> Box<[u8]> {
+        match self {
+            Self::Deflate => miniz_oxide::deflate::compress_to_vec(data, 6).into_boxed_slice(),
+        }
+    }
+
+    /// Decompress `data`, which is expected to decompress to exactly `uncompressed_len` bytes.
+    /// Returns `None` if `data` is corrupt or doesn't decompress to the expected length.
+    pub fn decompress(&self, data: &[u8], uncompressed_len: u64) -> Option<Vec<u8>> {
+        let limit = usize::try_from(uncompressed_len).ok()?;
+        let decompressed = match self {
+            Self::Deflate => {
+                miniz_oxide::inflate::decompress_to_vec_with_limit(data, limit).ok()?
+            }
+        };
+        (decompressed.len() == limit).then_some(decompressed)
+    }
+}
+
 /// The result for stdout or stderr for a job.
 #[derive(Clone, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
 pub enum JobOutputResult {
@@ -434,11 +1254,68 @@ pub enum JobOutputResult {
     /// request. The actual size of the output is also provided, though the remaining bytes will
     /// have been thrown away.
     Truncated { first: Box<[u8]>, truncated: u64 },
-    /*
-     * To come:
-    /// The output was stored in a digest, and is of the provided size.
-    External(Sha256Digest, u64),
-    */
+
+    /// The output was too large to return inline, so the worker stored all of it in its cache under
+    /// `digest`. The first part of the output, the size of which is based on the job request, is
+    /// also provided, as is the size of the whole output.
+    External {
+        first: Box<[u8]>,
+        digest: Sha256Digest,
+        size: u64,
+    },
+
+    /// The output is contained in the provided slice, compressed with `algorithm`.
+    InlineCompressed {
+        algorithm: CompressionAlgorithm,
+        data: Box<[u8]>,
+        uncompressed_len: u64,
+    },
+}
+
+impl JobOutputResult {
+    /// Compress `bytes` with `algorithm` and return the corresponding
+    /// [`JobOutputResult::InlineCompressed`].
+    pub fn inline_compressed(algorithm: CompressionAlgorithm, bytes: &[u8]) -> Self {
+        Self::InlineCompressed {
+            algorithm,
+            data: algorithm.compress(bytes),
+            uncompressed_len: bytes.len() as u64,
+        }
+    }
+
+    /// The size of the output, in bytes. This includes bytes that were truncated, stored
+    /// externally, or compressed.
+    pub fn len(&self) -> u64 {
+        match self {
+            JobOutputResult::None => 0,
+            JobOutputResult::Inline(bytes) => bytes.len() as u64,
+            JobOutputResult::InlineCompressed {
+                uncompressed_len, ..
+            } => *uncompressed_len,
+            JobOutputResult::Truncated { first, truncated } => first.len() as u64 + truncated,
+            JobOutputResult::External { size, .. } => *size,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the inline output, decompressing it if necessary. Returns `None` for output that
+    /// isn't inline, or that can't be decompressed.
+    pub fn inline_bytes(&self) -> Option<Cow<'_, [u8]>> {
+        match self {
+            JobOutputResult::Inline(bytes) => Some(Cow::Borrowed(bytes)),
+            JobOutputResult::InlineCompressed {
+                algorithm,
+                data,
+                uncompressed_len,
+            } => algorithm
+                .decompress(data, *uncompressed_len)
+                .map(Cow::Owned),
+            _ => None,
+        }
+    }
 }
 
 impl Debug for JobOutputResult {
@@ -449,6 +1326,19 @@ impl Debug for JobOutputResult {
                 let pretty_bytes = String::from_utf8_lossy(bytes);
                 f.debug_tuple("Inline").field(&pretty_bytes).finish()
             }
+            JobOutputResult::InlineCompressed {
+                algorithm,
+                data,
+                uncompressed_len,
+            } => {
+                let mut debug = f.debug_struct("InlineCompressed");
+                debug.field("algorithm", algorithm);
+                match self.inline_bytes() {
+                    Some(bytes) => debug.field("data", &String::from_utf8_lossy(&bytes)),
+                    None => debug.field("corrupt_data_len", &data.len()),
+                };
+                debug.field("uncompressed_len", uncompressed_len).finish()
+            }
             JobOutputResult::Truncated { first, truncated } => {
                 let pretty_first = String::from_utf8_lossy(first);
                 f.debug_struct("Truncated")
@@ -456,6 +1346,18 @@ impl Debug for JobOutputResult {
                     .field("truncated", truncated)
                     .finish()
             }
+            JobOutputResult::External {
+                first,
+                digest,
+                size,
+            } => {
+                let pretty_first = String::from_utf8_lossy(first);
+                f.debug_struct("External")
+                    .field("first", &pretty_first)
+                    .field("digest", digest)
+                    .field("size", size)
+                    .finish()
+            }
         }
     }
 }
@@ -465,10 +1367,28 @@ impl fmt::Display for JobOutputResult {
         match self {
             JobOutputResult::None => Ok(()),
             JobOutputResult::Inline(bytes) => fmt::Display::fmt(&String::from_utf8_lossy(bytes), f),
+            JobOutputResult::InlineCompressed { data, .. } => match self.inline_bytes() {
+                Some(bytes) => fmt::Display::fmt(&String::from_utf8_lossy(&bytes), f),
+                None => fmt::Display::fmt(
+                    &format!("<corrupt compressed output: {} bytes>", data.len()),
+                    f,
+                ),
+            },
             JobOutputResult::Truncated { first, truncated } => {
                 fmt::Display::fmt(&String::from_utf8_lossy(first), f)?;
                 fmt::Display::fmt(&format!("<{truncated} bytes truncated>"), f)
             }
+            JobOutputResult::External {
+                first,
+                digest,
+                size,
+            } => {
+                fmt::Display::fmt(&String::from_utf8_lossy(first), f)?;
+                fmt::Display::fmt(
+                    &format!("<output stored externally: {size} bytes, {digest}>"),
+                    f,
+                )
+            }
         }
     }
 }
@@ -481,6 +1401,10 @@ pub struct JobEffects {
     pub stdout: JobOutputResult,
     pub stderr: JobOutputResult,
     pub duration: Duration,
+    /// The worker that ran the job, if known. Workers don't know their own IDs, so this is filled
+    /// in by the broker when it forwards the result to the client.
+    #[serde(default)]
+    pub worker: Option<WorkerId>,
 }
 
 /// The outcome of a completed job. That is, a job that ran to completion, instead of timing out,
@@ -498,6 +1422,20 @@ pub enum JobOutcome {
     TimedOut(JobEffects),
 }
 
+impl JobOutcome {
+    pub fn effects(&self) -> &JobEffects {
+        match self {
+            Self::Completed(JobCompleted { effects, .. }) | Self::TimedOut(effects) => effects,
+        }
+    }
+
+    pub fn effects_mut(&mut self) -> &mut JobEffects {
+        match self {
+            Self::Completed(JobCompleted { effects, .. }) | Self::TimedOut(effects) => effects,
+        }
+    }
+}
+
 /// A job failed to execute for some reason. We separate the universe of errors into "execution"
 /// errors and "system" errors.
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
@@ -548,14 +1486,26 @@ pub type JobOutcomeResult = JobResult<JobOutcome, String>;
 )]
 pub struct WorkerId(u32);
 
+impl WorkerId {
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+}
+
 /// A SHA-256 digest.
-#[derive(Clone, Constructor, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+///
+/// Human-readable formats, like JSON and TOML, serialize it as a hex string. Binary formats
+/// serialize it as 32 bytes. Human-readable formats still accept the 32-element arrays they used
+/// to be serialized as.
+#[derive(Clone, Constructor, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct Sha256Digest([u8; 32]);
 
 impl Sha256Digest {
     /// Verify that two digests match. If not, return a [`Sha256DigestVerificationError`].
+    ///
+    /// The comparison takes the same amount of time no matter where the digests differ.
     pub fn verify(&self, expected: &Self) -> Result<(), Sha256DigestVerificationError> {
-        if *self != *expected {
+        if !self.constant_time_eq(expected) {
             Err(Sha256DigestVerificationError::new(
                 self.clone(),
                 expected.clone(),
@@ -568,7 +1518,66 @@ impl Sha256Digest {
     pub fn as_bytes(&self) -> &[u8] {
         self.0.as_ref()
     }
-}
+
+    /// Compare every byte of both digests, instead of stopping at the first difference.
+    fn constant_time_eq(&self, other: &Self) -> bool {
+        let diff = self
+            .0
+            .iter()
+            .zip(other.0.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+        std::hint::black_box(diff) == 0
+    }
+}
+
+impl Serialize for Sha256Digest {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_newtype_struct("Sha256Digest", &self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Sha256Digest {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Sha256Digest;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                write!(f, "a SHA-256 digest as a hex string or 32 bytes")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            fn visit_newtype_struct<D: serde::Deserializer<'de>>(
+                self,
+                deserializer: D,
+            ) -> Result<Self::Value, D::Error> {
+                <[u8; 32]>::deserialize(deserializer).map(Sha256Digest)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                seq: A,
+            ) -> Result<Self::Value, A::Error> {
+                <[u8; 32]>::deserialize(serde::de::value::SeqAccessDeserializer::new(seq))
+                    .map(Sha256Digest)
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(Visitor)
+        } else {
+            deserializer.deserialize_newtype_struct("Sha256Digest", Visitor)
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Sha256DigestTryFromError;
@@ -790,6 +1799,140 @@ mod tests {
         assert_eq!(s, s.parse::<Sha256Digest>().unwrap().to_string());
     }
 
+    #[test]
+    fn sha256_digest_json_round_trip() {
+        let s = "101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f";
+        let d = s.parse::<Sha256Digest>().unwrap();
+        let json = serde_json::to_string(&d).unwrap();
+        assert_eq!(json, format!(r#""{s}""#));
+        assert_eq!(serde_json::from_str::<Sha256Digest>(&json).unwrap(), d);
+    }
+
+    #[test]
+    fn sha256_digest_json_accepts_byte_array() {
+        let d = Sha256Digest::from(42u64);
+        let json = serde_json::to_string(&d.as_bytes()).unwrap();
+        assert_eq!(serde_json::from_str::<Sha256Digest>(&json).unwrap(), d);
+    }
+
+    #[test]
+    fn sha256_digest_json_rejects_bad_hex() {
+        serde_json::from_str::<Sha256Digest>(r#""not hex""#).unwrap_err();
+    }
+
+    #[test]
+    fn sha256_digest_bincode_round_trip() {
+        let d = "101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f"
+            .parse::<Sha256Digest>()
+            .unwrap();
+        let bytes = bincode::serialize(&d).unwrap();
+        assert_eq!(bytes, d.as_bytes());
+        assert_eq!(bincode::deserialize::<Sha256Digest>(&bytes).unwrap(), d);
+    }
+
+    #[test]
+    fn job_output_result_bincode_variant_indices() {
+        // Brokers, workers, and clients encode this with bincode, which identifies variants by
+        // index. New variants have to go at the end to keep existing encodings the same.
+        for (result, index) in [
+            (JobOutputResult::None, 0u32),
+            (JobOutputResult::Inline(Box::new(*b"a")), 1),
+            (
+                JobOutputResult::Truncated {
+                    first: Box::new(*b"a"),
+                    truncated: 1,
+                },
+                2,
+            ),
+            (
+                JobOutputResult::External {
+                    first: Box::new(*b"a"),
+                    digest: Sha256Digest::from(1u32),
+                    size: 2,
+                },
+                3,
+            ),
+            (
+                JobOutputResult::inline_compressed(CompressionAlgorithm::Deflate, b"a"),
+                4,
+            ),
+        ] {
+            let bytes = bincode::serialize(&result).unwrap();
+            assert_eq!(bytes[..4], index.to_le_bytes(), "{result:?}");
+        }
+    }
+
+    #[test]
+    fn verify() {
+        let digest = Sha256Digest::from(42u64);
+        assert!(digest.verify(&Sha256Digest::from(42u64)).is_ok());
+
+        let mut first_byte_differs = digest.clone();
+        first_byte_differs.0[0] ^= 1;
+        for expected in [Sha256Digest::from(43u64), first_byte_differs] {
+            let err = digest.verify(&expected).unwrap_err();
+            assert_eq!(err.actual, digest);
+            assert_eq!(err.expected, expected);
+            assert_eq!(
+                err.to_string(),
+                format!("mismatched SHA-256 digest (expected {expected}, found {digest})")
+            );
+        }
+    }
+
+    #[test]
+    fn timeout_duration() {
+        assert_eq!(Timeout::new(0), None);
+        assert_eq!(Timeout::from_millis(0), None);
+        assert_eq!(
+            Duration::from(Timeout::new(3).unwrap()),
+            Duration::from_secs(3)
+        );
+        assert_eq!(
+            Duration::from(Timeout::from_millis(500).unwrap()),
+            Duration::from_millis(500)
+        );
+        assert_eq!(Timeout::new(3), Timeout::from_millis(3000));
+        assert_eq!(Timeout::from_millis(1500).unwrap().as_u32(), 2);
+    }
+
+    #[test]
+    fn timeout_human_readable_serde() {
+        let whole = Timeout::new(30).unwrap();
+        assert_eq!(serde_json::to_string(&whole).unwrap(), "30");
+        assert_eq!(serde_json::from_str::<Timeout>("30").unwrap(), whole);
+
+        let fractional = Timeout::from_millis(500).unwrap();
+        assert_eq!(serde_json::to_string(&fractional).unwrap(), "0.5");
+        assert_eq!(serde_json::from_str::<Timeout>("0.5").unwrap(), fractional);
+
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        struct Table {
+            timeout: Timeout,
+        }
+        for timeout in [whole, fractional] {
+            let table = Table { timeout };
+            let toml = toml::to_string(&table).unwrap();
+            assert_eq!(toml::from_str::<Table>(&toml).unwrap(), table);
+        }
+        assert_eq!(
+            toml::from_str::<Table>("timeout = 30").unwrap().timeout,
+            whole
+        );
+
+        serde_json::from_str::<Timeout>("0").unwrap_err();
+        serde_json::from_str::<Timeout>("0.0001").unwrap_err();
+        serde_json::from_str::<Timeout>("-1").unwrap_err();
+    }
+
+    #[test]
+    fn timeout_binary_serde() {
+        let timeout = Timeout::from_millis(1500).unwrap();
+        let bytes = proto::serialize(&timeout).unwrap();
+        assert_eq!(proto::deserialize::<Timeout>(&bytes).unwrap(), timeout);
+        proto::deserialize::<Timeout>(&proto::serialize(&0u64).unwrap()).unwrap_err();
+    }
+
     #[test]
     fn display_padding() {
         let d = "101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f"
@@ -859,6 +2002,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn job_network_allows_loopback() {
+        assert!(!JobNetwork::Disabled.allows_loopback());
+        assert!(JobNetwork::Loopback.allows_loopback());
+        assert!(JobNetwork::Local.allows_loopback());
+        assert!(!JobNetwork::default().allows_loopback());
+    }
+
     #[test]
     fn job_spec_must_be_run_locally_network() {
         let spec = JobSpec::new(
@@ -893,10 +2044,19 @@ mod tests {
                 mount_point: Utf8PathBuf::from("/bind"),
                 local_path: Utf8PathBuf::from("/a"),
                 read_only: false,
+                security_label: None,
             },
         ]);
         assert_eq!(spec.must_be_run_locally(), true);
 
+        let spec = spec.mounts([JobMount::Overlay {
+            mount_point: Utf8PathBuf::from("/var"),
+            lower: Utf8PathBuf::from("/a"),
+            upper: Utf8PathBuf::from("/b"),
+            work: Utf8PathBuf::from("/c"),
+        }]);
+        assert_eq!(spec.must_be_run_locally(), true);
+
         let spec = spec.mounts([]);
         assert_eq!(spec.must_be_run_locally(), false);
     }
@@ -922,6 +2082,52 @@ mod tests {
         assert_eq!(spec.must_be_run_locally(), true);
     }
 
+    #[test]
+    fn job_root_overlay_validate() {
+        let local = |upper: &str, work: &str| JobRootOverlay::Local {
+            upper: upper.into(),
+            work: work.into(),
+        };
+        assert_eq!(JobRootOverlay::None.validate(), Ok(()));
+        assert_eq!(JobRootOverlay::Tmp.validate(), Ok(()));
+        assert_eq!(local("/a/upper", "/b/work").validate(), Ok(()));
+        assert_eq!(local("upper", "work").validate(), Ok(()));
+        assert_eq!(local("/tmp/upper", "/tmp/x/work").validate(), Ok(()));
+        assert_eq!(local("/dev/shm/upper", "/dev/shm/work").validate(), Ok(()));
+        assert_eq!(local("/tmpfoo/upper", "/home/work").validate(), Ok(()));
+
+        assert_eq!(
+            local("/a/upper", "work").validate(),
+            Err(JobRootOverlayError::MixedAbsoluteAndRelative {
+                upper: "/a/upper".into(),
+                work: "work".into(),
+            })
+        );
+        assert_eq!(
+            local("upper", "/a/work")
+                .validate()
+                .unwrap_err()
+                .to_string(),
+            "local root overlay upper upper and work /a/work must both be absolute or both be \
+            relative"
+        );
+        assert_eq!(
+            local("/tmp/upper", "/home/work").validate(),
+            Err(JobRootOverlayError::DifferentMountRoots {
+                upper: "/tmp/upper".into(),
+                work: "/home/work".into(),
+            })
+        );
+        assert_eq!(
+            local("/dev/shm/upper", "/dev/work")
+                .validate()
+                .unwrap_err()
+                .to_string(),
+            "local root overlay upper /dev/shm/upper and work /dev/work must be on the same file \
+            system"
+        );
+    }
+
     #[test]
     fn job_spec_must_be_run_locally_allocate_tty() {
         let spec = JobSpec::new(
@@ -937,64 +2143,1335 @@ mod tests {
         assert_eq!(spec.must_be_run_locally(), false);
     }
 
-    trait AssertError {
-        fn assert_error(&self, expected: &str);
+    #[test]
+    fn job_tty_abstract_socket_serde_round_trip() {
+        use serde_test::{assert_tokens, Token};
+
+        let job_tty = JobTty::new(b"\0abcde", WindowSize::new(20, 80));
+        assert_eq!(job_tty.socket, TtySocket::Abstract(*b"\0abcde"));
+        assert_tokens(
+            &job_tty,
+            &[
+                Token::Struct {
+                    name: "JobTty",
+                    len: 4,
+                },
+                Token::Str("socket"),
+                Token::NewtypeVariant {
+                    name: "TtySocket",
+                    variant: "Abstract",
+                },
+                Token::Tuple { len: 6 },
+                Token::U8(0),
+                Token::U8(b'a'),
+                Token::U8(b'b'),
+                Token::U8(b'c'),
+                Token::U8(b'd'),
+                Token::U8(b'e'),
+                Token::TupleEnd,
+                Token::Str("window_size"),
+                Token::Struct {
+                    name: "WindowSize",
+                    len: 2,
+                },
+                Token::Str("rows"),
+                Token::U16(20),
+                Token::Str("columns"),
+                Token::U16(80),
+                Token::StructEnd,
+                Token::Str("output_limit"),
+                Token::None,
+                Token::Str("framed_output"),
+                Token::Bool(false),
+                Token::StructEnd,
+            ],
+        );
+        assert_eq!(
+            proto::deserialize::<JobTty>(&proto::serialize(&job_tty).unwrap()).unwrap(),
+            job_tty
+        );
     }
 
-    impl AssertError for toml::de::Error {
-        fn assert_error(&self, expected: &str) {
-            let message = self.message();
-            assert!(message.starts_with(expected), "message: {message}");
-        }
-    }
+    #[test]
+    fn job_tty_path_socket_serde_round_trip() {
+        use serde_test::{assert_tokens, Token};
 
-    fn deserialize_value<T: for<'a> Deserialize<'a>>(file: &str) -> T {
-        T::deserialize(toml::de::ValueDeserializer::new(file)).unwrap()
+        let job_tty = JobTty::with_socket(
+            TtySocket::Path("/tmp/tty.sock".into()),
+            WindowSize::new(20, 80),
+        );
+        assert_tokens(
+            &job_tty,
+            &[
+                Token::Struct {
+                    name: "JobTty",
+                    len: 4,
+                },
+                Token::Str("socket"),
+                Token::NewtypeVariant {
+                    name: "TtySocket",
+                    variant: "Path",
+                },
+                Token::Str("/tmp/tty.sock"),
+                Token::Str("window_size"),
+                Token::Struct {
+                    name: "WindowSize",
+                    len: 2,
+                },
+                Token::Str("rows"),
+                Token::U16(20),
+                Token::Str("columns"),
+                Token::U16(80),
+                Token::StructEnd,
+                Token::Str("output_limit"),
+                Token::None,
+                Token::Str("framed_output"),
+                Token::Bool(false),
+                Token::StructEnd,
+            ],
+        );
+        assert_eq!(
+            proto::deserialize::<JobTty>(&proto::serialize(&job_tty).unwrap()).unwrap(),
+            job_tty
+        );
     }
 
-    fn deserialize_value_error<T: for<'a> Deserialize<'a> + Debug>(file: &str) -> toml::de::Error {
-        match T::deserialize(toml::de::ValueDeserializer::new(file)) {
-            Err(err) => err,
-            Ok(val) => panic!("expected a toml error but instead got value: {val:?}"),
-        }
+    #[test]
+    fn job_tty_output_limit_serde() {
+        let job_tty =
+            JobTty::new(b"\0abcde", WindowSize::new(20, 80)).output_limit(Some(TtyOutputLimit {
+                high_water_mark: 4096,
+                overflow: TtyOutputOverflow::Drop,
+            }));
+
+        let toml::Value::Table(mut table) = toml::Value::try_from(&job_tty).unwrap() else {
+            panic!("expected a table");
+        };
+        assert_eq!(
+            table.get("output_limit"),
+            Some(&toml::Value::Table(toml::map::Map::from_iter([
+                ("high_water_mark".into(), toml::Value::Integer(4096)),
+                ("overflow".into(), toml::Value::String("drop".into())),
+            ])))
+        );
+        assert_eq!(
+            toml::Value::Table(table.clone())
+                .try_into::<JobTty>()
+                .unwrap(),
+            job_tty
+        );
+        assert_eq!(
+            proto::deserialize::<JobTty>(&proto::serialize(&job_tty).unwrap()).unwrap(),
+            job_tty
+        );
+
+        // The overflow behavior defaults to backpressure.
+        let toml::Value::Table(output_limit) = table.get_mut("output_limit").unwrap() else {
+            panic!("expected a table");
+        };
+        output_limit.remove("overflow");
+        assert_eq!(
+            toml::Value::Table(table.clone())
+                .try_into::<JobTty>()
+                .unwrap()
+                .output_limit,
+            Some(TtyOutputLimit {
+                high_water_mark: 4096,
+                overflow: TtyOutputOverflow::Backpressure,
+            })
+        );
+
+        // TTYs from before the field existed don't have it at all.
+        table.remove("output_limit");
+        assert_eq!(
+            toml::Value::Table(table).try_into::<JobTty>().unwrap(),
+            job_tty.output_limit(None)
+        );
     }
 
     #[test]
-    fn enumset_job_device_for_toml_and_json_deserialized_as_list() {
-        let devices: EnumSet<JobDeviceForTomlAndJson> = deserialize_value(r#"["full", "null"]"#);
-        let devices: EnumSet<_> = devices.into_iter().map(Into::<JobDevice>::into).collect();
-        assert_eq!(devices, enum_set!(JobDevice::Full | JobDevice::Null));
+    fn job_tty_framed_output_serde() {
+        let job_tty = JobTty::new(b"\0abcde", WindowSize::new(20, 80)).framed_output(true);
+
+        let toml::Value::Table(mut table) = toml::Value::try_from(&job_tty).unwrap() else {
+            panic!("expected a table");
+        };
+        assert_eq!(
+            table.get("framed_output"),
+            Some(&toml::Value::Boolean(true))
+        );
+        assert_eq!(
+            proto::deserialize::<JobTty>(&proto::serialize(&job_tty).unwrap()).unwrap(),
+            job_tty
+        );
+
+        // TTYs from before the field existed don't have it at all.
+        table.remove("framed_output");
+        assert_eq!(
+            toml::Value::Table(table).try_into::<JobTty>().unwrap(),
+            job_tty.framed_output(false)
+        );
     }
 
     #[test]
-    fn enumset_job_device_for_toml_and_json_deserialize_unknown_field() {
-        deserialize_value_error::<EnumSet<JobDeviceForTomlAndJson>>(r#"["bull", "null"]"#)
-            .assert_error("unknown variant `bull`");
+    fn job_spec_oom_score_adj() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_eq!(spec.oom_score_adj, None);
+
+        let spec = spec.oom_score_adj(Some(500));
+        assert_eq!(spec.oom_score_adj, Some(500));
+
+        let spec = spec.oom_score_adj(None);
+        assert_eq!(spec.oom_score_adj, None);
     }
 
     #[test]
-    fn job_device_for_toml_and_json_and_job_device_match() {
-        for job_device in JobDevice::iter() {
-            let repr = format!(r#""{}""#, format!("{job_device:?}").to_kebab_case());
+    fn job_spec_validate_oom_score_adj() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_eq!(spec.validate(), Ok(()));
+
+        for value in [-1000, -1, 0, 1, 1000] {
+            let spec = spec.clone().oom_score_adj(Some(value));
+            assert_eq!(spec.validate(), Ok(()));
+        }
+
+        for value in [i16::MIN, -1001, 1001, i16::MAX] {
+            let spec = spec.clone().oom_score_adj(Some(value));
             assert_eq!(
-                JobDevice::from(deserialize_value::<JobDeviceForTomlAndJson>(&repr)),
-                job_device
+                spec.validate(),
+                Err(JobSpecValidationError::OomScoreAdjOutOfRange(value))
             );
         }
     }
 
     #[test]
-    fn bind_mount_no_read_only() {
-        let job_mount: JobMountForTomlAndJson =
-            deserialize_value(r#"{ type = "bind", mount_point = "/mnt", local_path = "/a" }"#);
-        let job_mount: JobMount = job_mount.into();
+    fn job_spec_cache_key() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .arguments(["bar"]);
+        assert_eq!(spec.cache_key(), None);
+
+        let spec = spec.cacheable(true);
+        assert_eq!(spec.cache_key(), Some(spec.content_hash()));
+        assert_eq!(spec.cache_key(), spec.clone().cache_key());
+        assert_ne!(
+            spec.cache_key(),
+            spec.clone().arguments(["baz"]).cache_key()
+        );
+
+        let tty_spec = spec
+            .clone()
+            .allocate_tty(Some(JobTty::new(b"\0abcde", WindowSize::new(20, 80))));
+        assert_eq!(tty_spec.cache_key(), None);
+
+        let local_network_spec = spec.clone().network(JobNetwork::Local);
+        assert_eq!(local_network_spec.cache_key(), None);
+
+        let non_cacheable_spec = spec.cacheable(false);
+        assert_eq!(non_cacheable_spec.cache_key(), None);
+    }
+
+    #[test]
+    fn job_spec_trace_id() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_eq!(spec.trace_id, None);
+
+        let spec = spec.trace_id(Some("abc123"));
+        assert_eq!(spec.trace_id.as_deref(), Some("abc123"));
+
+        let spec = spec.trace_id(None::<String>);
+        assert_eq!(spec.trace_id, None);
+    }
+
+    /// Check that `value` round-trips through TOML, JSON, and the wire protocol, with `field`
+    /// serialized as `expected`. Also check that, without `field`, like when `value` was
+    /// serialized before the field existed, it deserializes as `default`.
+    fn assert_optional_field_round_trips<T>(
+        value: &T,
+        field: &str,
+        expected: toml::Value,
+        default: &T,
+    ) where
+        T: Debug + PartialEq + Serialize + for<'de> Deserialize<'de>,
+    {
+        let toml::Value::Table(mut table) = toml::Value::try_from(value).unwrap() else {
+            panic!("expected a table");
+        };
+        assert_eq!(table.get(field), Some(&expected));
         assert_eq!(
-            job_mount,
-            JobMount::Bind {
-                mount_point: Utf8PathBuf::from("/mnt"),
-                local_path: Utf8PathBuf::from("/a"),
-                read_only: false,
+            &toml::Value::Table(table.clone()).try_into::<T>().unwrap(),
+            value
+        );
+        table.remove(field);
+        assert_eq!(&toml::Value::Table(table).try_into::<T>().unwrap(), default);
+
+        let mut json = serde_json::to_value(value).unwrap();
+        assert_eq!(json[field], serde_json::to_value(&expected).unwrap());
+        assert_eq!(&serde_json::from_value::<T>(json.clone()).unwrap(), value);
+        json.as_object_mut().unwrap().remove(field);
+        assert_eq!(&serde_json::from_value::<T>(json).unwrap(), default);
+
+        assert_eq!(
+            &proto::deserialize::<T>(&proto::serialize(value).unwrap()).unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn job_spec_trace_id_serde() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_optional_field_round_trips(
+            &spec.clone().trace_id(Some("abc123")),
+            "trace_id",
+            toml::Value::String("abc123".into()),
+            &spec,
+        );
+    }
+
+    #[test]
+    fn job_spec_display_name_or_program() {
+        let spec = JobSpec::new(
+            "/bin/foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_eq!(spec.display_name, None);
+        assert_eq!(spec.display_name_or_program(), "/bin/foo");
+
+        let spec = spec.display_name(Some("foo tests::bar"));
+        assert_eq!(spec.display_name.as_deref(), Some("foo tests::bar"));
+        assert_eq!(spec.display_name_or_program(), "foo tests::bar");
+
+        let spec = spec.display_name(None::<String>);
+        assert_eq!(spec.display_name_or_program(), "/bin/foo");
+    }
+
+    #[test]
+    fn job_spec_display_name_serde() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_optional_field_round_trips(
+            &spec.clone().display_name(Some("foo tests::bar")),
+            "display_name",
+            toml::Value::String("foo tests::bar".into()),
+            &spec,
+        );
+
+        let spec = spec.display_name(Some("foo tests::bar"));
+        assert_eq!(
+            JobSpec::from_canonical_json(&spec.to_canonical_json()).unwrap(),
+            spec
+        );
+    }
+
+    #[test]
+    fn job_spec_readonly_root_serde() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_optional_field_round_trips(
+            &spec.clone().readonly_root(true),
+            "readonly_root",
+            toml::Value::Boolean(true),
+            &spec,
+        );
+
+        let spec = spec.readonly_root(true);
+        assert_eq!(
+            JobSpec::from_canonical_json(&spec.to_canonical_json()).unwrap(),
+            spec
+        );
+    }
+
+    #[test]
+    fn job_spec_depends_on_serde() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_optional_field_round_trips(
+            &spec
+                .clone()
+                .depends_on([ClientJobId::from(1), ClientJobId::from(3)]),
+            "depends_on",
+            toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::Integer(3)]),
+            &spec,
+        );
+    }
+
+    #[test]
+    fn job_spec_priority_serde() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_optional_field_round_trips(
+            &spec.clone().priority(-3),
+            "priority",
+            toml::Value::Integer(-3),
+            &spec,
+        );
+    }
+
+    #[test]
+    fn job_effects_worker_serde() {
+        let effects = JobEffects {
+            stdout: JobOutputResult::None,
+            stderr: JobOutputResult::None,
+            duration: Duration::from_secs(1),
+            worker: None,
+        };
+        assert_optional_field_round_trips(
+            &JobEffects {
+                worker: Some(WorkerId::from(3)),
+                ..effects.clone()
+            },
+            "worker",
+            toml::Value::Integer(3),
+            &effects,
+        );
+    }
+
+    #[test]
+    fn job_spec_fits() {
+        let capacity = WorkerCapacity {
+            free_memory: 1 << 30,
+            free_slots: 1,
+            cpus: 4,
+        };
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+
+        // A job that declares nothing fits anywhere there's a free slot.
+        assert!(spec.fits(&WorkerCapacity {
+            free_slots: 1,
+            ..Default::default()
+        }));
+        assert!(!spec.fits(&WorkerCapacity {
+            free_slots: 0,
+            ..capacity
+        }));
+
+        let spec = spec.memory_limit(Some(1 << 30)).cpu_set(Some([0, 3]));
+        assert!(spec.fits(&capacity));
+
+        assert!(!spec
+            .clone()
+            .memory_limit(Some((1 << 30) + 1))
+            .fits(&capacity));
+        assert!(!spec.clone().cpu_set(Some([4])).fits(&capacity));
+    }
+
+    #[test]
+    fn job_spec_add_mount_accumulates() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .mounts([JobMount::Proc {
+            mount_point: "/proc".into(),
+        }])
+        .add_mount(JobMount::Tmp {
+            mount_point: "/tmp".into(),
+        })
+        .add_mount(JobMount::Sys {
+            mount_point: "/sys".into(),
+        });
+        assert_eq!(
+            spec.mounts,
+            vec![
+                JobMount::Proc {
+                    mount_point: "/proc".into(),
+                },
+                JobMount::Tmp {
+                    mount_point: "/tmp".into(),
+                },
+                JobMount::Sys {
+                    mount_point: "/sys".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn job_spec_requested_devices() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_eq!(spec.requested_devices(), EnumSet::empty());
+
+        let spec = spec
+            .add_mount(JobMount::Devices {
+                devices: JobDevice::Null | JobDevice::Zero,
+            })
+            .add_mount(JobMount::Proc {
+                mount_point: "/proc".into(),
+            })
+            .add_mount(JobMount::Devices {
+                devices: JobDevice::Zero | JobDevice::Fuse,
+            });
+        assert_eq!(
+            spec.requested_devices(),
+            JobDevice::Null | JobDevice::Zero | JobDevice::Fuse
+        );
+    }
+
+    #[test]
+    fn job_spec_check_mount_network_coherence() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .add_mount(JobMount::Proc {
+            mount_point: "/proc".into(),
+        })
+        .add_mount(JobMount::Sys {
+            mount_point: "/sys".into(),
+        });
+        assert_eq!(spec.check_mount_network_coherence(), Ok(()));
+        assert_eq!(
+            spec.clone()
+                .network(JobNetwork::Loopback)
+                .check_mount_network_coherence(),
+            Ok(())
+        );
+        assert_eq!(
+            spec.network(JobNetwork::Local)
+                .check_mount_network_coherence(),
+            Err(CoherenceError::SysMountWithLocalNetwork(1))
+        );
+    }
+
+    #[test]
+    fn job_spec_missing_layers() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![
+                (Sha256Digest::from(3u32), ArtifactType::Tar),
+                (Sha256Digest::from(1u32), ArtifactType::Manifest),
+                (Sha256Digest::from(4u32), ArtifactType::Tar),
+                (Sha256Digest::from(2u32), ArtifactType::Tar),
+                (Sha256Digest::from(3u32), ArtifactType::Manifest),
+                (Sha256Digest::from(4u32), ArtifactType::Tar)
+            ],
+        );
+        let present = HashSet::from([Sha256Digest::from(1u32), Sha256Digest::from(2u32)]);
+        assert_eq!(
+            spec.missing_layers(|digest| present.contains(digest)),
+            vec![Sha256Digest::from(3u32), Sha256Digest::from(4u32)]
+        );
+        assert_eq!(
+            spec.missing_layers(|_| false),
+            vec![
+                Sha256Digest::from(3u32),
+                Sha256Digest::from(1u32),
+                Sha256Digest::from(4u32),
+                Sha256Digest::from(2u32),
+            ]
+        );
+        assert_eq!(spec.missing_layers(|_| true), vec![]);
+    }
+
+    #[test]
+    fn job_spec_push_layer_appends() {
+        let mut spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        spec.push_layer((Sha256Digest::from(1u32), ArtifactType::Manifest));
+        spec.push_layer((Sha256Digest::from(2u32), ArtifactType::Tar));
+        assert_eq!(
+            spec.layers,
+            nonempty![
+                (Sha256Digest::from(0u32), ArtifactType::Tar),
+                (Sha256Digest::from(1u32), ArtifactType::Manifest),
+                (Sha256Digest::from(2u32), ArtifactType::Tar)
+            ]
+        );
+    }
+
+    #[test]
+    fn job_spec_has_dependencies() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert!(!spec.has_dependencies());
+        assert!(spec
+            .clone()
+            .depends_on([ClientJobId::from(1)])
+            .has_dependencies());
+        assert!(!spec.depends_on([]).has_dependencies());
+    }
+
+    #[test]
+    fn job_spec_readonly_root_and_root_overlay() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        let overlays = [
+            JobRootOverlay::Tmp,
+            JobRootOverlay::Local {
+                upper: "upper".into(),
+                work: "work".into(),
+            },
+        ];
+
+        for overlay in overlays.clone() {
+            let spec = spec.clone().root_overlay(overlay);
+            assert!(spec.root_is_writable());
+            assert_eq!(spec.validate(), Ok(()));
+        }
+        for overlay in overlays {
+            assert_eq!(
+                spec.clone()
+                    .root_overlay(overlay)
+                    .readonly_root(true)
+                    .validate(),
+                Err(JobSpecValidationError::ReadonlyRootWithOverlay)
+            );
+        }
+
+        let spec = spec.root_overlay(JobRootOverlay::None);
+        assert!(!spec.root_is_writable());
+        assert_eq!(spec.clone().readonly_root(true).validate(), Ok(()));
+        assert_ne!(
+            spec.content_hash(),
+            spec.clone().readonly_root(true).content_hash()
+        );
+    }
+
+    #[test]
+    fn job_spec_trace_id_does_not_affect_cache_key() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .cacheable(true);
+        let traced_spec = spec.clone().trace_id(Some("abc123"));
+        assert_eq!(traced_spec.content_hash(), spec.content_hash());
+        assert_eq!(traced_spec.cache_key(), spec.cache_key());
+        assert_eq!(
+            traced_spec.cache_key(),
+            traced_spec.clone().trace_id(Some("def456")).cache_key()
+        );
+    }
+
+    #[test]
+    fn job_spec_display_name_does_not_affect_cache_key() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .cacheable(true);
+        let named_spec = spec.clone().display_name(Some("foo tests::bar"));
+        assert_eq!(named_spec.content_hash(), spec.content_hash());
+        assert_eq!(named_spec.cache_key(), spec.cache_key());
+    }
+
+    #[test]
+    fn job_spec_scheduling_fields_do_not_affect_cache_key() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .cacheable(true);
+        for scheduled_spec in [
+            spec.clone()
+                .estimated_duration(Some(Duration::from_secs(10))),
+            spec.clone().depends_on([ClientJobId::from(1)]),
+            spec.clone().priority(5),
+            spec.clone().memory_limit(Some(1 << 30)),
+        ] {
+            assert_eq!(scheduled_spec.content_hash(), spec.content_hash());
+            assert_eq!(scheduled_spec.cache_key(), spec.cache_key());
+        }
+        assert_ne!(
+            spec.clone().timeout(Timeout::new(10)).cache_key(),
+            spec.cache_key()
+        );
+    }
+
+    #[test]
+    fn job_spec_canonical_json_round_trip() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(1u32), ArtifactType::Tar)],
+        )
+        .arguments(["bar", "baz"])
+        .environment(["FOO=1"])
+        .mounts([JobMount::Tmp {
+            mount_point: "/tmp".into(),
+        }])
+        .timeout(Timeout::new(10))
+        .oom_score_adj(Some(500))
+        .cpu_set(Some([0, 1]))
+        .trace_id(Some("trace"));
+
+        let json = spec.to_canonical_json();
+        assert_eq!(json, spec.clone().to_canonical_json());
+        assert_eq!(JobSpec::from_canonical_json(&json).unwrap(), spec);
+    }
+
+    #[test]
+    fn job_spec_from_canonical_json_malformed() {
+        let err = JobSpec::from_canonical_json("{\"program\": 1}").unwrap_err();
+        assert!(matches!(err, JobSpecFromJsonError::Json(_)), "{err:?}");
+        assert!(err.to_string().starts_with("malformed job spec: "), "{err}");
+    }
+
+    #[test]
+    fn job_spec_from_canonical_json_invalid() {
+        let json = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(1u32), ArtifactType::Tar)],
+        )
+        .oom_score_adj(Some(1001))
+        .to_canonical_json();
+        let err = JobSpec::from_canonical_json(&json).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                JobSpecFromJsonError::Invalid(JobSpecValidationError::OomScoreAdjOutOfRange(1001))
+            ),
+            "{err:?}"
+        );
+        assert!(err.to_string().starts_with("invalid job spec: "), "{err}");
+    }
+
+    #[test]
+    fn job_spec_mount_points() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .mounts([
+            JobMount::Proc {
+                mount_point: "/proc".into(),
+            },
+            JobMount::Devices {
+                devices: JobDevice::Null | JobDevice::Zero,
+            },
+            JobMount::Bind {
+                mount_point: "/mnt".into(),
+                local_path: "/home".into(),
+                read_only: true,
+                security_label: None,
+            },
+            JobMount::Devpts {
+                mount_point: "/dev/pts".into(),
+            },
+            JobMount::Mqueue {
+                mount_point: "/dev/mqueue".into(),
+            },
+            JobMount::Sys {
+                mount_point: "/sys".into(),
+            },
+            JobMount::Tmp {
+                mount_point: "/tmp".into(),
+            },
+        ]);
+        assert_eq!(
+            spec.mount_points(),
+            ["/proc", "/mnt", "/dev/pts", "/dev/mqueue", "/sys", "/tmp"].map(Utf8Path::new)
+        );
+
+        let spec = spec.mounts([JobMount::Devices {
+            devices: EnumSet::all(),
+        }]);
+        assert!(spec.mount_points().is_empty());
+    }
+
+    #[test]
+    fn job_spec_cpu_set() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_eq!(spec.cpu_set, None);
+
+        let spec = spec.cpu_set(Some([0, 2, 3]));
+        assert_eq!(spec.cpu_set, Some(vec![0, 2, 3]));
+
+        let spec = spec.cpu_set(None::<Vec<u16>>);
+        assert_eq!(spec.cpu_set, None);
+    }
+
+    #[test]
+    fn job_spec_cpu_set_serde() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+        assert_optional_field_round_trips(
+            &spec.clone().cpu_set(Some([1, 3])),
+            "cpu_set",
+            toml::Value::Array(vec![toml::Value::Integer(1), toml::Value::Integer(3)]),
+            &spec,
+        );
+    }
+
+    #[test]
+    fn job_spec_validate_cpu_set() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        );
+
+        for cpu_set in [vec![0], vec![0, 1, 2], vec![MAX_CPUS - 1]] {
+            let spec = spec.clone().cpu_set(Some(cpu_set));
+            assert_eq!(spec.validate(), Ok(()));
+        }
+
+        let spec = spec.cpu_set(Some([]));
+        assert_eq!(spec.validate(), Err(JobSpecValidationError::EmptyCpuSet));
+
+        for index in [MAX_CPUS, MAX_CPUS + 1, u16::MAX] {
+            let spec = spec.clone().cpu_set(Some([0, index]));
+            assert_eq!(
+                spec.validate(),
+                Err(JobSpecValidationError::CpuIndexOutOfRange(index))
+            );
+        }
+        assert_eq!(
+            JobSpecValidationError::CpuIndexOutOfRange(1024).to_string(),
+            "cpu_set index 1024 is out of range, it must be less than 1024"
+        );
+    }
+
+    #[test]
+    fn safe_join_stays_within_root() {
+        let root = Utf8Path::new("/jail");
+        for (path, expected) in [
+            ("a", "/jail/a"),
+            ("/a/b", "/jail/a/b"),
+            ("./a/./b", "/jail/a/b"),
+            ("a/../b", "/jail/b"),
+            ("/", "/jail"),
+            ("a/..", "/jail"),
+        ] {
+            assert_eq!(safe_join(root, path.into()).unwrap(), expected);
+        }
+        for path in ["..", "/..", "a/../..", "a/b/../../../c"] {
+            assert_eq!(
+                safe_join(root, path.into()),
+                Err(PathTraversalError {
+                    root: root.into(),
+                    path: path.into(),
+                })
+            );
+        }
+        assert_eq!(
+            safe_join(root, "..".into()).unwrap_err().to_string(),
+            "path .. escapes root /jail"
+        );
+    }
+
+    #[test]
+    fn job_spec_rebase_paths() {
+        let mut spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .mounts([
+            JobMount::Proc {
+                mount_point: "/proc".into(),
+            },
+            JobMount::Devices {
+                devices: EnumSet::all(),
+            },
+            JobMount::Bind {
+                mount_point: "/mnt/../data".into(),
+                local_path: "/home".into(),
+                read_only: true,
+                security_label: None,
+            },
+            JobMount::Overlay {
+                mount_point: "/overlay".into(),
+                lower: "/lower".into(),
+                upper: "upper".into(),
+                work: "/tmp/overlay-work".into(),
+            },
+        ])
+        .working_directory(Some("/work"))
+        .root_overlay(JobRootOverlay::Local {
+            upper: "upper".into(),
+            work: "/tmp/work".into(),
+        });
+        spec.rebase_paths("/jail".into()).unwrap();
+        assert_eq!(
+            spec.mount_points(),
+            ["/jail/proc", "/jail/data", "/jail/overlay"].map(Utf8Path::new)
+        );
+        assert_eq!(
+            spec.mounts[2],
+            JobMount::Bind {
+                mount_point: "/jail/data".into(),
+                local_path: "/jail/home".into(),
+                read_only: true,
+                security_label: None,
+            }
+        );
+        assert_eq!(
+            spec.mounts[3],
+            JobMount::Overlay {
+                mount_point: "/jail/overlay".into(),
+                lower: "/jail/lower".into(),
+                upper: "/jail/upper".into(),
+                work: "/jail/tmp/overlay-work".into(),
+            }
+        );
+        assert_eq!(spec.working_directory, Some("/jail/work".into()));
+        assert_eq!(
+            spec.root_overlay,
+            JobRootOverlay::Local {
+                upper: "/jail/upper".into(),
+                work: "/jail/tmp/work".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn job_spec_rebase_paths_traversal() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .mounts([JobMount::Tmp {
+            mount_point: "/tmp".into(),
+        }]);
+
+        for mut bad_spec in [
+            spec.clone().mounts([
+                JobMount::Tmp {
+                    mount_point: "/tmp".into(),
+                },
+                JobMount::Tmp {
+                    mount_point: "/../escape".into(),
+                },
+            ]),
+            spec.clone().mounts([JobMount::Bind {
+                mount_point: "/mnt".into(),
+                local_path: "data/../../escape".into(),
+                read_only: false,
+                security_label: None,
+            }]),
+            spec.clone().mounts([JobMount::Overlay {
+                mount_point: "/mnt".into(),
+                lower: "/lower".into(),
+                upper: "/upper/../../escape".into(),
+                work: "/work".into(),
+            }]),
+            spec.clone().working_directory(Some("../escape")),
+            spec.clone().root_overlay(JobRootOverlay::Local {
+                upper: "upper".into(),
+                work: "../escape".into(),
+            }),
+        ] {
+            let before = bad_spec.clone();
+            let err = bad_spec.rebase_paths("/jail".into()).unwrap_err();
+            assert!(err.path.as_str().ends_with("../escape"), "{err}");
+            assert_eq!(bad_spec, before);
+        }
+    }
+
+    #[test]
+    fn job_spec_conflicting_mounts() {
+        let spec = JobSpec::new(
+            "foo",
+            nonempty![(Sha256Digest::from(0u32), ArtifactType::Tar)],
+        )
+        .mounts([
+            JobMount::Proc {
+                mount_point: "/proc".into(),
+            },
+            JobMount::Devices {
+                devices: EnumSet::all(),
+            },
+            JobMount::Tmp {
+                mount_point: "/tmp".into(),
+            },
+        ]);
+        assert_eq!(spec.conflicting_mounts(), vec![]);
+        assert_eq!(spec.validate(), Ok(()));
+
+        let spec = spec.mounts([
+            JobMount::Tmp {
+                mount_point: "/tmp".into(),
+            },
+            JobMount::Devices {
+                devices: EnumSet::all(),
+            },
+            JobMount::Proc {
+                mount_point: "/proc".into(),
+            },
+            JobMount::Bind {
+                mount_point: "/tmp".into(),
+                local_path: "/home".into(),
+                read_only: true,
+                security_label: None,
+            },
+            JobMount::Devices {
+                devices: EnumSet::all(),
+            },
+            JobMount::Tmp {
+                mount_point: "/tmp".into(),
+            },
+        ]);
+        assert_eq!(spec.conflicting_mounts(), vec![(0, 3), (0, 5), (3, 5)]);
+        assert_eq!(
+            spec.validate(),
+            Err(JobSpecValidationError::ConflictingMounts(0, 3))
+        );
+        assert_eq!(
+            JobSpecValidationError::ConflictingMounts(0, 3).to_string(),
+            "mounts 0 and 3 have the same mount point"
+        );
+    }
+
+    #[test]
+    fn distinct_layers_across_specs() {
+        let specs = [
+            JobSpec::new(
+                "foo",
+                nonempty![
+                    (Sha256Digest::from(1u32), ArtifactType::Tar),
+                    (Sha256Digest::from(2u32), ArtifactType::Manifest)
+                ],
+            ),
+            JobSpec::new(
+                "bar",
+                nonempty![
+                    (Sha256Digest::from(3u32), ArtifactType::Tar),
+                    (Sha256Digest::from(1u32), ArtifactType::Tar)
+                ],
+            ),
+            JobSpec::new(
+                "baz",
+                nonempty![
+                    (Sha256Digest::from(2u32), ArtifactType::Manifest),
+                    (Sha256Digest::from(4u32), ArtifactType::Tar),
+                    (Sha256Digest::from(3u32), ArtifactType::Tar)
+                ],
+            ),
+        ];
+        assert_eq!(
+            distinct_layers(&specs),
+            vec![
+                (Sha256Digest::from(1u32), ArtifactType::Tar),
+                (Sha256Digest::from(2u32), ArtifactType::Manifest),
+                (Sha256Digest::from(3u32), ArtifactType::Tar),
+                (Sha256Digest::from(4u32), ArtifactType::Tar),
+            ]
+        );
+    }
+
+    #[test]
+    fn distinct_layers_empty() {
+        assert_eq!(distinct_layers(&[]), vec![]);
+    }
+
+    fn tar_layers(digests: impl IntoIterator<Item = u32>) -> Vec<(Sha256Digest, ArtifactType)> {
+        digests
+            .into_iter()
+            .map(|n| (Sha256Digest::from(n), ArtifactType::Tar))
+            .collect()
+    }
+
+    fn size_from_digest(digest: &Sha256Digest) -> u64 {
+        u64::from_be_bytes(digest.as_bytes()[24..].try_into().unwrap())
+    }
+
+    #[test]
+    fn batch_layers_even_splits() {
+        assert_eq!(
+            batch_layers(&tar_layers([5, 5, 5, 5, 5, 5]), size_from_digest, 10),
+            vec![tar_layers([5, 5]), tar_layers([5, 5]), tar_layers([5, 5])]
+        );
+        assert_eq!(
+            batch_layers(&tar_layers([3, 4, 6, 2, 9]), size_from_digest, 10),
+            vec![tar_layers([3, 4]), tar_layers([6, 2]), tar_layers([9])]
+        );
+    }
+
+    #[test]
+    fn batch_layers_single_oversize_layer() {
+        assert_eq!(
+            batch_layers(&tar_layers([2, 20, 3]), size_from_digest, 10),
+            vec![tar_layers([2]), tar_layers([20]), tar_layers([3])]
+        );
+        assert_eq!(
+            batch_layers(&tar_layers([20]), size_from_digest, 10),
+            vec![tar_layers([20])]
+        );
+    }
+
+    #[test]
+    fn batch_layers_all_fit_in_one_batch() {
+        assert_eq!(
+            batch_layers(&tar_layers([1, 2, 3, 4]), size_from_digest, 10),
+            vec![tar_layers([1, 2, 3, 4])]
+        );
+    }
+
+    #[test]
+    fn batch_layers_empty() {
+        assert_eq!(
+            batch_layers(&[], size_from_digest, 10),
+            Vec::<Vec<_>>::new()
+        );
+    }
+
+    trait AssertError {
+        fn assert_error(&self, expected: &str);
+    }
+
+    impl AssertError for toml::de::Error {
+        fn assert_error(&self, expected: &str) {
+            let message = self.message();
+            assert!(message.starts_with(expected), "message: {message}");
+        }
+    }
+
+    fn deserialize_value<T: for<'a> Deserialize<'a>>(file: &str) -> T {
+        T::deserialize(toml::de::ValueDeserializer::new(file)).unwrap()
+    }
+
+    fn deserialize_value_error<T: for<'a> Deserialize<'a> + Debug>(file: &str) -> toml::de::Error {
+        match T::deserialize(toml::de::ValueDeserializer::new(file)) {
+            Err(err) => err,
+            Ok(val) => panic!("expected a toml error but instead got value: {val:?}"),
+        }
+    }
+
+    #[test]
+    fn enumset_job_device_for_toml_and_json_deserialized_as_list() {
+        let devices: EnumSet<JobDeviceForTomlAndJson> = deserialize_value(r#"["full", "null"]"#);
+        let devices: EnumSet<_> = devices.into_iter().map(Into::<JobDevice>::into).collect();
+        assert_eq!(devices, enum_set!(JobDevice::Full | JobDevice::Null));
+    }
+
+    #[test]
+    fn enumset_job_device_for_toml_and_json_deserialize_unknown_field() {
+        deserialize_value_error::<EnumSet<JobDeviceForTomlAndJson>>(r#"["bull", "null"]"#)
+            .assert_error("unknown variant `bull`");
+    }
+
+    #[test]
+    fn job_device_for_toml_and_json_and_job_device_match() {
+        for job_device in JobDevice::iter() {
+            let repr = format!(r#""{}""#, format!("{job_device:?}").to_kebab_case());
+            assert_eq!(
+                JobDevice::from(deserialize_value::<JobDeviceForTomlAndJson>(&repr)),
+                job_device
+            );
+        }
+    }
+
+    #[test]
+    fn bind_mount_no_read_only() {
+        let job_mount: JobMountForTomlAndJson =
+            deserialize_value(r#"{ type = "bind", mount_point = "/mnt", local_path = "/a" }"#);
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Bind {
+                mount_point: Utf8PathBuf::from("/mnt"),
+                local_path: Utf8PathBuf::from("/a"),
+                read_only: false,
+                security_label: None,
+            }
+        );
+    }
+
+    #[test]
+    fn overlay_mount() {
+        let job_mount: JobMountForTomlAndJson = deserialize_value(
+            r#"{ type = "overlay", mount_point = "/var", lower = "/a", upper = "/b", work = "/c" }"#,
+        );
+        let job_mount: JobMount = job_mount.into();
+        assert_eq!(
+            job_mount,
+            JobMount::Overlay {
+                mount_point: Utf8PathBuf::from("/var"),
+                lower: Utf8PathBuf::from("/a"),
+                upper: Utf8PathBuf::from("/b"),
+                work: Utf8PathBuf::from("/c"),
+            }
+        );
+    }
+
+    #[test]
+    fn overlay_mount_missing_field() {
+        deserialize_value_error::<JobMountForTomlAndJson>(
+            r#"{ type = "overlay", mount_point = "/var", lower = "/a", upper = "/b" }"#,
+        )
+        .assert_error("missing field `work`");
+    }
+
+    #[test]
+    fn bind_mount_with_security_label() {
+        let job_mount: JobMountForTomlAndJson = deserialize_value(
+            r#"{ type = "bind", mount_point = "/mnt", local_path = "/a", security_label = "label" }"#,
+        );
+        assert_eq!(
+            JobMount::from(job_mount.clone()),
+            JobMount::Bind {
+                mount_point: Utf8PathBuf::from("/mnt"),
+                local_path: Utf8PathBuf::from("/a"),
+                read_only: false,
+                security_label: Some("label".into()),
+            }
+        );
+        let json = serde_json::to_string(&job_mount).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"bind","mount_point":"/mnt","local_path":"/a","read_only":false,"security_label":"label"}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<JobMountForTomlAndJson>(&json).unwrap(),
+            job_mount
+        );
+    }
+
+    #[test]
+    fn bind_mount_without_security_label_round_trips() {
+        let job_mount: JobMountForTomlAndJson =
+            deserialize_value(r#"{ type = "bind", mount_point = "/mnt", local_path = "/a" }"#);
+        let json = serde_json::to_string(&job_mount).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"bind","mount_point":"/mnt","local_path":"/a","read_only":false}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<JobMountForTomlAndJson>(&json).unwrap(),
+            job_mount
+        );
+
+        for security_label in [None, Some("label".into())] {
+            let job_mount = JobMount::Bind {
+                mount_point: Utf8PathBuf::from("/mnt"),
+                local_path: Utf8PathBuf::from("/a"),
+                read_only: true,
+                security_label,
+            };
+            let json = serde_json::to_string(&job_mount).unwrap();
+            assert_eq!(serde_json::from_str::<JobMount>(&json).unwrap(), job_mount);
+        }
+    }
+
+    #[test]
+    fn job_output_result_external() {
+        let digest = "101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f"
+            .parse::<Sha256Digest>()
+            .unwrap();
+        let result = JobOutputResult::External {
+            first: Box::new(*b"abc"),
+            digest: digest.clone(),
+            size: 12345,
+        };
+        assert_eq!(
+            result.to_string(),
+            format!("abc<output stored externally: 12345 bytes, {digest}>")
+        );
+        assert_eq!(
+            format!("{result:?}"),
+            format!("External {{ first: \"abc\", digest: {digest:?}, size: 12345 }}")
+        );
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(
+            serde_json::from_str::<JobOutputResult>(&json).unwrap(),
+            result
+        );
+    }
+
+    #[test]
+    fn job_output_result_inline_compressed() {
+        let output = "hello world\n".repeat(100);
+        let result =
+            JobOutputResult::inline_compressed(CompressionAlgorithm::Deflate, output.as_bytes());
+        let JobOutputResult::InlineCompressed { data, .. } = &result else {
+            panic!("expected compressed output, got {result:?}");
+        };
+        assert!(data.len() < output.len());
+        assert_eq!(result.len(), 1200);
+        assert_eq!(result.inline_bytes().unwrap(), output.as_bytes());
+        assert_eq!(result.to_string(), output);
+        assert_eq!(
+            format!("{result:?}"),
+            format!(
+                "InlineCompressed {{ algorithm: Deflate, data: {output:?}, uncompressed_len: 1200 }}"
+            )
+        );
+
+        let json = serde_json::to_string(&result).unwrap();
+        assert_eq!(
+            serde_json::from_str::<JobOutputResult>(&json).unwrap(),
+            result
+        );
+        assert_eq!(
+            proto::deserialize::<JobOutputResult>(&proto::serialize(&result).unwrap()).unwrap(),
+            result
+        );
+    }
+
+    #[test]
+    fn job_output_result_inline_compressed_corrupt() {
+        let result = JobOutputResult::InlineCompressed {
+            algorithm: CompressionAlgorithm::Deflate,
+            data: Box::new([1, 2, 3]),
+            uncompressed_len: 5,
+        };
+        assert_eq!(result.len(), 5);
+        assert_eq!(result.inline_bytes(), None);
+        assert_eq!(result.to_string(), "<corrupt compressed output: 3 bytes>");
+        assert_eq!(
+            format!("{result:?}"),
+            "InlineCompressed { algorithm: Deflate, corrupt_data_len: 3, uncompressed_len: 5 }"
+        );
+    }
+
+    #[test]
+    fn job_output_result_len() {
+        assert_eq!(JobOutputResult::None.len(), 0);
+        assert!(JobOutputResult::None.is_empty());
+        assert_eq!(JobOutputResult::Inline(Box::new(*b"abc")).len(), 3);
+        assert_eq!(
+            JobOutputResult::Truncated {
+                first: Box::new(*b"abc"),
+                truncated: 10
+            }
+            .len(),
+            13
+        );
+        assert_eq!(
+            JobOutputResult::External {
+                first: Box::new(*b"abc"),
+                digest: Sha256Digest::from(1u64),
+                size: 42,
             }
+            .len(),
+            42
         );
     }
 }
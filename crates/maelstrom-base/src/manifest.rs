@@ -1,7 +1,11 @@
-use crate::{Sha256Digest, Utf8PathBuf};
+use crate::{proto, Sha256Digest, Utf8PathBuf};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
-use std::fmt;
+use sha2::{Digest as _, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+};
 
 struct OctalFmt<T>(T);
 
@@ -69,6 +73,24 @@ pub enum ManifestFileData {
     Digest(Sha256Digest),
     Inline(Vec<u8>),
     Empty,
+    /// The file's contents are the concatenation of the given chunks, in order. Each chunk is
+    /// stored as its own artifact. See [`Chunker`].
+    ChunkedFile {
+        chunks: Vec<Sha256Digest>,
+        size: u64,
+    },
+}
+
+impl ManifestFileData {
+    /// Return the digests of all of the artifacts needed to reconstruct the file.
+    pub fn digests(&self) -> impl Iterator<Item = &Sha256Digest> {
+        let (single, chunks) = match self {
+            Self::Digest(digest) => (Some(digest), &[][..]),
+            Self::ChunkedFile { chunks, .. } => (None, &chunks[..]),
+            Self::Inline(_) | Self::Empty => (None, &[][..]),
+        };
+        single.into_iter().chain(chunks)
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -85,12 +107,532 @@ pub struct ManifestEntry {
     pub path: Utf8PathBuf,
     pub metadata: ManifestEntryMetadata,
     pub data: ManifestEntryData,
+    /// Extended attributes, by name. Nothing is written for an empty map, so entries without
+    /// extended attributes encode exactly as they did before this field existed.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl ManifestEntry {
+    /// Decode an entry serialized with [`proto::serialize`]. Since the `xattrs` field is omitted
+    /// when it is empty, and the encoding isn't self-describing, it is only read if there are
+    /// bytes left after the other fields.
+    pub fn decode(mut bytes: &[u8]) -> bincode::Result<Self> {
+        let (path, metadata, data) = proto::deserialize_from(&mut bytes)?;
+        let xattrs = if bytes.is_empty() {
+            BTreeMap::new()
+        } else {
+            proto::deserialize(bytes)?
+        };
+        Ok(Self {
+            path,
+            metadata,
+            data,
+            xattrs,
+        })
+    }
+}
+
+/// The entries of a manifest, held in memory.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn new(entries: Vec<ManifestEntry>) -> Self {
+        Self { entries }
+    }
+
+    /// Return the digests of all of the artifacts referenced by the manifest's file entries.
+    pub fn referenced_digests(&self) -> HashSet<Sha256Digest> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match &entry.data {
+                ManifestEntryData::File(data) => Some(data.digests()),
+                _ => None,
+            })
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Return the referenced digests for which `exists` returns false.
+    pub fn dangling_digests(
+        &self,
+        mut exists: impl FnMut(&Sha256Digest) -> bool,
+    ) -> HashSet<Sha256Digest> {
+        let mut digests = self.referenced_digests();
+        digests.retain(|digest| !exists(digest));
+        digests
+    }
+
+    /// The digest of the manifest when it is stored as an
+    /// [`ArtifactType::Manifest`](crate::ArtifactType::Manifest) artifact. The hash is computed over
+    /// the same bytes a manifest writer produces for these entries, with the default
+    /// [`ManifestVersion`] and [`DigestAlgorithm`] headers, so it matches the digest the worker
+    /// computes for the artifact.
+    pub fn content_digest(&self) -> Sha256Digest {
+        let mut hasher = Sha256::new();
+        hash_encoded(&mut hasher, &ManifestVersion::default());
+        hash_encoded(&mut hasher, &DigestAlgorithm::default());
+        for entry in &self.entries {
+            hash_encoded(&mut hasher, entry);
+        }
+        Sha256Digest::new(hasher.finalize().into())
+    }
+}
+
+/// Feed `t` to `hasher` the way it is encoded in a manifest: a big-endian `u64` length followed by
+/// the serialized value.
+fn hash_encoded(hasher: &mut Sha256, t: &impl Serialize) {
+    let bytes = proto::serialize(t).unwrap();
+    hasher.update((bytes.len() as u64).to_be_bytes());
+    hasher.update(bytes);
+}
+
+impl FromIterator<ManifestEntry> for Manifest {
+    fn from_iter<T: IntoIterator<Item = ManifestEntry>>(iter: T) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+/// The differences between two manifests, keyed by path. Created by [`diff`].
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ManifestDiff {
+    /// Entries of the new manifest whose paths aren't in the old one, in the new manifest's order.
+    pub added: Vec<ManifestEntry>,
+    /// Paths of the old manifest that aren't in the new one, in the old manifest's order.
+    pub removed: Vec<Utf8PathBuf>,
+    /// Entries of the new manifest whose metadata or data differ from the old manifest's entry
+    /// for the same path, in the new manifest's order.
+    pub modified: Vec<ManifestEntry>,
+}
+
+impl ManifestDiff {
+    /// Return true if the two manifests had the same entries.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+
+    /// Reconstruct the new manifest from the old one. Entries that are in both manifests keep
+    /// their order from `old`, and added entries are appended in their order from the new
+    /// manifest. So the result is the new manifest, up to the order of its entries.
+    pub fn apply(&self, old: &Manifest) -> Manifest {
+        let removed: HashSet<_> = self.removed.iter().collect();
+        let modified: HashMap<_, _> = self.modified.iter().map(|e| (&e.path, e)).collect();
+        old.entries
+            .iter()
+            .filter(|entry| !removed.contains(&entry.path))
+            .map(|entry| modified.get(&entry.path).copied().unwrap_or(entry).clone())
+            .chain(self.added.iter().cloned())
+            .collect()
+    }
+}
+
+/// Compute what changed between `old` and `new`. Entries are matched by path, and an entry is
+/// modified if its metadata or data, including any digests, changed.
+pub fn diff(old: &Manifest, new: &Manifest) -> ManifestDiff {
+    let old_entries: HashMap<_, _> = old.entries.iter().map(|e| (&e.path, e)).collect();
+    let new_paths: HashSet<_> = new.entries.iter().map(|e| &e.path).collect();
+    let mut diff = ManifestDiff::default();
+    for entry in &new.entries {
+        match old_entries.get(&entry.path) {
+            None => diff.added.push(entry.clone()),
+            Some(&old_entry) if old_entry != entry => diff.modified.push(entry.clone()),
+            Some(_) => {}
+        }
+    }
+    diff.removed = old
+        .entries
+        .iter()
+        .map(|e| &e.path)
+        .filter(|path| !new_paths.contains(path))
+        .cloned()
+        .collect();
+    diff
 }
 
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
 #[repr(u32)]
 pub enum ManifestVersion {
     V0 = 0,
-    #[default]
     V1 = 1,
+    /// Adds a [`DigestAlgorithm`] header right after the version.
+    #[default]
+    V2 = 2,
+}
+
+/// The algorithm used to compute the digests referenced by a manifest's entries. Manifests before
+/// [`ManifestVersion::V2`] don't record this, and always use SHA-256.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize_repr, Deserialize_repr)]
+#[repr(u32)]
+pub enum DigestAlgorithm {
+    #[default]
+    Sha256 = 0,
+}
+
+impl TryFrom<u32> for DigestAlgorithm {
+    type Error = UnknownDigestAlgorithmError;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Sha256),
+            _ => Err(UnknownDigestAlgorithmError(value)),
+        }
+    }
+}
+
+/// Error indicating that a manifest header named a digest algorithm we don't know about.
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownDigestAlgorithmError(pub u32);
+
+impl fmt::Display for UnknownDigestAlgorithmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown manifest digest algorithm {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownDigestAlgorithmError {}
+
+/// A table of 256 pseudo-random values used by the [`Chunker`]'s gear hash. It is generated with
+/// splitmix64 from a fixed seed so that chunk boundaries are stable across builds and platforms.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state = 0x6d61_656c_7374_726fu64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// A content-defined chunker. Chunk boundaries are picked using a gear rolling hash over the
+/// data, so an edit to one part of a file only changes the chunks around the edit. This lets
+/// similar files share most of their chunks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chunker {
+    min_size: usize,
+    mask: u64,
+    max_size: usize,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Self::new(16 * 1024, 64 * 1024, 256 * 1024)
+    }
+}
+
+impl Chunker {
+    /// Create a new chunker. Chunks will be at least `min_size` bytes and at most `max_size`
+    /// bytes, except for the last chunk, which may be shorter. `average_size` must be a power of
+    /// two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `average_size` isn't a power of two, or if the sizes don't satisfy
+    /// `0 < min_size <= average_size <= max_size`.
+    pub fn new(min_size: usize, average_size: usize, max_size: usize) -> Self {
+        assert!(average_size.is_power_of_two());
+        assert!(0 < min_size && min_size <= average_size && average_size <= max_size);
+        // The mask uses the high bits of the hash, since those have seen the most bytes.
+        let bits = average_size.trailing_zeros();
+        let mask = if bits == 0 { 0 } else { !0u64 << (64 - bits) };
+        Self {
+            min_size,
+            mask,
+            max_size,
+        }
+    }
+
+    /// Return the length of the first chunk in `data`.
+    fn next_boundary(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min_size {
+            return data.len();
+        }
+        let end = data.len().min(self.max_size);
+        let mut hash = 0u64;
+        for (i, byte) in data.iter().enumerate().take(end).skip(self.min_size) {
+            hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+            if hash & self.mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Split `data` into chunks. Concatenating the chunks yields the original data.
+    pub fn chunks<'a>(&self, data: &'a [u8]) -> Chunks<'a> {
+        Chunks {
+            chunker: *self,
+            data,
+        }
+    }
+}
+
+/// An iterator over the chunks of some data. Created by [`Chunker::chunks`].
+pub struct Chunks<'a> {
+    chunker: Chunker,
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let (chunk, rest) = self.data.split_at(self.chunker.next_boundary(self.data));
+        self.data = rest;
+        Some(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::pseudo_random_data;
+
+    #[test]
+    fn chunks_concatenate_to_data() {
+        let data = pseudo_random_data(100_000);
+        let chunker = Chunker::new(64, 512, 2048);
+        assert_eq!(chunker.chunks(&data).collect::<Vec<_>>().concat(), data);
+    }
+
+    #[test]
+    fn chunks_respect_size_bounds() {
+        let data = pseudo_random_data(100_000);
+        let chunker = Chunker::new(64, 512, 2048);
+        let chunks: Vec<_> = chunker.chunks(&data).collect();
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(rest.iter().all(|c| (64..=2048).contains(&c.len())));
+        assert!(last.len() <= 2048);
+    }
+
+    #[test]
+    fn chunks_of_zeros_are_max_size() {
+        let data = vec![0u8; 5000];
+        let chunker = Chunker::new(64, 512, 2048);
+        let lens: Vec<_> = chunker.chunks(&data).map(<[u8]>::len).collect();
+        assert_eq!(lens, vec![2048, 2048, 904]);
+    }
+
+    #[test]
+    fn chunks_of_empty_data() {
+        assert_eq!(Chunker::default().chunks(&[]).next(), None);
+    }
+
+    fn file_entry(path: &str, data: ManifestFileData) -> ManifestEntry {
+        ManifestEntry {
+            path: path.into(),
+            metadata: ManifestEntryMetadata {
+                size: 10,
+                mode: Mode(0o444),
+                mtime: UnixTimestamp::EPOCH,
+            },
+            data: ManifestEntryData::File(data),
+            xattrs: BTreeMap::new(),
+        }
+    }
+
+    fn test_manifest() -> Manifest {
+        Manifest::from_iter([
+            file_entry("a", ManifestFileData::Digest(1u32.into())),
+            file_entry("b", ManifestFileData::Inline(b"hello".to_vec())),
+            file_entry(
+                "c",
+                ManifestFileData::ChunkedFile {
+                    chunks: vec![2u32.into(), 3u32.into(), 1u32.into()],
+                    size: 10,
+                },
+            ),
+            file_entry("d", ManifestFileData::Empty),
+            ManifestEntry {
+                path: "e".into(),
+                metadata: ManifestEntryMetadata {
+                    size: 0,
+                    mode: Mode(0o555),
+                    mtime: UnixTimestamp::EPOCH,
+                },
+                data: ManifestEntryData::Directory { opaque: false },
+                xattrs: BTreeMap::new(),
+            },
+        ])
+    }
+
+    #[test]
+    fn referenced_digests() {
+        assert_eq!(
+            test_manifest().referenced_digests(),
+            HashSet::from([1u32.into(), 2u32.into(), 3u32.into()])
+        );
+        assert_eq!(Manifest::default().referenced_digests(), HashSet::new());
+    }
+
+    #[test]
+    fn dangling_digests() {
+        let present: HashSet<Sha256Digest> = HashSet::from([1u32.into(), 3u32.into()]);
+        assert_eq!(
+            test_manifest().dangling_digests(|d| present.contains(d)),
+            HashSet::from([2u32.into()])
+        );
+        assert_eq!(test_manifest().dangling_digests(|_| true), HashSet::new());
+        assert_eq!(
+            test_manifest().dangling_digests(|_| false),
+            test_manifest().referenced_digests()
+        );
+    }
+
+    #[test]
+    fn content_digest_is_stable() {
+        assert_eq!(
+            test_manifest().content_digest(),
+            test_manifest().clone().content_digest()
+        );
+        assert_ne!(
+            test_manifest().content_digest(),
+            Manifest::default().content_digest()
+        );
+        // The digest must not change between runs or releases, since it names the artifact.
+        assert_eq!(
+            test_manifest().content_digest().to_string(),
+            "50391c8e22fb1f81f759d26584ceedc27252fbcd0472af3d01642c6ead05ad41"
+        );
+    }
+
+    #[test]
+    fn entry_with_xattrs_round_trips() {
+        let mut entry = file_entry("a", ManifestFileData::Inline(b"hello".to_vec()));
+        entry.xattrs = BTreeMap::from([
+            ("user.foo".into(), b"bar".to_vec()),
+            ("security.capability".into(), vec![0, 1, 2, 255]),
+            ("user.empty".into(), vec![]),
+        ]);
+        let bytes = proto::serialize(&entry).unwrap();
+        assert_eq!(ManifestEntry::decode(&bytes).unwrap(), entry);
+    }
+
+    #[test]
+    fn entry_without_xattrs_encoding_is_unchanged() {
+        let entry = file_entry("a", ManifestFileData::Inline(b"hello".to_vec()));
+        let bytes = proto::serialize(&entry).unwrap();
+        assert_eq!(
+            bytes,
+            proto::serialize(&(&entry.path, &entry.metadata, &entry.data)).unwrap()
+        );
+        assert_eq!(ManifestEntry::decode(&bytes).unwrap(), entry);
+    }
+
+    #[test]
+    fn digest_algorithm_try_from() {
+        assert_eq!(DigestAlgorithm::try_from(0), Ok(DigestAlgorithm::Sha256));
+        assert_eq!(
+            DigestAlgorithm::try_from(7),
+            Err(UnknownDigestAlgorithmError(7))
+        );
+    }
+
+    #[test]
+    fn digests() {
+        assert_eq!(
+            ManifestFileData::Digest(1u32.into())
+                .digests()
+                .collect::<Vec<_>>(),
+            vec![&Sha256Digest::from(1u32)]
+        );
+        assert_eq!(
+            ManifestFileData::ChunkedFile {
+                chunks: vec![1u32.into(), 2u32.into()],
+                size: 10
+            }
+            .digests()
+            .collect::<Vec<_>>(),
+            vec![&Sha256Digest::from(1u32), &Sha256Digest::from(2u32)]
+        );
+        assert_eq!(ManifestFileData::Empty.digests().count(), 0);
+        assert_eq!(ManifestFileData::Inline(vec![1]).digests().count(), 0);
+    }
+
+    #[test]
+    fn diff_unchanged() {
+        let diff = diff(&test_manifest(), &test_manifest());
+        assert!(diff.is_empty());
+        assert_eq!(diff.apply(&test_manifest()), test_manifest());
+        assert!(super::diff(&Manifest::default(), &Manifest::default()).is_empty());
+    }
+
+    #[test]
+    fn diff_added() {
+        let mut new = test_manifest();
+        new.entries
+            .push(file_entry("f", ManifestFileData::Digest(4u32.into())));
+        let diff = diff(&test_manifest(), &new);
+        assert_eq!(
+            diff,
+            ManifestDiff {
+                added: vec![file_entry("f", ManifestFileData::Digest(4u32.into()))],
+                ..Default::default()
+            }
+        );
+        assert_eq!(diff.apply(&test_manifest()), new);
+    }
+
+    #[test]
+    fn diff_removed() {
+        let mut new = test_manifest();
+        new.entries.retain(|e| e.path != "b" && e.path != "e");
+        let diff = diff(&test_manifest(), &new);
+        assert_eq!(
+            diff,
+            ManifestDiff {
+                removed: vec!["b".into(), "e".into()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(diff.apply(&test_manifest()), new);
+    }
+
+    #[test]
+    fn diff_modified() {
+        let mut new = test_manifest();
+        new.entries[0] = file_entry("a", ManifestFileData::Digest(4u32.into()));
+        new.entries[4].metadata.mode = Mode(0o755);
+        let diff = diff(&test_manifest(), &new);
+        assert_eq!(
+            diff,
+            ManifestDiff {
+                modified: vec![new.entries[0].clone(), new.entries[4].clone()],
+                ..Default::default()
+            }
+        );
+        assert_eq!(diff.apply(&test_manifest()), new);
+    }
+
+    #[test]
+    fn diff_round_trip() {
+        let old = test_manifest();
+        let new = Manifest::from_iter([
+            file_entry("g", ManifestFileData::Empty),
+            file_entry("d", ManifestFileData::Inline(b"now inline".to_vec())),
+            old.entries[0].clone(),
+            old.entries[2].clone(),
+        ]);
+        let diff = diff(&old, &new);
+        assert_eq!(diff.added, vec![new.entries[0].clone()]);
+        assert_eq!(diff.removed, vec![Utf8PathBuf::from("b"), "e".into()]);
+        assert_eq!(diff.modified, vec![new.entries[1].clone()]);
+
+        // Only the order of the entries differs.
+        let applied = diff.apply(&old);
+        assert_ne!(applied, new);
+        assert!(super::diff(&applied, &new).is_empty());
+        assert!(super::diff(&new, &applied).is_empty());
+    }
 }
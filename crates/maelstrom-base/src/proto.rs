@@ -1,11 +1,19 @@
 //! Messages sent between various binaries.
+//!
+//! Messages are serialized with bincode using big-endian integers. When sent over a stream, each
+//! message is framed with a 4-byte, big-endian length prefix. See [`write_framed`] and
+//! [`read_framed`]. Both are independent of the host's byte order, so native and wasm peers agree
+//! on the wire format. Readers reject frames longer than a maximum message size, so that a peer
+//! can't make them allocate arbitrary amounts of memory.
 
 use crate::{
     stats::{BrokerStatistics, JobStateCounts},
-    ClientJobId, JobId, JobOutcomeResult, JobSpec, Sha256Digest,
+    ClientJobId, JobCompleted, JobEffects, JobError, JobId, JobOutcome, JobOutcomeResult, JobSpec,
+    JobStatus, Sha256Digest, WindowSize,
 };
 use bincode::Options;
 use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
 
 /// The first message sent by a connector to the broker. It identifies what the connector is, and
 /// provides any relevant information.
@@ -24,13 +32,88 @@ pub enum Hello {
 pub enum BrokerToWorker {
     EnqueueJob(JobId, JobSpec),
     CancelJob(JobId),
+    /// The terminal attached to an executing job's TTY was resized. The worker applies the new
+    /// size to the job's pty. This is ignored if the job isn't executing or doesn't have a TTY.
+    ResizeJobTty(JobId, WindowSize),
+}
+
+impl BrokerToWorker {
+    pub fn enqueue_job(jid: JobId, spec: JobSpec) -> Self {
+        Self::EnqueueJob(jid, spec)
+    }
+
+    pub fn cancel_job(jid: JobId) -> Self {
+        Self::CancelJob(jid)
+    }
+
+    pub fn resize_job_tty(jid: JobId, window_size: WindowSize) -> Self {
+        Self::ResizeJobTty(jid, window_size)
+    }
 }
 
 /// Message sent from a worker to the broker. These are responses to previous
 /// [`BrokerToWorker::EnqueueJob`] messages. After sending the initial [`Hello`], a worker will
 /// send a stream of these messages.
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
-pub struct WorkerToBroker(pub JobId, pub JobOutcomeResult);
+pub enum WorkerToBroker {
+    /// The job has finished, one way or another. This is the last message for the job.
+    JobResponse {
+        jid: JobId,
+        result: JobOutcomeResult,
+    },
+
+    /// The job has reported progress. The first value is the amount of work done so far, and the
+    /// second, if present, is the total amount of work. These are only sent for executing jobs.
+    JobProgress(JobId, u64, Option<u64>),
+}
+
+impl WorkerToBroker {
+    pub fn job_response(jid: JobId, result: JobOutcomeResult) -> Self {
+        Self::JobResponse { jid, result }
+    }
+
+    /// The job ran to completion.
+    pub fn completed(jid: JobId, status: JobStatus, effects: JobEffects) -> Self {
+        Self::job_response(
+            jid,
+            Ok(JobOutcome::Completed(JobCompleted { status, effects })),
+        )
+    }
+
+    /// The job was killed because it ran past its timeout.
+    pub fn timed_out(jid: JobId, effects: JobEffects) -> Self {
+        Self::job_response(jid, Ok(JobOutcome::TimedOut(effects)))
+    }
+
+    /// The job couldn't be executed because of a problem with the job itself.
+    pub fn execution_error(jid: JobId, message: impl Into<String>) -> Self {
+        Self::job_response(jid, Err(JobError::Execution(message.into())))
+    }
+
+    /// The job couldn't be executed because of a problem with the worker.
+    pub fn system_error(jid: JobId, message: impl Into<String>) -> Self {
+        Self::job_response(jid, Err(JobError::System(message.into())))
+    }
+
+    pub fn progress(jid: JobId, done: u64, total: Option<u64>) -> Self {
+        Self::JobProgress(jid, done, total)
+    }
+
+    /// The job this message is about.
+    pub fn jid(&self) -> JobId {
+        match self {
+            Self::JobResponse { jid, .. } | Self::JobProgress(jid, ..) => *jid,
+        }
+    }
+
+    /// The job's result, if this is the job's final message.
+    pub fn result(&self) -> Option<&JobOutcomeResult> {
+        match self {
+            Self::JobResponse { result, .. } => Some(result),
+            Self::JobProgress(..) => None,
+        }
+    }
+}
 
 /// Message sent from the broker to a client. The broker won't send a message until it has recevied
 /// a [`Hello`] and determined the type of its interlocutor.
@@ -40,6 +123,31 @@ pub enum BrokerToClient {
     TransferArtifact(Sha256Digest),
     StatisticsResponse(BrokerStatistics),
     JobStateCountsResponse(JobStateCounts),
+
+    /// A job has reported progress. This is forwarded from [`WorkerToBroker::JobProgress`].
+    JobProgress(ClientJobId, u64, Option<u64>),
+}
+
+impl BrokerToClient {
+    pub fn job_response(cjid: ClientJobId, result: JobOutcomeResult) -> Self {
+        Self::JobResponse(cjid, result)
+    }
+
+    /// The job ran to completion.
+    pub fn completed(cjid: ClientJobId, status: JobStatus, effects: JobEffects) -> Self {
+        Self::JobResponse(
+            cjid,
+            Ok(JobOutcome::Completed(JobCompleted { status, effects })),
+        )
+    }
+
+    pub fn transfer_artifact(digest: Sha256Digest) -> Self {
+        Self::TransferArtifact(digest)
+    }
+
+    pub fn progress(cjid: ClientJobId, done: u64, total: Option<u64>) -> Self {
+        Self::JobProgress(cjid, done, total)
+    }
 }
 
 /// Message sent from a client to the broker. After sending the initial [`Hello`], a client will
@@ -52,6 +160,12 @@ pub enum ClientToBroker {
     JobStateCountsRequest,
 }
 
+impl ClientToBroker {
+    pub fn job_request(cjid: ClientJobId, spec: JobSpec) -> Self {
+        Self::JobRequest(cjid, spec)
+    }
+}
+
 /// Message sent from the broker to an artifact fetcher. This will be in response to an
 /// [`ArtifactFetcherToBroker`] message. On failure to get the artifact, the result contains
 /// details about what went wrong. After a failure, the broker will close the artifact fetcher
@@ -107,6 +221,59 @@ pub fn deserialize_from<R: std::io::Read, T: serde::de::DeserializeOwned>(
     bincode().deserialize_from(reader)
 }
 
+/// The size of the length prefix written by [`write_framed`].
+pub const FRAME_LENGTH_SIZE: usize = 4;
+
+/// The largest message readers accept by default. Messages are small: bulk data, like artifacts,
+/// is transferred outside of them.
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+/// Decode a frame's length prefix. Returns [`bincode::ErrorKind::SizeLimit`] if the message would
+/// be larger than `max_message_bytes`.
+pub fn frame_length(
+    prefix: [u8; FRAME_LENGTH_SIZE],
+    max_message_bytes: usize,
+) -> bincode::Result<usize> {
+    let len = u32::from_be_bytes(prefix) as usize;
+    if len > max_message_bytes {
+        return Err(Box::new(bincode::ErrorKind::SizeLimit));
+    }
+    Ok(len)
+}
+
+/// Serialize `value` into a frame: a 4-byte, big-endian length prefix followed by the serialized
+/// message.
+pub fn frame<T: ?Sized + Serialize>(value: &T) -> bincode::Result<Vec<u8>> {
+    let len = u32::try_from(serialized_size(value)?)
+        .map_err(|_| Box::new(bincode::ErrorKind::SizeLimit))?;
+    let mut buf = Vec::with_capacity(FRAME_LENGTH_SIZE + len as usize);
+    buf.extend_from_slice(&len.to_be_bytes());
+    serialize_into(&mut buf, value)?;
+    Ok(buf)
+}
+
+/// Write `value` to `writer` as a single frame. See [`frame`].
+pub fn write_framed<W: Write, T: ?Sized + Serialize>(
+    mut writer: W,
+    value: &T,
+) -> bincode::Result<()> {
+    writer.write_all(&frame(value)?)?;
+    Ok(())
+}
+
+/// Read a single frame written by [`write_framed`] from `reader` and deserialize it. Frames longer
+/// than `max_message_bytes` are rejected before their contents are read. See [`frame_length`].
+pub fn read_framed<R: Read, T: serde::de::DeserializeOwned>(
+    mut reader: R,
+    max_message_bytes: usize,
+) -> bincode::Result<T> {
+    let mut len = [0; FRAME_LENGTH_SIZE];
+    reader.read_exact(&mut len)?;
+    let mut buf = vec![0; frame_length(len, max_message_bytes)?];
+    reader.read_exact(&mut buf)?;
+    deserialize(&buf)
+}
+
 fn fixint_bincode() -> impl Options {
     bincode().with_fixint_encoding()
 }
@@ -135,3 +302,243 @@ pub fn fixint_deserialize_from<R: std::io::Read, T: serde::de::DeserializeOwned>
 ) -> bincode::Result<T> {
     fixint_bincode().deserialize_from(reader)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{nonempty, ArtifactType, ClientId, JobOutputResult};
+    use std::time::Duration;
+
+    fn jid() -> JobId {
+        JobId {
+            cid: ClientId::from(1),
+            cjid: ClientJobId::from(2),
+        }
+    }
+
+    fn spec() -> JobSpec {
+        JobSpec::new(
+            "test",
+            nonempty![(Sha256Digest::from(1u32), ArtifactType::Tar)],
+        )
+    }
+
+    fn effects() -> JobEffects {
+        JobEffects {
+            stdout: JobOutputResult::Inline(Box::new(*b"out")),
+            stderr: JobOutputResult::None,
+            duration: Duration::from_secs(1),
+            worker: None,
+        }
+    }
+
+    #[test]
+    fn framed_round_trip() {
+        let mut buf = vec![];
+        write_framed(
+            &mut buf,
+            &ClientToBroker::job_request(ClientJobId::from(2), spec()),
+        )
+        .unwrap();
+        write_framed(&mut buf, &ClientToBroker::StatisticsRequest).unwrap();
+
+        let mut reader = &buf[..];
+        assert_eq!(
+            read_framed::<_, ClientToBroker>(&mut reader, DEFAULT_MAX_MESSAGE_BYTES).unwrap(),
+            ClientToBroker::job_request(ClientJobId::from(2), spec())
+        );
+        assert_eq!(
+            read_framed::<_, ClientToBroker>(&mut reader, DEFAULT_MAX_MESSAGE_BYTES).unwrap(),
+            ClientToBroker::StatisticsRequest
+        );
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn framed_length_is_big_endian() {
+        let payload = vec![7u8; 0x0102];
+        let buf = frame(&payload).unwrap();
+        let len = serialized_size(&payload).unwrap();
+        assert_eq!(len, 0x0105);
+        assert_eq!(buf[..FRAME_LENGTH_SIZE], [0, 0, 0x01, 0x05]);
+        assert_eq!(buf.len() as u64, FRAME_LENGTH_SIZE as u64 + len);
+        assert_eq!(
+            read_framed::<_, Vec<u8>>(&buf[..], DEFAULT_MAX_MESSAGE_BYTES).unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn read_framed_truncated() {
+        let buf = frame(&ClientToBroker::StatisticsRequest).unwrap();
+        assert!(
+            read_framed::<_, ClientToBroker>(&buf[..buf.len() - 1], DEFAULT_MAX_MESSAGE_BYTES)
+                .is_err()
+        );
+        assert!(read_framed::<_, ClientToBroker>(&buf[..2], DEFAULT_MAX_MESSAGE_BYTES).is_err());
+    }
+
+    #[test]
+    fn read_framed_rejects_length_over_max() {
+        // Only the length prefix is present. If the reader tried to read the body, it would get an
+        // I/O error instead.
+        let buf = u32::MAX.to_be_bytes();
+        let err = read_framed::<_, Vec<u8>>(&buf[..], 1024).unwrap_err();
+        assert!(matches!(*err, bincode::ErrorKind::SizeLimit), "{err:?}");
+
+        let buf = frame(&vec![7u8; 100]).unwrap();
+        let max = buf.len() - FRAME_LENGTH_SIZE - 1;
+        let err = read_framed::<_, Vec<u8>>(&buf[..], max).unwrap_err();
+        assert!(matches!(*err, bincode::ErrorKind::SizeLimit), "{err:?}");
+    }
+
+    #[test]
+    fn read_framed_accepts_length_up_to_max() {
+        let payload = vec![7u8; 100];
+        let buf = frame(&payload).unwrap();
+        let max = buf.len() - FRAME_LENGTH_SIZE;
+        assert_eq!(read_framed::<_, Vec<u8>>(&buf[..], max).unwrap(), payload);
+    }
+
+    #[test]
+    fn broker_to_worker_constructors() {
+        assert_eq!(
+            BrokerToWorker::enqueue_job(jid(), spec()),
+            BrokerToWorker::EnqueueJob(jid(), spec())
+        );
+        assert_eq!(
+            BrokerToWorker::cancel_job(jid()),
+            BrokerToWorker::CancelJob(jid())
+        );
+        assert_eq!(
+            BrokerToWorker::resize_job_tty(jid(), WindowSize::new(24, 80)),
+            BrokerToWorker::ResizeJobTty(jid(), WindowSize::new(24, 80))
+        );
+    }
+
+    #[test]
+    fn worker_to_broker_constructors() {
+        assert_eq!(
+            WorkerToBroker::completed(jid(), JobStatus::Exited(1), effects()),
+            WorkerToBroker::JobResponse {
+                jid: jid(),
+                result: Ok(JobOutcome::Completed(JobCompleted {
+                    status: JobStatus::Exited(1),
+                    effects: effects(),
+                }))
+            }
+        );
+        assert_eq!(
+            WorkerToBroker::timed_out(jid(), effects()),
+            WorkerToBroker::JobResponse {
+                jid: jid(),
+                result: Ok(JobOutcome::TimedOut(effects()))
+            }
+        );
+        assert_eq!(
+            WorkerToBroker::execution_error(jid(), "bad"),
+            WorkerToBroker::JobResponse {
+                jid: jid(),
+                result: Err(JobError::Execution("bad".into()))
+            }
+        );
+        assert_eq!(
+            WorkerToBroker::system_error(jid(), "bad"),
+            WorkerToBroker::JobResponse {
+                jid: jid(),
+                result: Err(JobError::System("bad".into()))
+            }
+        );
+        assert_eq!(
+            WorkerToBroker::progress(jid(), 42, Some(100)),
+            WorkerToBroker::JobProgress(jid(), 42, Some(100))
+        );
+    }
+
+    #[test]
+    fn worker_to_broker_accessors() {
+        let response = WorkerToBroker::system_error(jid(), "bad");
+        assert_eq!(response.jid(), jid());
+        assert_eq!(
+            response.result(),
+            Some(&Err(JobError::System("bad".into())))
+        );
+
+        let progress = WorkerToBroker::progress(jid(), 42, Some(100));
+        assert_eq!(progress.jid(), jid());
+        assert_eq!(progress.result(), None);
+    }
+
+    #[test]
+    fn worker_to_broker_wire_format_matches_tuple_variant() {
+        // This is how `WorkerToBroker` was defined before `JobResponse` had named fields. Workers
+        // and brokers built from either definition must be able to talk to each other.
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        enum TupleWorkerToBroker {
+            JobResponse(JobId, JobOutcomeResult),
+            JobProgress(JobId, u64, Option<u64>),
+        }
+
+        let pairs = [
+            (
+                WorkerToBroker::completed(jid(), JobStatus::Exited(1), effects()),
+                TupleWorkerToBroker::JobResponse(
+                    jid(),
+                    Ok(JobOutcome::Completed(JobCompleted {
+                        status: JobStatus::Exited(1),
+                        effects: effects(),
+                    })),
+                ),
+            ),
+            (
+                WorkerToBroker::execution_error(jid(), "bad"),
+                TupleWorkerToBroker::JobResponse(jid(), Err(JobError::Execution("bad".into()))),
+            ),
+            (
+                WorkerToBroker::progress(jid(), 42, Some(100)),
+                TupleWorkerToBroker::JobProgress(jid(), 42, Some(100)),
+            ),
+        ];
+        for (named, tuple) in pairs {
+            let bytes = serialize(&named).unwrap();
+            assert_eq!(bytes, serialize(&tuple).unwrap());
+            assert_eq!(deserialize::<TupleWorkerToBroker>(&bytes).unwrap(), tuple);
+            assert_eq!(deserialize::<WorkerToBroker>(&bytes).unwrap(), named);
+        }
+    }
+
+    #[test]
+    fn broker_to_client_constructors() {
+        let cjid = ClientJobId::from(2);
+        assert_eq!(
+            BrokerToClient::job_response(cjid, Err(JobError::System("bad".into()))),
+            BrokerToClient::JobResponse(cjid, Err(JobError::System("bad".into())))
+        );
+        assert_eq!(
+            BrokerToClient::completed(cjid, JobStatus::Signaled(9), effects()),
+            BrokerToClient::JobResponse(
+                cjid,
+                Ok(JobOutcome::Completed(JobCompleted {
+                    status: JobStatus::Signaled(9),
+                    effects: effects(),
+                }))
+            )
+        );
+        assert_eq!(
+            BrokerToClient::transfer_artifact(Sha256Digest::from(3u32)),
+            BrokerToClient::TransferArtifact(Sha256Digest::from(3u32))
+        );
+        assert_eq!(
+            BrokerToClient::progress(cjid, 42, None),
+            BrokerToClient::JobProgress(cjid, 42, None)
+        );
+    }
+
+    #[test]
+    fn client_to_broker_constructors() {
+        assert_eq!(
+            ClientToBroker::job_request(ClientJobId::from(2), spec()),
+            ClientToBroker::JobRequest(ClientJobId::from(2), spec())
+        );
+    }
+}
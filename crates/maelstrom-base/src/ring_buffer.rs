@@ -12,6 +12,7 @@ pub struct RingBuffer<T> {
     buf: Vec<T>,
     cursor: usize,
     capacity: usize,
+    total_pushed: u64,
 }
 
 impl<T> PartialEq for RingBuffer<T>
@@ -47,6 +48,7 @@ impl<T> RingBuffer<T> {
             buf,
             cursor: 0,
             capacity,
+            total_pushed: 0,
         }
     }
 
@@ -55,6 +57,7 @@ impl<T> RingBuffer<T> {
     }
 
     pub fn insert(&mut self, element: T) {
+        self.total_pushed += 1;
         if self.buf.len() < self.capacity() {
             self.buf.push(element);
         } else {
@@ -71,6 +74,21 @@ impl<T> RingBuffer<T> {
         self.buf.is_empty()
     }
 
+    /// The number of elements ever inserted, including ones that have since been overwritten.
+    pub fn total_pushed(&self) -> u64 {
+        self.total_pushed
+    }
+
+    /// The number of elements that were overwritten because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.total_pushed - self.buf.len() as u64
+    }
+
+    /// Returns true if any element has been overwritten.
+    pub fn is_saturated(&self) -> bool {
+        self.dropped() > 0
+    }
+
     pub fn iter(&self) -> RingBufferIter<'_, T> {
         self.buf[self.cursor..]
             .iter()
@@ -116,6 +134,25 @@ fn insert_and_iter() {
     }
 }
 
+#[test]
+fn dropped_counts_overwritten_elements() {
+    let mut r = RingBuffer::new(4);
+    for b in b"abc" {
+        r.insert(*b);
+    }
+    assert_eq!(r.total_pushed(), 3);
+    assert_eq!(r.dropped(), 0);
+    assert!(!r.is_saturated());
+
+    for b in b"defghij" {
+        r.insert(*b);
+    }
+    assert_eq!(r.total_pushed(), 10);
+    assert_eq!(r.dropped(), 6);
+    assert!(r.is_saturated());
+    assert_eq!(Vec::from_iter(r.iter().copied()), b"ghij");
+}
+
 #[test]
 fn equal_with_different_cursor() {
     let mut r1 = RingBuffer::new(3);
@@ -222,10 +259,13 @@ impl<T> From<RingBufferDeserProxy<T>> for RingBuffer<T> {
         let mut buf = proxy.elements;
         let capacity = proxy.capacity;
         buf.reserve_exact(capacity - buf.len());
+        // The counters aren't serialized, so only the retained elements are accounted for.
+        let total_pushed = buf.len() as u64;
         Self {
             cursor: 0,
             buf,
             capacity,
+            total_pushed,
         }
     }
 }
@@ -6,7 +6,11 @@ use crate::{
 };
 use enum_map::EnumMap;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt, time::Duration};
+use std::{
+    collections::HashMap,
+    fmt::{self, Write as _},
+    time::Duration,
+};
 
 /// The amount of time between broker statistic samples
 pub const BROKER_STATISTICS_INTERVAL: Duration = Duration::from_millis(500);
@@ -47,6 +51,16 @@ impl JobState {
     pub fn iter() -> impl DoubleEndedIterator<Item = Self> {
         <Self as strum::IntoEnumIterator>::iter()
     }
+
+    /// A name suitable for use as a metric label value.
+    fn label(&self) -> &'static str {
+        match self {
+            Self::WaitingForArtifacts => "waiting_for_artifacts",
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Complete => "complete",
+        }
+    }
 }
 
 #[test]
@@ -136,9 +150,334 @@ pub struct WorkerStatistics {
     pub slots: usize,
 }
 
+/// Running totals of how well a worker's artifact cache is doing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    /// Requests for artifacts that were already in the cache.
+    pub hits: u64,
+    /// Requests for artifacts that had to be fetched.
+    pub misses: u64,
+    /// Artifacts removed from the cache to make room.
+    pub evictions: u64,
+    /// Total size of the artifacts removed from the cache to make room.
+    pub bytes_evicted: u64,
+}
+
 /// Useful information for a client to display about the broker's state.
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct BrokerStatistics {
     pub worker_statistics: HashMap<WorkerId, WorkerStatistics>,
     pub job_statistics: JobStatisticsTimeSeries,
 }
+
+/// The default number of buckets a [`SlidingWindow`] divides its window into.
+pub const SLIDING_WINDOW_BUCKETS: usize = 60;
+
+/// Computes the rate of change of a monotonically increasing counter over a recent window of time.
+///
+/// Snapshots of the counter are fed in with [`record`](Self::record). The window is divided into
+/// a fixed number of buckets, and only the first sample in each bucket is kept, so memory use
+/// doesn't depend on how often samples are recorded. Timestamps are supplied by the caller as the
+/// time since some fixed point, so this works without access to a clock.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SlidingWindow {
+    window: Duration,
+    bucket_width: Duration,
+    samples: RingBuffer<(Duration, u64)>,
+    latest: Option<(Duration, u64)>,
+}
+
+impl SlidingWindow {
+    /// Create a `SlidingWindow` over `window`, with [`SLIDING_WINDOW_BUCKETS`] buckets.
+    pub fn new(window: Duration) -> Self {
+        Self::with_buckets(window, SLIDING_WINDOW_BUCKETS)
+    }
+
+    /// Create a `SlidingWindow` over `window`, divided into `buckets` buckets.
+    /// panics if `window` or `buckets` is zero
+    pub fn with_buckets(window: Duration, buckets: usize) -> Self {
+        assert!(!window.is_zero(), "window must not be zero");
+        assert!(buckets > 0, "buckets must not be zero");
+        let bucket_width = window / u32::try_from(buckets).unwrap();
+        Self {
+            window,
+            bucket_width: bucket_width.max(Duration::from_nanos(1)),
+            // One extra sample so that the oldest bucket still in the window is always kept.
+            samples: RingBuffer::new(buckets + 1),
+            latest: None,
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    fn bucket(&self, timestamp: Duration) -> u128 {
+        timestamp.as_nanos() / self.bucket_width.as_nanos()
+    }
+
+    /// Record that the counter had `value` at `timestamp`. Samples older than the latest recorded
+    /// one are ignored.
+    pub fn record(&mut self, timestamp: Duration, value: u64) {
+        if matches!(self.latest, Some((latest, _)) if timestamp < latest) {
+            return;
+        }
+        let new_bucket = match self.samples.iter().last() {
+            Some(&(last, _)) => self.bucket(last) != self.bucket(timestamp),
+            None => true,
+        };
+        if new_bucket {
+            self.samples.insert((timestamp, value));
+        }
+        self.latest = Some((timestamp, value));
+    }
+
+    /// The average increase of the counter per second over the window ending at the latest
+    /// sample. Returns `None` if there aren't at least two samples at different times in the
+    /// window. If the counter went down, the rate is zero.
+    pub fn rate_per_second(&self) -> Option<f64> {
+        let (end, end_value) = self.latest?;
+        let cutoff = end.saturating_sub(self.window);
+        let &(start, start_value) = self
+            .samples
+            .iter()
+            .find(|&&(timestamp, _)| timestamp >= cutoff)?;
+        if start == end {
+            return None;
+        }
+        let delta = end_value.saturating_sub(start_value);
+        Some(delta as f64 / (end - start).as_secs_f64())
+    }
+}
+
+#[test]
+fn sliding_window_rate_within_window() {
+    let mut w = SlidingWindow::with_buckets(Duration::from_secs(10), 10);
+    assert_eq!(w.rate_per_second(), None);
+
+    w.record(Duration::ZERO, 0);
+    assert_eq!(w.rate_per_second(), None);
+
+    for t in 1..=10 {
+        w.record(Duration::from_secs(t), 2 * t);
+    }
+    assert_eq!(w.rate_per_second(), Some(2.0));
+}
+
+#[test]
+fn sliding_window_rate_across_window_boundary() {
+    let mut w = SlidingWindow::with_buckets(Duration::from_secs(10), 10);
+    for t in 0..=10 {
+        w.record(Duration::from_secs(t), 2 * t);
+    }
+
+    // The rate goes up to 5 per second. Halfway through the next window, the rate is the average
+    // of the old and new rates.
+    for t in 11..=15 {
+        w.record(Duration::from_secs(t), 20 + 5 * (t - 10));
+    }
+    assert_eq!(w.rate_per_second(), Some(3.5));
+
+    // Once the old samples have left the window, only the new rate counts.
+    for t in 16..=20 {
+        w.record(Duration::from_secs(t), 20 + 5 * (t - 10));
+    }
+    assert_eq!(w.rate_per_second(), Some(5.0));
+}
+
+#[test]
+fn sliding_window_rate_beyond_window() {
+    let mut w = SlidingWindow::with_buckets(Duration::from_secs(10), 10);
+    w.record(Duration::ZERO, 0);
+    w.record(Duration::from_secs(5), 10);
+
+    // Everything but the latest sample has fallen out of the window.
+    w.record(Duration::from_secs(100), 1000);
+    assert_eq!(w.rate_per_second(), None);
+
+    w.record(Duration::from_secs(104), 1008);
+    assert_eq!(w.rate_per_second(), Some(2.0));
+}
+
+#[test]
+fn sliding_window_keeps_one_sample_per_bucket() {
+    let mut w = SlidingWindow::with_buckets(Duration::from_secs(10), 10);
+    for ms in 0..=10_000 {
+        w.record(Duration::from_millis(ms), ms);
+    }
+    assert_eq!(w.samples.len(), 11);
+    assert_eq!(w.rate_per_second(), Some(1000.0));
+
+    // Samples from the past are ignored.
+    w.record(Duration::from_secs(1), 0);
+    assert_eq!(w.rate_per_second(), Some(1000.0));
+}
+
+fn write_metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}").unwrap();
+    writeln!(out, "# TYPE {name} {kind}").unwrap();
+}
+
+/// Render `stats` in the Prometheus text exposition format. Job counts are taken from the most
+/// recent sample in the time-series.
+pub fn to_prometheus(stats: &BrokerStatistics) -> String {
+    let mut out = String::new();
+
+    write_metric_header(
+        &mut out,
+        "maelstrom_broker_workers",
+        "gauge",
+        "Number of workers connected to the broker.",
+    );
+    writeln!(
+        out,
+        "maelstrom_broker_workers {}",
+        stats.worker_statistics.len()
+    )
+    .unwrap();
+
+    write_metric_header(
+        &mut out,
+        "maelstrom_broker_worker_slots",
+        "gauge",
+        "Number of job slots a worker has.",
+    );
+    let mut workers = Vec::from_iter(&stats.worker_statistics);
+    workers.sort_by_key(|(id, _)| **id);
+    for (id, worker) in workers {
+        writeln!(
+            out,
+            "maelstrom_broker_worker_slots{{worker=\"{id}\"}} {}",
+            worker.slots
+        )
+        .unwrap();
+    }
+
+    write_metric_header(
+        &mut out,
+        "maelstrom_broker_jobs",
+        "gauge",
+        "Number of jobs for a client in a given state.",
+    );
+    if let Some(sample) = stats.job_statistics.iter().last() {
+        let mut clients = Vec::from_iter(&sample.client_to_stats);
+        clients.sort_by_key(|(id, _)| **id);
+        for (id, counts) in clients {
+            for state in JobState::iter() {
+                writeln!(
+                    out,
+                    "maelstrom_broker_jobs{{client=\"{id}\",state=\"{}\"}} {}",
+                    state.label(),
+                    counts[state]
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    out
+}
+
+/// Render a worker's cache statistics in the Prometheus text exposition format.
+pub fn cache_stats_to_prometheus(stats: &CacheStats) -> String {
+    let mut out = String::new();
+    for (name, help, value) in [
+        (
+            "maelstrom_worker_cache_hits_total",
+            "Artifact requests satisfied by the cache.",
+            stats.hits,
+        ),
+        (
+            "maelstrom_worker_cache_misses_total",
+            "Artifact requests that had to be fetched.",
+            stats.misses,
+        ),
+        (
+            "maelstrom_worker_cache_evictions_total",
+            "Artifacts removed from the cache to make room.",
+            stats.evictions,
+        ),
+        (
+            "maelstrom_worker_cache_evicted_bytes_total",
+            "Total size of artifacts removed from the cache to make room.",
+            stats.bytes_evicted,
+        ),
+    ] {
+        write_metric_header(&mut out, name, "counter", help);
+        writeln!(out, "{name} {value}").unwrap();
+    }
+    out
+}
+
+#[test]
+fn broker_statistics_to_prometheus() {
+    use enum_map::enum_map;
+
+    let stats = BrokerStatistics {
+        worker_statistics: HashMap::from_iter([
+            (WorkerId::from(2), WorkerStatistics { slots: 4 }),
+            (WorkerId::from(1), WorkerStatistics { slots: 2 }),
+        ]),
+        job_statistics: JobStatisticsTimeSeries::from_iter([
+            JobStatisticsSample {
+                client_to_stats: HashMap::from_iter([(ClientId::from(1), enum_map! { _ => 100 })]),
+            },
+            JobStatisticsSample {
+                client_to_stats: HashMap::from_iter([(
+                    ClientId::from(1),
+                    enum_map! {
+                        JobState::WaitingForArtifacts => 1,
+                        JobState::Pending => 2,
+                        JobState::Running => 3,
+                        JobState::Complete => 4,
+                    },
+                )]),
+            },
+        ]),
+    };
+    assert_eq!(
+        to_prometheus(&stats),
+        "\
+# HELP maelstrom_broker_workers Number of workers connected to the broker.
+# TYPE maelstrom_broker_workers gauge
+maelstrom_broker_workers 2
+# HELP maelstrom_broker_worker_slots Number of job slots a worker has.
+# TYPE maelstrom_broker_worker_slots gauge
+maelstrom_broker_worker_slots{worker=\"1\"} 2
+maelstrom_broker_worker_slots{worker=\"2\"} 4
+# HELP maelstrom_broker_jobs Number of jobs for a client in a given state.
+# TYPE maelstrom_broker_jobs gauge
+maelstrom_broker_jobs{client=\"1\",state=\"waiting_for_artifacts\"} 1
+maelstrom_broker_jobs{client=\"1\",state=\"pending\"} 2
+maelstrom_broker_jobs{client=\"1\",state=\"running\"} 3
+maelstrom_broker_jobs{client=\"1\",state=\"complete\"} 4
+"
+    );
+}
+
+#[test]
+fn cache_statistics_to_prometheus() {
+    let stats = CacheStats {
+        hits: 10,
+        misses: 3,
+        evictions: 1,
+        bytes_evicted: 4096,
+    };
+    assert_eq!(
+        cache_stats_to_prometheus(&stats),
+        "\
+# HELP maelstrom_worker_cache_hits_total Artifact requests satisfied by the cache.
+# TYPE maelstrom_worker_cache_hits_total counter
+maelstrom_worker_cache_hits_total 10
+# HELP maelstrom_worker_cache_misses_total Artifact requests that had to be fetched.
+# TYPE maelstrom_worker_cache_misses_total counter
+maelstrom_worker_cache_misses_total 3
+# HELP maelstrom_worker_cache_evictions_total Artifacts removed from the cache to make room.
+# TYPE maelstrom_worker_cache_evictions_total counter
+maelstrom_worker_cache_evictions_total 1
+# HELP maelstrom_worker_cache_evicted_bytes_total Total size of artifacts removed from the cache to make room.
+# TYPE maelstrom_worker_cache_evicted_bytes_total counter
+maelstrom_worker_cache_evicted_bytes_total 4096
+"
+    );
+}
@@ -0,0 +1,118 @@
+//! Functions for building values to use in tests. These are plain functions, so they can be used
+//! by crates that don't want to depend on the internal `maelstrom-test` macros. This module is
+//! only available with the `test-support` feature.
+
+use crate::{nonempty, ArtifactType, ClientId, ClientJobId, JobId, JobSpec, Sha256Digest};
+
+/// A digest that is all zeros except for the last eight bytes, which hold `n` in big-endian.
+///
+/// ```
+/// use maelstrom_base::{test_support::test_digest, Sha256Digest};
+///
+/// assert_eq!(test_digest(3), Sha256Digest::from(3u64));
+/// assert_eq!(
+///     test_digest(3).to_string(),
+///     "0000000000000000000000000000000000000000000000000000000000000003",
+/// );
+/// ```
+pub fn test_digest(n: u64) -> Sha256Digest {
+    Sha256Digest::from(n)
+}
+
+/// A job ID whose client ID and client job ID are both `n`.
+///
+/// ```
+/// use maelstrom_base::{test_support::test_jid, ClientId, ClientJobId};
+///
+/// let jid = test_jid(2);
+/// assert_eq!(jid.cid, ClientId::from(2));
+/// assert_eq!(jid.cjid, ClientJobId::from(2));
+/// ```
+pub fn test_jid(n: u32) -> JobId {
+    JobId {
+        cid: ClientId::from(n),
+        cjid: ClientJobId::from(n),
+    }
+}
+
+/// A job spec that runs the program `test_<n>` with the arguments `arg_1` through `arg_<n-1>`,
+/// using the single tar layer [`test_digest(n)`](test_digest). Different values of `n` yield
+/// different specs.
+///
+/// ```
+/// use maelstrom_base::{test_support::{test_digest, test_spec}, ArtifactType};
+///
+/// let spec = test_spec(3);
+/// assert_eq!(spec.program, "test_3");
+/// assert_eq!(spec.arguments, ["arg_1", "arg_2"]);
+/// assert_eq!(spec.layers.head, (test_digest(3), ArtifactType::Tar));
+/// ```
+pub fn test_spec(n: u32) -> JobSpec {
+    JobSpec::new(
+        format!("test_{n}"),
+        nonempty![(test_digest(n.into()), ArtifactType::Tar)],
+    )
+    .arguments((1..n).map(|i| format!("arg_{i}")))
+}
+
+/// `len` bytes from a fixed xorshift sequence. The data looks random enough for content-defined
+/// chunking to find boundaries, but is the same on every call.
+///
+/// ```
+/// use maelstrom_base::test_support::pseudo_random_data;
+///
+/// let data = pseudo_random_data(1000);
+/// assert_eq!(data.len(), 1000);
+/// assert_eq!(data, pseudo_random_data(1000));
+/// assert_eq!(data[..10], pseudo_random_data(10));
+/// ```
+pub fn pseudo_random_data(len: usize) -> Vec<u8> {
+    let mut state = 1u32;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_digest_holds_number() {
+        let mut expected = [0; 32];
+        expected[31] = 0x12;
+        expected[30] = 0x34;
+        assert_eq!(test_digest(0x3412), Sha256Digest::new(expected));
+    }
+
+    #[test]
+    fn test_jid_uses_number_for_both_ids() {
+        assert_eq!(
+            test_jid(7),
+            JobId {
+                cid: ClientId::from(7),
+                cjid: ClientJobId::from(7),
+            }
+        );
+    }
+
+    #[test]
+    fn test_spec_matches_number() {
+        let spec = test_spec(1);
+        assert_eq!(spec.program, "test_1");
+        assert!(spec.arguments.is_empty());
+        assert_eq!(spec.layers, nonempty![(test_digest(1), ArtifactType::Tar)]);
+
+        let spec = test_spec(4);
+        assert_eq!(spec.program, "test_4");
+        assert_eq!(spec.arguments, ["arg_1", "arg_2", "arg_3"]);
+        assert_eq!(spec.layers, nonempty![(test_digest(4), ArtifactType::Tar)]);
+
+        assert_ne!(test_spec(2), test_spec(3));
+    }
+}
@@ -1,5 +1,9 @@
 use crate::WindowSize;
-use std::mem;
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    mem,
+};
 
 pub fn encode_input(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
     bytes
@@ -23,6 +27,12 @@ pub fn encode_window_size_change(window_size: WindowSize) -> [u8; 6] {
     [0xff, 0, rows[0], rows[1], columns[0], columns[1]]
 }
 
+/// The marker the worker sends over a TTY job's socket in place of `bytes` bytes of output that
+/// it dropped. See [`TtyOutputOverflow::Drop`](crate::TtyOutputOverflow::Drop).
+pub fn encode_dropped_output(bytes: u64) -> Vec<u8> {
+    format!("\r\n[maelstrom: {bytes} bytes of output dropped]\r\n").into_bytes()
+}
+
 pub trait DecodeInputAcceptor<E> {
     fn input(&mut self, input: &[u8]) -> Result<(), E>;
     fn window_size_change(&mut self, window_size: WindowSize) -> Result<(), E>;
@@ -117,6 +127,89 @@ pub fn decode_input(input: &[u8]) -> DecodeInputIterator {
     DecodeInputIterator(input)
 }
 
+/// Tell the worker on the other end of a TTY job's socket that the terminal's window size changed.
+/// If the job's output is [framed](crate::JobTty::framed_output), the worker acknowledges the
+/// change with a [`TtyEvent::Resized`] once it has resized the job's terminal.
+pub fn send_resize(mut socket: impl Write, window_size: WindowSize) -> io::Result<()> {
+    socket.write_all(&encode_window_size_change(window_size))
+}
+
+/// Something received from the worker over a TTY job's socket.
+#[derive(Debug, PartialEq)]
+pub enum TtyEvent {
+    /// Output from the job.
+    Output(Vec<u8>),
+    /// The worker resized the job's terminal, in response to a [`send_resize`].
+    Resized(WindowSize),
+}
+
+/// Decodes the output the worker sends over a TTY job's socket when the job's output is
+/// [framed](crate::JobTty::framed_output). The output is framed just like input, so it is decoded
+/// with [`decode_input`].
+///
+/// This doesn't know anything about sockets: the caller accepts the worker's connection and hands
+/// over the stream, or anything else that can be read from.
+pub struct Reader<ReadT> {
+    read: ReadT,
+    buf: [u8; 1024],
+    remainder: DecodeInputRemainder,
+    events: VecDeque<TtyEvent>,
+}
+
+impl<ReadT: Read> Reader<ReadT> {
+    pub fn new(read: ReadT) -> Self {
+        Self {
+            read,
+            buf: [0; 1024],
+            remainder: DecodeInputRemainder::default(),
+            events: VecDeque::new(),
+        }
+    }
+
+    /// Block until the next event is available. Consecutive output that arrives together is
+    /// returned as a single event. Returns `None` once the worker closes the stream.
+    pub fn next_event(&mut self) -> io::Result<Option<TtyEvent>> {
+        while self.events.is_empty() {
+            let offset = self.remainder.move_to_slice(&mut self.buf);
+            let n = self.read.read(&mut self.buf[offset..])?;
+            if n == 0 {
+                if offset > 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "TTY stream ended in the middle of an escape sequence",
+                    ));
+                }
+                return Ok(None);
+            }
+            for chunk in decode_input(&self.buf[..offset + n]) {
+                match chunk {
+                    DecodeInputChunk::Input(output) => match self.events.back_mut() {
+                        Some(TtyEvent::Output(pending)) => pending.extend_from_slice(output),
+                        _ => self.events.push_back(TtyEvent::Output(output.to_vec())),
+                    },
+                    DecodeInputChunk::WindowSizeChange(window_size) => {
+                        self.events.push_back(TtyEvent::Resized(window_size))
+                    }
+                    DecodeInputChunk::Remainder(remainder) => self.remainder = remainder,
+                }
+            }
+        }
+        Ok(self.events.pop_front())
+    }
+
+    pub fn into_inner(self) -> ReadT {
+        self.read
+    }
+}
+
+impl<ReadT: Read> Iterator for Reader<ReadT> {
+    type Item = io::Result<TtyEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_event().transpose()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -129,6 +222,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn encode_dropped_output_basic() {
+        assert_eq!(
+            encode_dropped_output(1234),
+            b"\r\n[maelstrom: 1234 bytes of output dropped]\r\n"
+        );
+    }
+
     #[test]
     fn encode_input_empty() {
         assert_encode_input(b"", []);
@@ -306,6 +407,93 @@ mod tests {
         assert_decode_input(b"abcdef\xff\x01", [Input(b"abcdef"), Input(b"\xff\x01")]);
     }
 
+    /// One end of an in-memory pipe, which reads at most `chunk` bytes at a time, to exercise
+    /// escape sequences being split across reads.
+    struct PipeReader {
+        pipe: VecDeque<u8>,
+        chunk: usize,
+    }
+
+    impl Read for PipeReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.chunk).min(self.pipe.len());
+            for (dest, src) in buf.iter_mut().zip(self.pipe.drain(..n)) {
+                *dest = src;
+            }
+            Ok(n)
+        }
+    }
+
+    /// What the worker does with the job's output when the output is framed.
+    fn write_framed_output(pipe: &mut Vec<u8>, output: &[u8]) {
+        for chunk in encode_input(output) {
+            pipe.extend_from_slice(chunk);
+        }
+    }
+
+    fn read_events(pipe: Vec<u8>, chunk: usize) -> Vec<TtyEvent> {
+        let reader = Reader::new(PipeReader {
+            pipe: pipe.into(),
+            chunk,
+        });
+        reader.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn reader_loopback() {
+        // The client sends a resize to the worker over one pipe.
+        let mut to_worker = vec![];
+        send_resize(&mut to_worker, WindowSize::new(40, 100)).unwrap();
+        let [WindowSizeChange(window_size)] = Vec::from_iter(decode_input(&to_worker))[..] else {
+            panic!("expected a single window size change");
+        };
+
+        // The worker sends output and the acknowledgement back over the other.
+        let mut to_client = vec![];
+        write_framed_output(&mut to_client, b"before\xff");
+        write_framed_output(&mut to_client, b"resize");
+        to_client.extend(encode_window_size_change(window_size));
+        write_framed_output(&mut to_client, b"after\xff\xff");
+
+        let expected = vec![
+            TtyEvent::Output(b"before\xffresize".to_vec()),
+            TtyEvent::Resized(WindowSize::new(40, 100)),
+            TtyEvent::Output(b"after\xff\xff".to_vec()),
+        ];
+        assert_eq!(read_events(to_client.clone(), 1024), expected);
+
+        // Reading a byte at a time splits every escape sequence, and output comes out in pieces.
+        let events = read_events(to_client, 1);
+        let mut output = vec![];
+        let mut resizes = vec![];
+        for event in events {
+            match event {
+                TtyEvent::Output(bytes) => output.extend(bytes),
+                TtyEvent::Resized(window_size) => {
+                    resizes.push((output.len(), window_size));
+                }
+            }
+        }
+        assert_eq!(output, b"before\xffresizeafter\xff\xff");
+        assert_eq!(resizes, vec![(13, WindowSize::new(40, 100))]);
+    }
+
+    #[test]
+    fn reader_truncated_escape() {
+        let mut reader = Reader::new(PipeReader {
+            pipe: b"abc\xff\x00\x01".to_vec().into(),
+            chunk: 1024,
+        });
+        assert_eq!(
+            reader.next_event().unwrap(),
+            Some(TtyEvent::Output(b"abc".to_vec()))
+        );
+        assert_eq!(
+            reader.next_event().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
     #[test]
     fn decode_input_kitchen_sink() {
         assert_decode_input(
@@ -1214,6 +1214,7 @@ mod tests {
                 mtime: UnixTimestamp(1705538554),
             },
             data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+            xattrs: Default::default(),
         }];
         writer.write_entries(&entries).unwrap();
 
@@ -4,7 +4,7 @@
 use crate::scheduler_task::cache::{Cache, CacheFs, GetArtifact, GetArtifactForWorkerError};
 use anyhow::Result;
 use maelstrom_base::{
-    manifest::{ManifestEntryData, ManifestFileData},
+    manifest::ManifestEntryData,
     proto::{BrokerToClient, BrokerToWorker, ClientToBroker, WorkerToBroker},
     stats::{
         BrokerStatistics, JobState, JobStateCounts, JobStatisticsSample, JobStatisticsTimeSeries,
@@ -120,6 +120,7 @@ impl<FsT: CacheFs> SchedulerCache for Cache<FsT> {
 /// The incoming messages, or events, for [`Scheduler`].
 ///
 /// If [`Scheduler`] weren't implement as an async state machine, these would be its methods.
+#[allow(clippy::large_enum_variant)]
 pub enum Message<DepsT: SchedulerDeps> {
     /// The given client connected, and messages can be sent to it on the given sender.
     ClientConnected(ClientId, DepsT::ClientSender),
@@ -230,9 +231,12 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
                 self.receive_worker_connected(deps, id, slots, sender)
             }
             Message::WorkerDisconnected(id) => self.receive_worker_disconnected(deps, id),
-            Message::FromWorker(wid, WorkerToBroker(jid, result)) => {
+            Message::FromWorker(wid, WorkerToBroker::JobResponse { jid, result }) => {
                 self.receive_worker_response(deps, wid, jid, result)
             }
+            Message::FromWorker(wid, WorkerToBroker::JobProgress(jid, done, total)) => {
+                self.receive_worker_progress(deps, wid, jid, done, total)
+            }
             Message::GotArtifact(digest, size, path) => {
                 self.receive_got_artifact(deps, digest, size, path)
             }
@@ -566,12 +570,35 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
         self.possibly_start_jobs(deps);
     }
 
+    fn receive_worker_progress(
+        &mut self,
+        deps: &mut DepsT,
+        wid: WorkerId,
+        jid: JobId,
+        done: u64,
+        total: Option<u64>,
+    ) {
+        let worker = self.workers.0.get(&wid).unwrap();
+
+        if !worker.pending.contains(&jid) {
+            // The job has already completed, or its client has disconnected. Either way, there's
+            // no one to tell about its progress.
+            return;
+        }
+
+        let client = self.clients.0.get_mut(&jid.cid).unwrap();
+        deps.send_message_to_client(
+            &mut client.sender,
+            BrokerToClient::JobProgress(jid.cjid, done, total),
+        );
+    }
+
     fn receive_worker_response(
         &mut self,
         deps: &mut DepsT,
         wid: WorkerId,
         jid: JobId,
-        result: JobOutcomeResult,
+        mut result: JobOutcomeResult,
     ) {
         let worker = self.workers.0.get_mut(&wid).unwrap();
 
@@ -582,6 +609,9 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
             return;
         }
 
+        if let Ok(outcome) = &mut result {
+            outcome.effects_mut().worker = Some(wid);
+        }
         let client = self.clients.0.get_mut(&jid.cid).unwrap();
         deps.send_message_to_client(
             &mut client.sender,
@@ -619,8 +649,15 @@ impl<CacheT: SchedulerCache, DepsT: SchedulerDeps> Scheduler<CacheT, DepsT> {
     ) -> Result<()> {
         for entry in self.cache.read_manifest(digest)? {
             let entry = entry?;
-            if let ManifestEntryData::File(ManifestFileData::Digest(digest)) = entry.data {
-                self.ensure_artifact_for_job(deps, digest, jid, IsManifest::NotManifest);
+            if let ManifestEntryData::File(data) = &entry.data {
+                for digest in data.digests() {
+                    self.ensure_artifact_for_job(
+                        deps,
+                        digest.clone(),
+                        jid,
+                        IsManifest::NotManifest,
+                    );
+                }
             }
         }
         Ok(())
@@ -727,8 +764,9 @@ mod tests {
     use enum_map::enum_map;
     use itertools::Itertools;
     use maelstrom_base::{
-        manifest::{ManifestEntry, ManifestEntryMetadata, Mode, UnixTimestamp},
+        manifest::{ManifestEntry, ManifestEntryMetadata, ManifestFileData, Mode, UnixTimestamp},
         proto::BrokerToWorker::{self, *},
+        JobOutcome,
     };
     use maelstrom_test::*;
     use maelstrom_util::manifest::ManifestWriter;
@@ -736,6 +774,7 @@ mod tests {
     use std::{cell::RefCell, rc::Rc};
 
     #[derive(Clone, Debug, PartialEq)]
+    #[allow(clippy::large_enum_variant)]
     enum TestMessage {
         ToClient(ClientId, BrokerToClient),
         ToWorker(WorkerId, BrokerToWorker),
@@ -749,6 +788,12 @@ mod tests {
 
     use TestMessage::*;
 
+    /// The outcome the broker forwards to the client when `wid` sends it `outcome`.
+    fn ran_on(mut outcome: JobOutcome, wid: WorkerId) -> JobOutcome {
+        outcome.effects_mut().worker = Some(wid);
+        outcome
+    }
+
     struct TestClientSender(ClientId);
     struct TestWorkerSender(WorkerId);
     struct TestWorkerArtifactFetcherSender(u32);
@@ -997,7 +1042,10 @@ mod tests {
 
         fixture.receive_message(FromWorker(
             wid![1],
-            WorkerToBroker(jid![1], Ok(outcome![1])),
+            WorkerToBroker::JobResponse {
+                jid: jid![1],
+                result: Ok(outcome![1]),
+            },
         ));
     }
 
@@ -1019,7 +1067,7 @@ mod tests {
     script_test! {
         response_from_known_worker_for_unknown_job_ignored,
         WorkerConnected(wid![1], 2, worker_sender![1]) => {};
-        FromWorker(wid![1], WorkerToBroker(jid![1], Ok(outcome![1]))) => {};
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1], result: Ok(outcome![1]) }) => {};
     }
 
     script_test! {
@@ -1035,16 +1083,48 @@ mod tests {
             CacheGetArtifact(jid![1, 1], digest![1]),
             ToWorker(wid![1], EnqueueJob(jid![1], spec![1, Tar])),
         };
-        FromWorker(wid![1], WorkerToBroker(jid![1], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![1]),
         };
     }
 
+    script_test! {
+        progress_from_worker_forwarded_to_client,
+        {
+            Fixture::new([
+                ((jid![1, 1], digest![1]), vec![GetArtifact::Success]),
+            ], [], [], [])
+        },
+        ClientConnected(cid![1], client_sender![1]) => {};
+        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        FromClient(cid![1], ClientToBroker::JobRequest(cjid![1], spec![1, Tar])) => {
+            CacheGetArtifact(jid![1, 1], digest![1]),
+            ToWorker(wid![1], EnqueueJob(jid![1], spec![1, Tar])),
+        };
+        FromWorker(wid![1], WorkerToBroker::JobProgress(jid![1], 42, Some(100))) => {
+            ToClient(cid![1], BrokerToClient::JobProgress(cjid![1], 42, Some(100))),
+        };
+        FromWorker(wid![1], WorkerToBroker::JobProgress(jid![1], 43, None)) => {
+            ToClient(cid![1], BrokerToClient::JobProgress(cjid![1], 43, None)),
+        };
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(ran_on(outcome![1], wid![1])))),
+            CacheDecrementRefcount(digest![1]),
+        };
+        FromWorker(wid![1], WorkerToBroker::JobProgress(jid![1], 44, None)) => {};
+    }
+
+    script_test! {
+        progress_from_worker_for_unknown_job_ignored,
+        WorkerConnected(wid![1], 2, worker_sender![1]) => {};
+        FromWorker(wid![1], WorkerToBroker::JobProgress(jid![1], 1, None)) => {};
+    }
+
     script_test! {
         response_from_worker_for_disconnected_client_ignored,
         WorkerConnected(wid![1], 2, worker_sender![1]) => {};
-        FromWorker(wid![1], WorkerToBroker(jid![1], Ok(outcome![1]))) => {};
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1], result: Ok(outcome![1]) }) => {};
     }
 
     script_test! {
@@ -1110,8 +1190,8 @@ mod tests {
             ToWorker(wid![3], EnqueueJob(jid![1, 7], spec![7, Tar])),
         };
 
-        FromWorker(wid![1], WorkerToBroker(jid![1, 1], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1, 1], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![1]),
         };
         FromClient(cid![1], ClientToBroker::JobRequest(cjid![8], spec![8, Tar])) => {
@@ -1119,8 +1199,8 @@ mod tests {
             ToWorker(wid![1], EnqueueJob(jid![1, 8], spec![8, Tar])),
         };
 
-        FromWorker(wid![2], WorkerToBroker(jid![1, 2], Ok(outcome![2]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![2]))),
+        FromWorker(wid![2], WorkerToBroker::JobResponse { jid: jid![1, 2], result: Ok(outcome![2]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(ran_on(outcome![2], wid![2])))),
             CacheDecrementRefcount(digest![2]),
         };
         FromClient(cid![1], ClientToBroker::JobRequest(cjid![9], spec![9, Tar])) => {
@@ -1128,8 +1208,8 @@ mod tests {
             ToWorker(wid![2], EnqueueJob(jid![1, 9], spec![9, Tar])),
         };
 
-        FromWorker(wid![3], WorkerToBroker(jid![1, 3], Ok(outcome![3]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![3], Ok(outcome![3]))),
+        FromWorker(wid![3], WorkerToBroker::JobResponse { jid: jid![1, 3], result: Ok(outcome![3]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![3], Ok(ran_on(outcome![3], wid![3])))),
             CacheDecrementRefcount(digest![3]),
         };
         FromClient(cid![1], ClientToBroker::JobRequest(cjid![10], spec![10, Tar])) => {
@@ -1187,15 +1267,15 @@ mod tests {
         };
 
         // 2/2 1/2
-        FromWorker(wid![2], WorkerToBroker(jid![1, 2], Ok(outcome![2]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![2]))),
+        FromWorker(wid![2], WorkerToBroker::JobResponse { jid: jid![1, 2], result: Ok(outcome![2]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(ran_on(outcome![2], wid![2])))),
             CacheDecrementRefcount(digest![2]),
             ToWorker(wid![2], EnqueueJob(jid![1, 5], spec![5, Tar])),
         };
 
         // 1/2 2/2
-        FromWorker(wid![1], WorkerToBroker(jid![1, 1], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1, 1], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![1]),
             ToWorker(wid![1], EnqueueJob(jid![1, 6], spec![6, Tar])),
         };
@@ -1292,8 +1372,8 @@ mod tests {
             ToWorker(wid![3], EnqueueJob(jid![1, 1], spec![1, Tar])),
         };
 
-        FromWorker(wid![2], WorkerToBroker(jid![1, 2], Ok(outcome![2]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![2]))),
+        FromWorker(wid![2], WorkerToBroker::JobResponse { jid: jid![1, 2], result: Ok(outcome![2]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(ran_on(outcome![2], wid![2])))),
             CacheDecrementRefcount(digest![2]),
             ToWorker(wid![2], EnqueueJob(jid![1, 4], spec![4, Tar].estimated_duration(Some(millis!(40))))),
         };
@@ -1329,8 +1409,8 @@ mod tests {
             CacheGetArtifact(jid![1, 4], digest![4]),
         };
 
-        FromWorker(wid![1], WorkerToBroker(jid![1, 1], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1, 1], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![1]),
             ToWorker(wid![1], EnqueueJob(jid![1, 3], spec![3, Tar].estimated_duration(Some(millis!(300))))),
         };
@@ -1343,8 +1423,8 @@ mod tests {
             ToWorker(wid![2], EnqueueJob(jid![1, 2], spec![2, Tar])),
         };
 
-        FromWorker(wid![2], WorkerToBroker(jid![1, 2], Ok(outcome![2]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![2]))),
+        FromWorker(wid![2], WorkerToBroker::JobResponse { jid: jid![1, 2], result: Ok(outcome![2]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(ran_on(outcome![2], wid![2])))),
             CacheDecrementRefcount(digest![2]),
             ToWorker(wid![2], EnqueueJob(jid![1, 3], spec![3, Tar].estimated_duration(Some(millis!(300))))),
         };
@@ -1410,13 +1490,13 @@ mod tests {
             ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![2, Tar])),
         };
 
-        FromWorker(wid![1], WorkerToBroker(jid![1, 1], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1, 1], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![1]),
         };
 
-        FromWorker(wid![1], WorkerToBroker(jid![1, 2], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1, 2], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![2]),
         };
 
@@ -1518,8 +1598,8 @@ mod tests {
             CacheClientDisconnected(cid![2]),
         };
 
-        FromWorker(wid![1], WorkerToBroker(jid![1, 1], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1, 1], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![1]),
             ToWorker(wid![1], EnqueueJob(jid![1, 3], spec![3, Tar])),
         };
@@ -1635,8 +1715,8 @@ mod tests {
             ToWorker(wid![1], EnqueueJob(jid![1, 2], spec![1, [(42, Tar), (43, Tar), (44, Tar)]])),
         };
 
-        FromWorker(wid![1], WorkerToBroker(jid![1, 2], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1, 2], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![2], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![42]),
             CacheDecrementRefcount(digest![43]),
             CacheDecrementRefcount(digest![44]),
@@ -1758,6 +1838,7 @@ mod tests {
                         mtime: UnixTimestamp(1705538554),
                     },
                     data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+                    xattrs: Default::default(),
                 }])
             ])
         },
@@ -1807,6 +1888,7 @@ mod tests {
                             mtime: UnixTimestamp(1705538554),
                         },
                         data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+                        xattrs: Default::default(),
                     },
                     ManifestEntry {
                         path: "bar.txt".into(),
@@ -1816,6 +1898,7 @@ mod tests {
                             mtime: UnixTimestamp(1705538554),
                         },
                         data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+                        xattrs: Default::default(),
                     }
                 ])
             ])
@@ -1865,6 +1948,7 @@ mod tests {
                         mtime: UnixTimestamp(1705538554),
                     },
                     data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+                    xattrs: Default::default(),
                 }])
             ])
         },
@@ -1908,6 +1992,7 @@ mod tests {
                         mtime: UnixTimestamp(1705538554),
                     },
                     data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+                    xattrs: Default::default(),
                 }])
             ])
         },
@@ -1951,6 +2036,7 @@ mod tests {
                         mtime: UnixTimestamp(1705538554),
                     },
                     data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+                    xattrs: Default::default(),
                 }])
             ])
         },
@@ -1990,6 +2076,7 @@ mod tests {
                             mtime: UnixTimestamp(1705538554),
                         },
                         data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+                        xattrs: Default::default(),
                     },
                     ManifestEntry {
                         path: "bar.txt".into(),
@@ -1999,6 +2086,7 @@ mod tests {
                             mtime: UnixTimestamp(1705538554),
                         },
                         data: ManifestEntryData::File(ManifestFileData::Digest(digest![43])),
+                        xattrs: Default::default(),
                     }
                 ])
             ])
@@ -2166,8 +2254,8 @@ mod tests {
             CacheGetArtifact(jid![1, 1], digest![1]),
             ToWorker(wid![1], EnqueueJob(jid![1, 1], spec![1, Tar])),
         };
-        FromWorker(wid![1], WorkerToBroker(jid![1, 1], Ok(outcome![1]))) => {
-            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(outcome![1]))),
+        FromWorker(wid![1], WorkerToBroker::JobResponse { jid: jid![1, 1], result: Ok(outcome![1]) }) => {
+            ToClient(cid![1], BrokerToClient::JobResponse(cjid![1], Ok(ran_on(outcome![1], wid![1])))),
             CacheDecrementRefcount(digest![1]),
         };
         StatisticsHeartbeat => {};
@@ -9,18 +9,20 @@ const INTO_RESULT: [&str; 4] = [
     "RunJobResponse",
 ];
 
-const ENUM_PROTO: [(&str, &str); 6] = [
+const ENUM_PROTO: [(&str, &str); 8] = [
     ("JobDevice", "maelstrom_base::JobDevice"),
     ("BindMountFlag", "maelstrom_base::BindMountFlag"),
     ("ArtifactType", "maelstrom_base::ArtifactType"),
     ("JobCompleted.status", "maelstrom_base::JobStatus"),
     ("JobNetwork", "maelstrom_base::JobNetwork"),
     ("BindMountAccess", "maelstrom_base::BindMountAccess"),
+    ("TtyOutputOverflow", "maelstrom_base::TtyOutputOverflow"),
+    (
+        "CompressionAlgorithm",
+        "maelstrom_base::CompressionAlgorithm",
+    ),
 ];
 
-const MSG_PROTO: [(&str, &str, &str); 1] =
-    [("JobEffects", "maelstrom_base::JobEffects", "option_all")];
-
 fn test_for_protoc() -> Option<PathBuf> {
     if let Ok(o) = Command::new("protoc").arg("--version").output() {
         if let Ok(s) = from_utf8(&o.stdout[..]).map(str::trim) {
@@ -62,16 +64,6 @@ fn main() {
         );
     }
 
-    for (name, other_type, extra) in MSG_PROTO {
-        b = b.message_attribute(
-            name,
-            format!(
-                "#[derive(maelstrom_macro::IntoProtoBuf, maelstrom_macro::TryFromProtoBuf)] \
-                 #[proto(other_type = {other_type}, remote, {extra})]"
-            ),
-        );
-    }
-
     b = b.btree_map(["EnvironmentSpec.vars"]);
 
     b.compile(&["src/items.proto"], &["src/"]).unwrap();
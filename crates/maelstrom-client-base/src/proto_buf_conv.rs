@@ -68,6 +68,38 @@ impl TryFromProtoBuf for u8 {
     }
 }
 
+impl IntoProtoBuf for i16 {
+    type ProtoBufType = i32;
+
+    fn into_proto_buf(self) -> i32 {
+        self.into()
+    }
+}
+
+impl TryFromProtoBuf for i16 {
+    type ProtoBufType = i32;
+
+    fn try_from_proto_buf(v: i32) -> Result<Self> {
+        Ok(v.try_into()?)
+    }
+}
+
+impl IntoProtoBuf for i32 {
+    type ProtoBufType = i32;
+
+    fn into_proto_buf(self) -> i32 {
+        self
+    }
+}
+
+impl TryFromProtoBuf for i32 {
+    type ProtoBufType = i32;
+
+    fn try_from_proto_buf(v: i32) -> Result<Self> {
+        Ok(v)
+    }
+}
+
 impl IntoProtoBuf for u64 {
     type ProtoBufType = u64;
 
@@ -383,18 +415,42 @@ impl TryFromProtoBuf for maelstrom_base::GroupId {
 }
 
 impl IntoProtoBuf for maelstrom_base::Timeout {
-    type ProtoBufType = u32;
+    type ProtoBufType = proto::Duration;
 
-    fn into_proto_buf(self) -> u32 {
-        self.as_u32()
+    fn into_proto_buf(self) -> proto::Duration {
+        std::time::Duration::from(self).into_proto_buf()
     }
 }
 
 impl TryFromProtoBuf for maelstrom_base::Timeout {
-    type ProtoBufType = u32;
+    type ProtoBufType = proto::Duration;
 
-    fn try_from_proto_buf(v: u32) -> Result<Self> {
-        Self::new(v).ok_or_else(|| anyhow!("malformed Timeout"))
+    fn try_from_proto_buf(v: proto::Duration) -> Result<Self> {
+        let millis = std::time::Duration::try_from_proto_buf(v)?.as_millis();
+        Self::from_millis(millis.try_into()?).ok_or_else(|| anyhow!("malformed Timeout"))
+    }
+}
+
+/// The only list of `u16`s we send is a job's CPU set. It's wrapped in a message so that the field
+/// holding it can be optional.
+impl IntoProtoBuf for Vec<u16> {
+    type ProtoBufType = proto::CpuSet;
+
+    fn into_proto_buf(self) -> proto::CpuSet {
+        proto::CpuSet {
+            cpus: self.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl TryFromProtoBuf for Vec<u16> {
+    type ProtoBufType = proto::CpuSet;
+
+    fn try_from_proto_buf(v: proto::CpuSet) -> Result<Self> {
+        Ok(v.cpus
+            .into_iter()
+            .map(TryInto::try_into)
+            .collect::<std::result::Result<_, _>>()?)
     }
 }
 
@@ -440,9 +496,16 @@ impl IntoProtoBuf for maelstrom_base::JobTty {
     type ProtoBufType = proto::JobTty;
 
     fn into_proto_buf(self) -> Self::ProtoBufType {
+        let (socket_address, socket_path) = match self.socket {
+            maelstrom_base::TtySocket::Abstract(address) => (address.as_slice().to_vec(), None),
+            maelstrom_base::TtySocket::Path(path) => (vec![], Some(path.into_string())),
+        };
         Self::ProtoBufType {
-            socket_address: self.socket_address.as_slice().to_vec(),
+            socket_address,
             window_size: Some(self.window_size.into_proto_buf()),
+            socket_path,
+            output_limit: self.output_limit.map(IntoProtoBuf::into_proto_buf),
+            framed_output: self.framed_output,
         }
     }
 }
@@ -451,16 +514,49 @@ impl TryFromProtoBuf for maelstrom_base::JobTty {
     type ProtoBufType = proto::JobTty;
 
     fn try_from_proto_buf(job_tty: Self::ProtoBufType) -> Result<Self> {
+        let socket = match job_tty.socket_path {
+            Some(path) => maelstrom_base::TtySocket::Path(path.into()),
+            None => maelstrom_base::TtySocket::Abstract(
+                job_tty
+                    .socket_address
+                    .try_into()
+                    .map_err(|_| anyhow!("malformed JobTty"))?,
+            ),
+        };
         Ok(Self {
-            socket_address: job_tty
-                .socket_address
-                .try_into()
-                .map_err(|_| anyhow!("malformed JobTty"))?,
+            socket,
             window_size: maelstrom_base::WindowSize::try_from_proto_buf(
                 job_tty
                     .window_size
                     .ok_or_else(|| anyhow!("malformed JobTty"))?,
             )?,
+            output_limit: job_tty
+                .output_limit
+                .map(TryFromProtoBuf::try_from_proto_buf)
+                .transpose()?,
+            framed_output: job_tty.framed_output,
+        })
+    }
+}
+
+impl IntoProtoBuf for maelstrom_base::TtyOutputLimit {
+    type ProtoBufType = proto::TtyOutputLimit;
+
+    fn into_proto_buf(self) -> Self::ProtoBufType {
+        Self::ProtoBufType {
+            high_water_mark: self.high_water_mark,
+            overflow: self.overflow.into_proto_buf(),
+        }
+    }
+}
+
+impl TryFromProtoBuf for maelstrom_base::TtyOutputLimit {
+    type ProtoBufType = proto::TtyOutputLimit;
+
+    fn try_from_proto_buf(limit: Self::ProtoBufType) -> Result<Self> {
+        Ok(Self {
+            high_water_mark: limit.high_water_mark,
+            overflow: TryFromProtoBuf::try_from_proto_buf(limit.overflow)?,
         })
     }
 }
@@ -481,10 +577,12 @@ impl IntoProtoBuf for maelstrom_base::JobMount {
                 mount_point,
                 local_path,
                 read_only,
+                security_label,
             } => proto::job_mount::Mount::Bind(proto::BindMount {
                 mount_point: mount_point.into_proto_buf(),
                 local_path: local_path.into_proto_buf(),
                 read_only: read_only.into_proto_buf(),
+                security_label: security_label.into_proto_buf(),
             }),
             Self::Devices { devices } => proto::job_mount::Mount::Devices(proto::DevicesMount {
                 devices: devices.into_proto_buf(),
@@ -495,6 +593,17 @@ impl IntoProtoBuf for maelstrom_base::JobMount {
             Self::Mqueue { mount_point } => proto::job_mount::Mount::Mqueue(proto::MqueueMount {
                 mount_point: mount_point.into_proto_buf(),
             }),
+            Self::Overlay {
+                mount_point,
+                lower,
+                upper,
+                work,
+            } => proto::job_mount::Mount::Overlay(proto::OverlayMount {
+                mount_point: mount_point.into_proto_buf(),
+                lower: lower.into_proto_buf(),
+                upper: upper.into_proto_buf(),
+                work: work.into_proto_buf(),
+            }),
             Self::Proc { mount_point } => proto::job_mount::Mount::Proc(proto::ProcMount {
                 mount_point: mount_point.into_proto_buf(),
             }),
@@ -521,6 +630,7 @@ impl TryFromProtoBuf for maelstrom_base::JobMount {
                 mount_point: TryFromProtoBuf::try_from_proto_buf(bind_mount.mount_point)?,
                 local_path: TryFromProtoBuf::try_from_proto_buf(bind_mount.local_path)?,
                 read_only: TryFromProtoBuf::try_from_proto_buf(bind_mount.read_only)?,
+                security_label: TryFromProtoBuf::try_from_proto_buf(bind_mount.security_label)?,
             },
             proto::job_mount::Mount::Devices(devices_mount) => maelstrom_base::JobMount::Devices {
                 devices: TryFromProtoBuf::try_from_proto_buf(devices_mount.devices)?,
@@ -531,6 +641,12 @@ impl TryFromProtoBuf for maelstrom_base::JobMount {
             proto::job_mount::Mount::Mqueue(mqueue_mount) => maelstrom_base::JobMount::Mqueue {
                 mount_point: TryFromProtoBuf::try_from_proto_buf(mqueue_mount.mount_point)?,
             },
+            proto::job_mount::Mount::Overlay(overlay_mount) => maelstrom_base::JobMount::Overlay {
+                mount_point: TryFromProtoBuf::try_from_proto_buf(overlay_mount.mount_point)?,
+                lower: TryFromProtoBuf::try_from_proto_buf(overlay_mount.lower)?,
+                upper: TryFromProtoBuf::try_from_proto_buf(overlay_mount.upper)?,
+                work: TryFromProtoBuf::try_from_proto_buf(overlay_mount.work)?,
+            },
             proto::job_mount::Mount::Proc(proc_mount) => maelstrom_base::JobMount::Proc {
                 mount_point: TryFromProtoBuf::try_from_proto_buf(proc_mount.mount_point)?,
             },
@@ -600,12 +716,32 @@ impl IntoProtoBuf for maelstrom_base::JobOutputResult {
             result: Some(match self {
                 Self::None => ProtoJobOutputResult::None(proto::Void {}),
                 Self::Inline(bytes) => ProtoJobOutputResult::Inline(bytes.into()),
+                Self::InlineCompressed {
+                    algorithm,
+                    data,
+                    uncompressed_len,
+                } => {
+                    ProtoJobOutputResult::InlineCompressed(proto::JobOutputResultInlineCompressed {
+                        algorithm: algorithm.into_proto_buf(),
+                        data: data.into(),
+                        uncompressed_len,
+                    })
+                }
                 Self::Truncated { first, truncated } => {
                     ProtoJobOutputResult::Truncated(proto::JobOutputResultTruncated {
                         first: first.into(),
                         truncated,
                     })
                 }
+                Self::External {
+                    first,
+                    digest,
+                    size,
+                } => ProtoJobOutputResult::External(proto::JobOutputResultExternal {
+                    digest: digest.into_proto_buf(),
+                    size,
+                    first: first.into(),
+                }),
             }),
         }
     }
@@ -622,6 +758,15 @@ impl TryFromProtoBuf for maelstrom_base::JobOutputResult {
         {
             ProtoJobOutputResult::None(_) => Ok(Self::None),
             ProtoJobOutputResult::Inline(bytes) => Ok(Self::Inline(bytes.into())),
+            ProtoJobOutputResult::InlineCompressed(proto::JobOutputResultInlineCompressed {
+                algorithm,
+                data,
+                uncompressed_len,
+            }) => Ok(Self::InlineCompressed {
+                algorithm: TryFromProtoBuf::try_from_proto_buf(algorithm)?,
+                data: data.into(),
+                uncompressed_len,
+            }),
             ProtoJobOutputResult::Truncated(proto::JobOutputResultTruncated {
                 first,
                 truncated,
@@ -629,10 +774,67 @@ impl TryFromProtoBuf for maelstrom_base::JobOutputResult {
                 first: first.into(),
                 truncated,
             }),
+            ProtoJobOutputResult::External(proto::JobOutputResultExternal {
+                digest,
+                size,
+                first,
+            }) => Ok(Self::External {
+                first: first.into(),
+                digest: TryFromProtoBuf::try_from_proto_buf(digest)?,
+                size,
+            }),
         }
     }
 }
 
+impl IntoProtoBuf for maelstrom_base::WorkerId {
+    type ProtoBufType = u32;
+
+    fn into_proto_buf(self) -> u32 {
+        self.as_u32()
+    }
+}
+
+impl TryFromProtoBuf for maelstrom_base::WorkerId {
+    type ProtoBufType = u32;
+
+    fn try_from_proto_buf(v: u32) -> Result<Self> {
+        Ok(Self::from(v))
+    }
+}
+
+impl IntoProtoBuf for maelstrom_base::JobEffects {
+    type ProtoBufType = proto::JobEffects;
+
+    fn into_proto_buf(self) -> Self::ProtoBufType {
+        proto::JobEffects {
+            stdout: Some(self.stdout.into_proto_buf()),
+            stderr: Some(self.stderr.into_proto_buf()),
+            duration: Some(self.duration.into_proto_buf()),
+            worker: self.worker.into_proto_buf(),
+        }
+    }
+}
+
+impl TryFromProtoBuf for maelstrom_base::JobEffects {
+    type ProtoBufType = proto::JobEffects;
+
+    fn try_from_proto_buf(v: proto::JobEffects) -> Result<Self> {
+        Ok(Self {
+            stdout: TryFromProtoBuf::try_from_proto_buf(
+                v.stdout.ok_or_else(|| anyhow!("malformed JobEffects"))?,
+            )?,
+            stderr: TryFromProtoBuf::try_from_proto_buf(
+                v.stderr.ok_or_else(|| anyhow!("malformed JobEffects"))?,
+            )?,
+            duration: TryFromProtoBuf::try_from_proto_buf(
+                v.duration.ok_or_else(|| anyhow!("malformed JobEffects"))?,
+            )?,
+            worker: TryFromProtoBuf::try_from_proto_buf(v.worker)?,
+        })
+    }
+}
+
 impl IntoProtoBuf for maelstrom_base::JobOutcome {
     type ProtoBufType = proto::JobOutcome;
 
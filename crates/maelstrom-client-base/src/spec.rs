@@ -10,8 +10,8 @@ use crate::{proto, IntoProtoBuf, TryFromProtoBuf};
 use anyhow::{anyhow, Error, Result};
 use enumset::{EnumSet, EnumSetType};
 use maelstrom_base::{
-    enum_set, ArtifactType, GroupId, JobMount, JobNetwork, JobRootOverlay, JobTty, Sha256Digest,
-    Timeout, UserId, Utf8PathBuf,
+    enum_set, ArtifactType, ClientJobId, GroupId, JobMount, JobNetwork, JobRootOverlay, JobTty,
+    Sha256Digest, Timeout, UserId, Utf8PathBuf,
 };
 use maelstrom_util::template::{replace_template_vars, TemplateVars};
 use serde::{de, Deserialize, Deserializer, Serialize};
@@ -185,6 +185,15 @@ pub struct JobSpec {
     pub timeout: Option<Timeout>,
     pub estimated_duration: Option<Duration>,
     pub allocate_tty: Option<JobTty>,
+    pub oom_score_adj: Option<i16>,
+    pub cacheable: bool,
+    pub trace_id: Option<String>,
+    pub display_name: Option<String>,
+    pub cpu_set: Option<Vec<u16>>,
+    pub readonly_root: bool,
+    pub depends_on: Vec<ClientJobId>,
+    pub priority: i32,
+    pub memory_limit: Option<u64>,
 }
 
 impl JobSpec {
@@ -207,6 +216,15 @@ impl JobSpec {
             timeout: None,
             estimated_duration: None,
             allocate_tty: None,
+            oom_score_adj: None,
+            cacheable: false,
+            trace_id: None,
+            display_name: None,
+            cpu_set: None,
+            readonly_root: false,
+            depends_on: vec![],
+            priority: 0,
+            memory_limit: None,
         }
     }
 
@@ -263,6 +281,51 @@ impl JobSpec {
         self.timeout = timeout.map(Into::into);
         self
     }
+
+    pub fn oom_score_adj(mut self, oom_score_adj: Option<i16>) -> Self {
+        self.oom_score_adj = oom_score_adj;
+        self
+    }
+
+    pub fn cacheable(mut self, cacheable: bool) -> Self {
+        self.cacheable = cacheable;
+        self
+    }
+
+    pub fn trace_id(mut self, trace_id: Option<impl Into<String>>) -> Self {
+        self.trace_id = trace_id.map(Into::into);
+        self
+    }
+
+    pub fn display_name(mut self, display_name: Option<impl Into<String>>) -> Self {
+        self.display_name = display_name.map(Into::into);
+        self
+    }
+
+    pub fn cpu_set(mut self, cpu_set: Option<impl IntoIterator<Item = u16>>) -> Self {
+        self.cpu_set = cpu_set.map(|cpu_set| cpu_set.into_iter().collect());
+        self
+    }
+
+    pub fn readonly_root(mut self, readonly_root: bool) -> Self {
+        self.readonly_root = readonly_root;
+        self
+    }
+
+    pub fn depends_on(mut self, depends_on: impl IntoIterator<Item = ClientJobId>) -> Self {
+        self.depends_on = depends_on.into_iter().collect();
+        self
+    }
+
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn memory_limit(mut self, memory_limit: Option<u64>) -> Self {
+        self.memory_limit = memory_limit;
+        self
+    }
 }
 
 #[derive(
@@ -516,7 +579,7 @@ impl ConvertedImage {
 mod tests {
     use super::*;
     use indoc::indoc;
-    use maelstrom_test::{path_buf_vec, string, string_vec, tar_layer};
+    use maelstrom_test::{digest, path_buf_vec, string, string_vec, tar_layer};
     use maplit::btreemap;
     use std::{ffi::OsStr, os::unix::ffi::OsStrExt as _};
 
@@ -788,4 +851,26 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn job_spec_proto_buf_round_trip() {
+        let spec = JobSpec::new("program", vec![(digest!(1), ArtifactType::Tar)])
+            .oom_score_adj(Some(-500))
+            .cacheable(true)
+            .trace_id(Some("trace"))
+            .display_name(Some("name"))
+            .cpu_set(Some([0, 3]))
+            .readonly_root(true)
+            .depends_on([ClientJobId::from(1), ClientJobId::from(2)])
+            .priority(-3)
+            .memory_limit(Some(1 << 30));
+        let proto_spec = spec.clone().into_proto_buf();
+        assert_eq!(proto_spec.cpu_set, Some(proto::CpuSet { cpus: vec![0, 3] }));
+        assert_eq!(JobSpec::try_from_proto_buf(proto_spec).unwrap(), spec);
+
+        let spec = JobSpec::new("program", vec![(digest!(1), ArtifactType::Tar)]);
+        let proto_spec = spec.clone().into_proto_buf();
+        assert_eq!(proto_spec.cpu_set, None);
+        assert_eq!(JobSpec::try_from_proto_buf(proto_spec).unwrap(), spec);
+    }
 }
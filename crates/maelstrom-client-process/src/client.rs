@@ -363,10 +363,12 @@ impl Client {
                     mount_dir,
                     tmpfs_dir,
                     blob_dir,
+                    None,
                 )?;
 
                 // Create an ArtifactFetcher for the local_worker that just forwards requests to
-                // the router.
+                // the router. The router links artifacts instead of copying them, so it never
+                // asks for cache space.
                 struct ArtifactFetcher(router::Sender);
                 impl local_worker::ArtifactFetcher for ArtifactFetcher {
                     fn start_artifact_fetch(&mut self, digest: Sha256Digest, path: PathBuf) {
@@ -374,6 +376,13 @@ impl Client {
                             .send(router::Message::LocalWorkerStartArtifactFetch(digest, path))
                             .ok();
                     }
+
+                    fn artifact_space_reserved(
+                        &mut self,
+                        _digest: Sha256Digest,
+                        _result: Result<(), local_worker::CacheFull>,
+                    ) {
+                    }
                 }
                 let local_worker_artifact_fetcher = ArtifactFetcher(local_broker_sender.clone());
 
@@ -400,6 +409,7 @@ impl Client {
                     worker_broker_sender,
                     local_worker_cache,
                     slots,
+                    local_worker::SchedulingPolicy::default(),
                 );
 
                 let handle_worker_message =
@@ -552,6 +562,7 @@ impl Client {
         let (state, watcher) = self.state_machine.active_with_watcher()?;
         let (sender, receiver) = tokio::sync::oneshot::channel();
         debug!(state.log, "run_job"; "spec" => ?spec);
+        spec.root_overlay.validate()?;
 
         let mut layers = spec.layers;
         let mut initial_env = Default::default();
@@ -598,6 +609,15 @@ impl Client {
             timeout: spec.timeout,
             estimated_duration: spec.estimated_duration,
             allocate_tty: spec.allocate_tty,
+            oom_score_adj: spec.oom_score_adj,
+            cacheable: spec.cacheable,
+            trace_id: spec.trace_id,
+            display_name: spec.display_name,
+            cpu_set: spec.cpu_set,
+            readonly_root: spec.readonly_root,
+            depends_on: spec.depends_on,
+            priority: spec.priority,
+            memory_limit: spec.memory_limit,
         };
         state
             .local_broker_sender
@@ -18,6 +18,7 @@ use maelstrom_util::{
     root::RootBuf,
 };
 use sha2::{Digest as _, Sha256};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::path::{Path, PathBuf};
 use std::pin::pin;
@@ -189,6 +190,7 @@ impl LayerBuilder {
                 path: stub,
                 metadata,
                 data,
+                xattrs: BTreeMap::new(),
             };
             writer.write_entry(&entry).await?;
         }
@@ -218,6 +220,7 @@ impl LayerBuilder {
                 path: link,
                 metadata,
                 data,
+                xattrs: BTreeMap::new(),
             };
             writer.write_entry(&entry).await?;
         }
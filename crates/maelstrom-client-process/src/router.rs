@@ -42,6 +42,7 @@ pub trait Deps {
     fn shutdown_local_worker(&self, error: Error);
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum Message<DepsT: Deps> {
     // These are requests from the client.
     AddArtifact(PathBuf, Sha256Digest),
@@ -139,6 +140,10 @@ impl<DepsT: Deps> Router<DepsT> {
                 self.deps
                     .start_artifact_transfer_to_broker(digest, path.to_owned());
             }
+            // The client doesn't display progress yet.
+            Message::Broker(BrokerToClient::JobProgress(..)) => {
+                assert!(!self.standalone);
+            }
             Message::Broker(BrokerToClient::StatisticsResponse(_)) => {
                 panic!("got unexpected statistics response")
             }
@@ -156,7 +161,7 @@ impl<DepsT: Deps> Router<DepsT> {
                     counts,
                 );
             }
-            Message::LocalWorker(WorkerToBroker(jid, result)) => {
+            Message::LocalWorker(WorkerToBroker::JobResponse { jid, result }) => {
                 if self.counts[JobState::Pending] > 0 {
                     self.counts[JobState::Pending] -= 1;
                 } else {
@@ -165,6 +170,8 @@ impl<DepsT: Deps> Router<DepsT> {
                 self.counts[JobState::Complete] += 1;
                 self.receive_job_response(jid.cjid, result);
             }
+            // The client doesn't display progress yet.
+            Message::LocalWorker(WorkerToBroker::JobProgress(..)) => {}
             Message::LocalWorkerStartArtifactFetch(digest, path) => {
                 self.deps.send_artifact_fetch_completed_to_local_worker(
                     digest.clone(),
@@ -636,7 +643,10 @@ mod tests {
             .receive_message(RunJob(spec!(0, Tar), cjid!(0)));
         fixture
             .router
-            .receive_message(LocalWorker(WorkerToBroker(jid!(0, 1), Ok(outcome!(0)))));
+            .receive_message(LocalWorker(WorkerToBroker::JobResponse {
+                jid: jid!(0, 1),
+                result: Ok(outcome!(0)),
+            }));
     }
 
     script_test! {
@@ -645,7 +655,7 @@ mod tests {
         RunJob(spec!(0, Tar), cjid!(0)) => {
             EnqueueJobToLocalWorker(jid!(0, 0), spec!(0, Tar)),
         };
-        LocalWorker(WorkerToBroker(jid!(0, 0), Ok(outcome!(0)))) => {
+        LocalWorker(WorkerToBroker::JobResponse { jid: jid!(0, 0), result: Ok(outcome!(0)) }) => {
             JobDone(cjid!(0), Ok(outcome!(0))),
         };
     }
@@ -660,7 +670,10 @@ mod tests {
             .receive_message(RunJob(spec!(0, Tar).network(JobNetwork::Local), cjid!(0)));
         fixture
             .router
-            .receive_message(LocalWorker(WorkerToBroker(jid!(0, 1), Ok(outcome!(0)))));
+            .receive_message(LocalWorker(WorkerToBroker::JobResponse {
+                jid: jid!(0, 1),
+                result: Ok(outcome!(0)),
+            }));
     }
 
     script_test! {
@@ -669,7 +682,7 @@ mod tests {
         RunJob(spec!(0, Tar).network(JobNetwork::Local), cjid!(0)) => {
             EnqueueJobToLocalWorker(jid!(0, 0), spec!(0, Tar).network(JobNetwork::Local)),
         };
-        LocalWorker(WorkerToBroker(jid!(0, 0), Ok(outcome!(0)))) => {
+        LocalWorker(WorkerToBroker::JobResponse { jid: jid!(0, 0), result: Ok(outcome!(0)) }) => {
             JobDone(cjid!(0), Ok(outcome!(0))),
         };
     }
@@ -805,7 +818,7 @@ mod tests {
             }),
         };
 
-        LocalWorker(WorkerToBroker(jid!(0, 3), Ok(outcome!(3)))) => {
+        LocalWorker(WorkerToBroker::JobResponse { jid: jid!(0, 3), result: Ok(outcome!(3)) }) => {
             JobDone(cjid!(3), Ok(outcome!(3))),
         };
         GetJobStateCounts(5) => {
@@ -817,7 +830,7 @@ mod tests {
             }),
         };
 
-        LocalWorker(WorkerToBroker(jid!(0, 2), Ok(outcome!(2)))) => {
+        LocalWorker(WorkerToBroker::JobResponse { jid: jid!(0, 2), result: Ok(outcome!(2)) }) => {
             JobDone(cjid!(2), Ok(outcome!(2))),
         };
         GetJobStateCounts(6) => {
@@ -829,7 +842,7 @@ mod tests {
             }),
         };
 
-        LocalWorker(WorkerToBroker(jid!(0, 1), Ok(outcome!(1)))) => {
+        LocalWorker(WorkerToBroker::JobResponse { jid: jid!(0, 1), result: Ok(outcome!(1)) }) => {
             JobDone(cjid!(1), Ok(outcome!(1))),
         };
         GetJobStateCounts(7) => {
@@ -841,7 +854,7 @@ mod tests {
             }),
         };
 
-        LocalWorker(WorkerToBroker(jid!(0, 0), Ok(outcome!(0)))) => {
+        LocalWorker(WorkerToBroker::JobResponse { jid: jid!(0, 0), result: Ok(outcome!(0)) }) => {
             JobDone(cjid!(0), Ok(outcome!(0))),
         };
         GetJobStateCounts(8) => {
@@ -924,7 +937,7 @@ mod tests {
             }),
         };
 
-        LocalWorker(WorkerToBroker(jid!(0, 0), Ok(outcome!(0)))) => {
+        LocalWorker(WorkerToBroker::JobResponse { jid: jid!(0, 0), result: Ok(outcome!(0)) }) => {
             JobDone(cjid!(0), Ok(outcome!(0))),
         };
         Broker(BrokerToClient::JobStateCountsResponse(enum_map! {
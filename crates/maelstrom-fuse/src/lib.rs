@@ -6,7 +6,9 @@ pub mod fuser;
 pub use fuser::{FileAttr, FileType};
 
 use anyhow::Result;
-use fuser::{MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry};
+use fuser::{
+    MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry, ReplyXattr,
+};
 use futures::stream::{Stream, StreamExt};
 use maelstrom_linux::{self as linux, Errno};
 use maelstrom_util::r#async::await_and_every_sec;
@@ -207,6 +209,12 @@ impl ErrorResponse for ReplyEmpty {
     }
 }
 
+impl ErrorResponse for ReplyXattr {
+    fn error(self, e: i32) -> impl Future<Output = ()> {
+        ReplyXattr::error(self, e)
+    }
+}
+
 trait Response {
     type Reply: ErrorResponse;
     fn send(self, reply: Self::Reply) -> impl Future<Output = ()>;
@@ -365,6 +373,40 @@ impl<FileSystemT: FuseFileSystem + Send + Sync + 'static> fuser::Filesystem
             drop(permit);
         });
     }
+
+    async fn getxattr(
+        &mut self,
+        req: &fuser::Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let handler = self.handler.clone();
+        let permit = self.sem.clone().acquire_owned().await.unwrap();
+        let request = req.into();
+        let name = name.to_owned();
+        tokio::task::spawn(async move {
+            handle_resp(handler.get_xattr(request, ino, &name, size).await, reply).await;
+            drop(permit);
+        });
+    }
+
+    async fn listxattr(
+        &mut self,
+        req: &fuser::Request<'_>,
+        ino: u64,
+        size: u32,
+        reply: ReplyXattr,
+    ) {
+        let handler = self.handler.clone();
+        let permit = self.sem.clone().acquire_owned().await.unwrap();
+        let request = req.into();
+        tokio::task::spawn(async move {
+            handle_resp(handler.list_xattr(request, ino, size).await, reply).await;
+            drop(permit);
+        });
+    }
 }
 
 /// Passed to all the [`FuseFileSystem`] request functions and contains information about who is
@@ -451,6 +493,40 @@ impl Response for ReadResponse {
 /// Response from a [`FuseFileSystem::read_link`] request
 pub type ReadLinkResponse = ReadResponse;
 
+/// Response from a [`FuseFileSystem::get_xattr`] or [`FuseFileSystem::list_xattr`] request
+#[derive(Debug)]
+pub enum XattrResponse {
+    /// The size of the value. Sent when the request's `size` is 0.
+    Size(u32),
+    Data(Vec<u8>),
+}
+
+impl XattrResponse {
+    /// Respond with `value` to a request for at most `size` bytes. A `size` of 0 asks for the size
+    /// of the value. A value that doesn't fit is an `ERANGE` error.
+    pub fn new(value: Vec<u8>, size: u32) -> ErrnoResult<Self> {
+        let len = u32::try_from(value.len()).map_err(|_| Errno::E2BIG)?;
+        if size == 0 {
+            Ok(Self::Size(len))
+        } else if len > size {
+            Err(Errno::ERANGE)
+        } else {
+            Ok(Self::Data(value))
+        }
+    }
+}
+
+impl Response for XattrResponse {
+    type Reply = ReplyXattr;
+
+    async fn send(self, reply: ReplyXattr) {
+        match self {
+            Self::Size(size) => reply.size(size).await,
+            Self::Data(data) => reply.data(&data).await,
+        }
+    }
+}
+
 impl Response for () {
     type Reply = ReplyEmpty;
 
@@ -593,4 +669,23 @@ pub trait FuseFileSystem {
     ) -> impl Future<Output = ErrnoResult<()>> + Send {
         async move { Err(Errno::ENOSYS) }
     }
+
+    fn get_xattr(
+        &self,
+        _req: Request,
+        _ino: u64,
+        _name: &OsStr,
+        _size: u32,
+    ) -> impl Future<Output = ErrnoResult<XattrResponse>> + Send {
+        async move { Err(Errno::ENOSYS) }
+    }
+
+    fn list_xattr(
+        &self,
+        _req: Request,
+        _ino: u64,
+        _size: u32,
+    ) -> impl Future<Output = ErrnoResult<XattrResponse>> + Send {
+        async move { Err(Errno::ENOSYS) }
+    }
 }
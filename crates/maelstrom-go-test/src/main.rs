@@ -9,7 +9,9 @@ pub fn main() -> Result<ExitCode> {
         "maelstrom/maelstrom-go-test",
         "MAELSTROM_GO_TEST",
         env::args(),
-        |extra_options: &ExtraCommandLineOptions| extra_options.list,
+        |extra_options: &ExtraCommandLineOptions| {
+            extra_options.list || extra_options.report_metadata
+        },
         |_| Ok(".".into()),
         maelstrom_go_test::MAELSTROM_TEST_TOML,
         "",
@@ -53,6 +53,7 @@ fn do_maelstrom_go_test_test(
             slots: Slots::default(),
             accept_invalid_remote_container_tls_certs: true.into(),
             ui: ui::UiKind::Simple,
+            test_listing_read_retries: 0,
         },
     };
     let term = InMemoryTerm::new(50, 50);
@@ -100,6 +101,7 @@ fn test_simple_success() {
                 ..Default::default()
             },
             list: false,
+            report_metadata: false,
         },
     );
     assert!(
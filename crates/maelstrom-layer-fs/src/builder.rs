@@ -2,7 +2,7 @@ use crate::dir::{DirectoryDataReader, DirectoryDataWriter, OrderedDirectoryStrea
 use crate::file::{FileDataInput, FileMetadataWriter};
 use crate::ty::{
     DirectoryEntryData, DirectoryEntryFileData, FileAttributes, FileId, FileType, LayerId,
-    LayerSuper,
+    LayerSuper, XattrsTable,
 };
 use crate::{BlobDir, LayerFs};
 use anyhow::bail;
@@ -66,6 +66,7 @@ pub struct BottomLayerBuilder<'fs> {
     file_writer: FileMetadataWriter,
     time: UnixTimestamp,
     dir_writer_cache: DirectoryDataWriterCache<'fs>,
+    xattrs: XattrsTable,
 }
 
 #[anyhow_trace]
@@ -115,6 +116,7 @@ impl<'fs> BottomLayerBuilder<'fs> {
             file_writer,
             time,
             dir_writer_cache: DirectoryDataWriterCache::new(data_fs),
+            xattrs: XattrsTable::default(),
         })
     }
 
@@ -498,6 +500,27 @@ impl<'fs> BottomLayerBuilder<'fs> {
         Ok(())
     }
 
+    /// Pair each chunk of a chunked file with its length, which we get from the chunk's blob. The
+    /// lengths must add up to the size recorded in the manifest.
+    async fn chunk_lengths(
+        &self,
+        path: &Utf8Path,
+        chunks: Vec<Sha256Digest>,
+        size: u64,
+    ) -> Result<Vec<(Sha256Digest, u64)>> {
+        let mut lengths = Vec::with_capacity(chunks.len());
+        for digest in chunks {
+            let blob = self.layer_fs.cache_entry(&digest);
+            let length = self.layer_fs.data_fs.metadata(&blob).await?.len();
+            lengths.push((digest, length));
+        }
+        let total: u64 = lengths.iter().map(|(_, length)| length).sum();
+        if total != size {
+            bail!("chunks of {path} add up to {total} bytes, but its size is {size}");
+        }
+        Ok(lengths)
+    }
+
     /// Add all of the entries from the given manifest data to the new layer.
     pub async fn add_from_manifest(
         &mut self,
@@ -512,12 +535,13 @@ impl<'fs> BottomLayerBuilder<'fs> {
                 mtime: entry.metadata.mtime,
             };
             let path = Utf8Path::new("/").join(&entry.path);
-            match entry.data {
+            let file_id = match entry.data {
                 ManifestEntryData::Directory { opaque } => {
-                    self.add_dir_path(&path, attrs).await?;
+                    let file_id = self.add_dir_path(&path, attrs).await?;
                     if opaque {
                         self.set_opaque_dir_path(&path).await?;
                     }
+                    file_id
                 }
                 ManifestEntryData::File(data) => {
                     let data = match &data {
@@ -528,18 +552,21 @@ impl<'fs> BottomLayerBuilder<'fs> {
                         },
                         ManifestFileData::Inline(data) => FileDataInput::Inline(data),
                         ManifestFileData::Empty => FileDataInput::Empty,
+                        ManifestFileData::ChunkedFile { chunks, size } => FileDataInput::Chunked(
+                            self.chunk_lengths(&path, chunks.clone(), *size).await?,
+                        ),
                     };
-                    self.add_file_path(&path, attrs, data).await?;
-                }
-                ManifestEntryData::Symlink(data) => {
-                    self.add_symlink_path(&path, data).await?;
-                }
-                ManifestEntryData::Hardlink(target) => {
-                    self.add_link_path(&path, &target).await?;
+                    self.add_file_path(&path, attrs, data).await?
                 }
+                ManifestEntryData::Symlink(data) => self.add_symlink_path(&path, data).await?,
+                ManifestEntryData::Hardlink(target) => self.add_link_path(&path, &target).await?,
                 ManifestEntryData::Whiteout => {
                     self.add_whiteout_path(&path).await?;
+                    continue;
                 }
+            };
+            if !entry.xattrs.is_empty() {
+                self.xattrs.0.insert(file_id.offset(), entry.xattrs);
             }
         }
 
@@ -551,6 +578,12 @@ impl<'fs> BottomLayerBuilder<'fs> {
     pub async fn finish(mut self) -> Result<LayerFs> {
         self.file_writer.flush().await?;
         self.dir_writer_cache.flush().await?;
+        if !self.xattrs.0.is_empty() {
+            let path = self.layer_fs.xattrs_table_path(LayerId::BOTTOM).await?;
+            self.xattrs
+                .write_to_path(&self.layer_fs.data_fs, &path)
+                .await?;
+        }
 
         Ok(self.layer_fs)
     }
@@ -793,6 +826,13 @@ impl<'fs> UpperLayerBuilder<'fs> {
         self.do_hard_link(&other_inline_data, &upper_inline_data)
             .await?;
 
+        let other_xattrs_table = other.xattrs_table_path(other_layer_id).await?;
+        if other.data_fs.exists(&other_xattrs_table).await {
+            let upper_xattrs_table = self.upper.xattrs_table_path(upper_layer_id).await?;
+            self.do_hard_link(&other_xattrs_table, &upper_xattrs_table)
+                .await?;
+        }
+
         Ok(())
     }
 
@@ -800,8 +840,8 @@ impl<'fs> UpperLayerBuilder<'fs> {
     ///
     /// This function only supports being called once per builder.
     ///
-    /// The file-table and attribute-table from the given bottom layer are hardlinked into the new
-    /// layer.
+    /// The file-table, attribute-table, and xattrs-table (if any) from the given bottom layer are
+    /// hardlinked into the new layer.
     ///
     /// The file-system from the given bottom layer is walked together with the file-system we are
     /// being stacked on top of. Anywhere they overlap we merge the directory contents together. If
@@ -119,6 +119,7 @@ pub enum FileDataInput<'a> {
         offset: u64,
         length: u64,
     },
+    Chunked(Vec<(Sha256Digest, u64)>),
 }
 
 #[anyhow_trace]
@@ -188,6 +189,7 @@ impl FileMetadataWriter {
                 offset,
                 length,
             },
+            FileDataInput::Chunked(chunks) => FileData::Chunked { chunks },
             FileDataInput::Inline(data) => {
                 let offset = self.inline_data.stream_position().await?;
                 self.inline_data.write_all(data).await?;
@@ -106,7 +106,7 @@ mod ty;
 pub use builder::*;
 pub use dir::DirectoryDataReader;
 pub use file::FileMetadataReader;
-pub use ty::{FileAttributes, FileData, FileId, FileType, LayerId, LayerSuper};
+pub use ty::{FileAttributes, FileData, FileId, FileType, LayerId, LayerSuper, XattrsTable};
 
 use anyhow::{anyhow, Result};
 use anyhow_trace::anyhow_trace;
@@ -115,7 +115,7 @@ use lru::LruCache;
 use maelstrom_base::Sha256Digest;
 use maelstrom_fuse::{
     AttrResponse, EntryResponse, ErrnoResult, FileAttr, FuseFileSystem, ReadLinkResponse,
-    ReadResponse, Request,
+    ReadResponse, Request, XattrResponse,
 };
 use maelstrom_linux::Errno;
 use maelstrom_util::{
@@ -123,7 +123,9 @@ use maelstrom_util::{
     root::{Root, RootBuf},
 };
 use std::{
+    collections::BTreeMap,
     ffi::OsStr,
+    os::unix::fs::FileExt as _,
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
@@ -271,6 +273,10 @@ impl LayerFs {
         Ok(self.data_path(layer_id).await?.join("inline_data.bin"))
     }
 
+    async fn xattrs_table_path(&self, layer_id: LayerId) -> Result<PathBuf> {
+        Ok(self.data_path(layer_id).await?.join("xattrs_table.bin"))
+    }
+
     async fn layer_super(&self) -> Result<LayerSuper> {
         Ok(self.layer_super.read(&self.data_fs).await?.clone())
     }
@@ -314,6 +320,7 @@ pub struct ReaderCache {
     dir_readers: LruCache<PathBuf, Arc<Mutex<DirectoryDataReader>>>,
     file_readers: LruCache<PathBuf, Arc<Mutex<FileMetadataReader>>>,
     data_files: LruCache<PathBuf, Arc<std::fs::File>>,
+    xattrs_tables: LruCache<PathBuf, Arc<XattrsTable>>,
 }
 
 impl Default for ReaderCache {
@@ -328,6 +335,7 @@ impl ReaderCache {
             dir_readers: LruCache::new(300.try_into().unwrap()),
             file_readers: LruCache::new(300.try_into().unwrap()),
             data_files: LruCache::new(200.try_into().unwrap()),
+            xattrs_tables: LruCache::new(100.try_into().unwrap()),
         }
     }
 
@@ -396,6 +404,21 @@ impl ReaderCache {
             Ok(file)
         }
     }
+
+    async fn xattrs_table(
+        &mut self,
+        layer_fs: &LayerFs,
+        layer_id: LayerId,
+    ) -> Result<Arc<XattrsTable>> {
+        let path = layer_fs.xattrs_table_path(layer_id).await?;
+        if let Some(table) = self.xattrs_tables.get(&path) {
+            Ok(table.clone())
+        } else {
+            let table = Arc::new(XattrsTable::read_from_path(&layer_fs.data_fs, &path).await?);
+            self.xattrs_tables.push(path, table.clone());
+            Ok(table)
+        }
+    }
 }
 
 struct LayerFsFuseAdapter {
@@ -436,6 +459,81 @@ impl LayerFsFuseAdapter {
             length: read_length as usize,
         })
     }
+
+    /// Read from a file made of chunks. A read that falls within one chunk is spliced from that
+    /// chunk's blob. A read that spans chunks is copied into a buffer.
+    async fn read_chunks(
+        &self,
+        chunks: Vec<(Sha256Digest, u64)>,
+        offset: i64,
+        size: u64,
+    ) -> ErrnoResult<ReadResponse> {
+        let read_start = to_einval::<u64, _>(self.log.clone(), offset.try_into())?;
+        let file_length: u64 = chunks.iter().map(|(_, length)| length).sum();
+        if read_start > file_length {
+            return Err(Errno::EINVAL);
+        }
+        let read_end = std::cmp::min(read_start + size, file_length);
+
+        let mut pieces = vec![];
+        let mut chunk_start = 0;
+        for (digest, length) in chunks {
+            let chunk_end = chunk_start + length;
+            if chunk_start < read_end && read_start < chunk_end {
+                let start = std::cmp::max(read_start, chunk_start) - chunk_start;
+                let end = std::cmp::min(read_end, chunk_end) - chunk_start;
+                pieces.push((digest, start, end - start));
+            }
+            chunk_start = chunk_end;
+        }
+
+        let mut files = vec![];
+        for (digest, start, length) in pieces {
+            let file = to_eio(
+                self.log.clone(),
+                self.cache
+                    .lock()
+                    .await
+                    .data_file(&self.layer_fs, &digest)
+                    .await,
+            )?;
+            files.push((file, start, length));
+        }
+
+        match <[_; 1]>::try_from(files) {
+            Ok([(file, offset, length)]) => Ok(ReadResponse::Splice {
+                file,
+                offset,
+                length: length as usize,
+            }),
+            Err(files) => {
+                let mut data = vec![0; (read_end - read_start) as usize];
+                let mut buf = &mut data[..];
+                for (file, offset, length) in files {
+                    let (piece, rest) = buf.split_at_mut(length as usize);
+                    to_eio(self.log.clone(), file.read_exact_at(piece, offset))?;
+                    buf = rest;
+                }
+                Ok(ReadResponse::Buffer { data })
+            }
+        }
+    }
+
+    async fn xattrs(&self, ino: u64) -> ErrnoResult<BTreeMap<String, Vec<u8>>> {
+        let mut file = to_einval(self.log.clone(), FileId::try_from(ino))?;
+        if file.is_root() {
+            file = to_eio(self.log.clone(), self.layer_fs.root().await)?;
+        }
+        let table = to_eio(
+            self.log.clone(),
+            self.cache
+                .lock()
+                .await
+                .xattrs_table(&self.layer_fs, file.layer())
+                .await,
+        )?;
+        Ok(table.0.get(&file.offset()).cloned().unwrap_or_default())
+    }
 }
 
 impl FuseFileSystem for LayerFsFuseAdapter {
@@ -545,6 +643,7 @@ impl FuseFileSystem for LayerFsFuseAdapter {
                 )?;
                 self.splice_file(file, file_offset, file_length, offset, size as u64)
             }
+            FileData::Chunked { chunks } => self.read_chunks(chunks, offset, size as u64).await,
         }
     }
 
@@ -619,13 +718,38 @@ impl FuseFileSystem for LayerFsFuseAdapter {
                 )?;
                 self.splice_file(file, offset, length, 0, length)
             }
-            FileData::Digest { .. } => Err(Errno::EIO),
+            FileData::Digest { .. } | FileData::Chunked { .. } => Err(Errno::EIO),
         }
     }
 
     async fn access(&self, _req: Request, _ino: u64, _mask: i32) -> ErrnoResult<()> {
         Ok(())
     }
+
+    async fn get_xattr(
+        &self,
+        _req: Request,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+    ) -> ErrnoResult<XattrResponse> {
+        let mut xattrs = self.xattrs(ino).await?;
+        let value = name
+            .to_str()
+            .and_then(|name| xattrs.remove(name))
+            .ok_or(Errno::ENODATA)?;
+        XattrResponse::new(value, size)
+    }
+
+    async fn list_xattr(&self, _req: Request, ino: u64, size: u32) -> ErrnoResult<XattrResponse> {
+        let names = self
+            .xattrs(ino)
+            .await?
+            .into_keys()
+            .flat_map(|name| name.into_bytes().into_iter().chain([0]))
+            .collect();
+        XattrResponse::new(names, size)
+    }
 }
 
 #[cfg(test)]
@@ -641,7 +765,9 @@ mod tests {
     };
     use maelstrom_util::manifest::AsyncManifestWriter;
     use std::collections::HashMap;
+    use std::ffi::CString;
     use std::future::Future;
+    use std::os::unix::ffi::OsStrExt as _;
     use std::os::unix::fs::MetadataExt as _;
     use std::pin::Pin;
 
@@ -667,6 +793,7 @@ mod tests {
     struct BuildEntry {
         path: String,
         data: BuildEntryData,
+        xattrs: BTreeMap<String, Vec<u8>>,
     }
 
     impl BuildEntry {
@@ -691,6 +818,10 @@ mod tests {
             )
         }
 
+        fn reg_chunked(path: impl Into<String>, chunks: Vec<(Sha256Digest, u64)>) -> Self {
+            Self::reg_mode(path, FileDataInput::Chunked(chunks), 0o555)
+        }
+
         fn reg_empty(path: impl Into<String>) -> Self {
             Self::reg_empty_mode(path, 0o555)
         }
@@ -704,6 +835,7 @@ mod tests {
                     mode,
                     opaque_dir: false,
                 },
+                xattrs: BTreeMap::new(),
             }
         }
 
@@ -716,6 +848,7 @@ mod tests {
                     mode,
                     opaque_dir: false,
                 },
+                xattrs: BTreeMap::new(),
             }
         }
 
@@ -736,6 +869,7 @@ mod tests {
                     mode,
                     opaque_dir: opaque,
                 },
+                xattrs: BTreeMap::new(),
             }
         }
 
@@ -746,6 +880,7 @@ mod tests {
                     target: target.into(),
                     hard: false,
                 },
+                xattrs: BTreeMap::new(),
             }
         }
 
@@ -756,6 +891,7 @@ mod tests {
                     target: target.into(),
                     hard: true,
                 },
+                xattrs: BTreeMap::new(),
             }
         }
 
@@ -763,9 +899,15 @@ mod tests {
             Self {
                 path: path.into(),
                 data: BuildEntryData::Whiteout,
+                xattrs: BTreeMap::new(),
             }
         }
 
+        fn xattr(mut self, name: &str, value: &[u8]) -> Self {
+            self.xattrs.insert(name.into(), value.into());
+            self
+        }
+
         fn from_str(s: &str) -> Self {
             if s.starts_with("wh:") {
                 Self::whiteout(&s[3..])
@@ -832,7 +974,7 @@ mod tests {
                 assert_eq!(Mode(attrs.mode() & 0o777), *mode);
             }
             if let Some(mtime) = &self.mtime {
-                assert_eq!(attrs.mtime(), (*mtime).into());
+                assert_eq!(attrs.mtime(), i64::from(*mtime));
             }
             if let Some(ino) = &self.ino {
                 assert_eq!(attrs.ino(), *ino);
@@ -840,6 +982,35 @@ mod tests {
         }
     }
 
+    async fn assert_xattrs(path: &Path, expected: Vec<(&str, &[u8])>) {
+        fn read_buffer(f: impl Fn(&mut [u8]) -> std::result::Result<usize, Errno>) -> Vec<u8> {
+            let mut buffer = vec![0; f(&mut []).unwrap()];
+            let len = f(&mut buffer).unwrap();
+            buffer.truncate(len);
+            buffer
+        }
+
+        let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let actual = tokio::task::spawn_blocking(move || {
+            read_buffer(|buf| maelstrom_linux::llistxattr(&path, buf))
+                .split(|b| *b == 0)
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    let name = CString::new(name).unwrap();
+                    let value = read_buffer(|buf| maelstrom_linux::lgetxattr(&path, &name, buf));
+                    (name.into_string().unwrap(), value)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .unwrap();
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
     async fn assert_contents(fs: &Fs, path: &Path, expected: &str) {
         let actual = fs.read_to_string(path).await.unwrap();
         assert_eq!(actual, expected);
@@ -852,6 +1023,7 @@ mod tests {
         Attrs(&'static str, ExpectedAttrs),
         Contents(&'static str, &'static str),
         IsSymlink(&'static str),
+        Xattrs(&'static str, Vec<(&'static str, &'static [u8])>),
     }
 
     async fn assert_expectations(fs: &Fs, root: &Path, expected: Vec<Expect>) {
@@ -866,6 +1038,7 @@ mod tests {
                     let sym_meta = fs.symlink_metadata(root.join(e)).await.unwrap();
                     assert!(sym_meta.is_symlink(), "/{e}");
                 }
+                Expect::Xattrs(e, xattrs) => assert_xattrs(&root.join(e), xattrs).await,
             }
         }
     }
@@ -936,7 +1109,7 @@ mod tests {
             let data_dir = self.new_data_dir().await;
             let mut builder = self.bottom_layer_builder(&data_dir).await;
 
-            for BuildEntry { path, data } in files {
+            for BuildEntry { path, data, .. } in files {
                 let size = match &data {
                     BuildEntryData::Regular {
                         data: FileDataInput::Inline(data),
@@ -1026,7 +1199,7 @@ mod tests {
             let tar_path = self.blob_dir.join::<BlobFile>("temp.tar");
             let f = self.fs.create_file(&tar_path).await.unwrap();
             let mut ar = tokio_tar::Builder::new(f.into_inner());
-            for BuildEntry { path, data } in files {
+            for BuildEntry { path, data, .. } in files {
                 let mut header = tokio_tar::Header::new_gnu();
                 match data {
                     BuildEntryData::Regular {
@@ -1099,7 +1272,7 @@ mod tests {
             let manifest_path = self.blob_dir.join::<BlobFile>("temp.manifest");
             let f = self.fs.create_file(&manifest_path).await.unwrap();
             let mut builder = AsyncManifestWriter::new(f).await.unwrap();
-            for BuildEntry { path, data } in files {
+            for BuildEntry { path, data, xattrs } in files {
                 let path: Utf8PathBuf = path.into();
                 match data {
                     BuildEntryData::Regular {
@@ -1112,6 +1285,9 @@ mod tests {
                             FileDataInput::Empty => 0,
                             FileDataInput::Inline(d) => d.len() as u64,
                             FileDataInput::Digest { length, .. } => *length,
+                            FileDataInput::Chunked(chunks) => {
+                                chunks.iter().map(|(_, length)| length).sum()
+                            }
                         };
                         let metadata = ManifestEntryMetadata {
                             size,
@@ -1124,6 +1300,7 @@ mod tests {
                                     path,
                                     metadata,
                                     data: ManifestEntryData::Directory { opaque: opaque_dir },
+                                    xattrs,
                                 })
                                 .await
                                 .unwrap(),
@@ -1135,12 +1312,22 @@ mod tests {
                                         assert_eq!(offset, 0);
                                         ManifestFileData::Digest(digest)
                                     }
+                                    FileDataInput::Chunked(chunks) => {
+                                        ManifestFileData::ChunkedFile {
+                                            chunks: chunks
+                                                .into_iter()
+                                                .map(|(digest, _)| digest)
+                                                .collect(),
+                                            size,
+                                        }
+                                    }
                                 };
                                 builder
                                     .write_entry(&ManifestEntry {
                                         path,
                                         metadata,
                                         data: ManifestEntryData::File(data),
+                                        xattrs,
                                     })
                                     .await
                                     .unwrap();
@@ -1159,6 +1346,7 @@ mod tests {
                                 path,
                                 metadata,
                                 data: ManifestEntryData::Hardlink(target.into()),
+                                xattrs,
                             })
                             .await
                             .unwrap();
@@ -1177,6 +1365,7 @@ mod tests {
                                 path,
                                 metadata,
                                 data: ManifestEntryData::Symlink(target.into()),
+                                xattrs,
                             })
                             .await
                             .unwrap();
@@ -1192,6 +1381,7 @@ mod tests {
                                 path,
                                 metadata,
                                 data: ManifestEntryData::Whiteout,
+                                xattrs,
                             })
                             .await
                             .unwrap();
@@ -1533,6 +1723,81 @@ mod tests {
         .await
     }
 
+    #[tokio::test]
+    async fn layer_from_manifest_with_chunked_file() {
+        let mut fix = Fixture::new().await;
+
+        let hello = fix.add_to_cache(b"hello").await;
+        let space = fix.add_to_cache(b" ").await;
+        let world = fix.add_to_cache(b"world").await;
+
+        let layer_fs = fix
+            .build_bottom_layer_from_manifest(vec![
+                BuildEntry::reg_chunked(
+                    "Foo",
+                    vec![(hello.clone(), 5), (space, 1), (world.clone(), 5)],
+                ),
+                BuildEntry::reg_chunked("Bar", vec![(world, 5)]),
+                BuildEntry::reg_chunked("Baz", vec![]),
+            ])
+            .await;
+
+        let mount_handle = fix.mount(layer_fs).await;
+        let mount_path = mount_handle.mount_path();
+
+        assert_expectations(
+            &fix.fs,
+            mount_path,
+            vec![
+                Expect::Contents("Foo", "hello world"),
+                Expect::Contents("Bar", "world"),
+                Expect::Contents("Baz", ""),
+            ],
+        )
+        .await;
+
+        mount_handle.umount_and_join().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn layer_from_manifest_with_xattrs() {
+        let mut fix = Fixture::new().await;
+
+        let layer_fs1 = fix
+            .build_bottom_layer_from_manifest(vec![
+                BuildEntry::reg("Foo", b"hello world")
+                    .xattr("user.a", b"1")
+                    .xattr("user.b", b"22"),
+                BuildEntry::dir("Bar").xattr("user.c", b""),
+                BuildEntry::reg_empty("Bar/Baz"),
+            ])
+            .await;
+        let layer_fs2 = fix
+            .build_bottom_layer_from_manifest(vec![
+                BuildEntry::reg_empty("Qux").xattr("user.d", &[0, 255])
+            ])
+            .await;
+        let layer_fs = fix.build_upper_layer(&layer_fs1, &layer_fs2).await;
+
+        let mount_handle = fix.mount(layer_fs).await;
+        let mount_path = mount_handle.mount_path();
+
+        assert_expectations(
+            &fix.fs,
+            mount_path,
+            vec![
+                Expect::Xattrs("Foo", vec![("user.a", b"1"), ("user.b", b"22")]),
+                Expect::Xattrs("Bar", vec![("user.c", b"")]),
+                Expect::Xattrs("Bar/Baz", vec![]),
+                Expect::Xattrs("Qux", vec![("user.d", &[0, 255])]),
+                Expect::Contents("Foo", "hello world"),
+            ],
+        )
+        .await;
+
+        mount_handle.umount_and_join().await.unwrap();
+    }
+
     #[cfg(test)]
     async fn layer_from_tar_or_manifest_with_whiteout_and_opaque_dir(
         mut populate_fn: impl for<'a> FnMut(
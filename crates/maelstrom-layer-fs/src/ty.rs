@@ -12,7 +12,7 @@ use maelstrom_util::async_fs::{Fs, GetPath};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     num::{NonZeroU32, NonZeroU64},
     path::{Path, PathBuf},
 };
@@ -62,6 +62,25 @@ impl LayerSuper {
     }
 }
 
+/// The data stored in `xattrs_table.bin` for a LayerFS layer: the extended attributes of the
+/// layer's files, keyed by file-table offset. Few files have any, so they aren't kept in the
+/// fixed-size attribute-table. A layer without the file has no extended attributes.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct XattrsTable(pub BTreeMap<NonZeroU32, BTreeMap<String, Vec<u8>>>);
+
+impl XattrsTable {
+    pub async fn read_from_path(fs: &Fs, path: &Path) -> Result<Self> {
+        if !fs.exists(path).await {
+            return Ok(Self::default());
+        }
+        decode(fs.open_file(path).await?).await
+    }
+
+    pub async fn write_to_path(&self, fs: &Fs, path: &Path) -> Result<()> {
+        encode(fs.create_file(path).await?, self).await
+    }
+}
+
 /// Identifies a LayerFS file. Basically a tuple of [`LayerId`] and file-table offset
 #[derive(Copy, Clone, Debug, Hash, Deserialize, Serialize, PartialEq, Eq)]
 pub struct FileId {
@@ -219,6 +238,10 @@ pub enum FileData {
         offset: u64,
         length: u64,
     },
+    /// The concatenation of whole artifacts, each paired with its length.
+    Chunked {
+        chunks: Vec<(Sha256Digest, u64)>,
+    },
 }
 
 /// What is stored in the file-table about each file.
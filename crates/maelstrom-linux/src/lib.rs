@@ -127,6 +127,31 @@ pub enum CloseRangeLast {
     Fd(Fd),
 }
 
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+pub struct CpuSet(libc::cpu_set_t);
+
+impl CpuSet {
+    /// One more than the largest CPU index a set can hold.
+    pub const SIZE: usize = libc::CPU_SETSIZE as usize;
+
+    pub fn empty() -> Self {
+        let mut inner: MaybeUninit<libc::cpu_set_t> = MaybeUninit::uninit();
+        unsafe { libc::CPU_ZERO(&mut *inner.as_mut_ptr()) };
+        Self(unsafe { inner.assume_init() })
+    }
+
+    /// Add `cpu` to the set. Panics if `cpu` isn't less than [`Self::SIZE`].
+    pub fn insert(&mut self, cpu: usize) {
+        assert!(cpu < Self::SIZE);
+        unsafe { libc::CPU_SET(cpu, &mut self.0) };
+    }
+
+    pub fn contains(&self, cpu: usize) -> bool {
+        cpu < Self::SIZE && unsafe { libc::CPU_ISSET(cpu, &self.0) }
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub struct Errno(c_int);
 
@@ -198,6 +223,8 @@ impl Errno {
     pub const EPIPE: Self = Self(libc::EPIPE);
     pub const EDOM: Self = Self(libc::EDOM);
     pub const ERANGE: Self = Self(libc::ERANGE);
+    pub const ENODATA: Self = Self(libc::ENODATA);
+    pub const EOPNOTSUPP: Self = Self(libc::EOPNOTSUPP);
     pub const EWOULDBLOCK: Self = Self::EAGAIN;
 }
 
@@ -1218,6 +1245,22 @@ pub fn kill(pid: Pid, signal: Signal) -> Result<(), Errno> {
     Errno::result(unsafe { libc::kill(pid.0, signal.0) }).map(drop)
 }
 
+pub fn lgetxattr(path: &CStr, name: &CStr, value: &mut [u8]) -> Result<usize, Errno> {
+    let path_ptr = path.as_ptr();
+    let name_ptr = name.as_ptr();
+    let value_ptr = value.as_mut_ptr() as *mut c_void;
+    let value_len = value.len();
+    Errno::result(unsafe { libc::lgetxattr(path_ptr, name_ptr, value_ptr, value_len) })
+        .map(|ret| ret as usize)
+}
+
+pub fn llistxattr(path: &CStr, list: &mut [u8]) -> Result<usize, Errno> {
+    let path_ptr = path.as_ptr();
+    let list_ptr = list.as_mut_ptr() as *mut c_char;
+    let list_len = list.len();
+    Errno::result(unsafe { libc::llistxattr(path_ptr, list_ptr, list_len) }).map(|ret| ret as usize)
+}
+
 pub fn listen(fd: &impl AsFd, backlog: u32) -> Result<(), Errno> {
     let fd = fd.fd();
     Errno::result(unsafe { libc::listen(fd.0, backlog as c_int) }).map(drop)
@@ -1367,10 +1410,34 @@ pub fn read(fd: &impl AsFd, buf: &mut [u8]) -> Result<usize, Errno> {
     Errno::result(unsafe { libc::read(fd.0, buf_ptr, buf_len) }).map(|ret| ret as usize)
 }
 
+/// Get the CPU affinity of the calling thread.
+pub fn sched_getaffinity() -> Result<CpuSet, Errno> {
+    let mut cpu_set = CpuSet::empty();
+    let cpu_set_ptr = &mut cpu_set.0 as *mut libc::cpu_set_t;
+    let cpu_set_size = mem::size_of::<libc::cpu_set_t>();
+    Errno::result(unsafe { libc::sched_getaffinity(0, cpu_set_size, cpu_set_ptr) })?;
+    Ok(cpu_set)
+}
+
+/// Set the CPU affinity of the calling thread.
+pub fn sched_setaffinity(cpu_set: &CpuSet) -> Result<(), Errno> {
+    let cpu_set_ptr = &cpu_set.0 as *const libc::cpu_set_t;
+    let cpu_set_size = mem::size_of::<libc::cpu_set_t>();
+    Errno::result(unsafe { libc::sched_setaffinity(0, cpu_set_size, cpu_set_ptr) }).map(drop)
+}
+
 pub fn setsid() -> Result<(), Errno> {
     Errno::result(unsafe { libc::setsid() }).map(drop)
 }
 
+pub fn setxattr(path: &CStr, name: &CStr, value: &[u8]) -> Result<(), Errno> {
+    let path_ptr = path.as_ptr();
+    let name_ptr = name.as_ptr();
+    let value_ptr = value.as_ptr() as *const c_void;
+    let value_len = value.len();
+    Errno::result(unsafe { libc::setxattr(path_ptr, name_ptr, value_ptr, value_len, 0) }).map(drop)
+}
+
 pub fn sigprocmask(how: SigprocmaskHow, set: Option<&SignalSet>) -> Result<SignalSet, Errno> {
     let set: *const sigset_t = set.map(|s| &s.0 as *const sigset_t).unwrap_or(ptr::null());
     let mut oldset: MaybeUninit<sigset_t> = MaybeUninit::uninit();
@@ -1703,6 +1770,37 @@ mod tests {
         assert_eq!(std::format!("{:?}", Errno(1234)).as_str(), "UNKNOWN(1234)");
     }
 
+    #[test]
+    fn cpu_set_insert_and_contains() {
+        let mut cpu_set = CpuSet::empty();
+        assert!(!cpu_set.contains(0));
+        cpu_set.insert(0);
+        cpu_set.insert(CpuSet::SIZE - 1);
+        assert!(cpu_set.contains(0));
+        assert!(!cpu_set.contains(1));
+        assert!(cpu_set.contains(CpuSet::SIZE - 1));
+        assert!(!cpu_set.contains(CpuSet::SIZE));
+    }
+
+    #[test]
+    fn sched_setaffinity_round_trips() {
+        let allowed = sched_getaffinity().unwrap();
+        let cpu = (0..CpuSet::SIZE)
+            .find(|&cpu| allowed.contains(cpu))
+            .unwrap();
+        let mut cpu_set = CpuSet::empty();
+        cpu_set.insert(cpu);
+
+        let handle = std::thread::spawn(move || {
+            sched_setaffinity(&cpu_set).unwrap();
+            let actual = sched_getaffinity().unwrap();
+            (0..CpuSet::SIZE)
+                .filter(|&cpu| actual.contains(cpu))
+                .collect::<std::vec::Vec<_>>()
+        });
+        assert_eq!(handle.join().unwrap(), [cpu]);
+    }
+
     #[test]
     fn unix_stream_send_recv() {
         let (a, b) = UnixStream::pair().unwrap();
@@ -12,6 +12,15 @@ pub struct ExtraCommandLineOptions {
         help_heading = "List Options"
     )]
     pub list: bool,
+
+    #[arg(
+        long = "report-metadata",
+        conflicts_with = "list",
+        help = "Instead of running tests, print the metadata that would have been used for each \
+            test as a line of JSON.",
+        help_heading = "List Options"
+    )]
+    pub report_metadata: bool,
 }
 
 impl AsRef<maelstrom_test_runner::config::ExtraCommandLineOptions> for ExtraCommandLineOptions {
@@ -15,8 +15,12 @@ use maelstrom_client::{
 use maelstrom_container::{DockerReference, ImageName};
 use maelstrom_macro::Config;
 use maelstrom_test_runner::{
-    metadata::TestMetadata, run_app_with_ui_multithreaded, ui::Ui, ui::UiSender, BuildDir,
-    CollectTests, ListAction, LoggingOutput, MainAppDeps, MainAppState, TestArtifact,
+    golden::{FsGoldenStore, GoldenFiles},
+    metadata::TestMetadata,
+    run_app_with_ui_multithreaded,
+    ui::Ui,
+    ui::UiSender,
+    BuildDir, CollectTests, ListAction, LoggingOutput, MainAppDeps, MainAppState, TestArtifact,
     TestArtifactKey, TestCaseMetadata, TestFilter, TestLayers, TestPackage, TestPackageId, Wait,
 };
 use maelstrom_util::{
@@ -185,6 +189,7 @@ impl TestFilter for pattern::Pattern {
     }
 }
 
+#[derive(Debug)]
 struct PytestOptions;
 
 struct PytestTestCollector<'client> {
@@ -638,7 +643,11 @@ pub fn main_with_stderr_and_project_dir(
     let logging_output = LoggingOutput::default();
     let log = logger.build(logging_output.clone());
 
-    let list_action = extra_options.list.then_some(ListAction::ListTests);
+    let list_action = if extra_options.report_metadata {
+        Some(ListAction::ReportMetadata)
+    } else {
+        extra_options.list.then_some(ListAction::ListTests)
+    };
     let build_dir = AsRef::<Path>::as_ref(project_dir).join(".maelstrom-pytest");
     let build_dir = Root::<BuildDir>::new(&build_dir);
     let state_dir = build_dir.join::<StateDir>("state");
@@ -678,15 +687,33 @@ pub fn main_with_stderr_and_project_dir(
         deps,
         extra_options.parent.include,
         extra_options.parent.exclude,
+        extra_options.parent.shard,
         list_action,
         stderr_is_tty,
         project_dir,
         &packages,
         &state_dir,
+        config.parent.test_listing_read_retries,
         PytestOptions,
         logging_output,
         log,
-    )?;
+    )?
+    .include_quarantined(extra_options.parent.include_quarantined)
+    .junit_output(extra_options.parent.junit_output)
+    .golden_files(extra_options.parent.golden_dir.map(|dir| {
+        GoldenFiles::new(FsGoldenStore::new(dir))
+            .create_missing(extra_options.parent.create_missing_golden_files)
+    }))
+    .retries(extra_options.parent.retries)
+    .ordering(extra_options.parent.order)
+    .max_concurrent_packages(extra_options.parent.max_concurrent_packages)
+    .package_timeouts(
+        extra_options
+            .parent
+            .timeout_per_package
+            .into_iter()
+            .map(|package_timeout| (package_timeout.package, package_timeout.timeout)),
+    );
 
     let res = run_app_with_ui_multithreaded(state, config.parent.timeout.map(Timeout::new), ui);
     maybe_print_collect_error(&mut stderr, res)
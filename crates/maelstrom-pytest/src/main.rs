@@ -10,7 +10,9 @@ pub fn main() -> Result<ExitCode> {
         "maelstrom/maelstrom-pytest",
         "MAELSTROM_PYTEST",
         env::args(),
-        |extra_options: &ExtraCommandLineOptions| extra_options.list,
+        |extra_options: &ExtraCommandLineOptions| {
+            extra_options.list || extra_options.report_metadata
+        },
         |_| -> Result<Utf8PathBuf> { Ok(".".into()) },
         maelstrom_pytest::MAELSTROM_TEST_TOML,
         "",
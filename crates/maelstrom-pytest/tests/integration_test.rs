@@ -107,6 +107,7 @@ fn do_maelstrom_pytest_test(
             slots: Slots::default(),
             accept_invalid_remote_container_tls_certs: true.into(),
             ui: ui::UiKind::Simple,
+            test_listing_read_retries: 0,
         },
         pytest_options: Default::default(),
     };
@@ -159,6 +160,7 @@ fn test_simple_success() {
                 ..Default::default()
             },
             list: false,
+            report_metadata: false,
         },
     );
     assert!(
@@ -190,6 +192,7 @@ fn test_simple_failure() {
                 ..Default::default()
             },
             list: false,
+            report_metadata: false,
         },
     );
     assert_eq!(stderr, "");
@@ -233,6 +236,7 @@ fn test_collection_failure() {
                 ..Default::default()
             },
             list: false,
+            report_metadata: false,
         },
     );
     assert_eq!(contents, "");
@@ -269,6 +273,7 @@ fn test_listing_all() {
                 ..Default::default()
             },
             list: true,
+            report_metadata: false,
         },
     );
 
@@ -298,6 +303,7 @@ fn test_listing_node_id() {
                 ..Default::default()
             },
             list: true,
+            report_metadata: false,
         },
     );
 
@@ -329,6 +335,7 @@ fn test_listing_marker() {
                 ..Default::default()
             },
             list: true,
+            report_metadata: false,
         },
     );
 
@@ -365,6 +372,7 @@ fn test_ignore() {
                 ..Default::default()
             },
             list: false,
+            report_metadata: false,
         },
     );
 
@@ -1,2 +1,3 @@
 pub mod escape;
 pub mod spec;
+pub mod submit;
@@ -184,6 +184,7 @@ fn print_effects(
         stdout,
         stderr,
         duration: _,
+        worker: _,
     }: JobEffects,
 ) -> Result<()> {
     match stdout {
@@ -191,6 +192,16 @@ fn print_effects(
         JobOutputResult::Inline(bytes) => {
             io::stdout().lock().write_all(&bytes)?;
         }
+        JobOutputResult::InlineCompressed { .. } => match stdout.inline_bytes() {
+            Some(bytes) => io::stdout().lock().write_all(&bytes)?,
+            None => {
+                if let Some(cjid) = cjid {
+                    eprintln!("job {cjid}: stdout {stdout}");
+                } else {
+                    eprintln!("stdout {stdout}");
+                }
+            }
+        },
         JobOutputResult::Truncated { first, truncated } => {
             io::stdout().lock().write_all(&first)?;
             io::stdout().lock().flush()?;
@@ -200,12 +211,35 @@ fn print_effects(
                 eprintln!("stdout truncated, {truncated} bytes lost");
             }
         }
+        JobOutputResult::External {
+            first,
+            digest,
+            size,
+        } => {
+            io::stdout().lock().write_all(&first)?;
+            io::stdout().lock().flush()?;
+            if let Some(cjid) = cjid {
+                eprintln!("job {cjid}: stdout stored externally, {size} bytes, {digest}");
+            } else {
+                eprintln!("stdout stored externally, {size} bytes, {digest}");
+            }
+        }
     }
     match stderr {
         JobOutputResult::None => {}
         JobOutputResult::Inline(bytes) => {
             io::stderr().lock().write_all(&bytes)?;
         }
+        JobOutputResult::InlineCompressed { .. } => match stderr.inline_bytes() {
+            Some(bytes) => io::stderr().lock().write_all(&bytes)?,
+            None => {
+                if let Some(cjid) = cjid {
+                    eprintln!("job {cjid}: stderr {stderr}");
+                } else {
+                    eprintln!("stderr {stderr}");
+                }
+            }
+        },
         JobOutputResult::Truncated { first, truncated } => {
             io::stderr().lock().write_all(&first)?;
             if let Some(cjid) = cjid {
@@ -214,6 +248,18 @@ fn print_effects(
                 eprintln!("stderr truncated, {truncated} bytes lost");
             }
         }
+        JobOutputResult::External {
+            first,
+            digest,
+            size,
+        } => {
+            io::stderr().lock().write_all(&first)?;
+            if let Some(cjid) = cjid {
+                eprintln!("job {cjid}: stderr stored externally, {size} bytes, {digest}");
+            } else {
+                eprintln!("stderr stored externally, {size} bytes, {digest}");
+            }
+        }
     }
     Ok(())
 }
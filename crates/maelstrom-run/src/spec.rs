@@ -144,6 +144,15 @@ impl Job {
             timeout: self.timeout.and_then(Timeout::new),
             estimated_duration: None,
             allocate_tty: None,
+            oom_score_adj: None,
+            cacheable: false,
+            trace_id: None,
+            display_name: None,
+            cpu_set: None,
+            readonly_root: false,
+            depends_on: vec![],
+            priority: 0,
+            memory_limit: None,
         })
     }
 }
@@ -954,16 +963,19 @@ mod tests {
                         mount_point: utf8_path_buf!("/bind"),
                         local_path: utf8_path_buf!("/a"),
                         read_only: false,
+                        security_label: None,
                     },
                     JobMount::Bind {
                         mount_point: utf8_path_buf!("/bind2"),
                         local_path: utf8_path_buf!("/b"),
                         read_only: false,
+                        security_label: None,
                     },
                     JobMount::Bind {
                         mount_point: utf8_path_buf!("/bind3"),
                         local_path: utf8_path_buf!("/c"),
                         read_only: true,
+                        security_label: None,
                     },
                 ])
         )
@@ -0,0 +1,211 @@
+use anyhow::Result;
+use maelstrom_base::{ClientJobId, JobOutcomeResult};
+use maelstrom_client::{spec::JobSpec, Client};
+use std::{
+    iter::Fuse,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+/// Something jobs can be submitted to. This is implemented by [`Client`], and exists so that
+/// [`submit_jobs`] can be used with other implementations.
+pub trait SubmitJob {
+    fn add_job(
+        &self,
+        spec: JobSpec,
+        handler: impl FnOnce(Result<(ClientJobId, JobOutcomeResult)>) + Send + Sync + 'static,
+    ) -> Result<()>;
+}
+
+impl SubmitJob for Client {
+    fn add_job(
+        &self,
+        spec: JobSpec,
+        handler: impl FnOnce(Result<(ClientJobId, JobOutcomeResult)>) + Send + Sync + 'static,
+    ) -> Result<()> {
+        Client::add_job(self, spec, handler)
+    }
+}
+
+type JobResult = (JobSpec, Result<(ClientJobId, JobOutcomeResult)>);
+
+/// Submit the jobs from `specs` to `client`, never having more than `max_in_flight` of them
+/// outstanding at once. A `max_in_flight` of zero is treated as one.
+///
+/// Specs are only pulled from `specs` when there is room for another job, so `specs` can be lazy,
+/// like the iterator returned by [`crate::spec::job_spec_iter_from_reader`]. Results are yielded in
+/// the order the jobs complete. Errors reading a spec, submitting a job, or running a job are
+/// yielded in place of a result, and don't stop the remaining jobs from being submitted.
+pub fn submit_jobs<ClientT, SpecsT>(
+    client: &ClientT,
+    specs: SpecsT,
+    max_in_flight: usize,
+) -> SubmitJobs<'_, ClientT, SpecsT::IntoIter>
+where
+    ClientT: SubmitJob,
+    SpecsT: IntoIterator<Item = Result<JobSpec>>,
+{
+    let (sender, receiver) = mpsc::channel();
+    SubmitJobs {
+        client,
+        specs: specs.into_iter().fuse(),
+        max_in_flight: max_in_flight.max(1),
+        in_flight: 0,
+        sender,
+        receiver,
+    }
+}
+
+/// The iterator returned by [`submit_jobs`].
+pub struct SubmitJobs<'client, ClientT, SpecsT> {
+    client: &'client ClientT,
+    specs: Fuse<SpecsT>,
+    max_in_flight: usize,
+    in_flight: usize,
+    sender: Sender<JobResult>,
+    receiver: Receiver<JobResult>,
+}
+
+impl<ClientT, SpecsT> Iterator for SubmitJobs<'_, ClientT, SpecsT>
+where
+    ClientT: SubmitJob,
+    SpecsT: Iterator<Item = Result<JobSpec>>,
+{
+    type Item = Result<(JobSpec, JobOutcomeResult)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.in_flight < self.max_in_flight {
+            let Some(spec) = self.specs.next() else {
+                break;
+            };
+            let spec = match spec {
+                Ok(spec) => spec,
+                Err(err) => return Some(Err(err)),
+            };
+            let sender = self.sender.clone();
+            let handler_spec = spec.clone();
+            let handler = move |res| {
+                let _ = sender.send((handler_spec, res));
+            };
+            if let Err(err) = self.client.add_job(spec, handler) {
+                return Some(Err(err));
+            }
+            self.in_flight += 1;
+        }
+
+        if self.in_flight == 0 {
+            return None;
+        }
+        let (spec, res) = self
+            .receiver
+            .recv()
+            .expect("we hold a sender, so the channel can't be disconnected");
+        self.in_flight -= 1;
+        Some(res.map(|(_, outcome)| (spec, outcome)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use maelstrom_base::{JobCompleted, JobEffects, JobOutcome, JobOutputResult, JobStatus};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    #[derive(Default)]
+    struct MockClient {
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+        submitted: AtomicUsize,
+    }
+
+    impl SubmitJob for MockClient {
+        fn add_job(
+            &self,
+            spec: JobSpec,
+            handler: impl FnOnce(Result<(ClientJobId, JobOutcomeResult)>) + Send + Sync + 'static,
+        ) -> Result<()> {
+            if spec.program == "reject" {
+                return Err(anyhow!("rejected"));
+            }
+            let cjid = ClientJobId::from_u32(self.submitted.fetch_add(1, Ordering::SeqCst) as u32);
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            let in_flight = self.in_flight.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(10));
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                handler(Ok((cjid, Ok(outcome()))));
+            });
+            Ok(())
+        }
+    }
+
+    fn outcome() -> JobOutcome {
+        JobOutcome::Completed(JobCompleted {
+            status: JobStatus::Exited(0),
+            effects: JobEffects {
+                stdout: JobOutputResult::None,
+                stderr: JobOutputResult::None,
+                duration: Duration::from_secs(1),
+                worker: None,
+            },
+        })
+    }
+
+    fn spec(n: usize) -> JobSpec {
+        JobSpec::new(format!("job_{n}"), vec![])
+    }
+
+    #[test]
+    fn in_flight_cap_is_respected() {
+        let client = MockClient::default();
+        let mut results = Vec::from_iter(
+            submit_jobs(&client, (0..10).map(|n| Ok(spec(n))), 3).map(Result::unwrap),
+        );
+        results.sort();
+        assert_eq!(
+            results,
+            Vec::from_iter((0..10).map(|n| (spec(n), Ok(outcome()))))
+        );
+        assert_eq!(client.submitted.load(Ordering::SeqCst), 10);
+        assert!(client.max_in_flight.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn zero_max_in_flight_is_treated_as_one() {
+        let client = MockClient::default();
+        assert_eq!(
+            submit_jobs(&client, (0..3).map(|n| Ok(spec(n))), 0).count(),
+            3
+        );
+        assert_eq!(client.max_in_flight.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn no_specs() {
+        let client = MockClient::default();
+        assert_eq!(submit_jobs(&client, [], 3).count(), 0);
+    }
+
+    #[test]
+    fn errors_are_yielded_and_other_jobs_still_run() {
+        let client = MockClient::default();
+        let specs = [
+            Ok(spec(0)),
+            Err(anyhow!("bad spec")),
+            Ok(JobSpec::new("reject", vec![])),
+            Ok(spec(1)),
+        ];
+        let results = Vec::from_iter(submit_jobs(&client, specs, 1));
+        assert_eq!(results.len(), 4);
+        assert_eq!(results.iter().filter(|res| res.is_err()).count(), 2);
+        assert_eq!(client.submitted.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,6 +1,8 @@
-use crate::ui::UiKind;
+use crate::{shard::Shard, ui::UiKind, TestOrdering};
+use anyhow::{anyhow, Error};
 use clap::{command, Args};
 use derive_more::From;
+use maelstrom_base::Timeout;
 use maelstrom_client::{AcceptInvalidRemoteContainerTlsCerts, ContainerImageDepotDir};
 use maelstrom_macro::Config;
 use maelstrom_util::{
@@ -10,7 +12,10 @@ use maelstrom_util::{
 use serde::Deserialize;
 use std::{
     fmt::{self, Debug, Formatter},
+    num::NonZeroUsize,
+    path::PathBuf,
     result,
+    str::FromStr,
 };
 use xdg::BaseDirectories;
 
@@ -30,6 +35,30 @@ impl Debug for Quiet {
     }
 }
 
+/// A timeout that overrides the timeout of every test in a package.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PackageTimeout {
+    pub package: String,
+    /// `None` means the tests have no timeout.
+    pub timeout: Option<Timeout>,
+}
+
+/// Parses package timeouts of the form `PACKAGE=SECONDS`, like `foo=60`. Zero seconds means no
+/// timeout.
+impl FromStr for PackageTimeout {
+    type Err = Error;
+
+    fn from_str(s: &str) -> result::Result<Self, Error> {
+        let (package, seconds) = s
+            .rsplit_once('=')
+            .ok_or_else(|| anyhow!("package timeout {s:?} isn't of the form PACKAGE=SECONDS"))?;
+        Ok(Self {
+            package: package.into(),
+            timeout: Timeout::new(seconds.parse()?),
+        })
+    }
+}
+
 #[derive(Config, Debug)]
 pub struct Config {
     /// The target amount of disk space to use for the cache. This bound won't be followed
@@ -91,6 +120,11 @@ pub struct Config {
     #[config(value_name = "UI_KIND", default = "UiKind::Simple")]
     pub ui: UiKind,
 
+    /// How many times to retry reading the saved test listing if the read fails with an I/O
+    /// error. This papers over transient errors on network filesystems.
+    #[config(value_name = "N", default = "3")]
+    pub test_listing_read_retries: u32,
+
     /// Override timeout value for all tests specified (O indicates no timeout).
     #[config(
         option,
@@ -125,6 +159,84 @@ pub struct ExtraCommandLineOptions {
     )]
     pub exclude: Vec<String>,
 
+    #[arg(
+        long,
+        help = "Also include tests that are marked as quarantined in the test metadata.",
+        help_heading = "Test Selection Options"
+    )]
+    pub include_quarantined: bool,
+
+    #[arg(
+        long,
+        value_name = "INDEX/COUNT",
+        help = "Only run the tests in the given shard. Tests are split into COUNT disjoint shards, \
+            numbered from 0.",
+        help_heading = "Test Selection Options"
+    )]
+    pub shard: Option<Shard>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Write the results of the tests to the given path as JUnit XML.",
+        help_heading = "Test Output Options"
+    )]
+    pub junit_output: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Compare the stdout of each passing test against its golden file in the given \
+            directory, failing the test if they differ.",
+        help_heading = "Test Output Options"
+    )]
+    pub golden_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        requires = "golden_dir",
+        help = "Store the stdout of each passing test that doesn't have a golden file as its \
+            golden file.",
+        help_heading = "Test Output Options"
+    )]
+    pub create_missing_golden_files: bool,
+
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        help = "Run a failed test up to N more times. Tests that pass on a retry are reported as flaky.",
+        help_heading = "Test Execution Options"
+    )]
+    pub retries: u32,
+
+    #[arg(
+        long,
+        value_name = "ORDER",
+        value_enum,
+        default_value_t,
+        help = "The order in which to run the tests of each test binary.",
+        help_heading = "Test Execution Options"
+    )]
+    pub order: TestOrdering,
+
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Build and enqueue the tests of at most N packages at a time.",
+        help_heading = "Test Execution Options"
+    )]
+    pub max_concurrent_packages: Option<NonZeroUsize>,
+
+    #[arg(
+        long,
+        value_name = "PACKAGE=SECONDS",
+        help = "Override the timeout of the tests in the given package (0 indicates no timeout). \
+            Takes precedence over --timeout. Can be specified multiple times.",
+        help_heading = "Test Override Config Options"
+    )]
+    pub timeout_per_package: Vec<PackageTimeout>,
+
     #[arg(
         long,
         help = "Write out a starter test metadata file if one does not exist, then exit.",
@@ -164,7 +164,10 @@ pub trait CollectTests {
     type ArtifactStream: Iterator<Item = Result<Self::Artifact>>;
     type CaseMetadata: TestCaseMetadata;
 
-    type Options;
+    /// Options that control how tests are collected. The [`Debug`] representation is used as part
+    /// of the environment fingerprint, so it should include everything that could affect the
+    /// tests' timings.
+    type Options: fmt::Debug;
     fn start(
         &self,
         color: bool,
@@ -308,3 +311,11 @@ pub trait MainAppDeps: Sync {
 
     const MAELSTROM_TEST_TOML: &'static str;
 }
+
+/// A mapping from files to the packages whose tests could be affected when those files change.
+/// This is provided by the caller so that the test runner doesn't need to know how any particular
+/// language or build system relates files to packages.
+pub trait AffectedPackages {
+    /// Return the names of the packages that could be affected by a change to `path`.
+    fn packages_affected_by(&self, path: &Path) -> Vec<String>;
+}
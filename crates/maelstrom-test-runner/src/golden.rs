@@ -0,0 +1,132 @@
+//! Comparing the stdout of test cases against stored "golden" files.
+
+use anyhow::Result;
+use maelstrom_util::fs::Fs;
+use std::{path::PathBuf, sync::Arc};
+
+/// Where the expected stdout of each test case is stored.
+pub trait GoldenStore: Send + Sync {
+    /// Return the expected stdout of the case, or `None` if the case doesn't have a golden file.
+    fn read(&self, package: &str, artifact: &str, case: &str) -> Result<Option<String>>;
+
+    /// Store `stdout` as the expected stdout of the case.
+    fn write(&self, package: &str, artifact: &str, case: &str, stdout: &str) -> Result<()>;
+}
+
+/// A [`GoldenStore`] that keeps each case's golden file at
+/// `<dir>/<package>/<artifact>/<case>.stdout`. The artifact is included because cases with the
+/// same name can come from different artifacts of a package. Any slashes in the artifact or case
+/// name are replaced with underscores.
+pub struct FsGoldenStore {
+    fs: Fs,
+    dir: PathBuf,
+}
+
+impl FsGoldenStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            fs: Fs::new(),
+            dir: dir.into(),
+        }
+    }
+
+    fn path(&self, package: &str, artifact: &str, case: &str) -> PathBuf {
+        self.dir
+            .join(package)
+            .join(artifact.replace('/', "_"))
+            .join(format!("{}.stdout", case.replace('/', "_")))
+    }
+}
+
+impl GoldenStore for FsGoldenStore {
+    fn read(&self, package: &str, artifact: &str, case: &str) -> Result<Option<String>> {
+        self.fs
+            .read_to_string_if_exists(self.path(package, artifact, case))
+    }
+
+    fn write(&self, package: &str, artifact: &str, case: &str, stdout: &str) -> Result<()> {
+        let path = self.path(package, artifact, case);
+        self.fs.create_dir_all(path.parent().unwrap())?;
+        self.fs.write(path, stdout)
+    }
+}
+
+/// How test cases' stdout is compared against golden files. Only cases that pass are compared. A
+/// passing case whose stdout doesn't match its golden file is reported as a failure.
+#[derive(Clone)]
+pub struct GoldenFiles {
+    store: Arc<dyn GoldenStore>,
+    create_missing: bool,
+}
+
+impl GoldenFiles {
+    pub fn new(store: impl GoldenStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            create_missing: false,
+        }
+    }
+
+    /// Store the stdout of a passing case that doesn't have a golden file as its golden file. By
+    /// default, cases without golden files aren't compared against anything.
+    pub fn create_missing(mut self, create_missing: bool) -> Self {
+        self.create_missing = create_missing;
+        self
+    }
+
+    /// Compare `stdout` against the case's golden file. If they don't match, return a colored diff
+    /// of the expected and actual output, one line per element.
+    pub fn check(
+        &self,
+        package: &str,
+        artifact: &str,
+        case: &str,
+        stdout: &str,
+    ) -> Result<Option<Vec<String>>> {
+        let Some(expected) = self.store.read(package, artifact, case)? else {
+            if self.create_missing {
+                self.store.write(package, artifact, case, stdout)?;
+            }
+            return Ok(None);
+        };
+        if expected == stdout {
+            return Ok(None);
+        }
+        let diff = colored_diff::PrettyDifference {
+            expected: &expected,
+            actual: stdout,
+        };
+        Ok(Some(
+            diff.to_string().lines().map(ToOwned::to_owned).collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn fs_golden_store_keeps_artifacts_apart() {
+        let dir = tempdir().unwrap();
+        let store = FsGoldenStore::new(dir.path());
+        store
+            .write("foo", "foo.library", "tests::a", "library\n")
+            .unwrap();
+        store
+            .write("foo", "foo/bin", "tests::a", "binary\n")
+            .unwrap();
+
+        assert_eq!(
+            store.read("foo", "foo.library", "tests::a").unwrap(),
+            Some("library\n".into())
+        );
+        assert_eq!(
+            store.read("foo", "foo/bin", "tests::a").unwrap(),
+            Some("binary\n".into())
+        );
+        assert_eq!(store.read("foo", "foo.other", "tests::a").unwrap(), None);
+        assert!(dir.path().join("foo/foo_bin/tests::a.stdout").exists());
+    }
+}
@@ -0,0 +1,271 @@
+//! Reporting test results as JUnit XML, for consumption by CI systems.
+
+use crate::ui::UiJobStatus;
+use std::{collections::BTreeMap, fmt::Write as _, sync::Mutex, time::Duration};
+
+/// How a test case turned out, in JUnit terms.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum JUnitOutcome {
+    Passed,
+    /// The test ran and failed, possibly with a reason.
+    Failed(Option<String>),
+    /// The test couldn't be run, or its result couldn't be determined.
+    Errored(String),
+    Skipped,
+}
+
+impl From<&UiJobStatus> for JUnitOutcome {
+    fn from(status: &UiJobStatus) -> Self {
+        match status {
+            UiJobStatus::Ok => Self::Passed,
+            UiJobStatus::Failure(reason) => Self::Failed(reason.clone()),
+            UiJobStatus::TimedOut => Self::Failed(Some("timed out".into())),
+            UiJobStatus::Error(message) => Self::Errored(message.clone()),
+            UiJobStatus::Ignored => Self::Skipped,
+        }
+    }
+}
+
+/// The result of one test case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JUnitCase {
+    pub name: String,
+    pub outcome: JUnitOutcome,
+    pub duration: Option<Duration>,
+    pub stdout: Vec<String>,
+    pub stderr: Vec<String>,
+}
+
+/// Accumulates the results of test cases as they finish, grouped into suites. Suites are written
+/// in name order, and cases in the order they were recorded.
+#[derive(Default)]
+pub struct JUnitReport {
+    suites: Mutex<BTreeMap<String, Vec<JUnitCase>>>,
+}
+
+#[derive(Default)]
+struct Counts {
+    tests: usize,
+    failures: usize,
+    errors: usize,
+    skipped: usize,
+}
+
+impl Counts {
+    fn add(&mut self, outcome: &JUnitOutcome) {
+        self.tests += 1;
+        match outcome {
+            JUnitOutcome::Passed => {}
+            JUnitOutcome::Failed(_) => self.failures += 1,
+            JUnitOutcome::Errored(_) => self.errors += 1,
+            JUnitOutcome::Skipped => self.skipped += 1,
+        }
+    }
+
+    fn attributes(&self) -> String {
+        format!(
+            r#"tests="{}" failures="{}" errors="{}" skipped="{}""#,
+            self.tests, self.failures, self.errors, self.skipped
+        )
+    }
+}
+
+impl JUnitReport {
+    pub fn record(&self, suite: &str, case: JUnitCase) {
+        self.suites
+            .lock()
+            .unwrap()
+            .entry(suite.into())
+            .or_default()
+            .push(case);
+    }
+
+    /// Render the recorded results as a `<testsuites>` document.
+    pub fn to_xml(&self) -> String {
+        let suites = self.suites.lock().unwrap();
+        let mut total = Counts::default();
+        let mut body = String::new();
+        for (suite, cases) in suites.iter() {
+            let mut counts = Counts::default();
+            let mut suite_body = String::new();
+            for case in cases {
+                counts.add(&case.outcome);
+                write_case(&mut suite_body, suite, case);
+            }
+            total.tests += counts.tests;
+            total.failures += counts.failures;
+            total.errors += counts.errors;
+            total.skipped += counts.skipped;
+            let time: Duration = cases.iter().filter_map(|case| case.duration).sum();
+            writeln!(
+                body,
+                r#"  <testsuite name="{}" {} time="{:.3}">"#,
+                escape(suite),
+                counts.attributes(),
+                time.as_secs_f64()
+            )
+            .unwrap();
+            body += &suite_body;
+            body += "  </testsuite>\n";
+        }
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites {}>\n{body}</testsuites>\n",
+            total.attributes()
+        )
+    }
+}
+
+fn write_case(out: &mut String, suite: &str, case: &JUnitCase) {
+    write!(
+        out,
+        r#"    <testcase name="{}" classname="{}""#,
+        escape(&case.name),
+        escape(suite)
+    )
+    .unwrap();
+    if let Some(duration) = case.duration {
+        write!(out, r#" time="{:.3}""#, duration.as_secs_f64()).unwrap();
+    }
+    let mut children = String::new();
+    match &case.outcome {
+        JUnitOutcome::Passed => {}
+        JUnitOutcome::Failed(None) => children += "      <failure/>\n",
+        JUnitOutcome::Failed(Some(message)) => writeln!(
+            children,
+            r#"      <failure message="{}"/>"#,
+            escape(message)
+        )
+        .unwrap(),
+        JUnitOutcome::Errored(message) => {
+            writeln!(children, r#"      <error message="{}"/>"#, escape(message)).unwrap()
+        }
+        JUnitOutcome::Skipped => children += "      <skipped/>\n",
+    }
+    for (tag, lines) in [("system-out", &case.stdout), ("system-err", &case.stderr)] {
+        if !lines.is_empty() {
+            writeln!(
+                children,
+                "      <{tag}>{}</{tag}>",
+                escape(&lines.join("\n"))
+            )
+            .unwrap();
+        }
+    }
+    if children.is_empty() {
+        out.push_str("/>\n");
+    } else {
+        out.push_str(">\n");
+        out.push_str(&children);
+        out.push_str("    </testcase>\n");
+    }
+}
+
+/// Escape `s` for use in XML text or attribute values. Control characters that XML doesn't allow,
+/// like the escapes used to color output, are replaced with U+FFFD.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    fn case(name: &str, outcome: JUnitOutcome, millis: Option<u64>) -> JUnitCase {
+        JUnitCase {
+            name: name.into(),
+            outcome,
+            duration: millis.map(Duration::from_millis),
+            stdout: vec![],
+            stderr: vec![],
+        }
+    }
+
+    #[test]
+    fn empty_report() {
+        assert_eq!(
+            JUnitReport::default().to_xml(),
+            indoc! {r#"
+                <?xml version="1.0" encoding="UTF-8"?>
+                <testsuites tests="0" failures="0" errors="0" skipped="0">
+                </testsuites>
+            "#}
+        );
+    }
+
+    #[test]
+    fn report_with_every_outcome() {
+        let report = JUnitReport::default();
+        report.record(
+            "foo",
+            case("foo tests::a", JUnitOutcome::Passed, Some(1500)),
+        );
+        report.record(
+            "bar",
+            JUnitCase {
+                stdout: vec!["left: 1".into(), "right: <2>".into()],
+                stderr: vec!["\u{1b}[31mpanicked\u{1b}[0m".into()],
+                ..case(
+                    "bar tests::b",
+                    JUnitOutcome::Failed(Some("killed by signal 9".into())),
+                    Some(250),
+                )
+            },
+        );
+        report.record("foo", case("foo tests::c", JUnitOutcome::Skipped, None));
+        report.record(
+            "foo",
+            case(
+                "foo tests::d",
+                JUnitOutcome::Errored("system error: \"oops\" & more".into()),
+                None,
+            ),
+        );
+        report.record(
+            "bar",
+            case("bar tests::e", JUnitOutcome::Failed(None), Some(1)),
+        );
+        assert_eq!(
+            report.to_xml(),
+            indoc! {r#"
+                <?xml version="1.0" encoding="UTF-8"?>
+                <testsuites tests="5" failures="2" errors="1" skipped="1">
+                  <testsuite name="bar" tests="2" failures="2" errors="0" skipped="0" time="0.251">
+                    <testcase name="bar tests::b" classname="bar" time="0.250">
+                      <failure message="killed by signal 9"/>
+                      <system-out>left: 1
+                right: &lt;2&gt;</system-out>
+                      <system-err>�[31mpanicked�[0m</system-err>
+                    </testcase>
+                    <testcase name="bar tests::e" classname="bar" time="0.001">
+                      <failure/>
+                    </testcase>
+                  </testsuite>
+                  <testsuite name="foo" tests="3" failures="0" errors="1" skipped="1" time="1.500">
+                    <testcase name="foo tests::a" classname="foo" time="1.500"/>
+                    <testcase name="foo tests::c" classname="foo">
+                      <skipped/>
+                    </testcase>
+                    <testcase name="foo tests::d" classname="foo">
+                      <error message="system error: &quot;oops&quot; &amp; more"/>
+                    </testcase>
+                  </testsuite>
+                </testsuites>
+            "#}
+        );
+    }
+}
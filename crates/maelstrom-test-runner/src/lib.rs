@@ -2,8 +2,11 @@ mod alternative_mains;
 pub mod artifacts;
 pub mod config;
 mod deps;
+pub mod golden;
 mod introspect_driver;
+pub mod junit;
 pub mod metadata;
+pub mod shard;
 pub mod test_listing;
 pub mod ui;
 pub mod visitor;
@@ -15,33 +18,51 @@ pub use deps::*;
 
 use anyhow::Result;
 use artifacts::GeneratedArtifacts;
-use clap::{Args, Command};
+use clap::{Args, Command, ValueEnum};
+use golden::GoldenFiles;
 use introspect_driver::{DefaultIntrospectDriver, IntrospectDriver};
+use junit::JUnitReport;
 use maelstrom_base::{ArtifactType, JobRootOverlay, Sha256Digest, Timeout, Utf8PathBuf};
 use maelstrom_client::{spec::JobSpec, ClientBgProcess, ProjectDir, StateDir};
 use maelstrom_util::{
     config::common::LogLevel, config::Config, fs::Fs, process::ExitCode, root::Root,
 };
 use metadata::{AllMetadata, TestMetadata};
+use shard::Shard;
 use slog::Drain as _;
 use std::{
-    collections::{BTreeMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     ffi::OsString,
-    fmt::Debug,
+    fmt::{self, Debug},
     io::{self, IsTerminal as _},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
     str,
     sync::{
         atomic::{AtomicU64, Ordering},
         Arc, Mutex,
     },
+    time::Duration,
 };
-use test_listing::TestListingStore;
-use ui::{Ui, UiSender, UiSenderWriteAdapter};
-use visitor::{JobStatusTracker, JobStatusVisitor};
+use test_listing::{fingerprint_environment, TestListingStore};
+use ui::{SkipReason, Ui, UiSender, UiSenderWriteAdapter};
+use visitor::{JobStatusTracker, JobStatusVisitor, NoOpTimingSink, TimingSink};
 
 #[derive(Debug)]
 pub enum ListAction {
     ListTests,
+    /// Resolve the metadata for each matched test case and print it as a line of JSON.
+    ReportMetadata,
+}
+
+/// The order in which the test cases of an artifact are enqueued.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum TestOrdering {
+    /// The order the test framework lists them in.
+    #[default]
+    Listed,
+    /// Ascending by the expected duration from previous runs. Cases without any timings go last.
+    FastestFirst,
 }
 
 type TestListing<TestCollectorT> = test_listing::TestListing<
@@ -83,6 +104,7 @@ type ArtifactStreamM<MainAppDepsT> =
 struct JobQueuingState<TestCollectorT: CollectTests> {
     packages: BTreeMap<TestCollectorT::PackageId, TestCollectorT::Package>,
     filter: TestCollectorT::TestFilter,
+    shard: Option<Shard>,
     stderr_color: bool,
     tracker: Arc<JobStatusTracker>,
     jobs_queued: AtomicU64,
@@ -91,6 +113,14 @@ struct JobQueuingState<TestCollectorT: CollectTests> {
     test_listing: Arc<Mutex<Option<TestListing<TestCollectorT>>>>,
     list_action: Option<ListAction>,
     collector_options: TestCollectorT::Options,
+    include_quarantined: bool,
+    timing_sink: Arc<dyn TimingSink>,
+    golden_files: Option<GoldenFiles>,
+    junit_output: Option<(PathBuf, Arc<JUnitReport>)>,
+    retries: u32,
+    ordering: TestOrdering,
+    package_timeouts: HashMap<String, Option<Timeout>>,
+    max_concurrent_packages: Option<NonZeroUsize>,
 }
 
 impl<TestCollectorT: CollectTests> JobQueuingState<TestCollectorT> {
@@ -98,29 +128,71 @@ impl<TestCollectorT: CollectTests> JobQueuingState<TestCollectorT> {
     fn new(
         packages: BTreeMap<TestCollectorT::PackageId, TestCollectorT::Package>,
         filter: TestCollectorT::TestFilter,
+        shard: Option<Shard>,
         stderr_color: bool,
         test_metadata: AllMetadata<TestCollectorT::TestFilter>,
         test_listing: TestListing<TestCollectorT>,
         list_action: Option<ListAction>,
         collector_options: TestCollectorT::Options,
     ) -> Result<Self> {
-        let expected_job_count = test_listing.expected_job_count(&filter);
-
-        Ok(Self {
+        let mut state = Self {
             packages,
             filter,
+            shard,
             stderr_color,
             tracker: Arc::new(JobStatusTracker::default()),
             jobs_queued: AtomicU64::new(0),
             test_metadata,
-            expected_job_count,
+            expected_job_count: 0,
             test_listing: Arc::new(Mutex::new(Some(test_listing))),
             list_action,
             collector_options,
-        })
+            include_quarantined: false,
+            timing_sink: Arc::new(NoOpTimingSink),
+            golden_files: None,
+            junit_output: None,
+            retries: 0,
+            ordering: TestOrdering::default(),
+            package_timeouts: HashMap::new(),
+            max_concurrent_packages: None,
+        };
+        state.update_expected_job_count();
+        Ok(state)
+    }
+
+    /// Count the jobs we expect to enqueue, based on the test listing saved by previous runs. Cases
+    /// that aren't in one of our packages, aren't in our shard, or are quarantined, aren't
+    /// counted.
+    fn update_expected_job_count(&mut self) {
+        let package_names: HashSet<&str> = self.packages.values().map(|p| p.name()).collect();
+        self.expected_job_count = self
+            .test_listing
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .expected_job_count_where(&self.filter, |package, artifact, case, case_metadata| {
+                package_names.contains(package)
+                    && in_shard(self.shard, package, artifact, case)
+                    && (self.include_quarantined
+                        || !self
+                            .test_metadata
+                            .get_metadata_for_test_with_env(
+                                package,
+                                artifact,
+                                (case, case_metadata),
+                            )
+                            .is_ok_and(|test_metadata| test_metadata.quarantined))
+            });
     }
 }
 
+/// Whether the given test case should be run when only running `shard`. Every case should be run
+/// when there is no shard.
+fn in_shard(shard: Option<Shard>, package: &str, artifact: &impl fmt::Display, case: &str) -> bool {
+    shard.map_or(true, |shard| shard.contains(package, artifact, case))
+}
+
 /// Enqueues test cases as jobs in the given client from the given artifact
 ///
 /// This object is like an iterator, it maintains a position in the test listing and enqueues the
@@ -135,6 +207,7 @@ struct ArtifactQueuing<'a, MainAppDepsT: MainAppDeps> {
     ui: UiSender,
     artifact: ArtifactM<MainAppDepsT>,
     ignored_cases: HashSet<String>,
+    quarantined_cases: HashSet<String>,
     package_name: String,
     cases: CaseIter<CaseMetadataM<MainAppDepsT>>,
     timeout_override: Option<Option<Timeout>>,
@@ -145,6 +218,7 @@ struct ArtifactQueuing<'a, MainAppDepsT: MainAppDeps> {
 struct TestListingResult<CaseMetadataT> {
     cases: Vec<(String, CaseMetadataT)>,
     ignored_cases: HashSet<String>,
+    quarantined_cases: HashSet<String>,
 }
 
 fn list_test_cases<TestCollectorT: CollectTests>(
@@ -175,10 +249,35 @@ fn list_test_cases<TestCollectorT: CollectTests>(
             .filter
             .filter(package_name, Some(&artifact_key), Some((c.as_str(), cd)))
             .expect("should have case")
+            && in_shard(queuing_state.shard, package_name, &artifact_key, c)
     });
+
+    let mut quarantined_cases = HashSet::new();
+    if !queuing_state.include_quarantined {
+        for (c, cd) in &cases {
+            let test_metadata = queuing_state.test_metadata.get_metadata_for_test_with_env(
+                package_name,
+                &artifact_key,
+                (c.as_str(), cd),
+            )?;
+            if test_metadata.quarantined {
+                quarantined_cases.insert(c.clone());
+            }
+        }
+    }
+
+    if let TestOrdering::FastestFirst = queuing_state.ordering {
+        let listing = listing.as_ref().unwrap();
+        cases.sort_by_cached_key(|(c, _)| {
+            let timing = listing.get_timing(package_name, &artifact_key, c);
+            (timing.is_none(), timing)
+        });
+    }
+
     Ok(TestListingResult {
         cases,
         ignored_cases,
+        quarantined_cases,
     })
 }
 
@@ -212,6 +311,7 @@ where
             ui,
             artifact,
             ignored_cases: listing.ignored_cases,
+            quarantined_cases: listing.quarantined_cases,
             package_name,
             cases: listing.cases.into_iter(),
             timeout_override,
@@ -258,7 +358,15 @@ where
             .update_enqueue_status(format!("processing {case_str}"));
         slog::debug!(self.log, "enqueuing test case"; "case" => &case_str);
 
-        if self.queuing_state.list_action.is_some() {
+        if self.quarantined_cases.contains(case_name) {
+            slog::debug!(self.log, "skipping quarantined test"; "case" => &case_str);
+            let tracker = &self.queuing_state.tracker;
+            tracker.add_outstanding();
+            tracker.job_skipped(self.package_name.clone(), case_str, SkipReason::Quarantined);
+            return Ok(EnqueueResult::Skipped(SkipReason::Quarantined));
+        }
+
+        if matches!(self.queuing_state.list_action, Some(ListAction::ListTests)) {
             self.ui.list(case_str);
             return Ok(EnqueueResult::Listed);
         }
@@ -271,6 +379,15 @@ where
                 &self.artifact.to_key(),
                 (case_name, case_metadata),
             )?;
+
+        if matches!(
+            self.queuing_state.list_action,
+            Some(ListAction::ReportMetadata)
+        ) {
+            self.ui
+                .list(serde_json::to_string(&test_metadata.report(case_str))?);
+            return Ok(EnqueueResult::Listed);
+        }
         self.ui
             .update_enqueue_status(format!("calculating layers for {case_str}"));
         slog::debug!(&self.log, "calculating job layers"; "case" => &case_str);
@@ -317,11 +434,17 @@ where
             self.ui.clone(),
             MainAppDepsT::TestCollector::remove_fixture_output
                 as fn(&str, Vec<String>) -> Vec<String>,
+            self.queuing_state.timing_sink.clone(),
+            self.queuing_state.golden_files.clone(),
+            self.queuing_state
+                .junit_output
+                .as_ref()
+                .map(|(_, report)| report.clone()),
         );
 
         if self.ignored_cases.contains(case_name) {
             visitor.job_ignored();
-            return Ok(EnqueueResult::Ignored);
+            return Ok(EnqueueResult::Skipped(SkipReason::Ignored));
         }
 
         let estimated_duration = self
@@ -337,29 +460,57 @@ where
             .update_enqueue_status(format!("submitting job for {case_str}"));
         slog::debug!(&self.log, "submitting job"; "case" => &case_str);
         let (program, arguments) = self.artifact.build_command(case_name, case_metadata);
-        self.deps.client().add_job(
-            JobSpec {
-                program,
-                arguments,
-                image: test_metadata.image,
-                environment: test_metadata.environment,
-                layers,
-                mounts: test_metadata.mounts,
-                network: test_metadata.network,
-                root_overlay: if test_metadata.enable_writable_file_system {
-                    JobRootOverlay::Tmp
-                } else {
-                    JobRootOverlay::None
-                },
-                working_directory: test_metadata.working_directory,
-                user: test_metadata.user,
-                group: test_metadata.group,
-                timeout: self.timeout_override.unwrap_or(test_metadata.timeout),
-                estimated_duration,
-                allocate_tty: None,
+        let spec = JobSpec {
+            program,
+            arguments,
+            image: test_metadata.image,
+            environment: test_metadata.environment,
+            layers,
+            mounts: test_metadata.mounts,
+            network: test_metadata.network,
+            root_overlay: if test_metadata.enable_writable_file_system {
+                JobRootOverlay::Tmp
+            } else {
+                JobRootOverlay::None
             },
-            move |res| visitor.job_finished(res),
-        )?;
+            working_directory: test_metadata.working_directory,
+            user: test_metadata.user,
+            group: test_metadata.group,
+            timeout: self
+                .queuing_state
+                .package_timeouts
+                .get(&self.package_name)
+                .copied()
+                .or(self.timeout_override)
+                .unwrap_or(test_metadata.timeout),
+            estimated_duration,
+            allocate_tty: None,
+            oom_score_adj: None,
+            cacheable: false,
+            trace_id: None,
+            display_name: None,
+            cpu_set: None,
+            readonly_root: false,
+            depends_on: vec![],
+            priority: 0,
+            memory_limit: None,
+        };
+        let cleanup_spec =
+            test_metadata
+                .cleanup_command
+                .split_first()
+                .map(|(program, arguments)| JobSpec {
+                    program: program.into(),
+                    arguments: arguments.to_vec(),
+                    estimated_duration: None,
+                    ..spec.clone()
+                });
+        let visitor = visitor
+            .retries(spec.clone(), self.queuing_state.retries)
+            .cleanup(cleanup_spec);
+        self.deps
+            .client()
+            .add_job(spec, move |res| visitor.job_finished(res))?;
 
         Ok(EnqueueResult::Enqueued {
             package_name: self.package_name.clone(),
@@ -388,6 +539,8 @@ struct JobQueuing<'a, MainAppDepsT: MainAppDeps> {
     queuing_state: &'a JobQueuingState<MainAppDepsT::TestCollector>,
     deps: &'a MainAppDepsT,
     ui: UiSender,
+    /// The packages that the test collector hasn't been started on yet.
+    pending_packages: VecDeque<&'a PackageM<MainAppDepsT>>,
     wait_handle: Option<BuildHandleM<MainAppDepsT>>,
     package_match: bool,
     artifacts: Option<ArtifactStreamM<MainAppDepsT>>,
@@ -411,32 +564,54 @@ where
         let building_tests = !queuing_state.packages.is_empty()
             && matches!(
                 queuing_state.list_action,
-                None | Some(ListAction::ListTests)
+                None | Some(ListAction::ListTests | ListAction::ReportMetadata)
             );
 
-        let (wait_handle, artifacts) = building_tests
-            .then(|| {
-                deps.test_collector().start(
-                    queuing_state.stderr_color,
-                    &queuing_state.collector_options,
-                    queuing_state.packages.values().collect(),
-                    &ui,
-                )
-            })
-            .transpose()?
-            .unzip();
+        let pending_packages = if building_tests {
+            queuing_state.packages.values().collect()
+        } else {
+            VecDeque::new()
+        };
 
-        Ok(Self {
+        let mut job_queuing = Self {
             log,
             queuing_state,
             deps,
             ui,
+            pending_packages,
             package_match: false,
-            artifacts,
+            artifacts: None,
             artifact_queuing: None,
-            wait_handle,
+            wait_handle: None,
             timeout_override,
-        })
+        };
+        job_queuing.start_collecting_packages()?;
+        Ok(job_queuing)
+    }
+
+    /// Start the test collector on the next batch of pending packages. There are at most
+    /// `max_concurrent_packages` packages in a batch, and we wait for the previous batch to be
+    /// collected before starting the next one. Returns false if there are no more packages.
+    fn start_collecting_packages(&mut self) -> Result<bool> {
+        if self.pending_packages.is_empty() {
+            return Ok(false);
+        }
+        let batch_size = self
+            .queuing_state
+            .max_concurrent_packages
+            .map_or(usize::MAX, NonZeroUsize::get)
+            .min(self.pending_packages.len());
+        let packages: Vec<_> = self.pending_packages.drain(..batch_size).collect();
+        slog::debug!(self.log, "starting test collector"; "packages" => packages.len());
+        let (wait_handle, artifacts) = self.deps.test_collector().start(
+            self.queuing_state.stderr_color,
+            &self.queuing_state.collector_options,
+            packages,
+            &self.ui,
+        )?;
+        self.wait_handle = Some(wait_handle);
+        self.artifacts = Some(artifacts);
+        Ok(true)
     }
 
     fn start_queuing_from_artifact(&mut self) -> Result<bool> {
@@ -444,13 +619,18 @@ where
             .update_enqueue_status(MainAppDepsT::TestCollector::ENQUEUE_MESSAGE);
 
         slog::debug!(self.log, "getting artifacts");
-        let Some(ref mut artifacts) = self.artifacts else {
-            return Ok(false);
-        };
-        let Some(artifact) = artifacts.next() else {
-            return Ok(false);
+        let artifact = loop {
+            let Some(ref mut artifacts) = self.artifacts else {
+                return Ok(false);
+            };
+            if let Some(artifact) = artifacts.next() {
+                break artifact?;
+            }
+            // This batch of packages is done. Check it for errors before moving on to the next.
+            self.artifacts = None;
+            self.finish()?;
+            self.start_collecting_packages()?;
         };
-        let artifact = artifact?;
 
         slog::debug!(self.log, "got artifact"; "artifact" => ?artifact);
         let package_name = self
@@ -506,6 +686,14 @@ where
     }
 }
 
+/// The exit code used when no packages match the filter, so there is nothing to run. This lets CI
+/// tell a run that did nothing apart from one where everything passed. It is the same exit code
+/// pytest uses when it doesn't collect any tests.
+pub const NO_TESTS_MATCHED_EXIT_CODE: u8 = 5;
+
+/// How long to wait between retries of reading the test listing.
+const TEST_LISTING_READ_RETRY_DELAY: Duration = Duration::from_millis(100);
+
 /// This is where cached data goes. If there is build output it is also here.
 pub struct BuildDir;
 
@@ -525,10 +713,12 @@ impl<MainAppDepsT: MainAppDeps> MainAppState<MainAppDepsT> {
     /// `bg_proc`: handle to background client process
     /// `include_filter`: tests which match any of the patterns in this filter are run
     /// `exclude_filter`: tests which match any of the patterns in this filter are not run
+    /// `shard`: if some, only the tests that match the filters and are in this shard are run
     /// `list_action`: if some, tests aren't run, instead tests or other things are listed
     /// `stderr_color`: should terminal color codes be written to `stderr` or not
     /// `project_dir`: the path to the root of the project
     /// `packages`: a listing of all the packages
+    /// `test_listing_read_retries`: how many times to retry reading the test listing on I/O errors
     /// `broker_addr`: the network address of the broker which we connect to
     /// `client_driver`: an object which drives the background work of the `Client`
     #[allow(clippy::too_many_arguments)]
@@ -536,11 +726,13 @@ impl<MainAppDepsT: MainAppDeps> MainAppState<MainAppDepsT> {
         deps: MainAppDepsT,
         include_filter: Vec<String>,
         exclude_filter: Vec<String>,
+        shard: Option<Shard>,
         list_action: Option<ListAction>,
         stderr_color: bool,
         project_dir: impl AsRef<Root<ProjectDir>>,
         packages: &[PackageM<MainAppDepsT>],
         state_dir: impl AsRef<Root<StateDir>>,
+        test_listing_read_retries: u32,
         collector_options: CollectOptionsM<MainAppDepsT>,
         logging_output: LoggingOutput,
         log: slog::Logger,
@@ -549,12 +741,17 @@ impl<MainAppDepsT: MainAppDeps> MainAppState<MainAppDepsT> {
             log, "creating app state";
             "include_filter" => ?include_filter,
             "exclude_filter" => ?exclude_filter,
+            "shard" => ?shard,
             "list_action" => ?list_action,
         );
 
         let mut test_metadata =
             AllMetadata::load(log.clone(), project_dir, MainAppDepsT::MAELSTROM_TEST_TOML)?;
-        let test_listing_store = TestListingStore::new(Fs::new(), &state_dir);
+        let environment_fingerprint =
+            fingerprint_environment(test_metadata.content_hash(), &collector_options);
+        let test_listing_store = TestListingStore::new(Fs::new(), &state_dir)
+            .environment_fingerprint(environment_fingerprint)
+            .read_retries(test_listing_read_retries, TEST_LISTING_READ_RETRY_DELAY);
         let mut test_listing = test_listing_store.load()?;
         test_listing
             .retain_packages_and_artifacts(packages.iter().map(|p| (p.name(), p.artifacts())));
@@ -571,7 +768,7 @@ impl<MainAppDepsT: MainAppDeps> MainAppState<MainAppDepsT> {
             "selected_packages" => ?Vec::from_iter(selected_packages.keys()),
         );
 
-        let vars = deps.get_template_vars(&collector_options)?;
+        let vars = deps.get_template_vars(&collector_options)?.with_host_env();
         test_metadata.replace_template_vars(&vars)?;
 
         Ok(Self {
@@ -579,6 +776,7 @@ impl<MainAppDepsT: MainAppDeps> MainAppState<MainAppDepsT> {
             queuing_state: JobQueuingState::new(
                 selected_packages,
                 filter,
+                shard,
                 stderr_color,
                 test_metadata,
                 test_listing,
@@ -592,14 +790,107 @@ impl<MainAppDepsT: MainAppDeps> MainAppState<MainAppDepsT> {
     }
 }
 
+impl<MainAppDepsT: MainAppDeps> MainAppState<MainAppDepsT> {
+    /// Whether to run tests that the test metadata marks as quarantined. By default, they are
+    /// skipped, even if they match the filter.
+    pub fn include_quarantined(mut self, include_quarantined: bool) -> Self {
+        self.queuing_state.include_quarantined = include_quarantined;
+        self.queuing_state.update_expected_job_count();
+        self
+    }
+
+    /// Report the timing of each test case to `timing_sink` as its job completes. By default,
+    /// timings are only persisted in the test listing at the end of the run.
+    pub fn timing_sink(mut self, timing_sink: impl TimingSink + 'static) -> Self {
+        self.queuing_state.timing_sink = Arc::new(timing_sink);
+        self
+    }
+
+    /// Compare the stdout of each passing test case against its golden file, failing the case if
+    /// they differ. By default, stdout isn't compared against anything.
+    pub fn golden_files(mut self, golden_files: Option<GoldenFiles>) -> Self {
+        self.queuing_state.golden_files = golden_files;
+        self
+    }
+
+    /// Write the results of the test cases to `path` as JUnit XML at the end of the run. Each
+    /// package is a test suite. By default, no JUnit XML is written.
+    pub fn junit_output(mut self, path: Option<impl Into<PathBuf>>) -> Self {
+        self.queuing_state.junit_output = path.map(|path| (path.into(), Arc::default()));
+        self
+    }
+
+    /// Run a failed test case again, up to `retries` times. A case that fails and then passes on a
+    /// retry is reported as flaky, and doesn't cause the run to fail. By default, failed cases
+    /// aren't retried.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.queuing_state.retries = retries;
+        self
+    }
+
+    /// The order in which to enqueue the test cases of each artifact. By default, they are enqueued
+    /// in the order they are listed.
+    pub fn ordering(mut self, ordering: TestOrdering) -> Self {
+        self.queuing_state.ordering = ordering;
+        self
+    }
+
+    /// Limit how many packages the test collector works on at once. Packages are given to the test
+    /// collector in batches of at most `max_concurrent_packages`, and a batch's tests are enqueued
+    /// before the next batch is started. By default, all packages are given to the test collector
+    /// at once.
+    pub fn max_concurrent_packages(
+        mut self,
+        max_concurrent_packages: Option<NonZeroUsize>,
+    ) -> Self {
+        self.queuing_state.max_concurrent_packages = max_concurrent_packages;
+        self
+    }
+
+    /// Override the timeout of every test case in the given packages. A `None` timeout means the
+    /// cases have no timeout. A package's override takes precedence over the timeout override
+    /// given to the app, which in turn takes precedence over the timeout from the test metadata.
+    pub fn package_timeouts(
+        mut self,
+        package_timeouts: impl IntoIterator<Item = (String, Option<Timeout>)>,
+    ) -> Self {
+        self.queuing_state.package_timeouts = package_timeouts.into_iter().collect();
+        self
+    }
+
+    /// Only run tests in packages that could be affected by changes to `changed_files`, according
+    /// to `affected_packages`. Tests in other packages aren't enqueued, even if they match the
+    /// filter.
+    pub fn only_affected_by(
+        mut self,
+        changed_files: &[impl AsRef<Path>],
+        affected_packages: &impl AffectedPackages,
+    ) -> Self {
+        let affected: HashSet<String> = changed_files
+            .iter()
+            .flat_map(|path| affected_packages.packages_affected_by(path.as_ref()))
+            .collect();
+        slog::debug!(
+            self.log, "selecting packages affected by changed files";
+            "affected_packages" => ?affected,
+        );
+
+        self.queuing_state
+            .packages
+            .retain(|_, package| affected.contains(package.name()));
+        self.queuing_state.update_expected_job_count();
+        self
+    }
+}
+
 /// The `MainApp` enqueues tests as jobs. With each attempted job enqueued this object is returned
 /// and describes what happened.
 pub enum EnqueueResult {
     /// A job successfully enqueued with the following information
     Enqueued { package_name: String, case: String },
-    /// No job was enqueued, instead the test that would have been enqueued has been ignored
-    /// because it has been marked as `#[ignored]`
-    Ignored,
+    /// No job was enqueued, instead the test that would have been enqueued has been skipped for
+    /// the given reason
+    Skipped(SkipReason),
     /// No job was enqueued, we have run out of tests to run
     Done,
     /// No job was enqueued, we listed the test case instead
@@ -612,9 +903,9 @@ impl EnqueueResult {
         matches!(self, Self::Done)
     }
 
-    /// Is this `EnqueueResult` the `Ignored` variant
-    pub fn is_ignored(&self) -> bool {
-        matches!(self, Self::Ignored)
+    /// Is this `EnqueueResult` the `Skipped` variant
+    pub fn is_skipped(&self) -> bool {
+        matches!(self, Self::Skipped(_))
     }
 }
 
@@ -681,12 +972,30 @@ where
     /// Waits for all outstanding jobs to finish, displays a summary, and obtains an `ExitCode`
     fn finish(&mut self) -> Result<ExitCode> {
         slog::debug!(self.queuing.log, "waiting for outstanding jobs");
-        self.state.queuing_state.tracker.wait_for_outstanding();
+        while let Some(follow_up) = self.state.queuing_state.tracker.wait_for_follow_up() {
+            slog::debug!(
+                self.queuing.log, "submitting follow-up job";
+                "program" => %follow_up.spec.program
+            );
+            self.state
+                .deps
+                .client()
+                .add_job(follow_up.spec, follow_up.handler)?;
+        }
         self.introspect_driver.stop()?;
 
+        let no_tests_matched = self.state.queuing_state.packages.is_empty();
+        if no_tests_matched {
+            self.ui.log_message("no tests matched the filter".into());
+        }
+
         let summary = self.state.queuing_state.tracker.ui_summary();
         self.ui.finished(summary)?;
 
+        if let Some((path, report)) = &self.state.queuing_state.junit_output {
+            Fs::new().write(path, report.to_xml())?;
+        }
+
         self.state.test_listing_store.save(
             self.state
                 .queuing_state
@@ -697,6 +1006,9 @@ where
                 .unwrap(),
         )?;
 
+        if no_tests_matched {
+            return Ok(ExitCode::from(NO_TESTS_MATCHED_EXIT_CODE));
+        }
         Ok(self.state.queuing_state.tracker.exit_code())
     }
 }
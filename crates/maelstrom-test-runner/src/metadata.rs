@@ -5,17 +5,19 @@ use anyhow::{anyhow, Context as _, Result};
 use directive::TestDirective;
 use enumset::enum_set;
 use maelstrom_base::{
-    GroupId, JobDeviceForTomlAndJson, JobMount, JobMountForTomlAndJson, JobNetwork, Timeout,
-    UserId, Utf8PathBuf,
+    GroupId, JobDeviceForTomlAndJson, JobMount, JobMountForTomlAndJson, JobNetwork, Sha256Digest,
+    Timeout, UserId, Utf8PathBuf,
 };
 use maelstrom_client::{
     spec::{EnvironmentSpec, ImageSpec, Layer, PossiblyImage},
     ProjectDir,
 };
 use maelstrom_util::{fs::Fs, root::Root, template::TemplateVars};
-use serde::Deserialize;
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
 use std::{
-    fmt::Display,
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Display},
     str::{self, FromStr},
 };
 
@@ -28,6 +30,16 @@ pub const DEFAULT_TEST_METADATA: &str = include_str!("default-test-metadata.toml
 pub struct AllMetadata<TestFilterT> {
     #[serde(bound(deserialize = "TestFilterT: FromStr, TestFilterT::Err: Display"))]
     directives: Vec<TestDirective<TestFilterT>>,
+    #[serde(skip, default = "default_content_hash")]
+    content_hash: Sha256Digest,
+}
+
+fn content_hash(contents: &str) -> Sha256Digest {
+    Sha256Digest::new(Sha256::digest(contents).into())
+}
+
+fn default_content_hash() -> Sha256Digest {
+    content_hash(DEFAULT_TEST_METADATA)
 }
 
 impl<TestFilterT> Default for AllMetadata<TestFilterT> {
@@ -43,6 +55,8 @@ impl<TestFilterT> Default for AllMetadata<TestFilterT> {
             user: None,
             group: None,
             timeout: None,
+            quarantined: None,
+            cleanup_command: None,
             // Create directories and files for mounting special file-systems and device files
             layers: Some(PossiblyImage::Explicit(vec![Layer::Stubs {
                 stubs: vec![
@@ -84,6 +98,7 @@ impl<TestFilterT> Default for AllMetadata<TestFilterT> {
         };
         Self {
             directives: vec![single_directive],
+            content_hash: default_content_hash(),
         }
     }
 }
@@ -108,11 +123,29 @@ pub struct TestMetadata {
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
     pub timeout: Option<Timeout>,
+    /// Quarantined tests are skipped unless quarantined tests are explicitly included.
+    pub quarantined: bool,
+    /// A command to run as a separate job once the test has finished, whether it passed or failed.
+    /// The first element is the program, and the rest are its arguments. Empty if there is none.
+    pub cleanup_command: Vec<String>,
     pub layers: Vec<Layer>,
     pub environment: Vec<EnvironmentSpec>,
     pub mounts: Vec<JobMount>,
 }
 
+/// The metadata resolved for a single test case, as emitted by `--report-metadata`.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TestMetadataReport {
+    pub case: String,
+    pub image: Option<String>,
+    pub mounts: Vec<JobMount>,
+    pub network: JobNetwork,
+    pub user: Option<UserId>,
+    pub group: Option<GroupId>,
+    pub timeout: Option<Timeout>,
+    pub environment: BTreeSet<String>,
+}
+
 impl TestMetadata {
     /// Return whether to include a layer of shared library dependencies.
     ///
@@ -128,6 +161,25 @@ impl TestMetadata {
         }
     }
 
+    /// Summarize the resolved metadata for the given test case. Only the names of environment
+    /// variables are reported, not their values.
+    pub fn report(&self, case: String) -> TestMetadataReport {
+        TestMetadataReport {
+            case,
+            image: self.image.as_ref().map(|image| image.name.clone()),
+            mounts: self.mounts.clone(),
+            network: self.network,
+            user: self.user,
+            group: self.group,
+            timeout: self.timeout,
+            environment: self
+                .environment
+                .iter()
+                .flat_map(|spec| spec.vars.keys().cloned())
+                .collect(),
+        }
+    }
+
     fn try_fold<TestFilterT>(
         mut self,
         &TestDirective {
@@ -139,6 +191,8 @@ impl TestMetadata {
             user,
             group,
             timeout,
+            quarantined,
+            ref cleanup_command,
             ref layers,
             ref added_layers,
             ref mounts,
@@ -162,6 +216,10 @@ impl TestMetadata {
         self.user = user.or(self.user);
         self.group = group.or(self.group);
         self.timeout = timeout.unwrap_or(self.timeout);
+        self.quarantined = quarantined.unwrap_or(self.quarantined);
+        if let Some(cleanup_command) = cleanup_command {
+            self.cleanup_command.clone_from(cleanup_command);
+        }
 
         match layers {
             Some(PossiblyImage::Explicit(layers)) => {
@@ -222,10 +280,101 @@ impl TestMetadata {
     }
 }
 
+/// A problem found by [`AllMetadata::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MetadataError {
+    pub message: String,
+    /// The 1-based line and column of the problem, if known.
+    pub location: Option<(usize, usize)>,
+}
+
+impl MetadataError {
+    fn new(contents: &str, message: impl Into<String>, offset: Option<usize>) -> Self {
+        let location = offset.map(|offset| {
+            let before = &contents[..offset];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            (
+                before.matches('\n').count() + 1,
+                before[line_start..].chars().count() + 1,
+            )
+        });
+        Self {
+            message: message.into(),
+            location,
+        }
+    }
+
+    fn from_toml(contents: &str, err: toml::de::Error) -> Self {
+        let offset = err.span().map(|span| span.start);
+        Self::new(contents, err.message().trim_end(), offset)
+    }
+}
+
+impl Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(f, "{line}:{column}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// The shape of a test metadata file, with each directive left unparsed so that they can be
+/// checked independently.
+#[derive(Deserialize)]
+struct UncheckedMetadata {
+    #[serde(default)]
+    directives: Vec<toml::Spanned<toml::Table>>,
+}
+
 impl<TestFilterT: TestFilter> AllMetadata<TestFilterT>
 where
     TestFilterT::Err: Display,
 {
+    /// Check `contents` as a test metadata file, returning every problem found instead of stopping
+    /// at the first one. Each directive is checked on its own, so a bad directive doesn't hide
+    /// problems in the others. A TOML syntax error prevents any further checking, so it is the
+    /// only error returned.
+    pub fn validate(contents: &str) -> Vec<MetadataError> {
+        let keys: BTreeMap<toml::Spanned<String>, IgnoredAny> = match toml::from_str(contents) {
+            Ok(keys) => keys,
+            Err(err) => return vec![MetadataError::from_toml(contents, err)],
+        };
+        let mut errors = Vec::from_iter(
+            keys.keys()
+                .filter(|key| key.get_ref() != "directives")
+                .map(|key| {
+                    MetadataError::new(
+                        contents,
+                        format!("unknown field `{}`, expected `directives`", key.get_ref()),
+                        Some(key.span().start),
+                    )
+                }),
+        );
+        let unchecked: UncheckedMetadata = match toml::from_str(contents) {
+            Ok(unchecked) => unchecked,
+            Err(err) => {
+                errors.push(MetadataError::from_toml(contents, err));
+                return errors;
+            }
+        };
+        for directive in unchecked.directives {
+            // Errors from deserializing a value have no span of their own, so they are reported at
+            // the start of the directive.
+            let start = directive.span().start;
+            if let Err(err) = TestDirective::<TestFilterT>::deserialize(toml::Value::Table(
+                directive.into_inner(),
+            )) {
+                errors.push(MetadataError::new(
+                    contents,
+                    err.message().trim_end(),
+                    Some(start),
+                ));
+            }
+        }
+        errors
+    }
+
     pub fn replace_template_vars(&mut self, vars: &TemplateVars) -> Result<()> {
         for directive in &mut self.directives {
             if let Some(PossiblyImage::Explicit(layers)) = &mut directive.layers {
@@ -269,8 +418,17 @@ where
         self.get_metadata_for_test(package, artifact, case)
     }
 
+    /// A hash of the text the metadata was parsed from. The default metadata hashes the same as
+    /// [`DEFAULT_TEST_METADATA`].
+    pub fn content_hash(&self) -> &Sha256Digest {
+        &self.content_hash
+    }
+
     fn from_str(contents: &str) -> Result<Self> {
-        Ok(toml::from_str(contents)?)
+        Ok(Self {
+            content_hash: content_hash(contents),
+            ..toml::from_str(contents)?
+        })
     }
 
     pub fn load(
@@ -389,7 +547,8 @@ mod tests {
         assert_eq!(
             res,
             AllMetadata {
-                directives: vec![TestDirective::default()]
+                directives: vec![TestDirective::default()],
+                content_hash: content_hash("[[directives]]"),
             }
         );
         assert_eq!(log_lines.len(), 0, "{log_lines:?}");
@@ -407,7 +566,8 @@ mod tests {
         assert_eq!(
             res,
             AllMetadata {
-                directives: vec![TestDirective::default()]
+                directives: vec![TestDirective::default()],
+                content_hash: content_hash("[[directives]]"),
             }
         );
         assert_eq!(log_lines.len(), 1, "{log_lines:?}");
@@ -422,9 +582,12 @@ mod tests {
     #[test]
     fn default() {
         assert_eq!(
-            AllMetadata::<SimpleFilter> { directives: vec![] }
-                .get_metadata_for_test("mod", &"mod".into(), ("foo", &NoCaseMetadata))
-                .unwrap(),
+            AllMetadata::<SimpleFilter> {
+                directives: vec![],
+                content_hash: content_hash(""),
+            }
+            .get_metadata_for_test("mod", &"mod".into(), ("foo", &NoCaseMetadata))
+            .unwrap(),
             TestMetadata::default(),
         );
     }
@@ -564,6 +727,64 @@ mod tests {
         );
     }
 
+    #[test]
+    fn quarantined() {
+        let all = AllMetadata::<SimpleFilter>::from_str(
+            r#"
+            [[directives]]
+            filter = "package = \"package1\""
+            quarantined = true
+
+            [[directives]]
+            filter = "and = [{ package = \"package1\" }, { name = \"test1\" }]"
+            quarantined = false
+            "#,
+        )
+        .unwrap();
+        assert!(
+            !all.get_metadata_for_test("package1", &"package1".into(), ("test1", &NoCaseMetadata))
+                .unwrap()
+                .quarantined
+        );
+        assert!(
+            all.get_metadata_for_test("package1", &"package1".into(), ("test2", &NoCaseMetadata))
+                .unwrap()
+                .quarantined
+        );
+        assert!(
+            !all.get_metadata_for_test("package2", &"package2".into(), ("test1", &NoCaseMetadata))
+                .unwrap()
+                .quarantined
+        );
+    }
+
+    #[test]
+    fn cleanup_command() {
+        let all = AllMetadata::<SimpleFilter>::from_str(
+            r#"
+            [[directives]]
+            filter = "package = \"package1\""
+            cleanup_command = ["/bin/cleanup", "--all"]
+
+            [[directives]]
+            filter = "and = [{ package = \"package1\" }, { name = \"test1\" }]"
+            cleanup_command = []
+            "#,
+        )
+        .unwrap();
+        let cleanup_command = |package: &str, case| {
+            all.get_metadata_for_test(package, &package.into(), (case, &NoCaseMetadata))
+                .unwrap()
+                .cleanup_command
+        };
+        assert_eq!(cleanup_command("package1", "test1"), Vec::<String>::new());
+        assert_eq!(
+            cleanup_command("package1", "test2"),
+            vec!["/bin/cleanup", "--all"]
+        );
+        assert_eq!(cleanup_command("package2", "test1"), Vec::<String>::new());
+    }
+
     #[test]
     fn working_directory() {
         let all = AllMetadata::<SimpleFilter>::from_str(
@@ -1120,6 +1341,7 @@ mod tests {
                     mount_point: utf8_path_buf!("/foo"),
                     local_path: utf8_path_buf!("/local"),
                     read_only: true,
+                    security_label: None,
                 },
             ],
         );
@@ -1212,6 +1434,7 @@ mod tests {
                     mount_point: utf8_path_buf!("/foo"),
                     local_path: utf8_path_buf!("/local"),
                     read_only: false,
+                    security_label: None,
                 },
             ],
         );
@@ -1356,6 +1579,68 @@ mod tests {
         assert!(message.starts_with(expected), "message: {message}");
     }
 
+    #[test]
+    fn validate_reports_every_error() {
+        let contents = indoc::indoc! {r#"
+            [[directives]]
+            filter = "package = \"foo\""
+            network = "loopback"
+
+            [[directives]]
+            not_a_field = 1
+
+            [[directives]]
+            timeout = "ten"
+
+            [not_a_field]
+            foo = "three"
+
+            [[directives]]
+            filter = "package ="
+        "#};
+        let errors = AllMetadata::<SimpleFilter>::validate(contents);
+        let errors = Vec::from_iter(errors.iter().map(|err| {
+            let (line, column) = err.location.unwrap();
+            (line, column, err.message.lines().next().unwrap())
+        }));
+        assert_eq!(
+            errors,
+            vec![
+                (11, 2, "unknown field `not_a_field`, expected `directives`"),
+                (
+                    5,
+                    1,
+                    "unknown field `not_a_field`, expected one of `filter`, \
+                    `include_shared_libraries`, `network`, `enable_writable_file_system`, \
+                    `user`, `group`, `timeout`, `quarantined`, `cleanup_command`, `mounts`, \
+                    `added_mounts`, `image`, `working_directory`, `layers`, `added_layers`, \
+                    `environment`, `added_environment`"
+                ),
+                (8, 1, "invalid type: string \"ten\", expected u32"),
+                (14, 1, "TOML parse error at line 1, column 10"),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_valid_file() {
+        assert_eq!(
+            AllMetadata::<SimpleFilter>::validate(DEFAULT_TEST_METADATA),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn validate_syntax_error() {
+        assert_eq!(
+            AllMetadata::<SimpleFilter>::validate("[[directives]]\nnetwork = \n"),
+            vec![MetadataError {
+                message: "invalid string\nexpected `\"`, `'`".into(),
+                location: Some((2, 11)),
+            }]
+        );
+    }
+
     #[test]
     fn bad_field_in_all_metadata() {
         assert_toml_error(
@@ -20,6 +20,9 @@ pub struct TestDirective<TestFilterT> {
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
     pub timeout: Option<Option<Timeout>>,
+    pub quarantined: Option<bool>,
+    /// An empty command means no cleanup command, overriding any from earlier directives.
+    pub cleanup_command: Option<Vec<String>>,
     pub layers: Option<PossiblyImage<Vec<Layer>>>,
     pub added_layers: Vec<Layer>,
     pub mounts: Option<Vec<JobMountForTomlAndJson>>,
@@ -41,6 +44,8 @@ impl<TestFilterT> Default for TestDirective<TestFilterT> {
             user: None,
             group: None,
             timeout: None,
+            quarantined: None,
+            cleanup_command: None,
             layers: None,
             added_layers: Default::default(),
             mounts: None,
@@ -62,6 +67,8 @@ enum DirectiveField {
     User,
     Group,
     Timeout,
+    Quarantined,
+    CleanupCommand,
     Mounts,
     AddedMounts,
     Image,
@@ -95,6 +102,8 @@ where
         let mut user = None;
         let mut group = None;
         let mut timeout = None;
+        let mut quarantined = None;
+        let mut cleanup_command = None;
         let mut mounts = None;
         let mut added_mounts = None;
         let mut image = None;
@@ -130,6 +139,12 @@ where
                 DirectiveField::Timeout => {
                     timeout = Some(Timeout::new(map.next_value()?));
                 }
+                DirectiveField::Quarantined => {
+                    quarantined = Some(map.next_value()?);
+                }
+                DirectiveField::CleanupCommand => {
+                    cleanup_command = Some(map.next_value()?);
+                }
                 DirectiveField::Mounts => {
                     incompatible(
                         &added_mounts,
@@ -222,6 +237,8 @@ where
             user,
             group,
             timeout,
+            quarantined,
+            cleanup_command,
             layers,
             added_layers: added_layers.unwrap_or_default(),
             mounts,
@@ -310,6 +327,8 @@ mod tests {
                 user = 101
                 group = 202
                 timeout = 1
+                quarantined = true
+                cleanup_command = ["/usr/bin/dropdb", "--if-exists", "test_db"]
                 "#
             )
             .unwrap(),
@@ -325,6 +344,12 @@ mod tests {
                 user: Some(UserId::from(101)),
                 group: Some(GroupId::from(202)),
                 timeout: Some(Timeout::new(1)),
+                quarantined: Some(true),
+                cleanup_command: Some(vec![
+                    "/usr/bin/dropdb".into(),
+                    "--if-exists".into(),
+                    "test_db".into()
+                ]),
                 ..Default::default()
             }
         );
@@ -373,11 +398,13 @@ mod tests {
                         mount_point: utf8_path_buf!("/bind"),
                         local_path: utf8_path_buf!("/local"),
                         read_only: false,
+                        security_label: None,
                     },
                     JobMountForTomlAndJson::Bind {
                         mount_point: utf8_path_buf!("/bind2"),
                         local_path: utf8_path_buf!("/local2"),
                         read_only: true,
+                        security_label: None,
                     },
                     JobMountForTomlAndJson::Devices {
                         devices: enum_set!(
@@ -408,6 +435,7 @@ mod tests {
                         mount_point: utf8_path_buf!("/bind"),
                         local_path: utf8_path_buf!("/local"),
                         read_only: true,
+                        security_label: None,
                     },
                     JobMountForTomlAndJson::Devices {
                         devices: enum_set!(
@@ -508,6 +536,7 @@ mod tests {
                     mount_point: utf8_path_buf!("/bind"),
                     local_path: utf8_path_buf!("/a"),
                     read_only: false,
+                    security_label: None,
                 }]),
                 ..Default::default()
             }
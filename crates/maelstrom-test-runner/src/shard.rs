@@ -0,0 +1,92 @@
+//! Splitting a test suite into disjoint shards, so that it can be spread across machines.
+
+use anyhow::{anyhow, bail, Error, Result};
+use sha2::{Digest as _, Sha256};
+use std::{fmt, str::FromStr};
+
+/// One of `count` disjoint parts of a test suite. Every test case belongs to exactly one shard,
+/// which is determined by hashing the case's package, artifact, and name. The hash is stable, so a
+/// case lands in the same shard on every machine and in every run, as long as `count` doesn't
+/// change.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Shard {
+    index: u64,
+    count: u64,
+}
+
+impl Shard {
+    /// The shard with the given zero-based `index`, out of `count` shards.
+    pub fn new(index: u64, count: u64) -> Result<Self> {
+        if count == 0 {
+            bail!("shard count must be greater than zero");
+        }
+        if index >= count {
+            bail!("shard index {index} must be less than the shard count {count}");
+        }
+        Ok(Self { index, count })
+    }
+
+    /// Whether the given test case belongs to this shard.
+    pub fn contains(&self, package: &str, artifact: &impl fmt::Display, case: &str) -> bool {
+        let mut hasher = Sha256::new();
+        for part in [package, &artifact.to_string(), case] {
+            hasher.update(part.len().to_le_bytes());
+            hasher.update(part);
+        }
+        let hash = hasher.finalize();
+        u64::from_le_bytes(hash[..8].try_into().unwrap()) % self.count == self.index
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Parses shards of the form `INDEX/COUNT`, like `0/4`.
+impl FromStr for Shard {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| anyhow!("shard {s:?} isn't of the form INDEX/COUNT"))?;
+        Self::new(index.parse()?, count.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_rejects_bad_index_and_count() {
+        assert!(Shard::new(0, 1).is_ok());
+        assert!(Shard::new(2, 3).is_ok());
+        assert!(Shard::new(0, 0).is_err());
+        assert!(Shard::new(3, 3).is_err());
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!("1/4".parse::<Shard>().unwrap(), Shard::new(1, 4).unwrap());
+        assert_eq!(Shard::new(1, 4).unwrap().to_string(), "1/4");
+        assert!("1".parse::<Shard>().is_err());
+        assert!("a/4".parse::<Shard>().is_err());
+        assert!("4/4".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn every_case_is_in_exactly_one_shard() {
+        let shards = Vec::from_iter((0..3).map(|index| Shard::new(index, 3).unwrap()));
+        for case in 0..100 {
+            let case = format!("test_{case}");
+            let containing = shards
+                .iter()
+                .filter(|shard| shard.contains("foo", &"foo.library", &case))
+                .count();
+            assert_eq!(containing, 1, "{case}");
+        }
+    }
+}
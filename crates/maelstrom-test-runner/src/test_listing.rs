@@ -1,5 +1,6 @@
 use crate::{TestArtifactKey, TestCaseMetadata, TestFilter};
 use anyhow::{anyhow, bail, Result};
+use maelstrom_base::Sha256Digest;
 use maelstrom_client::StateDir;
 use maelstrom_util::{
     fs::Fs,
@@ -10,8 +11,11 @@ use num_traits::FromPrimitive as _;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 use serde_with::{serde_as, DisplayFromStr, DurationSecondsWithFrac};
+use sha2::{Digest as _, Sha256};
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    fmt::Debug,
+    io,
     marker::PhantomData,
     path::Path,
     time::Duration,
@@ -29,6 +33,7 @@ use std::{
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct CaseData<CaseMetadataT> {
     timings: Vec<Duration>,
+    failures: u32,
     metadata: CaseMetadataT,
 }
 
@@ -85,9 +90,55 @@ where
     }
 }
 
+/// The number of timings [`TestListing::add_timing`] keeps for each case, unless configured
+/// otherwise with [`TestListing::max_timings_per_case`].
+pub const DEFAULT_MAX_TIMINGS_PER_CASE: usize = 3;
+
+/// How [`TestListing::get_timing`] turns a case's recorded timings into a single estimate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimingEstimate {
+    /// The mean of the recorded timings.
+    #[default]
+    Mean,
+
+    /// The given percentile of the recorded timings, using the nearest-rank method. For example,
+    /// `Percentile(90)` is the smallest timing that is at least as large as 90% of the timings.
+    /// Values over 100 are treated as 100.
+    Percentile(u8),
+}
+
+/// A test case in a [`ListingDiff`], identified by its package name, artifact key, and case name.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DiffCase<ArtifactKeyT> {
+    pub package_name: String,
+    pub artifact_key: ArtifactKeyT,
+    pub case_name: String,
+}
+
+/// A test case whose estimated duration differs between two listings.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TimingChange<ArtifactKeyT> {
+    pub case: DiffCase<ArtifactKeyT>,
+    pub before: Duration,
+    pub after: Duration,
+}
+
+/// How one test listing differs from another. See [`TestListing::diff`]. Each list is sorted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListingDiff<ArtifactKeyT> {
+    /// Cases that are only in the newer listing.
+    pub added: Vec<DiffCase<ArtifactKeyT>>,
+    /// Cases that are only in the older listing.
+    pub removed: Vec<DiffCase<ArtifactKeyT>>,
+    /// Cases in both listings whose estimated duration changed by more than the threshold.
+    pub timing_changed: Vec<TimingChange<ArtifactKeyT>>,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct TestListing<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> {
     pub packages: HashMap<String, Package<ArtifactKeyT, CaseMetadataT>>,
+    max_timings_per_case: usize,
+    timing_estimate: TimingEstimate,
 }
 
 impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> Default
@@ -96,6 +147,8 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> Default
     fn default() -> Self {
         Self {
             packages: HashMap::new(),
+            max_timings_per_case: DEFAULT_MAX_TIMINGS_PER_CASE,
+            timing_estimate: TimingEstimate::default(),
         }
     }
 }
@@ -111,6 +164,7 @@ where
     fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
         Self {
             packages: HashMap::from_iter(iter.into_iter().map(|(k, v)| (k.into(), v.into()))),
+            ..Default::default()
         }
     }
 }
@@ -118,6 +172,21 @@ where
 impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
     TestListing<ArtifactKeyT, CaseMetadataT>
 {
+    /// Keep at most `max_timings_per_case` of the most recent timings for each case. More timings
+    /// give a steadier estimate for tests whose durations vary a lot. The default is
+    /// [`DEFAULT_MAX_TIMINGS_PER_CASE`].
+    pub fn max_timings_per_case(mut self, max_timings_per_case: usize) -> Self {
+        self.max_timings_per_case = max_timings_per_case;
+        self
+    }
+
+    /// Choose how [`get_timing`](Self::get_timing) estimates a case's duration. The default is
+    /// [`TimingEstimate::Mean`].
+    pub fn timing_estimate(mut self, timing_estimate: TimingEstimate) -> Self {
+        self.timing_estimate = timing_estimate;
+        self
+    }
+
     pub fn update_artifact_cases<K, I, T>(&mut self, package_name: &str, artifact_key: K, cases: I)
     where
         K: Into<ArtifactKeyT>,
@@ -140,6 +209,7 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
                     case_name,
                     CaseData {
                         timings: vec![],
+                        failures: 0,
                         metadata,
                     },
                 )
@@ -168,17 +238,47 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
         });
     }
 
+    /// Iterate over every case in the listing, along with the package and artifact it belongs to.
+    /// The order is unspecified.
+    pub fn iter_cases(
+        &self,
+    ) -> impl Iterator<Item = (&str, &ArtifactKeyT, &str, &CaseData<CaseMetadataT>)> {
+        self.packages.iter().flat_map(|(package_name, package)| {
+            package
+                .artifacts
+                .iter()
+                .flat_map(move |(artifact_key, artifact)| {
+                    artifact.cases.iter().map(move |(case_name, case_data)| {
+                        (
+                            package_name.as_str(),
+                            artifact_key,
+                            case_name.as_str(),
+                            case_data,
+                        )
+                    })
+                })
+        })
+    }
+
     pub fn expected_job_count<TestFilterT>(&self, filter: &TestFilterT) -> u64
     where
         TestFilterT: TestFilter<ArtifactKey = ArtifactKeyT, CaseMetadata = CaseMetadataT>,
     {
-        self.packages
-            .iter()
-            .flat_map(|(p, a)| {
-                a.artifacts
-                    .iter()
-                    .flat_map(move |(a, c)| c.cases.iter().map(move |(c, cd)| (p, a, c, cd)))
-            })
+        self.expected_job_count_where(filter, |_, _, _, _| true)
+    }
+
+    /// Like [`Self::expected_job_count`], but only count cases for which `cases` returns true. It
+    /// is given each case's package name, artifact key, case name, and case metadata.
+    pub fn expected_job_count_where<TestFilterT>(
+        &self,
+        filter: &TestFilterT,
+        cases: impl Fn(&str, &ArtifactKeyT, &str, &CaseMetadataT) -> bool,
+    ) -> u64
+    where
+        TestFilterT: TestFilter<ArtifactKey = ArtifactKeyT, CaseMetadata = CaseMetadataT>,
+    {
+        self.iter_cases()
+            .filter(|(p, a, c, cd)| cases(p, a, c, &cd.metadata))
             .filter(|(p, a, c, cd)| {
                 filter
                     .filter(p, Some(a), Some((c, &cd.metadata)))
@@ -194,7 +294,6 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
         case_name: &str,
         timing: Duration,
     ) {
-        const MAX_TIMINGS_PER_CASE: usize = 3;
         let package = self.packages.entry(package_name.to_owned()).or_default();
         let artifact = package.artifacts.entry(artifact_key).or_default();
         let case = artifact
@@ -202,8 +301,67 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
             .get_mut(case_name)
             .expect("case should have been added");
         case.timings.push(timing);
-        while case.timings.len() > MAX_TIMINGS_PER_CASE {
-            case.timings.remove(0);
+        Self::trim_timings(&mut case.timings, self.max_timings_per_case);
+    }
+
+    /// Record that the case failed or timed out. Unlike timings, failures are never trimmed.
+    pub fn add_failure(&mut self, package_name: &str, artifact_key: ArtifactKeyT, case_name: &str) {
+        let package = self.packages.entry(package_name.to_owned()).or_default();
+        let artifact = package.artifacts.entry(artifact_key).or_default();
+        let case = artifact
+            .cases
+            .get_mut(case_name)
+            .expect("case should have been added");
+        case.failures = case.failures.saturating_add(1);
+    }
+
+    /// The number of times the case has failed or timed out, or zero if the case isn't known.
+    pub fn get_failure_count(
+        &self,
+        package_name: &str,
+        artifact_key: &ArtifactKeyT,
+        case_name: &str,
+    ) -> u32 {
+        self.packages
+            .get(package_name)
+            .and_then(|package| package.artifacts.get(artifact_key))
+            .and_then(|artifact| artifact.cases.get(case_name))
+            .map_or(0, |case| case.failures)
+    }
+
+    /// Merge `other` into this listing, for example to combine the listings produced by different
+    /// machines that each ran part of a test suite. Packages, artifacts, and cases are unioned. For
+    /// cases in both listings, `other`'s timings are treated as the most recent, failure counts are
+    /// added together, and `other`'s metadata wins.
+    pub fn merge(&mut self, other: Self) {
+        for (package_name, other_package) in other.packages {
+            let package = self.packages.entry(package_name).or_default();
+            for (artifact_key, other_artifact) in other_package.artifacts {
+                let artifact = package.artifacts.entry(artifact_key).or_default();
+                for (case_name, other_case) in other_artifact.cases {
+                    match artifact.cases.entry(case_name) {
+                        Entry::Vacant(entry) => {
+                            let case = entry.insert(other_case);
+                            Self::trim_timings(&mut case.timings, self.max_timings_per_case);
+                        }
+                        Entry::Occupied(mut entry) => {
+                            let case = entry.get_mut();
+                            case.timings.extend(other_case.timings);
+                            case.failures = case.failures.saturating_add(other_case.failures);
+                            case.metadata = other_case.metadata;
+                            Self::trim_timings(&mut case.timings, self.max_timings_per_case);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop the oldest timings so that at most `max` remain.
+    fn trim_timings(timings: &mut Vec<Duration>, max: usize) {
+        if timings.len() > max {
+            let excess = timings.len() - max;
+            timings.drain(..excess);
         }
     }
 
@@ -219,15 +377,109 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
         if case.timings.is_empty() {
             return None;
         }
-        let mut avg = Duration::ZERO;
-        let len: u32 = case.timings.len().try_into().unwrap();
-        for timing in &case.timings {
-            avg += *timing / len;
+        match self.timing_estimate {
+            TimingEstimate::Mean => {
+                let mut avg = Duration::ZERO;
+                let len: u32 = case.timings.len().try_into().unwrap();
+                for timing in &case.timings {
+                    avg += *timing / len;
+                }
+                Some(avg)
+            }
+            TimingEstimate::Percentile(percentile) => {
+                let mut timings = case.timings.clone();
+                timings.sort();
+                let percentile = usize::from(percentile.min(100));
+                let rank = (percentile * timings.len()).div_ceil(100);
+                Some(timings[rank.saturating_sub(1)])
+            }
+        }
+    }
+
+    /// Compare this listing against `other`, a newer listing of the same project. A case's timing
+    /// is reported as changed when its estimate from [`get_timing`](Self::get_timing) moved by
+    /// more than `threshold` times the old estimate, in either direction. For example, with a
+    /// `threshold` of `0.5`, a case has to get 50% slower or faster. Cases without any timings in
+    /// either listing are never reported as changed.
+    pub fn diff(&self, other: &Self, threshold: f64) -> ListingDiff<ArtifactKeyT> {
+        let diff_case =
+            |package_name: &str, artifact_key: &ArtifactKeyT, case_name: &str| DiffCase {
+                package_name: package_name.into(),
+                artifact_key: artifact_key.clone(),
+                case_name: case_name.into(),
+            };
+        let mut diff = ListingDiff {
+            added: vec![],
+            removed: vec![],
+            timing_changed: vec![],
+        };
+        for (package_name, artifact_key, case_name, _) in self.iter_cases() {
+            if !other.contains_case(package_name, artifact_key, case_name) {
+                diff.removed
+                    .push(diff_case(package_name, artifact_key, case_name));
+                continue;
+            }
+            let (Some(before), Some(after)) = (
+                self.get_timing(package_name, artifact_key, case_name),
+                other.get_timing(package_name, artifact_key, case_name),
+            ) else {
+                continue;
+            };
+            if before.max(after) - before.min(after) > before.mul_f64(threshold) {
+                diff.timing_changed.push(TimingChange {
+                    case: diff_case(package_name, artifact_key, case_name),
+                    before,
+                    after,
+                });
+            }
+        }
+        for (package_name, artifact_key, case_name, _) in other.iter_cases() {
+            if !self.contains_case(package_name, artifact_key, case_name) {
+                diff.added
+                    .push(diff_case(package_name, artifact_key, case_name));
+            }
+        }
+        diff.added.sort();
+        diff.removed.sort();
+        diff.timing_changed.sort();
+        diff
+    }
+
+    fn contains_case(
+        &self,
+        package_name: &str,
+        artifact_key: &ArtifactKeyT,
+        case_name: &str,
+    ) -> bool {
+        self.packages
+            .get(package_name)
+            .and_then(|package| package.artifacts.get(artifact_key))
+            .is_some_and(|artifact| artifact.cases.contains_key(case_name))
+    }
+
+    fn clear_timings(&mut self) {
+        for package in self.packages.values_mut() {
+            for artifact in package.artifacts.values_mut() {
+                for case in artifact.cases.values_mut() {
+                    case.timings.clear();
+                }
+            }
         }
-        Some(avg)
     }
 }
 
+/// Compute a fingerprint of the environment that test timings are recorded in. Timings recorded
+/// in one environment aren't expected to be useful in another.
+pub fn fingerprint_environment(
+    metadata_hash: &Sha256Digest,
+    collector_options: &impl Debug,
+) -> Sha256Digest {
+    let mut hasher = Sha256::new();
+    hasher.update(metadata_hash.as_bytes());
+    hasher.update(format!("{collector_options:?}"));
+    Sha256Digest::new(hasher.finalize().into())
+}
+
 /*                    _ _     _
  *   ___  _ __     __| (_)___| | __
  *  / _ \| '_ \   / _` | / __| |/ /
@@ -240,13 +492,72 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
 #[repr(u32)]
 enum OnDiskTestListingVersion {
     V3 = 3,
+    V4 = 4,
+}
+
+// The V3 format. It's only read, so that existing listings can be migrated to V4 instead of being
+// discarded.
+
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct OnDiskCaseDataV3<CaseMetadataT: TestCaseMetadata> {
+    #[serde_as(as = "Vec<DurationSecondsWithFrac>")]
+    timings: Vec<Duration>,
+    #[serde(bound(serialize = ""))]
+    #[serde(bound(deserialize = ""))]
+    #[serde(flatten)]
+    metadata: CaseMetadataT,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct OnDiskArtifactV3<CaseMetadataT: TestCaseMetadata> {
+    #[serde(bound(serialize = ""))]
+    #[serde(bound(deserialize = ""))]
+    cases: BTreeMap<String, OnDiskCaseDataV3<CaseMetadataT>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct OnDiskPackageV3<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> {
+    #[serde(bound(serialize = ""))]
+    #[serde(bound(deserialize = ""))]
+    artifacts: BTreeMap<OnDiskArtifactKey<ArtifactKeyT>, OnDiskArtifactV3<CaseMetadataT>>,
 }
 
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+struct OnDiskTestListingV3<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> {
+    version: OnDiskTestListingVersion,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    environment_fingerprint: Option<Sha256Digest>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum: Option<Sha256Digest>,
+    #[serde(flatten)]
+    #[serde(bound(serialize = ""))]
+    #[serde(bound(deserialize = ""))]
+    packages: BTreeMap<String, OnDiskPackageV3<ArtifactKeyT, CaseMetadataT>>,
+}
+
+impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
+    OnDiskTestListingV3<ArtifactKeyT, CaseMetadataT>
+{
+    fn packages_checksum(&self) -> Result<Sha256Digest> {
+        packages_checksum(&self.packages)
+    }
+}
+
+// The V4 format. This is the current format. It adds per-case failure counts to V3.
+
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
-struct OnDiskCaseData<CaseMetadataT: TestCaseMetadata> {
+struct OnDiskCaseDataV4<CaseMetadataT: TestCaseMetadata> {
     #[serde_as(as = "Vec<DurationSecondsWithFrac>")]
     timings: Vec<Duration>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    failures: u32,
     #[serde(bound(serialize = ""))]
     #[serde(bound(deserialize = ""))]
     #[serde(flatten)]
@@ -255,10 +566,10 @@ struct OnDiskCaseData<CaseMetadataT: TestCaseMetadata> {
 
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(transparent)]
-struct OnDiskArtifact<CaseMetadataT: TestCaseMetadata> {
+struct OnDiskArtifactV4<CaseMetadataT: TestCaseMetadata> {
     #[serde(bound(serialize = ""))]
     #[serde(bound(deserialize = ""))]
-    cases: BTreeMap<String, OnDiskCaseData<CaseMetadataT>>,
+    cases: BTreeMap<String, OnDiskCaseDataV4<CaseMetadataT>>,
 }
 
 #[serde_as]
@@ -274,50 +585,60 @@ struct OnDiskArtifactKey<ArtifactKeyT: TestArtifactKey> {
 #[serde_as]
 #[derive(Serialize, Deserialize)]
 #[serde(transparent)]
-struct OnDiskPackage<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> {
+struct OnDiskPackageV4<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> {
     #[serde(bound(serialize = ""))]
     #[serde(bound(deserialize = ""))]
-    artifacts: BTreeMap<OnDiskArtifactKey<ArtifactKeyT>, OnDiskArtifact<CaseMetadataT>>,
+    artifacts: BTreeMap<OnDiskArtifactKey<ArtifactKeyT>, OnDiskArtifactV4<CaseMetadataT>>,
 }
 
+#[serde_as]
 #[derive(Serialize, Deserialize)]
-struct OnDiskTestListing<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> {
+struct OnDiskTestListingV4<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata> {
     version: OnDiskTestListingVersion,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    environment_fingerprint: Option<Sha256Digest>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum: Option<Sha256Digest>,
     #[serde(flatten)]
     #[serde(bound(serialize = ""))]
     #[serde(bound(deserialize = ""))]
-    packages: BTreeMap<String, OnDiskPackage<ArtifactKeyT, CaseMetadataT>>,
+    packages: BTreeMap<String, OnDiskPackageV4<ArtifactKeyT, CaseMetadataT>>,
 }
 
 impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
     From<TestListing<ArtifactKeyT, CaseMetadataT>>
-    for OnDiskTestListing<ArtifactKeyT, CaseMetadataT>
+    for OnDiskTestListingV4<ArtifactKeyT, CaseMetadataT>
 {
     fn from(in_memory: TestListing<ArtifactKeyT, CaseMetadataT>) -> Self {
         Self {
-            version: OnDiskTestListingVersion::V3,
+            version: OnDiskTestListingVersion::V4,
+            environment_fingerprint: None,
+            checksum: None,
             packages: in_memory
                 .packages
                 .into_iter()
                 .map(|(package_name, package)| {
                     (
                         package_name,
-                        OnDiskPackage {
+                        OnDiskPackageV4 {
                             artifacts: package
                                 .artifacts
                                 .into_iter()
                                 .map(|(key, artifact)| {
                                     (
                                         OnDiskArtifactKey { key },
-                                        OnDiskArtifact {
+                                        OnDiskArtifactV4 {
                                             cases: {
                                                 let mut cases =
                                                     Vec::from_iter(artifact.cases.into_iter().map(
                                                         |(case, data)| {
                                                             (
                                                                 case,
-                                                                OnDiskCaseData {
+                                                                OnDiskCaseDataV4 {
                                                                     timings: data.timings,
+                                                                    failures: data.failures,
                                                                     metadata: data.metadata,
                                                                 },
                                                             )
@@ -341,10 +662,69 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
 }
 
 impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
-    From<OnDiskTestListing<ArtifactKeyT, CaseMetadataT>>
+    OnDiskTestListingV4<ArtifactKeyT, CaseMetadataT>
+{
+    /// A digest of the serialized packages. This is stored in the file, and checked when it is
+    /// loaded, to detect corruption.
+    fn packages_checksum(&self) -> Result<Sha256Digest> {
+        packages_checksum(&self.packages)
+    }
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+fn packages_checksum(packages: &impl Serialize) -> Result<Sha256Digest> {
+    let mut hasher = Sha256::new();
+    hasher.update(toml::to_string(packages)?);
+    Ok(Sha256Digest::new(hasher.finalize().into()))
+}
+
+impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
+    From<OnDiskTestListingV3<ArtifactKeyT, CaseMetadataT>>
+    for OnDiskTestListingV4<ArtifactKeyT, CaseMetadataT>
+{
+    fn from(v3: OnDiskTestListingV3<ArtifactKeyT, CaseMetadataT>) -> Self {
+        Self {
+            version: OnDiskTestListingVersion::V4,
+            environment_fingerprint: v3.environment_fingerprint,
+            checksum: v3.checksum,
+            packages: v3
+                .packages
+                .into_iter()
+                .map(|(package_name, package)| {
+                    let artifacts = package
+                        .artifacts
+                        .into_iter()
+                        .map(|(key, artifact)| {
+                            let cases = artifact
+                                .cases
+                                .into_iter()
+                                .map(|(case, data)| {
+                                    let data = OnDiskCaseDataV4 {
+                                        timings: data.timings,
+                                        failures: 0,
+                                        metadata: data.metadata,
+                                    };
+                                    (case, data)
+                                })
+                                .collect();
+                            (key, OnDiskArtifactV4 { cases })
+                        })
+                        .collect();
+                    (package_name, OnDiskPackageV4 { artifacts })
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
+    From<OnDiskTestListingV4<ArtifactKeyT, CaseMetadataT>>
     for TestListing<ArtifactKeyT, CaseMetadataT>
 {
-    fn from(on_disk: OnDiskTestListing<ArtifactKeyT, CaseMetadataT>) -> Self {
+    fn from(on_disk: OnDiskTestListingV4<ArtifactKeyT, CaseMetadataT>) -> Self {
         Self::from_iter(on_disk.packages.into_iter().map(|(package_name, package)| {
             (
                 package_name,
@@ -356,6 +736,7 @@ impl<ArtifactKeyT: TestArtifactKey, CaseMetadataT: TestCaseMetadata>
                                 case,
                                 CaseData {
                                     timings: data.timings,
+                                    failures: data.failures,
                                     metadata: data.metadata,
                                 },
                             )
@@ -385,6 +766,9 @@ pub trait TestListingStoreDeps {
     fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
         unimplemented!("{:?} {:?}", path.as_ref(), contents.as_ref());
     }
+    fn sleep(&self, duration: Duration) {
+        unimplemented!("{duration:?}");
+    }
 }
 
 impl TestListingStoreDeps for Fs {
@@ -399,6 +783,10 @@ impl TestListingStoreDeps for Fs {
     fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
         Fs::write(self, path, contents)
     }
+
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
 }
 
 struct TestListingFile;
@@ -407,11 +795,15 @@ pub struct TestListingStore<ArtifactKeyT, CaseMetadataT, DepsT = Fs> {
     generics: PhantomData<(ArtifactKeyT, CaseMetadataT)>,
     deps: DepsT,
     test_listing_file: RootBuf<TestListingFile>,
+    environment_fingerprint: Option<Sha256Digest>,
+    read_retries: u32,
+    read_retry_delay: Duration,
 }
 
 const MISSING_VERSION: &str = "missing version";
 const VERSION_NOT_AN_INTEGER: &str = "version field is not an integer";
 const TEST_LISTING_FILE: &str = "test-listing.toml";
+const CORRUPT_TEST_LISTING_FILE: &str = "test-listing.toml.corrupt";
 
 impl<ArtifactKeyT, CaseMetadataT, DepsT> TestListingStore<ArtifactKeyT, CaseMetadataT, DepsT> {
     pub fn new(deps: DepsT, state_dir: impl AsRef<Root<StateDir>>) -> Self {
@@ -419,8 +811,29 @@ impl<ArtifactKeyT, CaseMetadataT, DepsT> TestListingStore<ArtifactKeyT, CaseMeta
             generics: PhantomData,
             deps,
             test_listing_file: state_dir.as_ref().join(TEST_LISTING_FILE),
+            environment_fingerprint: None,
+            read_retries: 0,
+            read_retry_delay: Duration::ZERO,
         }
     }
+
+    /// Record the fingerprint of the current environment when saving, and discard the timings of
+    /// a loaded listing that was saved in a different environment. See
+    /// [`fingerprint_environment`].
+    pub fn environment_fingerprint(mut self, environment_fingerprint: Sha256Digest) -> Self {
+        self.environment_fingerprint = Some(environment_fingerprint);
+        self
+    }
+
+    /// Retry reading the listing up to `retries` times, waiting `delay` between attempts, if the
+    /// read fails with an I/O error. This papers over transient errors on network filesystems.
+    /// Other errors, like errors parsing the listing, aren't retried. By default, there are no
+    /// retries.
+    pub fn read_retries(mut self, retries: u32, delay: Duration) -> Self {
+        self.read_retries = retries;
+        self.read_retry_delay = delay;
+        self
+    }
 }
 
 impl<
@@ -429,11 +842,13 @@ impl<
         DepsT: TestListingStoreDeps,
     > TestListingStore<ArtifactKeyT, CaseMetadataT, DepsT>
 {
+    /// Load the listing. A V3 listing is migrated to the current format, with no failures recorded
+    /// for any case. A listing written by an even older or a newer version is ignored, and the
+    /// default listing is returned. A listing whose checksum doesn't match its contents is treated
+    /// as corrupt: it is copied to `test-listing.toml.corrupt` for inspection, and the default
+    /// listing is returned.
     pub fn load(&self) -> Result<TestListing<ArtifactKeyT, CaseMetadataT>> {
-        let Some(contents) = self
-            .deps
-            .read_to_string_if_exists(&self.test_listing_file)?
-        else {
+        let Some(contents) = self.read_with_retries()? else {
             return Ok(Default::default());
         };
         let mut table: toml::Table = toml::from_str(&contents)?;
@@ -443,12 +858,69 @@ impl<
         let Some(version) = version.as_integer() else {
             bail!(VERSION_NOT_AN_INTEGER);
         };
-        match OnDiskTestListingVersion::from_i64(version) {
-            None => Ok(Default::default()),
-            Some(OnDiskTestListingVersion::V3) => Ok(toml::from_str::<
-                OnDiskTestListing<ArtifactKeyT, CaseMetadataT>,
-            >(&contents)?
-            .into()),
+        let on_disk: OnDiskTestListingV4<ArtifactKeyT, CaseMetadataT> =
+            match OnDiskTestListingVersion::from_i64(version) {
+                None => return Ok(Default::default()),
+                Some(OnDiskTestListingVersion::V3) => {
+                    let v3: OnDiskTestListingV3<ArtifactKeyT, CaseMetadataT> =
+                        toml::from_str(&contents)?;
+                    if !self.checksum_matches(&v3.checksum, v3.packages_checksum()?, &contents)? {
+                        return Ok(Default::default());
+                    }
+                    v3.into()
+                }
+                Some(OnDiskTestListingVersion::V4) => {
+                    let v4: OnDiskTestListingV4<ArtifactKeyT, CaseMetadataT> =
+                        toml::from_str(&contents)?;
+                    if !self.checksum_matches(&v4.checksum, v4.packages_checksum()?, &contents)? {
+                        return Ok(Default::default());
+                    }
+                    v4
+                }
+            };
+        let stale = self.environment_fingerprint.is_some()
+            && on_disk.environment_fingerprint != self.environment_fingerprint;
+        let mut listing = TestListing::from(on_disk);
+        if stale {
+            listing.clear_timings();
+        }
+        Ok(listing)
+    }
+
+    /// Check a listing's recorded checksum, if it has one, against the checksum of its contents. On
+    /// a mismatch, the file's contents are copied to `test-listing.toml.corrupt`.
+    fn checksum_matches(
+        &self,
+        recorded: &Option<Sha256Digest>,
+        actual: Sha256Digest,
+        contents: &str,
+    ) -> Result<bool> {
+        if recorded
+            .as_ref()
+            .is_some_and(|recorded| *recorded != actual)
+        {
+            self.deps.write(
+                self.test_listing_file
+                    .parent()
+                    .unwrap()
+                    .join(CORRUPT_TEST_LISTING_FILE),
+                contents,
+            )?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn read_with_retries(&self) -> Result<Option<String>> {
+        let mut retries = self.read_retries;
+        loop {
+            match self.deps.read_to_string_if_exists(&self.test_listing_file) {
+                Err(err) if retries > 0 && err.downcast_ref::<io::Error>().is_some() => {
+                    retries -= 1;
+                    self.deps.sleep(self.read_retry_delay);
+                }
+                result => return result,
+            }
         }
     }
 }
@@ -462,10 +934,11 @@ where
     pub fn save(&self, job_listing: TestListing<ArtifactKeyT, CaseMetadataT>) -> Result<()> {
         self.deps
             .create_dir_all(self.test_listing_file.parent().unwrap())?;
-        self.deps.write(
-            &self.test_listing_file,
-            toml::to_string::<OnDiskTestListing<ArtifactKeyT, CaseMetadataT>>(&job_listing.into())?,
-        )
+        let mut on_disk = OnDiskTestListingV4::<ArtifactKeyT, CaseMetadataT>::from(job_listing);
+        on_disk.environment_fingerprint = self.environment_fingerprint.clone();
+        on_disk.checksum = Some(on_disk.packages_checksum()?);
+        self.deps
+            .write(&self.test_listing_file, toml::to_string(&on_disk)?)
     }
 }
 
@@ -473,7 +946,7 @@ where
 mod tests {
     use super::*;
     use crate::{NoCaseMetadata, SimpleFilter, StringArtifactKey};
-    use indoc::indoc;
+    use indoc::{formatdoc, indoc};
     use maelstrom_test::millis;
     use maelstrom_util::ext::OptionExt as _;
     use pretty_assertions::assert_eq;
@@ -493,6 +966,7 @@ mod tests {
                 name,
                 CaseData {
                     timings: timings,
+                    failures: 0,
                     metadata: NoCaseMetadata,
                 },
             )
@@ -750,6 +1224,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn iter_cases() {
+        let listing = TestListing::<StringArtifactKey, NoCaseMetadata>::from_iter([
+            (
+                "package-1",
+                Package::from_iter([
+                    (
+                        StringArtifactKey::from("artifact-1.library"),
+                        artifact_from_timings([
+                            ("case-1-1L-1", vec![millis!(10), millis!(11)]),
+                            ("case-1-1L-2", vec![]),
+                        ]),
+                    ),
+                    (
+                        StringArtifactKey::from("artifact-1.binary"),
+                        artifact_from_timings([("case-1-1B-1", vec![millis!(15)])]),
+                    ),
+                ]),
+            ),
+            (
+                "package-2",
+                Package::from_iter([(
+                    StringArtifactKey::from("artifact-1.library"),
+                    artifact_from_timings([("case-2-1L-1", vec![])]),
+                )]),
+            ),
+            ("package-3", Package::default()),
+        ]);
+
+        let mut cases = Vec::from_iter(
+            listing
+                .iter_cases()
+                .map(|(p, a, c, cd)| (p.to_owned(), a.clone(), c.to_owned(), cd.timings.clone())),
+        );
+        cases.sort();
+        assert_eq!(
+            cases,
+            vec![
+                (
+                    "package-1".into(),
+                    StringArtifactKey::from("artifact-1.binary"),
+                    "case-1-1B-1".into(),
+                    vec![millis!(15)],
+                ),
+                (
+                    "package-1".into(),
+                    StringArtifactKey::from("artifact-1.library"),
+                    "case-1-1L-1".into(),
+                    vec![millis!(10), millis!(11)],
+                ),
+                (
+                    "package-1".into(),
+                    StringArtifactKey::from("artifact-1.library"),
+                    "case-1-1L-2".into(),
+                    vec![],
+                ),
+                (
+                    "package-2".into(),
+                    StringArtifactKey::from("artifact-1.library"),
+                    "case-2-1L-1".into(),
+                    vec![],
+                ),
+            ]
+        );
+    }
+
     #[test]
     fn add_timing() {
         let mut listing = TestListing::default();
@@ -873,6 +1413,141 @@ mod tests {
                 )]),
             )]),
         );
+
+        let mut listing = listing.max_timings_per_case(5);
+        for timing in [millis!(16), millis!(17), millis!(18)] {
+            listing.add_timing(
+                "package-1",
+                StringArtifactKey::from("artifact-1.library"),
+                "case-1-1L-1",
+                timing,
+            );
+        }
+        assert_eq!(
+            listing.packages["package-1"].artifacts[&StringArtifactKey::from("artifact-1.library")]
+                .cases["case-1-1L-1"]
+                .timings,
+            vec![
+                millis!(14),
+                millis!(15),
+                millis!(16),
+                millis!(17),
+                millis!(18)
+            ]
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let mut listing = TestListing::<StringArtifactKey, NoCaseMetadata>::from_iter([(
+            "package-1",
+            Package::from_iter([(
+                StringArtifactKey::from("artifact-1.library"),
+                artifact_from_timings([
+                    ("case-1-1L-1", vec![millis!(10), millis!(11)]),
+                    ("case-1-1L-2", vec![millis!(20)]),
+                ]),
+            )]),
+        )]);
+        let other = TestListing::from_iter([
+            (
+                "package-1",
+                Package::from_iter([
+                    (
+                        StringArtifactKey::from("artifact-1.library"),
+                        artifact_from_timings([
+                            ("case-1-1L-1", vec![millis!(12), millis!(13)]),
+                            ("case-1-1L-3", vec![millis!(30)]),
+                        ]),
+                    ),
+                    (
+                        StringArtifactKey::from("artifact-1.binary"),
+                        artifact_from_timings([(
+                            "case-1-1B-1",
+                            vec![millis!(1), millis!(2), millis!(3), millis!(4)],
+                        )]),
+                    ),
+                ]),
+            ),
+            (
+                "package-2",
+                Package::from_iter([(
+                    StringArtifactKey::from("artifact-2.library"),
+                    artifact_from_timings([("case-2-2L-1", vec![])]),
+                )]),
+            ),
+        ]);
+        listing.merge(other);
+
+        let expected = TestListing::from_iter([
+            (
+                "package-1",
+                Package::from_iter([
+                    (
+                        StringArtifactKey::from("artifact-1.library"),
+                        artifact_from_timings([
+                            ("case-1-1L-1", vec![millis!(11), millis!(12), millis!(13)]),
+                            ("case-1-1L-2", vec![millis!(20)]),
+                            ("case-1-1L-3", vec![millis!(30)]),
+                        ]),
+                    ),
+                    (
+                        StringArtifactKey::from("artifact-1.binary"),
+                        artifact_from_timings([(
+                            "case-1-1B-1",
+                            vec![millis!(2), millis!(3), millis!(4)],
+                        )]),
+                    ),
+                ]),
+            ),
+            (
+                "package-2",
+                Package::from_iter([(
+                    StringArtifactKey::from("artifact-2.library"),
+                    artifact_from_timings([("case-2-2L-1", vec![])]),
+                )]),
+            ),
+        ]);
+        assert_eq!(listing, expected);
+    }
+
+    #[test]
+    fn add_failure() {
+        let mut listing = TestListing::<StringArtifactKey, NoCaseMetadata>::from_iter([(
+            "package-1",
+            Package::from_iter([(
+                StringArtifactKey::from("artifact-1.library"),
+                artifact_from_timings([("case-1-1L-1", vec![]), ("case-1-1L-2", vec![])]),
+            )]),
+        )]);
+        let artifact_key = StringArtifactKey::from("artifact-1.library");
+        listing.add_failure("package-1", artifact_key.clone(), "case-1-1L-1");
+        listing.add_failure("package-1", artifact_key.clone(), "case-1-1L-1");
+
+        assert_eq!(
+            listing.get_failure_count("package-1", &artifact_key, "case-1-1L-1"),
+            2
+        );
+        assert_eq!(
+            listing.get_failure_count("package-1", &artifact_key, "case-1-1L-2"),
+            0
+        );
+        assert_eq!(
+            listing.get_failure_count("package-1", &artifact_key, "case-1-1L-3"),
+            0
+        );
+
+        let mut other = listing.clone();
+        other.add_failure("package-1", artifact_key.clone(), "case-1-1L-2");
+        listing.merge(other);
+        assert_eq!(
+            listing.get_failure_count("package-1", &artifact_key, "case-1-1L-1"),
+            4
+        );
+        assert_eq!(
+            listing.get_failure_count("package-1", &artifact_key, "case-1-1L-2"),
+            1
+        );
     }
 
     #[test]
@@ -914,6 +1589,176 @@ mod tests {
             None
         );
         assert_eq!(listing.get_timing("package-2", &artifact_1, "case-1"), None);
+
+        let listing = listing.timing_estimate(TimingEstimate::Percentile(90));
+        assert_eq!(listing.get_timing("package-1", &artifact_1, "case-1"), None);
+        assert_eq!(
+            listing.get_timing("package-1", &artifact_1, "case-2"),
+            Some(millis!(10))
+        );
+        assert_eq!(
+            listing.get_timing("package-1", &artifact_1, "case-4"),
+            Some(millis!(16))
+        );
+    }
+
+    #[test]
+    fn get_timing_percentile_of_larger_window() {
+        let artifact_1 = StringArtifactKey::from("artifact-1.library");
+        // Ten timings, out of order, with one slow outlier.
+        let timings = vec![
+            millis!(14),
+            millis!(10),
+            millis!(19),
+            millis!(11),
+            millis!(100),
+            millis!(13),
+            millis!(18),
+            millis!(12),
+            millis!(16),
+            millis!(15),
+        ];
+        let listing = TestListing::<StringArtifactKey, NoCaseMetadata>::from_iter([(
+            "package-1",
+            Package::from_iter([(
+                artifact_1.clone(),
+                artifact_from_timings([("case-1", timings)]),
+            )]),
+        )])
+        .max_timings_per_case(10);
+
+        let estimate = |estimate| {
+            listing
+                .clone()
+                .timing_estimate(estimate)
+                .get_timing("package-1", &artifact_1, "case-1")
+        };
+        assert_eq!(
+            estimate(TimingEstimate::Mean),
+            Some(Duration::from_micros(22_800))
+        );
+        assert_eq!(estimate(TimingEstimate::Percentile(0)), Some(millis!(10)));
+        assert_eq!(estimate(TimingEstimate::Percentile(50)), Some(millis!(14)));
+        assert_eq!(estimate(TimingEstimate::Percentile(90)), Some(millis!(19)));
+        assert_eq!(estimate(TimingEstimate::Percentile(91)), Some(millis!(100)));
+        assert_eq!(
+            estimate(TimingEstimate::Percentile(100)),
+            Some(millis!(100))
+        );
+        assert_eq!(
+            estimate(TimingEstimate::Percentile(200)),
+            Some(millis!(100))
+        );
+    }
+
+    fn diff_case(
+        package_name: &str,
+        artifact_key: &str,
+        case_name: &str,
+    ) -> DiffCase<StringArtifactKey> {
+        DiffCase {
+            package_name: package_name.into(),
+            artifact_key: StringArtifactKey::from(artifact_key),
+            case_name: case_name.into(),
+        }
+    }
+
+    #[test]
+    fn diff() {
+        let before = TestListing::<StringArtifactKey, NoCaseMetadata>::from_iter([
+            (
+                "package-1",
+                Package::from_iter([
+                    (
+                        StringArtifactKey::from("artifact-1.library"),
+                        artifact_from_timings([
+                            ("unchanged", vec![millis!(10)]),
+                            ("slightly-slower", vec![millis!(10)]),
+                            ("regressed", vec![millis!(10), millis!(12)]),
+                            ("improved", vec![millis!(100)]),
+                            ("untimed", vec![]),
+                            ("removed", vec![millis!(10)]),
+                        ]),
+                    ),
+                    (
+                        StringArtifactKey::from("artifact-1.binary"),
+                        artifact_from_timings([("removed-with-artifact", vec![])]),
+                    ),
+                ]),
+            ),
+            (
+                "package-2",
+                Package::from_iter([(
+                    StringArtifactKey::from("artifact-2.library"),
+                    artifact_from_timings([("removed-with-package", vec![millis!(10)])]),
+                )]),
+            ),
+        ]);
+        let after = TestListing::<StringArtifactKey, NoCaseMetadata>::from_iter([
+            (
+                "package-1",
+                Package::from_iter([(
+                    StringArtifactKey::from("artifact-1.library"),
+                    artifact_from_timings([
+                        ("unchanged", vec![millis!(10)]),
+                        ("slightly-slower", vec![millis!(14)]),
+                        ("regressed", vec![millis!(10), millis!(30)]),
+                        ("improved", vec![millis!(20)]),
+                        ("untimed", vec![millis!(1000)]),
+                        ("added", vec![millis!(10)]),
+                    ]),
+                )]),
+            ),
+            (
+                "package-3",
+                Package::from_iter([(
+                    StringArtifactKey::from("artifact-3.library"),
+                    artifact_from_timings([("added-with-package", vec![])]),
+                )]),
+            ),
+        ]);
+
+        assert_eq!(
+            before.diff(&after, 0.5),
+            ListingDiff {
+                added: vec![
+                    diff_case("package-1", "artifact-1.library", "added"),
+                    diff_case("package-3", "artifact-3.library", "added-with-package"),
+                ],
+                removed: vec![
+                    diff_case("package-1", "artifact-1.binary", "removed-with-artifact"),
+                    diff_case("package-1", "artifact-1.library", "removed"),
+                    diff_case("package-2", "artifact-2.library", "removed-with-package"),
+                ],
+                timing_changed: vec![
+                    TimingChange {
+                        case: diff_case("package-1", "artifact-1.library", "improved"),
+                        before: millis!(100),
+                        after: millis!(20),
+                    },
+                    TimingChange {
+                        case: diff_case("package-1", "artifact-1.library", "regressed"),
+                        before: millis!(11),
+                        after: millis!(20),
+                    },
+                ],
+            }
+        );
+
+        let diff = before.diff(&after, 0.2);
+        assert_eq!(
+            Vec::from_iter(
+                diff.timing_changed
+                    .iter()
+                    .map(|change| change.case.case_name.as_str())
+            ),
+            vec!["improved", "regressed", "slightly-slower"]
+        );
+
+        let diff = before.diff(&before, 0.0);
+        assert_eq!(diff.added, vec![]);
+        assert_eq!(diff.removed, vec![]);
+        assert_eq!(diff.timing_changed, vec![]);
     }
 
     #[test]
@@ -949,6 +1794,98 @@ mod tests {
         assert_eq!(store.load().unwrap_err().to_string(), "error!");
     }
 
+    #[derive(Default)]
+    struct FlakyReadDeps {
+        failures: u32,
+        contents: Option<String>,
+        reads: RefCell<u32>,
+        sleeps: RefCell<Vec<Duration>>,
+    }
+
+    impl TestListingStoreDeps for FlakyReadDeps {
+        fn read_to_string_if_exists(&self, _: impl AsRef<Path>) -> Result<Option<String>> {
+            let mut reads = self.reads.borrow_mut();
+            *reads += 1;
+            if *reads <= self.failures {
+                Err(io::Error::other(format!("error {reads}!")).into())
+            } else {
+                Ok(self.contents.clone())
+            }
+        }
+
+        fn sleep(&self, duration: Duration) {
+            self.sleeps.borrow_mut().push(duration);
+        }
+    }
+
+    #[test]
+    fn load_retries_read_errors() {
+        let store = TestListingStore::<StringArtifactKey, NoCaseMetadata, _>::new(
+            FlakyReadDeps {
+                failures: 2,
+                ..Default::default()
+            },
+            RootBuf::new("".into()),
+        )
+        .read_retries(3, millis!(10));
+        assert_eq!(store.load().unwrap(), TestListing::default());
+        assert_eq!(*store.deps.reads.borrow(), 3);
+        assert_eq!(*store.deps.sleeps.borrow(), vec![millis!(10), millis!(10)]);
+    }
+
+    #[test]
+    fn load_gives_up_after_read_retries_are_exhausted() {
+        let store = TestListingStore::<StringArtifactKey, NoCaseMetadata, _>::new(
+            FlakyReadDeps {
+                failures: 5,
+                ..Default::default()
+            },
+            RootBuf::new("".into()),
+        )
+        .read_retries(2, millis!(10));
+        assert_eq!(store.load().unwrap_err().to_string(), "error 3!");
+        assert_eq!(*store.deps.reads.borrow(), 3);
+        assert_eq!(*store.deps.sleeps.borrow(), vec![millis!(10), millis!(10)]);
+    }
+
+    #[test]
+    fn load_does_not_retry_non_io_read_errors() {
+        struct Deps(RefCell<u32>);
+        impl TestListingStoreDeps for Deps {
+            fn read_to_string_if_exists(&self, _: impl AsRef<Path>) -> Result<Option<String>> {
+                *self.0.borrow_mut() += 1;
+                Err(anyhow!("error!"))
+            }
+
+            fn sleep(&self, _: Duration) {
+                panic!("unexpected sleep");
+            }
+        }
+        let store = TestListingStore::<StringArtifactKey, NoCaseMetadata, _>::new(
+            Deps(RefCell::new(0)),
+            RootBuf::new("".into()),
+        )
+        .read_retries(3, millis!(10));
+        assert_eq!(store.load().unwrap_err().to_string(), "error!");
+        assert_eq!(*store.deps.0.borrow(), 1);
+    }
+
+    #[test]
+    fn load_does_not_retry_parse_errors() {
+        let store = TestListingStore::<StringArtifactKey, NoCaseMetadata, _>::new(
+            FlakyReadDeps {
+                contents: Some(r#""garbage": { "foo", "bar" }"#.into()),
+                ..Default::default()
+            },
+            RootBuf::new("".into()),
+        )
+        .read_retries(3, millis!(10));
+        let error = store.load().unwrap_err().to_string();
+        assert!(error.starts_with("TOML parse error"));
+        assert_eq!(*store.deps.reads.borrow(), 1);
+        assert!(store.deps.sleeps.borrow().is_empty());
+    }
+
     #[test]
     fn load_of_nonexistent_file_gives_default_listing() {
         struct Deps;
@@ -1098,6 +2035,67 @@ mod tests {
         assert_eq!(store.load().unwrap(), expected);
     }
 
+    #[test]
+    fn load_of_v3_file_preserves_timings_and_saves_as_v4() {
+        let file = Rc::new(RefCell::new(Some(
+            indoc! {r#"
+                version = 3
+
+                [package-1."artifact-1.library".case-1-1L-1]
+                timings = [0.01, 0.011]
+
+                [package-1."artifact-1.library".case-1-1L-2]
+                timings = []
+            "#}
+            .into(),
+        )));
+        let store = TestListingStore::new(FileDeps(file.clone()), RootBuf::new("".into()));
+        let listing = store.load().unwrap();
+        assert_eq!(listing, listing_with_timings());
+
+        store.save(listing).unwrap();
+        assert!(file.borrow().as_ref().unwrap().starts_with("version = 4\n"));
+        assert_eq!(store.load().unwrap(), listing_with_timings());
+    }
+
+    #[test]
+    fn save_and_load_preserve_failure_counts() {
+        let file = Rc::new(RefCell::new(None));
+        let store = TestListingStore::new(FileDeps(file.clone()), RootBuf::new("".into()));
+        let mut listing = listing_with_timings();
+        let artifact_key = StringArtifactKey::from("artifact-1.library");
+        listing.add_failure("package-1", artifact_key.clone(), "case-1-1L-2");
+        store.save(listing.clone()).unwrap();
+        assert!(file.borrow().as_ref().unwrap().contains(indoc! {r#"
+            [package-1."artifact-1.library".case-1-1L-2]
+            timings = []
+            failures = 1
+        "#}));
+
+        let loaded = store.load().unwrap();
+        assert_eq!(loaded, listing);
+        assert_eq!(
+            loaded.get_failure_count("package-1", &artifact_key, "case-1-1L-2"),
+            1
+        );
+    }
+
+    #[test]
+    fn load_of_v3_file_with_mismatched_checksum_gives_default_listing() {
+        let file = Rc::new(RefCell::new(Some(formatdoc! {r#"
+            version = 3
+            checksum = "{}"
+
+            [package-1."artifact-1.library".case-1-1L-1]
+            timings = [0.01, 0.011]
+        "#, Sha256Digest::from(0u32)})));
+        let store = TestListingStore::<StringArtifactKey, NoCaseMetadata, _>::new(
+            FileDeps(file.clone()),
+            RootBuf::new("".into()),
+        );
+        assert_eq!(store.load().unwrap(), TestListing::default());
+    }
+
     #[test]
     fn load_of_file_with_correct_version_but_bad_toml_gives_toml_parse_error() {
         struct Deps;
@@ -1127,6 +2125,96 @@ mod tests {
         assert!(error.starts_with("TOML parse error"));
     }
 
+    struct FileDeps(Rc<RefCell<Option<String>>>);
+
+    impl TestListingStoreDeps for FileDeps {
+        fn read_to_string_if_exists(&self, _: impl AsRef<Path>) -> Result<Option<String>> {
+            Ok(self.0.borrow().clone())
+        }
+        fn create_dir_all(&self, _: impl AsRef<Path>) -> Result<()> {
+            Ok(())
+        }
+        fn write(&self, _: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+            *self.0.borrow_mut() = Some(str::from_utf8(contents.as_ref()).unwrap().into());
+            Ok(())
+        }
+    }
+
+    fn listing_with_timings() -> TestListing<StringArtifactKey, NoCaseMetadata> {
+        TestListing::from_iter([(
+            "package-1",
+            Package::from_iter([(
+                StringArtifactKey::from("artifact-1.library"),
+                artifact_from_timings([
+                    ("case-1-1L-1", vec![millis!(10), millis!(11)]),
+                    ("case-1-1L-2", vec![]),
+                ]),
+            )]),
+        )])
+    }
+
+    fn store_with_fingerprint(
+        file: &Rc<RefCell<Option<String>>>,
+        metadata_hash: u64,
+    ) -> TestListingStore<StringArtifactKey, NoCaseMetadata, FileDeps> {
+        TestListingStore::new(FileDeps(file.clone()), RootBuf::new("".into()))
+            .environment_fingerprint(fingerprint_environment(
+                &Sha256Digest::from(metadata_hash),
+                &"options",
+            ))
+    }
+
+    #[test]
+    fn fingerprint_environment_depends_on_metadata_and_options() {
+        let fingerprint = fingerprint_environment(&Sha256Digest::from(1u64), &"options");
+        assert_eq!(
+            fingerprint,
+            fingerprint_environment(&Sha256Digest::from(1u64), &"options")
+        );
+        assert_ne!(
+            fingerprint,
+            fingerprint_environment(&Sha256Digest::from(2u64), &"options")
+        );
+        assert_ne!(
+            fingerprint,
+            fingerprint_environment(&Sha256Digest::from(1u64), &"other options")
+        );
+    }
+
+    #[test]
+    fn load_with_unchanged_environment_preserves_timings() {
+        let file = Rc::new(RefCell::new(None));
+        store_with_fingerprint(&file, 1)
+            .save(listing_with_timings())
+            .unwrap();
+        assert_eq!(
+            store_with_fingerprint(&file, 1).load().unwrap(),
+            listing_with_timings()
+        );
+    }
+
+    #[test]
+    fn load_with_changed_metadata_hash_discards_timings() {
+        let file = Rc::new(RefCell::new(None));
+        store_with_fingerprint(&file, 1)
+            .save(listing_with_timings())
+            .unwrap();
+        let mut expected = listing_with_timings();
+        expected.clear_timings();
+        assert_eq!(store_with_fingerprint(&file, 2).load().unwrap(), expected);
+    }
+
+    #[test]
+    fn load_without_recorded_fingerprint_discards_timings() {
+        let file = Rc::new(RefCell::new(None));
+        TestListingStore::new(FileDeps(file.clone()), RootBuf::new("".into()))
+            .save(listing_with_timings())
+            .unwrap();
+        let mut expected = listing_with_timings();
+        expected.clear_timings();
+        assert_eq!(store_with_fingerprint(&file, 1).load().unwrap(), expected);
+    }
+
     #[test]
     fn error_creating_dir_in_save_propagates_error() {
         struct Deps;
@@ -1192,6 +2280,53 @@ mod tests {
         }
     }
 
+    fn checksum(listing: TestListing<StringArtifactKey, NoCaseMetadata>) -> Sha256Digest {
+        OnDiskTestListingV4::from(listing)
+            .packages_checksum()
+            .unwrap()
+    }
+
+    #[test]
+    fn load_of_file_with_matching_checksum_gives_deserialized_listing() {
+        let file = Rc::new(RefCell::new(None));
+        let store = TestListingStore::new(FileDeps(file.clone()), RootBuf::new("".into()));
+        store.save(listing_with_timings()).unwrap();
+        assert!(file.borrow().as_ref().unwrap().contains("checksum = "));
+        assert_eq!(store.load().unwrap(), listing_with_timings());
+    }
+
+    #[test]
+    fn load_of_file_with_mismatched_checksum_backs_it_up_and_gives_default_listing() {
+        let file = Rc::new(RefCell::new(None));
+        TestListingStore::new(FileDeps(file.clone()), RootBuf::new("".into()))
+            .save(listing_with_timings())
+            .unwrap();
+        let tampered = file.borrow().as_ref().unwrap().replace("0.011", "0.012");
+
+        let deps = Rc::new(RefCell::new(LoggingDeps::default()));
+        struct TamperedDeps(String, Rc<RefCell<LoggingDeps>>);
+        impl TestListingStoreDeps for TamperedDeps {
+            fn read_to_string_if_exists(&self, _: impl AsRef<Path>) -> Result<Option<String>> {
+                Ok(Some(self.0.clone()))
+            }
+            fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<()> {
+                self.1.write(path, contents)
+            }
+        }
+        let store = TestListingStore::<StringArtifactKey, NoCaseMetadata, _>::new(
+            TamperedDeps(tampered.clone(), deps.clone()),
+            RootBuf::new("maelstrom/state".into()),
+        );
+        assert_eq!(store.load().unwrap(), TestListing::default());
+        assert_eq!(
+            deps.borrow().write,
+            Some((
+                format!("maelstrom/state/{CORRUPT_TEST_LISTING_FILE}"),
+                tampered
+            ))
+        );
+    }
+
     #[test]
     fn save_creates_parent_directory() {
         let deps = Rc::new(RefCell::new(LoggingDeps::default()));
@@ -1215,7 +2350,10 @@ mod tests {
             deps.borrow().write,
             Some((
                 format!("maelstrom/state/{TEST_LISTING_FILE}"),
-                "version = 3\n".into()
+                format!(
+                    "version = 4\nchecksum = \"{}\"\n",
+                    checksum(TestListing::default())
+                )
             ))
         );
     }
@@ -1256,13 +2394,15 @@ mod tests {
                 ]),
             ),
         ]);
+        let checksum = checksum(listing.clone());
         store.save(listing).unwrap();
         let (actual_path, actual_contents) = deps.borrow_mut().write.take().unwrap();
         assert_eq!(actual_path, format!("maelstrom/state/{TEST_LISTING_FILE}"));
         assert_eq!(
             actual_contents,
-            indoc! {r#"
-                version = 3
+            formatdoc! {r#"
+                version = 4
+                checksum = "{checksum}"
 
                 [package-1."artifact-1.binary".case-1-1B-1]
                 timings = [0.015, 0.016]
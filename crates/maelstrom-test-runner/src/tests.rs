@@ -3,10 +3,12 @@ mod fake_test_framework;
 use crate::{
     config::Quiet,
     introspect_driver::IntrospectDriver,
+    shard::Shard,
     test_listing::TestListingStore,
-    ui::{self, Ui as _},
-    BuildDir, ClientTrait, EnqueueResult, ListAction, LoggingOutput, MainApp, MainAppDeps,
-    MainAppState,
+    ui::{self, SkipReason, Ui as _},
+    visitor::TimingSink,
+    AffectedPackages, BuildDir, ClientTrait, EnqueueResult, ListAction, LoggingOutput, MainApp,
+    MainAppDeps, MainAppState, TestOrdering, NO_TESTS_MATCHED_EXIT_CODE,
 };
 use anyhow::Result;
 use fake_test_framework::{
@@ -17,7 +19,7 @@ use indoc::indoc;
 use maelstrom_base::{
     stats::{JobState, JobStateCounts},
     ArtifactType, ClientJobId, JobCompleted, JobEffects, JobOutcome, JobOutcomeResult,
-    JobOutputResult, JobStatus, Sha256Digest,
+    JobOutputResult, JobStatus, Sha256Digest, Timeout,
 };
 use maelstrom_client::{
     spec::{JobSpec, Layer},
@@ -27,14 +29,21 @@ use maelstrom_test::digest;
 use maelstrom_util::{
     fs::Fs,
     log::test_logger,
+    process::ExitCode,
     root::{Root, RootBuf},
     template::TemplateVars,
 };
 use pretty_assertions::assert_eq;
 use std::{
     cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
     rc::Rc,
-    sync::atomic::{AtomicU32, Ordering},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
     time::Duration,
 };
 use tempfile::tempdir;
@@ -57,6 +66,7 @@ impl TestMainAppDeps {
                 tests,
                 bin_path,
                 target_dir,
+                started_packages: Default::default(),
             },
         }
     }
@@ -147,91 +157,241 @@ impl<'scope> TestIntrospectDriver<'scope> {
     }
 }
 
-#[allow(clippy::too_many_arguments)]
-fn run_app(
-    bin_dir: &Root<BinDir>,
-    term: InMemoryTerm,
+type TestAppState = MainAppState<TestMainAppDeps>;
+
+/// A run of the app against some fake tests. Create one with [`TestRun::new`], adjust it with the
+/// builder methods, and then call [`TestRun::run`].
+struct TestRun<'a> {
+    bin_dir: RootBuf<BinDir>,
+    project_dir: RootBuf<ProjectDir>,
     fake_tests: FakeTests,
-    project_dir: &Root<ProjectDir>,
-    stdout_tty: bool,
-    quiet: Quiet,
     include_filter: Vec<String>,
     exclude_filter: Vec<String>,
+    shard: Option<Shard>,
     list: Option<ListAction>,
+    timeout_override: Option<Option<Timeout>>,
     finish: bool,
-) -> String {
-    let fs = Fs::new();
-    let log = test_logger();
-    let packages = fake_tests.packages();
-    slog::info!(
-        log, "doing test";
-        "quiet" => ?quiet,
-        "include_filter" => ?include_filter,
-        "exclude_filter" => ?exclude_filter,
-        "list" => ?list,
-        "packages" => ?packages
-    );
+    configure_state: Box<dyn FnOnce(TestAppState) -> TestAppState + 'a>,
+    on_enqueue: Box<OnEnqueue<'a>>,
+}
 
-    fs.create_dir_all(bin_dir).unwrap();
-    fake_tests.create_binaries(&fs, bin_dir);
+/// Called with the package and case name of each job as it is enqueued.
+type OnEnqueue<'a> = dyn FnMut(&TestAppState, &str, &str) + 'a;
+
+/// What happened during a [`TestRun`].
+struct TestRunResult {
+    state: TestAppState,
+    ui_recv: std::sync::mpsc::Receiver<ui::UiMessage>,
+    /// The package and case of every job enqueued, in order.
+    enqueued: Vec<(String, String)>,
+    skipped: BTreeMap<SkipReason, usize>,
+    /// `None` if the run wasn't finished.
+    exit_code: Option<ExitCode>,
+}
 
-    let target_directory = project_dir.join::<BuildDir>("target");
-    let deps = TestMainAppDeps::new(
-        fake_tests.clone(),
-        bin_dir.to_owned(),
-        target_directory.clone(),
-    );
+impl<'a> TestRun<'a> {
+    /// Run all of `fake_tests`, with the binaries in `{tmp_dir}/bin` and the project in
+    /// `{tmp_dir}/project`.
+    fn new(tmp_dir: &Root<TmpDir>, fake_tests: FakeTests) -> Self {
+        Self::with_dirs(
+            &tmp_dir.join::<BinDir>("bin"),
+            &tmp_dir.join::<ProjectDir>("project"),
+            fake_tests,
+        )
+    }
 
-    let is_list = list.is_some();
-    let state = MainAppState::new(
-        deps,
-        include_filter,
-        exclude_filter,
-        list,
-        false, // stderr_color
-        project_dir,
-        &packages,
-        target_directory.join::<StateDir>("maelstrom/state"),
-        TestOptions,
-        LoggingOutput::default(),
-        log.clone(),
-    )
-    .unwrap();
-    let (ui_send, ui_recv) = std::sync::mpsc::channel();
-    let ui_sender = ui::UiSender::new(ui_send);
-    let introspect_driver = TestIntrospectDriver::default();
-    let mut app = MainApp::new(&state, ui_sender, introspect_driver.clone(), None).unwrap();
-
-    let mut running = vec![];
-    loop {
-        let res = app.enqueue_one().unwrap();
-        let (package_name, case) = match res {
-            EnqueueResult::Done => break,
-            EnqueueResult::Ignored | EnqueueResult::Listed => continue,
-            EnqueueResult::Enqueued { package_name, case } => (package_name, case),
-        };
-        let test = fake_tests.find_case(&package_name, &case);
-        running.push(test.desired_state);
+    fn with_dirs(
+        bin_dir: &Root<BinDir>,
+        project_dir: &Root<ProjectDir>,
+        fake_tests: FakeTests,
+    ) -> Self {
+        Self {
+            bin_dir: bin_dir.to_owned(),
+            project_dir: project_dir.to_owned(),
+            fake_tests,
+            include_filter: vec!["all".into()],
+            exclude_filter: vec![],
+            shard: None,
+            list: None,
+            timeout_override: None,
+            finish: true,
+            configure_state: Box::new(|state| state),
+            on_enqueue: Box::new(|_, _, _| {}),
+        }
+    }
+
+    fn include_filter(mut self, include_filter: Vec<String>) -> Self {
+        self.include_filter = include_filter;
+        self
+    }
+
+    fn exclude_filter(mut self, exclude_filter: Vec<String>) -> Self {
+        self.exclude_filter = exclude_filter;
+        self
+    }
+
+    fn shard(mut self, shard: Option<Shard>) -> Self {
+        self.shard = shard;
+        self
+    }
 
-        introspect_driver.update(counts_from_states(&running));
+    fn list(mut self, list: Option<ListAction>) -> Self {
+        self.list = list;
+        self
     }
 
-    app.drain().unwrap();
+    fn timeout_override(mut self, timeout: Option<Timeout>) -> Self {
+        self.timeout_override = Some(timeout);
+        self
+    }
 
-    if finish {
-        app.finish().unwrap();
+    /// Whether to wait for the jobs and finish the run once everything is enqueued.
+    fn finish(mut self, finish: bool) -> Self {
+        self.finish = finish;
+        self
     }
 
-    drop(app);
-    drop(introspect_driver);
-    drop(state);
+    /// Call the [`MainAppState`] builder methods before the app is created.
+    fn configure_state(
+        mut self,
+        configure_state: impl FnOnce(TestAppState) -> TestAppState + 'a,
+    ) -> Self {
+        self.configure_state = Box::new(configure_state);
+        self
+    }
 
-    let mut ui = ui::SimpleUi::new(is_list, stdout_tty, quiet, term.clone());
-    ui.run(ui_recv).unwrap();
+    /// Called with the package and case of each job right after it's enqueued.
+    fn on_enqueue(mut self, on_enqueue: impl FnMut(&TestAppState, &str, &str) + 'a) -> Self {
+        self.on_enqueue = Box::new(on_enqueue);
+        self
+    }
 
-    slog::info!(log, "test complete");
+    fn run(mut self) -> TestRunResult {
+        let fs = Fs::new();
+        let log = test_logger();
+        let packages = self.fake_tests.packages();
+        slog::info!(
+            log, "doing test";
+            "include_filter" => ?self.include_filter,
+            "exclude_filter" => ?self.exclude_filter,
+            "list" => ?self.list,
+            "packages" => ?packages
+        );
+
+        fs.create_dir_all(&self.bin_dir).unwrap();
+        self.fake_tests.create_binaries(&fs, &self.bin_dir);
+
+        let target_directory = self.project_dir.join::<BuildDir>("target");
+        let deps = TestMainAppDeps::new(
+            self.fake_tests.clone(),
+            self.bin_dir.clone(),
+            target_directory.clone(),
+        );
+        let state = MainAppState::new(
+            deps,
+            self.include_filter,
+            self.exclude_filter,
+            self.shard,
+            self.list,
+            false, // stderr_color
+            &self.project_dir,
+            &packages,
+            target_directory.join::<StateDir>("maelstrom/state"),
+            0, // test_listing_read_retries
+            TestOptions,
+            LoggingOutput::default(),
+            log.clone(),
+        )
+        .unwrap();
+        let state = (self.configure_state)(state);
+
+        let (ui_send, ui_recv) = std::sync::mpsc::channel();
+        let introspect_driver = TestIntrospectDriver::default();
+        let mut app = MainApp::new(
+            &state,
+            ui::UiSender::new(ui_send),
+            introspect_driver.clone(),
+            self.timeout_override,
+        )
+        .unwrap();
+
+        let mut running = vec![];
+        let mut enqueued = vec![];
+        let mut skipped = BTreeMap::<SkipReason, usize>::new();
+        loop {
+            let (package_name, case) = match app.enqueue_one().unwrap() {
+                EnqueueResult::Done => break,
+                EnqueueResult::Listed => continue,
+                EnqueueResult::Skipped(reason) => {
+                    *skipped.entry(reason).or_default() += 1;
+                    continue;
+                }
+                EnqueueResult::Enqueued { package_name, case } => (package_name, case),
+            };
+            let test = self.fake_tests.find_case(&package_name, &case);
+            running.push(test.desired_state);
+            introspect_driver.update(counts_from_states(&running));
+
+            (self.on_enqueue)(&state, &package_name, &case);
+            enqueued.push((package_name, case));
+        }
 
-    term.contents()
+        app.drain().unwrap();
+
+        let exit_code = self.finish.then(|| app.finish().unwrap());
+
+        drop(app);
+        drop(introspect_driver);
+        slog::info!(log, "test complete");
+
+        TestRunResult {
+            state,
+            ui_recv,
+            enqueued,
+            skipped,
+            exit_code,
+        }
+    }
+}
+
+impl TestRunResult {
+    /// The enqueued jobs' packages and cases, sorted.
+    fn sorted_enqueued(&self) -> Vec<(String, String)> {
+        let mut enqueued = self.enqueued.clone();
+        enqueued.sort();
+        enqueued
+    }
+
+    /// Render everything the app sent to the UI into `term`, and return the contents.
+    fn render(self, term: InMemoryTerm, is_list: bool, stdout_tty: bool, quiet: Quiet) -> String {
+        drop(self.state);
+        let mut ui = ui::SimpleUi::new(is_list, stdout_tty, quiet, term.clone());
+        ui.run(self.ui_recv).unwrap();
+        term.contents()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_app(
+    bin_dir: &Root<BinDir>,
+    term: InMemoryTerm,
+    fake_tests: FakeTests,
+    project_dir: &Root<ProjectDir>,
+    stdout_tty: bool,
+    quiet: Quiet,
+    include_filter: Vec<String>,
+    exclude_filter: Vec<String>,
+    list: Option<ListAction>,
+    finish: bool,
+) -> String {
+    let is_list = list.is_some();
+    TestRun::with_dirs(bin_dir, project_dir, fake_tests)
+        .include_filter(include_filter)
+        .exclude_filter(exclude_filter)
+        .list(list)
+        .finish(finish)
+        .run()
+        .render(term, is_list, stdout_tty, quiet)
 }
 
 fn run_or_list_all_tests_sync(
@@ -616,6 +776,490 @@ fn three_tests_single_package_filtered_sync() {
     );
 }
 
+struct TestAffectedPackages(HashMap<PathBuf, Vec<String>>);
+
+impl AffectedPackages for TestAffectedPackages {
+    fn packages_affected_by(&self, path: &Path) -> Vec<String> {
+        self.0.get(path).cloned().unwrap_or_default()
+    }
+}
+
+#[test]
+fn only_affected_packages_selected() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let fake_tests = FakeTests {
+        test_binaries: ["foo", "bar", "baz"]
+            .into_iter()
+            .map(|name| FakeTestBinary {
+                name: name.into(),
+                tests: vec![
+                    FakeTestCase {
+                        name: "test_it".into(),
+                        ..Default::default()
+                    },
+                    FakeTestCase {
+                        name: "testy".into(),
+                        ..Default::default()
+                    },
+                ],
+            })
+            .collect(),
+    };
+    let affected_packages = TestAffectedPackages(HashMap::from([
+        (PathBuf::from("src/foo.rs"), vec!["foo".into()]),
+        (
+            PathBuf::from("src/shared.rs"),
+            vec!["foo".into(), "baz".into()],
+        ),
+    ]));
+    let result = TestRun::new(tmp_dir, fake_tests)
+        .configure_state(|state| {
+            state.only_affected_by(&["src/shared.rs", "src/unknown.rs"], &affected_packages)
+        })
+        .finish(false)
+        .run();
+
+    assert_eq!(
+        result.sorted_enqueued(),
+        vec![
+            ("baz".into(), "test_it".into()),
+            ("baz".into(), "testy".into()),
+            ("foo".into(), "test_it".into()),
+            ("foo".into(), "testy".into()),
+        ]
+    );
+}
+
+#[test]
+fn no_packages_matched() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let fake_tests = FakeTests {
+        test_binaries: vec![FakeTestBinary {
+            name: "foo".into(),
+            tests: vec![FakeTestCase {
+                name: "test_it".into(),
+                ..Default::default()
+            }],
+        }],
+    };
+    let result = TestRun::new(tmp_dir, fake_tests)
+        .include_filter(vec!["package = \"bar\"".into()])
+        .run();
+    assert!(result.enqueued.is_empty());
+
+    let exit_code = result.exit_code.unwrap();
+    assert_eq!(exit_code, ExitCode::from(NO_TESTS_MATCHED_EXIT_CODE));
+    assert_ne!(exit_code, ExitCode::SUCCESS);
+
+    assert_eq!(
+        result.render(InMemoryTerm::new(50, 50), false, false, false.into()),
+        "\
+        no tests matched the filter\n\
+        \n\
+        ================== Test Summary ==================\n\
+        Successful Tests:         0\n\
+        Failed Tests    :         0\
+        "
+    );
+}
+
+/// Run the tests in `tmp_dir`, where the test metadata quarantines any named "flaky". The cases
+/// that aren't ignored are expected to have the given estimated duration.
+fn quarantined_test_run_in(
+    tmp_dir: &Root<TmpDir>,
+    include_quarantined: bool,
+    expected_estimated_duration: Option<Duration>,
+) -> TestRunResult {
+    let project_dir = tmp_dir.join::<ProjectDir>("project");
+    let fake_tests = FakeTests {
+        test_binaries: ["foo", "bar"]
+            .into_iter()
+            .map(|name| FakeTestBinary {
+                name: name.into(),
+                tests: vec![
+                    FakeTestCase {
+                        name: "test_it".into(),
+                        expected_estimated_duration,
+                        ..Default::default()
+                    },
+                    FakeTestCase {
+                        name: "flaky".into(),
+                        expected_estimated_duration,
+                        ..Default::default()
+                    },
+                    FakeTestCase {
+                        name: "test_ignored".into(),
+                        ignored: true,
+                        ..Default::default()
+                    },
+                ],
+            })
+            .collect(),
+    };
+    let fs = Fs::new();
+    fs.create_dir_all(&project_dir).unwrap();
+    fs.write(
+        (**project_dir).join("maelstrom-test.toml"),
+        indoc! {r#"
+            [[directives]]
+            filter = "name = \"flaky\""
+            quarantined = true
+        "#},
+    )
+    .unwrap();
+
+    TestRun::new(tmp_dir, fake_tests)
+        .configure_state(|state| state.include_quarantined(include_quarantined))
+        .run()
+}
+
+/// Run the tests, returning the enqueued cases and the number of skipped cases by reason.
+fn quarantined_test_run(
+    include_quarantined: bool,
+) -> (Vec<(String, String)>, BTreeMap<SkipReason, usize>) {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let result = quarantined_test_run_in(tmp_dir, include_quarantined, None);
+
+    let summary = result.state.queuing_state.tracker.ui_summary();
+    assert_eq!(summary.skipped, result.skipped);
+    assert_eq!(summary.succeeded, result.enqueued.len());
+
+    (result.sorted_enqueued(), result.skipped)
+}
+
+#[test]
+fn quarantined_tests_skipped_by_default() {
+    assert_eq!(
+        quarantined_test_run(false),
+        (
+            vec![
+                ("bar".into(), "test_it".into()),
+                ("foo".into(), "test_it".into()),
+            ],
+            BTreeMap::from([(SkipReason::Ignored, 2), (SkipReason::Quarantined, 2)])
+        )
+    );
+}
+
+#[test]
+fn quarantined_tests_run_when_included() {
+    assert_eq!(
+        quarantined_test_run(true),
+        (
+            vec![
+                ("bar".into(), "flaky".into()),
+                ("bar".into(), "test_it".into()),
+                ("foo".into(), "flaky".into()),
+                ("foo".into(), "test_it".into()),
+            ],
+            BTreeMap::from([(SkipReason::Ignored, 2)])
+        )
+    );
+}
+
+#[test]
+fn quarantined_tests_not_expected_unless_included() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+
+    // The first run saves the test listing, so later runs know how many jobs to expect.
+    quarantined_test_run_in(tmp_dir, true, None);
+
+    let expected_estimated_duration = Some(Duration::from_secs(1));
+    // Ignored cases are expected too, since they are reported as skipped.
+    let result = quarantined_test_run_in(tmp_dir, false, expected_estimated_duration);
+    assert_eq!(result.state.queuing_state.expected_job_count, 4);
+    assert_eq!(result.enqueued.len(), 2);
+
+    let result = quarantined_test_run_in(tmp_dir, true, expected_estimated_duration);
+    assert_eq!(result.state.queuing_state.expected_job_count, 6);
+    assert_eq!(result.enqueued.len(), 4);
+}
+
+/// Run the tests in `shard`, skipping any named "test_skipped". Returns the cases that were
+/// enqueued, along with the expected job count, which is based on the test listing saved by
+/// previous runs.
+fn sharded_test_run(
+    tmp_dir: &Root<TmpDir>,
+    fake_tests: &FakeTests,
+    shard: Option<Shard>,
+) -> (Vec<(String, String)>, u64) {
+    let result = TestRun::new(tmp_dir, fake_tests.clone())
+        .exclude_filter(vec!["name = \"test_skipped\"".into()])
+        .shard(shard)
+        .run();
+    (
+        result.sorted_enqueued(),
+        result.state.queuing_state.expected_job_count,
+    )
+}
+
+#[test]
+fn shards_are_disjoint_and_cover_filtered_cases() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let fake_tests = |expected_estimated_duration| FakeTests {
+        test_binaries: ["foo", "bar", "baz"]
+            .into_iter()
+            .map(|name| FakeTestBinary {
+                name: name.into(),
+                tests: (0..5)
+                    .map(|i| format!("test_{i}"))
+                    .chain(["test_skipped".into()])
+                    .map(|name| FakeTestCase {
+                        name,
+                        expected_estimated_duration,
+                        ..Default::default()
+                    })
+                    .collect(),
+            })
+            .collect(),
+    };
+    let fs = Fs::new();
+    let bin_dir = tmp_dir.join::<BinDir>("bin");
+    fs.create_dir_all(&bin_dir).unwrap();
+    fake_tests(None).create_binaries(&fs, &bin_dir);
+
+    // The first run saves the test listing, so later runs know how many jobs to expect, and how
+    // long each will take.
+    let (all, _) = sharded_test_run(tmp_dir, &fake_tests(None), None);
+    assert_eq!(all.len(), 15);
+    let fake_tests = fake_tests(Some(Duration::from_secs(1)));
+    let (cases, expected_job_count) = sharded_test_run(tmp_dir, &fake_tests, None);
+    assert_eq!(cases, all);
+    assert_eq!(expected_job_count, 15);
+
+    let mut union = vec![];
+    for index in 0..3 {
+        let shard = Shard::new(index, 3).unwrap();
+        let (cases, expected_job_count) = sharded_test_run(tmp_dir, &fake_tests, Some(shard));
+        assert_eq!(expected_job_count, cases.len() as u64, "shard {shard}");
+        for case in &cases {
+            assert!(!union.contains(case), "{case:?} is in more than one shard");
+        }
+        union.extend(cases);
+    }
+    union.sort();
+    assert_eq!(union, all);
+}
+
+fn ordered_test_run(
+    tmp_dir: &Root<TmpDir>,
+    fake_tests: &FakeTests,
+    ordering: TestOrdering,
+) -> Vec<String> {
+    let result = TestRun::new(tmp_dir, fake_tests.clone())
+        .configure_state(|state| state.ordering(ordering))
+        .run();
+    result.enqueued.into_iter().map(|(_, case)| case).collect()
+}
+
+#[test]
+fn fastest_first_ordering_enqueues_cases_by_timing() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let fake_case = |name: &str, seconds: u64, has_timing: bool| {
+        let duration = Duration::from_secs(seconds);
+        let mut case = FakeTestCase {
+            name: name.into(),
+            expected_estimated_duration: has_timing.then_some(duration),
+            ..Default::default()
+        };
+        let JobOutcome::Completed(JobCompleted { effects, .. }) = &mut case.outcome else {
+            unreachable!()
+        };
+        effects.duration = duration;
+        case
+    };
+    let fake_tests = |cases| FakeTests {
+        test_binaries: vec![FakeTestBinary {
+            name: "foo".into(),
+            tests: cases,
+        }],
+    };
+    let fs = Fs::new();
+    let bin_dir = tmp_dir.join::<BinDir>("bin");
+    fs.create_dir_all(&bin_dir).unwrap();
+
+    // Without any timings, the cases are enqueued in the order they're listed. The first run saves
+    // their timings in the test listing.
+    let first = fake_tests(vec![
+        fake_case("test_slow", 3, false),
+        fake_case("test_fast", 1, false),
+        fake_case("test_medium", 2, false),
+    ]);
+    first.create_binaries(&fs, &bin_dir);
+    assert_eq!(
+        ordered_test_run(tmp_dir, &first, TestOrdering::FastestFirst),
+        vec!["test_slow", "test_fast", "test_medium"]
+    );
+
+    let second = fake_tests(vec![
+        fake_case("test_new", 1, false),
+        fake_case("test_slow", 3, true),
+        fake_case("test_fast", 1, true),
+        fake_case("test_medium", 2, true),
+    ]);
+    assert_eq!(
+        ordered_test_run(tmp_dir, &second, TestOrdering::FastestFirst),
+        vec!["test_fast", "test_medium", "test_slow", "test_new"]
+    );
+}
+
+#[test]
+fn package_timeouts_override_global_timeout() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let fake_tests = FakeTests {
+        test_binaries: [
+            ("foo", Timeout::new(60)),
+            ("bar", None),
+            ("baz", Timeout::new(10)),
+        ]
+        .into_iter()
+        .map(|(name, expected_timeout)| FakeTestBinary {
+            name: name.into(),
+            tests: vec![FakeTestCase {
+                name: "test_it".into(),
+                expected_timeout,
+                ..Default::default()
+            }],
+        })
+        .collect(),
+    };
+    // The fake client checks that each job's timeout matches the case's expected timeout.
+    let result = TestRun::new(tmp_dir, fake_tests)
+        .configure_state(|state| {
+            state.package_timeouts([("foo".into(), Timeout::new(60)), ("bar".into(), None)])
+        })
+        .timeout_override(Timeout::new(10))
+        .run();
+
+    assert_eq!(
+        result.sorted_enqueued(),
+        vec![
+            ("bar".into(), "test_it".into()),
+            ("baz".into(), "test_it".into()),
+            ("foo".into(), "test_it".into()),
+        ]
+    );
+}
+
+#[derive(Clone, Default)]
+struct RecordingTimingSink(Arc<Mutex<Vec<(String, String, Duration)>>>);
+
+impl TimingSink for RecordingTimingSink {
+    fn record(&self, package: &str, case: &str, duration: Duration) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((package.into(), case.into(), duration));
+    }
+}
+
+#[test]
+fn timing_sink_receives_timings_as_jobs_complete() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let fake_case = |name: &str, secs| FakeTestCase {
+        name: name.into(),
+        outcome: JobOutcome::Completed(JobCompleted {
+            status: JobStatus::Exited(0),
+            effects: JobEffects {
+                stdout: JobOutputResult::None,
+                stderr: JobOutputResult::None,
+                duration: Duration::from_secs(secs),
+                worker: None,
+            },
+        }),
+        ..Default::default()
+    };
+    let fake_tests = FakeTests {
+        test_binaries: vec![FakeTestBinary {
+            name: "foo".into(),
+            tests: vec![fake_case("test_a", 1), fake_case("test_b", 2)],
+        }],
+    };
+    let sink = RecordingTimingSink::default();
+    let mut expected = vec![];
+    let result = TestRun::new(tmp_dir, fake_tests)
+        .configure_state(|state| state.timing_sink(sink.clone()))
+        .on_enqueue(|_, package_name, case| {
+            // The test client completes jobs as soon as they are enqueued.
+            let secs = if case == "test_a" { 1 } else { 2 };
+            expected.push((package_name.into(), case.into(), Duration::from_secs(secs)));
+            assert_eq!(*sink.0.lock().unwrap(), expected);
+        })
+        .finish(false)
+        .run();
+
+    assert_eq!(result.enqueued.len(), 2);
+}
+
+#[test]
+fn report_metadata_covers_every_matched_case() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let project_dir = tmp_dir.join::<ProjectDir>("project");
+    let fs = Fs::new();
+    fs.create_dir_all(&project_dir).unwrap();
+    fs.write(
+        (**project_dir).join("maelstrom-test.toml"),
+        indoc! {r#"
+            [[directives]]
+            network = "loopback"
+            environment = { FOO = "foo" }
+
+            [[directives]]
+            filter = "package = \"bar\""
+            image = "alpine"
+            user = 100
+            group = 200
+            timeout = 30
+            added_environment = { BAR = "bar" }
+        "#},
+    )
+    .unwrap();
+    let fake_tests = FakeTests {
+        test_binaries: ["foo", "bar", "baz"]
+            .into_iter()
+            .map(|name| FakeTestBinary {
+                name: name.into(),
+                tests: vec![FakeTestCase {
+                    name: "test_it".into(),
+                    ..Default::default()
+                }],
+            })
+            .collect(),
+    };
+    let result = TestRun::new(tmp_dir, fake_tests)
+        .exclude_filter(vec!["package = \"baz\"".into()])
+        .list(Some(ListAction::ReportMetadata))
+        .finish(false)
+        .run();
+
+    let report = Vec::from_iter(result.ui_recv.try_iter().filter_map(|msg| match msg {
+        ui::UiMessage::List(line) => Some(line),
+        _ => None,
+    }));
+    assert_eq!(
+        report,
+        vec![
+            concat!(
+                r#"{"case":"foo test_it","image":null,"mounts":[],"network":"loopback","#,
+                r#""user":null,"group":null,"timeout":null,"environment":["FOO"]}"#,
+            ),
+            concat!(
+                r#"{"case":"bar test_it","image":"alpine","mounts":[],"network":"loopback","#,
+                r#""user":100,"group":200,"timeout":30,"environment":["BAR","FOO"]}"#,
+            ),
+        ]
+    );
+}
+
 #[test]
 fn ignored_test_sync() {
     let tmp_dir = tempdir().unwrap();
@@ -741,6 +1385,7 @@ fn failed_tests() {
             )),
             stderr: JobOutputResult::Inline(Box::new(*b"error output")),
             duration: Duration::from_secs(1),
+            worker: None,
         },
     });
     let fake_tests = FakeTests {
@@ -992,6 +1637,7 @@ fn expected_count_updates_packages() {
                         stdout: JobOutputResult::None,
                         stderr: JobOutputResult::None,
                         duration: Duration::from_secs(1),
+                        worker: None,
                     }),
                     ..Default::default()
                 }],
@@ -1119,3 +1765,46 @@ fn filtering_none_does_not_build() {
         .collect();
     assert_eq!(entries, vec!["maelstrom"]);
 }
+
+#[test]
+fn max_concurrent_packages_limits_packages_given_to_collector() {
+    let tmp_dir = tempdir().unwrap();
+    let tmp_dir = Root::<TmpDir>::new(tmp_dir.path());
+    let fake_tests = FakeTests {
+        test_binaries: ["bin_a", "bin_b", "bin_c", "bin_d", "bin_e"]
+            .into_iter()
+            .map(|name| FakeTestBinary {
+                name: name.into(),
+                tests: vec![FakeTestCase {
+                    name: "test_it".into(),
+                    ..Default::default()
+                }],
+            })
+            .collect(),
+    };
+    let result = TestRun::new(tmp_dir, fake_tests)
+        .configure_state(|state| state.max_concurrent_packages(NonZeroUsize::new(2)))
+        .on_enqueue(|state, package_name, _| {
+            // The collector isn't started on the next batch until this one's tests are enqueued.
+            let started_packages = state.deps.test_collector.started_packages.lock().unwrap();
+            assert!(started_packages
+                .last()
+                .unwrap()
+                .iter()
+                .any(|p| p == package_name));
+        })
+        .run();
+    assert_eq!(result.exit_code, Some(ExitCode::SUCCESS));
+
+    let enqueued = Vec::from_iter(result.enqueued.into_iter().map(|(package, _)| package));
+    assert_eq!(enqueued, vec!["bin_a", "bin_b", "bin_c", "bin_d", "bin_e"]);
+    let started_packages = &result.state.deps.test_collector.started_packages;
+    assert_eq!(
+        *started_packages.lock().unwrap(),
+        vec![
+            vec!["bin_a".to_owned(), "bin_b".into()],
+            vec!["bin_c".to_owned(), "bin_d".into()],
+            vec!["bin_e".to_owned()],
+        ]
+    );
+}
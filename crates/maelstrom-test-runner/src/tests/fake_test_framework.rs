@@ -7,7 +7,8 @@ use crate::{
 };
 use anyhow::Result;
 use maelstrom_base::{
-    stats::JobState, JobCompleted, JobEffects, JobOutcome, JobOutputResult, JobStatus, Utf8PathBuf,
+    stats::JobState, JobCompleted, JobEffects, JobOutcome, JobOutputResult, JobStatus, Timeout,
+    Utf8PathBuf,
 };
 use maelstrom_client::spec::JobSpec;
 use maelstrom_util::{fs::Fs, root::RootBuf};
@@ -15,6 +16,7 @@ use pretty_assertions::assert_eq;
 use std::{
     collections::HashSet,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
@@ -26,6 +28,7 @@ pub struct FakeTestCase {
     pub ignored: bool,
     pub desired_state: JobState,
     pub expected_estimated_duration: Option<Duration>,
+    pub expected_timeout: Option<Timeout>,
     pub outcome: JobOutcome,
 }
 
@@ -38,6 +41,16 @@ impl FakeTestCase {
         })) = self.outcome;
         duration
     }
+
+    fn failed(&self) -> bool {
+        !matches!(
+            self.outcome,
+            JobOutcome::Completed(JobCompleted {
+                status: JobStatus::Exited(0),
+                ..
+            })
+        )
+    }
 }
 
 impl Default for FakeTestCase {
@@ -47,12 +60,14 @@ impl Default for FakeTestCase {
             ignored: false,
             desired_state: JobState::Complete,
             expected_estimated_duration: None,
+            expected_timeout: None,
             outcome: JobOutcome::Completed(JobCompleted {
                 status: JobStatus::Exited(0),
                 effects: JobEffects {
                     stdout: JobOutputResult::None,
                     stderr: JobOutputResult::Inline(Box::new(*b"this output should be ignored")),
                     duration: Duration::from_secs(1),
+                    worker: None,
                 },
             }),
         }
@@ -108,6 +123,9 @@ impl FakeTests {
                     &case.name,
                     case.timing(),
                 );
+                if case.failed() {
+                    listing.add_failure(&binary.name, binary.artifact_key(), &case.name);
+                }
             }
         }
     }
@@ -181,6 +199,7 @@ impl FakeTests {
             .unwrap();
         let case = binary.tests.iter().find(|c| &c.name == case_name).unwrap();
         assert_eq!(&spec.estimated_duration, &case.expected_estimated_duration);
+        assert_eq!(&spec.timeout, &case.expected_timeout);
         (case.desired_state == JobState::Complete).then(|| case.outcome.clone())
     }
 
@@ -209,6 +228,8 @@ pub struct TestCollector {
     pub tests: FakeTests,
     pub bin_path: RootBuf<BinDir>,
     pub target_dir: RootBuf<BuildDir>,
+    /// The names of the packages passed to each call to `start`.
+    pub started_packages: Arc<Mutex<Vec<Vec<String>>>>,
 }
 
 #[derive(Clone, Debug)]
@@ -301,6 +322,7 @@ impl TestPackage for FakeTestPackage {
     }
 }
 
+#[derive(Debug)]
 pub struct TestOptions;
 
 impl CollectTests for TestCollector {
@@ -327,6 +349,10 @@ impl CollectTests for TestCollector {
         fs.create_dir_all(&self.target_dir).unwrap();
         fs.write((**self.target_dir).join("test_run"), "").unwrap();
 
+        self.started_packages
+            .lock()
+            .unwrap()
+            .push(packages.iter().map(|p| p.name.clone()).collect());
         let artifacts: Vec<_> = self.tests.artifacts(&self.bin_path, packages);
         Ok((WaitForNothing, artifacts.into_iter()))
     }
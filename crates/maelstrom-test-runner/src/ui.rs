@@ -1,14 +1,18 @@
 mod fancy;
+mod json;
 mod simple;
 
 use crate::config::Quiet;
 use anyhow::Result;
+use maelstrom_base::WorkerId;
 use maelstrom_client::IntrospectResponse;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::mpsc::{Receiver, Sender};
 use std::time::Duration;
 use std::{fmt, io, str};
 
+pub use json::JsonUi;
 pub use simple::SimpleUi;
 
 pub trait Ui: Send + Sync + 'static {
@@ -28,6 +32,8 @@ impl<PrintCbT, RetT> PrintWidthCb<RetT> for PrintCbT where
 {
 }
 
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", content = "details", rename_all = "snake_case")]
 pub enum UiJobStatus {
     Ok,
     Failure(Option<String>),
@@ -37,6 +43,11 @@ pub enum UiJobStatus {
 }
 
 impl UiJobStatus {
+    /// Whether the case didn't pass. Ignored cases aren't failures.
+    fn is_failure(&self) -> bool {
+        matches!(self, Self::Failure(_) | Self::TimedOut | Self::Error(_))
+    }
+
     fn details(self) -> Option<String> {
         match self {
             Self::Failure(d) => d,
@@ -46,9 +57,35 @@ impl UiJobStatus {
     }
 }
 
+/// Why a test case that matched the filter wasn't run.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The test is marked as ignored by the test framework.
+    Ignored,
+    /// The test metadata marks the test as quarantined, and quarantined tests weren't included.
+    Quarantined,
+}
+
+impl SkipReason {
+    /// The heading used for this reason in the test summary.
+    pub fn heading(&self) -> &'static str {
+        match self {
+            Self::Ignored => "Ignored Tests",
+            Self::Quarantined => "Quarantined Tests",
+        }
+    }
+}
+
+#[derive(Serialize)]
 pub struct UiJobSummary {
     pub failed: Vec<String>,
+    /// Cases that failed, but then passed when retried.
+    pub flaky: Vec<String>,
     pub ignored: Vec<String>,
+    /// The number of cases that weren't run, by why they weren't run. Reasons for which no cases
+    /// were skipped are left out.
+    pub skipped: BTreeMap<SkipReason, usize>,
     pub succeeded: usize,
 }
 
@@ -58,6 +95,8 @@ pub struct UiJobResult {
     pub status: UiJobStatus,
     pub stdout: Vec<String>,
     pub stderr: Vec<String>,
+    /// The worker that ran the case's job, if known.
+    pub worker: Option<WorkerId>,
 }
 
 pub enum UiMessage {
@@ -178,6 +217,7 @@ impl io::Write for UiSenderWriteAdapter {
 pub enum UiKind {
     Simple,
     Fancy,
+    Json,
 }
 
 impl fmt::Display for UiKind {
@@ -185,6 +225,7 @@ impl fmt::Display for UiKind {
         match self {
             Self::Simple => write!(f, "simple"),
             Self::Fancy => write!(f, "fancy"),
+            Self::Json => write!(f, "json"),
         }
     }
 }
@@ -209,6 +250,7 @@ impl str::FromStr for UiKind {
         match s {
             "simple" => Ok(Self::Simple),
             "fancy" => Ok(Self::Fancy),
+            "json" => Ok(Self::Json),
             ui_name => Err(UnknownUiError {
                 ui_name: ui_name.into(),
             }),
@@ -225,5 +267,6 @@ pub fn factory(kind: UiKind, list: bool, stdout_is_tty: bool, quiet: Quiet) -> B
             console::Term::buffered_stdout(),
         )),
         UiKind::Fancy => Box::new(fancy::FancyUi::new(list, stdout_is_tty, quiet)),
+        UiKind::Json => Box::new(JsonUi::new(io::stdout())),
     }
 }
@@ -1,6 +1,6 @@
 mod multi_gauge;
 
-use super::{Ui, UiJobResult, UiJobStatus, UiJobSummary, UiMessage};
+use super::{SkipReason, Ui, UiJobResult, UiJobStatus, UiJobSummary, UiMessage};
 use crate::config::Quiet;
 use anyhow::Result;
 use derive_more::From;
@@ -52,9 +52,13 @@ fn format_finished(res: UiJobResult) -> Vec<PrintAbove> {
 
     let mut output = vec![Row::new(line.into_iter()).into()];
 
+    let ran_on = res.status.is_failure().then_some(res.worker).flatten();
     if let Some(details) = res.status.details() {
         output.extend(details.split('\n').map(|l| Line::from(l.to_owned()).into()));
     }
+    if let Some(worker) = ran_on {
+        output.push(Line::from(format!("ran on worker {worker}")).into());
+    }
 
     for l in res.stdout {
         output.push(Line::from(l).into());
@@ -326,6 +330,7 @@ impl FancyUi {
 
         let summary = self.all_done.as_ref().unwrap();
         let num_failed = summary.failed.len();
+        let num_flaky = summary.flaky.len();
         let num_ignored = summary.ignored.len();
         let num_succeeded = summary.succeeded;
 
@@ -365,10 +370,20 @@ impl FancyUi {
             summary_line("Failed Tests".red(), num_failed),
             list_tests(&summary.failed, "failure".red()),
         ];
+        if num_flaky > 0 {
+            sections.push(summary_line("Flaky Tests".magenta(), num_flaky));
+            sections.push(list_tests(&summary.flaky, "flaky".magenta()));
+        }
         if num_ignored > 0 {
             sections.push(summary_line("Ignored Tests".yellow(), num_ignored));
             sections.push(list_tests(&summary.ignored, "ignored".yellow()));
         }
+        // Ignored tests are listed by name above, so we only count the other reasons.
+        for (reason, count) in &summary.skipped {
+            if *reason != SkipReason::Ignored {
+                sections.push(summary_line(reason.heading().yellow(), *count));
+            }
+        }
 
         let layout = Layout::vertical(sections.iter().map(|(c, _)| *c));
         let areas = layout.split(table_area);
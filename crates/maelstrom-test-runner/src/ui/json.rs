@@ -0,0 +1,229 @@
+use super::{Ui, UiJobResult, UiJobStatus, UiJobSummary, UiMessage};
+use anyhow::Result;
+use maelstrom_base::WorkerId;
+use serde::Serialize;
+use serde_with::{serde_as, DurationSecondsWithFrac};
+use std::{io::Write, sync::mpsc::Receiver, time::Duration};
+
+/// The most lines of stdout or stderr included with a finished job. When there are more, the last
+/// lines are kept, since that's usually where the failure is reported.
+const MAX_OUTPUT_LINES: usize = 100;
+
+/// One line of output from [`JsonUi`].
+#[serde_as]
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum JsonEvent<'a> {
+    Listed {
+        line: &'a str,
+    },
+    Log {
+        message: &'a str,
+    },
+    Enqueued {
+        name: &'a str,
+    },
+    Ignored {
+        name: &'a str,
+    },
+    Finished {
+        name: &'a str,
+        #[serde(flatten)]
+        status: &'a UiJobStatus,
+        #[serde_as(as = "Option<DurationSecondsWithFrac>")]
+        duration: Option<Duration>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        worker: Option<WorkerId>,
+        stdout: &'a [String],
+        stdout_lines_omitted: usize,
+        stderr: &'a [String],
+        stderr_lines_omitted: usize,
+    },
+    Summary(&'a UiJobSummary),
+}
+
+fn last_lines(lines: &[String]) -> (&[String], usize) {
+    let omitted = lines.len().saturating_sub(MAX_OUTPUT_LINES);
+    (&lines[omitted..], omitted)
+}
+
+/// A UI for scripts. Each event is written as a single line of JSON, and flushed immediately.
+pub struct JsonUi<WriterT> {
+    out: WriterT,
+}
+
+impl<WriterT> JsonUi<WriterT> {
+    pub fn new(out: WriterT) -> Self {
+        Self { out }
+    }
+}
+
+impl<WriterT: Write> JsonUi<WriterT> {
+    fn emit(&mut self, event: JsonEvent<'_>) -> Result<()> {
+        serde_json::to_writer(&mut self.out, &event)?;
+        self.out.write_all(b"\n")?;
+        self.out.flush()?;
+        Ok(())
+    }
+
+    fn job_finished(&mut self, res: &UiJobResult) -> Result<()> {
+        if let UiJobStatus::Ignored = res.status {
+            return self.emit(JsonEvent::Ignored { name: &res.name });
+        }
+        let (stdout, stdout_lines_omitted) = last_lines(&res.stdout);
+        let (stderr, stderr_lines_omitted) = last_lines(&res.stderr);
+        self.emit(JsonEvent::Finished {
+            name: &res.name,
+            status: &res.status,
+            duration: res.duration,
+            worker: res.worker,
+            stdout,
+            stdout_lines_omitted,
+            stderr,
+            stderr_lines_omitted,
+        })
+    }
+}
+
+impl<WriterT: Write + Send + Sync + 'static> Ui for JsonUi<WriterT> {
+    fn run(&mut self, recv: Receiver<UiMessage>) -> Result<()> {
+        for msg in recv {
+            match msg {
+                UiMessage::List(line) => self.emit(JsonEvent::Listed { line: &line })?,
+                UiMessage::LogMessage(message) => {
+                    self.emit(JsonEvent::Log { message: &message })?
+                }
+                UiMessage::JobEnqueued(name) => self.emit(JsonEvent::Enqueued { name: &name })?,
+                UiMessage::JobFinished(res) => self.job_finished(&res)?,
+                UiMessage::AllJobsFinished(summary) => self.emit(JsonEvent::Summary(&summary))?,
+                UiMessage::Shutdown => break,
+                UiMessage::BuildOutputLine(_)
+                | UiMessage::BuildOutputChunk(_)
+                | UiMessage::UpdatePendingJobsCount(_)
+                | UiMessage::UpdateIntrospectState(_)
+                | UiMessage::UpdateEnqueueStatus(_)
+                | UiMessage::DoneBuilding
+                | UiMessage::DoneQueuingJobs => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::{SkipReason, UiSender};
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+    use std::{
+        collections::BTreeMap,
+        sync::{mpsc, Arc, Mutex},
+    };
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn job_result(name: &str, status: UiJobStatus, stdout: Vec<String>) -> UiJobResult {
+        UiJobResult {
+            name: name.into(),
+            duration: Some(Duration::from_millis(1500)),
+            status,
+            stdout,
+            stderr: vec![],
+            worker: None,
+        }
+    }
+
+    #[test]
+    fn events_are_written_as_json_lines() {
+        let (send, recv) = mpsc::channel();
+        let sender = UiSender::new(send);
+        sender.job_enqueued("foo test_a".into());
+        sender.job_enqueued("foo test_b".into());
+        sender.update_length(2);
+        sender.log_message("hello".into());
+        sender.job_finished(job_result("foo test_a", UiJobStatus::Ok, vec![]));
+        sender.job_finished(UiJobResult {
+            worker: Some(WorkerId::from(3)),
+            ..job_result(
+                "foo test_b",
+                UiJobStatus::Failure(Some("killed by signal 9".into())),
+                (0..102).map(|i| format!("line {i}")).collect(),
+            )
+        });
+        sender.job_finished(UiJobResult {
+            duration: None,
+            ..job_result("foo test_c", UiJobStatus::Ignored, vec![])
+        });
+        sender.done_queuing_jobs();
+        sender
+            .finished(UiJobSummary {
+                failed: vec!["foo test_b".into()],
+                flaky: vec![],
+                ignored: vec!["foo test_c".into()],
+                skipped: BTreeMap::from([(SkipReason::Ignored, 1), (SkipReason::Quarantined, 2)]),
+                succeeded: 1,
+            })
+            .unwrap();
+        sender.shutdown();
+
+        let out = SharedBuffer::default();
+        JsonUi::new(out.clone()).run(recv).unwrap();
+
+        let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        let events = Vec::from_iter(
+            out.lines()
+                .map(|line| serde_json::from_str::<Value>(line).unwrap()),
+        );
+        assert_eq!(
+            events,
+            vec![
+                json!({"event": "enqueued", "name": "foo test_a"}),
+                json!({"event": "enqueued", "name": "foo test_b"}),
+                json!({"event": "log", "message": "hello"}),
+                json!({
+                    "event": "finished",
+                    "name": "foo test_a",
+                    "status": "ok",
+                    "duration": 1.5,
+                    "stdout": [],
+                    "stdout_lines_omitted": 0,
+                    "stderr": [],
+                    "stderr_lines_omitted": 0,
+                }),
+                json!({
+                    "event": "finished",
+                    "name": "foo test_b",
+                    "status": "failure",
+                    "details": "killed by signal 9",
+                    "duration": 1.5,
+                    "worker": 3,
+                    "stdout": Vec::from_iter((2..102).map(|i| format!("line {i}"))),
+                    "stdout_lines_omitted": 2,
+                    "stderr": [],
+                    "stderr_lines_omitted": 0,
+                }),
+                json!({"event": "ignored", "name": "foo test_c"}),
+                json!({
+                    "event": "summary",
+                    "succeeded": 1,
+                    "failed": ["foo test_b"],
+                    "flaky": [],
+                    "ignored": ["foo test_c"],
+                    "skipped": {"ignored": 1, "quarantined": 2},
+                }),
+            ]
+        );
+    }
+}
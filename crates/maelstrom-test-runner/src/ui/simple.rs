@@ -1,6 +1,6 @@
 mod progress;
 
-use super::{Ui, UiJobResult, UiJobStatus, UiJobSummary, UiMessage};
+use super::{SkipReason, Ui, UiJobResult, UiJobStatus, UiJobSummary, UiMessage};
 use crate::config::Quiet;
 use anyhow::Result;
 use colored::Colorize as _;
@@ -111,9 +111,13 @@ where
         }
     });
 
+    let ran_on = res.status.is_failure().then_some(res.worker).flatten();
     if let Some(details) = res.status.details() {
         printer.println(details);
     }
+    if let Some(worker) = ran_on {
+        printer.println(format!("ran on worker {worker}"));
+    }
     for line in res.stdout {
         printer.println(line);
     }
@@ -144,15 +148,29 @@ where
         ));
         let success = "Successful Tests";
         let failure = "Failed Tests";
+        let flake = "Flaky Tests";
         let ignore = "Ignored Tests";
         let mut column1_width = std::cmp::max(success.width(), failure.width());
         let max_digits = 9;
         let num_failed = summary.failed.len();
+        let num_flaky = summary.flaky.len();
         let num_ignored = summary.ignored.len();
         let num_succeeded = summary.succeeded;
+        if num_flaky > 0 {
+            column1_width = std::cmp::max(column1_width, flake.width());
+        }
         if num_ignored > 0 {
             column1_width = std::cmp::max(column1_width, ignore.width());
         }
+        // Ignored tests are listed by name, so we only count the other reasons.
+        let other_skipped: Vec<_> = summary
+            .skipped
+            .iter()
+            .filter(|(reason, _)| **reason != SkipReason::Ignored)
+            .collect();
+        for (reason, _) in &other_skipped {
+            column1_width = std::cmp::max(column1_width, reason.heading().width());
+        }
         summary_lines.push(format!(
             "{:<column1_width$}: {num_succeeded:>max_digits$}",
             success.green(),
@@ -165,6 +183,16 @@ where
         for failed in &summary.failed {
             summary_lines.push(format!("    {failed:<failed_width$}: {}", "failure".red()));
         }
+        if num_flaky > 0 {
+            summary_lines.push(format!(
+                "{:<column1_width$}: {num_flaky:>max_digits$}",
+                flake.magenta(),
+            ));
+            let flaky_width = summary.flaky.iter().map(|n| n.width()).max().unwrap_or(0);
+            for flaky in &summary.flaky {
+                summary_lines.push(format!("    {flaky:<flaky_width$}: {}", "flaky".magenta()));
+            }
+        }
         if num_ignored > 0 {
             summary_lines.push(format!(
                 "{:<column1_width$}: {num_ignored:>max_digits$}",
@@ -178,6 +206,12 @@ where
                 ));
             }
         }
+        for (reason, count) in other_skipped {
+            summary_lines.push(format!(
+                "{:<column1_width$}: {count:>max_digits$}",
+                reason.heading().yellow(),
+            ));
+        }
         summary_lines
     })
 }
@@ -1,24 +1,80 @@
+use crate::golden::GoldenFiles;
+use crate::junit::{JUnitCase, JUnitReport};
 use crate::test_listing::TestListing;
-use crate::ui::{UiJobResult, UiJobStatus, UiJobSummary, UiSender};
+use crate::ui::{SkipReason, UiJobResult, UiJobStatus, UiJobSummary, UiSender};
 use crate::{TestArtifactKey, TestCaseMetadata};
-use anyhow::Result;
+use anyhow::{bail, Result};
 use maelstrom_base::{
     ClientJobId, JobCompleted, JobEffects, JobError, JobOutcome, JobOutcomeResult, JobOutputResult,
     JobStatus,
 };
+use maelstrom_client::spec::JobSpec;
 use maelstrom_util::process::{ExitCode, ExitCodeAccumulator};
-use std::sync::{Arc, Condvar, Mutex};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+/// Receives the timing of each test case as soon as its job completes, instead of waiting for the
+/// test listing to be saved at the end of the run. This can be used to feed live metrics.
+pub trait TimingSink: Send + Sync {
+    fn record(&self, package: &str, case: &str, duration: Duration);
+}
+
+/// A [`TimingSink`] that drops every timing. This is the default.
+pub struct NoOpTimingSink;
+
+impl TimingSink for NoOpTimingSink {
+    fn record(&self, _package: &str, _case: &str, _duration: Duration) {}
+}
 
 #[derive(Clone)]
 enum CaseResult {
-    Ignored,
+    Skipped(SkipReason),
     Ran(ExitCode),
+    /// The case failed, but then passed when it was retried.
+    Flaky,
+}
+
+impl CaseResult {
+    fn is_failure(&self) -> bool {
+        matches!(self, Self::Ran(exit_code) if exit_code != &ExitCode::SUCCESS)
+    }
+}
+
+#[derive(Clone)]
+struct CompletedCase {
+    package: String,
+    case: String,
+    result: CaseResult,
+}
+
+/// Called with the outcome of a job once it completes.
+pub type JobHandler = Box<dyn FnOnce(Result<(ClientJobId, JobOutcomeResult)>) + Send + Sync>;
+
+/// A job that should be submitted because a test case's job finished: either a retry of a failed
+/// case, or the case's cleanup command. Either way, the run isn't over until the job finishes.
+pub struct FollowUpJob {
+    pub spec: JobSpec,
+    pub handler: JobHandler,
 }
 
-#[derive(Clone, Default)]
+#[derive(Default)]
 struct Statuses {
     outstanding: u64,
-    completed: Vec<(String, CaseResult)>,
+    completed: Vec<CompletedCase>,
+    follow_ups: VecDeque<FollowUpJob>,
+}
+
+/// The number of test cases in a package that succeeded, failed, were flaky, or were skipped for
+/// any reason.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PackageSummary {
+    pub succeeded: usize,
+    pub failed: usize,
+    pub flaky: usize,
+    pub skipped: usize,
 }
 
 #[derive(Default)]
@@ -34,21 +90,82 @@ impl JobStatusTracker {
         statuses.outstanding += 1;
     }
 
-    pub fn job_exited(&self, case: String, exit_code: ExitCode) {
+    pub fn job_exited(&self, package: String, case: String, exit_code: ExitCode) {
         let mut statuses = self.statuses.lock().unwrap();
         statuses.outstanding -= 1;
-        statuses.completed.push((case, CaseResult::Ran(exit_code)));
+        statuses.completed.push(CompletedCase {
+            package,
+            case,
+            result: CaseResult::Ran(exit_code),
+        });
         self.exit_code.add(exit_code);
         self.condvar.notify_one();
     }
 
-    pub fn job_ignored(&self, case: String) {
+    /// Record that a case wasn't run, and why. Skipped cases don't affect the exit code.
+    pub fn job_skipped(&self, package: String, case: String, reason: SkipReason) {
         let mut statuses = self.statuses.lock().unwrap();
         statuses.outstanding -= 1;
-        statuses.completed.push((case, CaseResult::Ignored));
+        statuses.completed.push(CompletedCase {
+            package,
+            case,
+            result: CaseResult::Skipped(reason),
+        });
         self.condvar.notify_one();
     }
 
+    /// Record that a case passed, but only after failing at least once. Flaky cases don't affect
+    /// the exit code.
+    pub fn job_flaky(&self, package: String, case: String) {
+        let mut statuses = self.statuses.lock().unwrap();
+        statuses.outstanding -= 1;
+        statuses.completed.push(CompletedCase {
+            package,
+            case,
+            result: CaseResult::Flaky,
+        });
+        self.condvar.notify_one();
+    }
+
+    /// Queue a job to be submitted again by whoever is calling
+    /// [`wait_for_follow_up`](Self::wait_for_follow_up).
+    pub fn job_retrying(&self, retry: FollowUpJob) {
+        let mut statuses = self.statuses.lock().unwrap();
+        statuses.follow_ups.push_back(retry);
+        self.condvar.notify_one();
+    }
+
+    /// Queue a case's cleanup job to be submitted by whoever is calling
+    /// [`wait_for_follow_up`](Self::wait_for_follow_up). The cleanup job is outstanding until
+    /// [`cleanup_finished`](Self::cleanup_finished) is called, but isn't counted as a case.
+    pub fn cleanup_queued(&self, cleanup: FollowUpJob) {
+        let mut statuses = self.statuses.lock().unwrap();
+        statuses.outstanding += 1;
+        statuses.follow_ups.push_back(cleanup);
+        self.condvar.notify_one();
+    }
+
+    pub fn cleanup_finished(&self) {
+        let mut statuses = self.statuses.lock().unwrap();
+        statuses.outstanding -= 1;
+        self.condvar.notify_one();
+    }
+
+    /// Wait until there is a job to submit, or until there are no outstanding jobs. Returns `None`
+    /// in the latter case.
+    pub fn wait_for_follow_up(&self) -> Option<FollowUpJob> {
+        let mut statuses = self.statuses.lock().unwrap();
+        loop {
+            if let Some(follow_up) = statuses.follow_ups.pop_front() {
+                return Some(follow_up);
+            }
+            if statuses.outstanding == 0 {
+                return None;
+            }
+            statuses = self.condvar.wait(statuses).unwrap();
+        }
+    }
+
     pub fn wait_for_outstanding(&self) {
         let mut statuses = self.statuses.lock().unwrap();
         while statuses.outstanding > 0 {
@@ -62,29 +179,61 @@ impl JobStatusTracker {
         let failed: Vec<_> = statuses
             .completed
             .iter()
-            .filter(|(_, res)| matches!(res, CaseResult::Ran(e) if e != &ExitCode::SUCCESS))
-            .map(|(n, _)| n.clone())
+            .filter(|c| c.result.is_failure())
+            .map(|c| c.case.clone())
+            .collect();
+        let flaky: Vec<_> = statuses
+            .completed
+            .iter()
+            .filter(|c| matches!(c.result, CaseResult::Flaky))
+            .map(|c| c.case.clone())
             .collect();
         let ignored: Vec<_> = statuses
             .completed
             .iter()
-            .filter(|(_, res)| matches!(res, CaseResult::Ignored))
-            .map(|(n, _)| n.clone())
+            .filter(|c| matches!(c.result, CaseResult::Skipped(SkipReason::Ignored)))
+            .map(|c| c.case.clone())
             .collect();
+        let mut skipped = BTreeMap::<SkipReason, usize>::new();
+        for completed in &statuses.completed {
+            if let CaseResult::Skipped(reason) = completed.result {
+                *skipped.entry(reason).or_default() += 1;
+            }
+        }
 
-        let succeeded = statuses.completed.len() - failed.len() - ignored.len();
+        let succeeded =
+            statuses.completed.len() - failed.len() - flaky.len() - skipped.values().sum::<usize>();
         UiJobSummary {
             succeeded,
             failed,
+            flaky,
             ignored,
+            skipped,
         }
     }
 
+    /// Break the completed test cases down by package.
+    pub fn package_summaries(&self) -> BTreeMap<String, PackageSummary> {
+        let statuses = self.statuses.lock().unwrap();
+        let mut summaries = BTreeMap::<String, PackageSummary>::new();
+        for completed in &statuses.completed {
+            let summary = summaries.entry(completed.package.clone()).or_default();
+            match completed.result {
+                CaseResult::Skipped(_) => summary.skipped += 1,
+                CaseResult::Flaky => summary.flaky += 1,
+                ref result if result.is_failure() => summary.failed += 1,
+                CaseResult::Ran(_) => summary.succeeded += 1,
+            }
+        }
+        summaries
+    }
+
     pub fn exit_code(&self) -> ExitCode {
         self.exit_code.get()
     }
 }
 
+#[derive(Clone)]
 pub struct JobStatusVisitor<
     ArtifactKeyT: TestArtifactKey,
     CaseMetadataT: TestCaseMetadata,
@@ -98,6 +247,13 @@ pub struct JobStatusVisitor<
     case_str: String,
     ui: UiSender,
     remove_fixture_output: RemoveFixtureOutputFn,
+    timing_sink: Arc<dyn TimingSink>,
+    golden_files: Option<GoldenFiles>,
+    junit_report: Option<Arc<JUnitReport>>,
+    retry_spec: Option<JobSpec>,
+    retries_remaining: u32,
+    failed_attempts: u32,
+    cleanup_spec: Option<JobSpec>,
 }
 
 impl<ArtifactKeyT, CaseMetadataT, RemoveFixtureOutputFn>
@@ -116,6 +272,9 @@ where
         case_str: String,
         ui: UiSender,
         remove_fixture_output: RemoveFixtureOutputFn,
+        timing_sink: Arc<dyn TimingSink>,
+        golden_files: Option<GoldenFiles>,
+        junit_report: Option<Arc<JUnitReport>>,
     ) -> Self {
         Self {
             tracker,
@@ -126,60 +285,232 @@ where
             case_str,
             ui,
             remove_fixture_output,
+            timing_sink,
+            golden_files,
+            junit_report,
+            retry_spec: None,
+            retries_remaining: 0,
+            failed_attempts: 0,
+            cleanup_spec: None,
+        }
+    }
+
+    /// Submit `spec` again, up to `retries` times, if the job fails. A case that fails and then
+    /// passes on a retry is reported as flaky instead of failed. By default, jobs aren't retried.
+    pub fn retries(mut self, spec: JobSpec, retries: u32) -> Self {
+        self.retry_spec = Some(spec);
+        self.retries_remaining = retries;
+        self
+    }
+
+    /// Submit `spec` every time the case's job finishes, whether it passed or failed, including
+    /// after each retry. A cleanup job that fails is logged, but doesn't affect the case's result.
+    /// By default, there is no cleanup job.
+    pub fn cleanup(mut self, spec: Option<JobSpec>) -> Self {
+        self.cleanup_spec = spec;
+        self
+    }
+
+    fn record_timing(&self, duration: Duration) {
+        self.test_listing
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .add_timing(
+                self.package.as_str(),
+                self.artifact.clone(),
+                self.case.as_str(),
+                duration,
+            );
+        self.timing_sink
+            .record(self.package.as_str(), self.case.as_str(), duration);
+    }
+
+    fn record_failure(&self) {
+        self.test_listing
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .add_failure(
+                self.package.as_str(),
+                self.artifact.clone(),
+                self.case.as_str(),
+            );
+    }
+
+    fn record_junit_case(&self, result: &UiJobResult) {
+        if let Some(junit_report) = &self.junit_report {
+            junit_report.record(
+                &self.package,
+                JUnitCase {
+                    name: result.name.clone(),
+                    outcome: (&result.status).into(),
+                    duration: result.duration,
+                    stdout: result.stdout.clone(),
+                    stderr: result.stderr.clone(),
+                },
+            );
         }
     }
 }
 
+/// Describe why a cleanup job failed, or return `None` if it succeeded.
+fn cleanup_error(res: Result<(ClientJobId, JobOutcomeResult)>) -> Option<String> {
+    match res {
+        Ok((_, Ok(JobOutcome::Completed(JobCompleted { status, .. })))) => match status {
+            JobStatus::Exited(0) => None,
+            JobStatus::Exited(code) => Some(format!("exited with code {code}")),
+            JobStatus::Signaled(signo) => Some(format!("killed by signal {signo}")),
+        },
+        Ok((_, Ok(JobOutcome::TimedOut(_)))) => Some("timed out".into()),
+        Ok((_, Err(JobError::Execution(err)))) => Some(format!("execution error: {err}")),
+        Ok((_, Err(JobError::System(err)))) => Some(format!("system error: {err}")),
+        Err(err) => Some(format!("remote error: {err}")),
+    }
+}
+
 fn format_test_output(
     res: &JobOutputResult,
     name: &str,
     cjid: ClientJobId,
-    case_str: &str,
+    case: &str,
     remove_fixture_output: impl Fn(&str, Vec<String>) -> Vec<String>,
 ) -> Vec<String> {
-    let (_, case_str) = case_str.rsplit_once(' ').unwrap_or(("", case_str));
     let mut test_output_lines = vec![];
     match res {
         JobOutputResult::None => {}
-        JobOutputResult::Inline(bytes) => {
+        JobOutputResult::Inline(_) | JobOutputResult::InlineCompressed { .. } => {
+            test_output_lines.extend(res.to_string().split('\n').map(ToOwned::to_owned));
+            if name == "stdout" {
+                test_output_lines = remove_fixture_output(case, test_output_lines);
+            }
+        }
+        JobOutputResult::Truncated { first, truncated } => {
             test_output_lines.extend(
-                String::from_utf8_lossy(bytes)
+                String::from_utf8_lossy(first)
                     .split('\n')
                     .map(ToOwned::to_owned),
             );
             if name == "stdout" {
-                test_output_lines = remove_fixture_output(case_str, test_output_lines);
+                test_output_lines = remove_fixture_output(case, test_output_lines);
             }
+            test_output_lines.push(format!(
+                "job {cjid}: {name} truncated, {truncated} bytes lost"
+            ));
         }
-        JobOutputResult::Truncated { first, truncated } => {
+        JobOutputResult::External {
+            first,
+            digest,
+            size,
+        } => {
             test_output_lines.extend(
                 String::from_utf8_lossy(first)
                     .split('\n')
                     .map(ToOwned::to_owned),
             );
             if name == "stdout" {
-                test_output_lines = remove_fixture_output(case_str, test_output_lines);
+                test_output_lines = remove_fixture_output(case, test_output_lines);
             }
             test_output_lines.push(format!(
-                "job {cjid}: {name} truncated, {truncated} bytes lost"
+                "job {cjid}: {name} stored externally, {size} bytes, {digest}"
             ));
         }
     }
     test_output_lines
 }
 
+/// The stdout of a job, as it is compared against a golden file.
+fn golden_stdout(
+    res: &JobOutputResult,
+    case: &str,
+    remove_fixture_output: impl Fn(&str, Vec<String>) -> Vec<String>,
+) -> Result<String> {
+    match res {
+        JobOutputResult::None => Ok(String::new()),
+        JobOutputResult::Inline(_) | JobOutputResult::InlineCompressed { .. } => {
+            let lines = res.to_string().split('\n').map(ToOwned::to_owned).collect();
+            Ok(remove_fixture_output(case, lines).join("\n"))
+        }
+        JobOutputResult::Truncated { .. } | JobOutputResult::External { .. } => {
+            bail!("stdout was truncated or stored externally")
+        }
+    }
+}
+
 impl<ArtifactKeyT, CaseMetadataT, RemoveFixtureOutputFn>
     JobStatusVisitor<ArtifactKeyT, CaseMetadataT, RemoveFixtureOutputFn>
 where
     ArtifactKeyT: TestArtifactKey,
     CaseMetadataT: TestCaseMetadata,
-    RemoveFixtureOutputFn: Fn(&str, Vec<String>) -> Vec<String>,
+    RemoveFixtureOutputFn: Fn(&str, Vec<String>) -> Vec<String> + Clone + Send + Sync + 'static,
 {
+    /// Compare a passing job's stdout against the case's golden file, if golden files are being
+    /// used. If the comparison fails, return the status to report instead, along with the stdout
+    /// lines to show.
+    fn check_golden_file(&self, stdout: &JobOutputResult) -> Option<(UiJobStatus, Vec<String>)> {
+        let golden_files = self.golden_files.as_ref()?;
+        let result =
+            golden_stdout(stdout, &self.case, &self.remove_fixture_output).and_then(|stdout| {
+                golden_files.check(
+                    &self.package,
+                    &self.artifact.to_string(),
+                    &self.case,
+                    &stdout,
+                )
+            });
+        match result {
+            Ok(None) => None,
+            Ok(Some(diff)) => Some((
+                UiJobStatus::Failure(Some("stdout doesn't match golden file".into())),
+                diff,
+            )),
+            Err(err) => Some((
+                UiJobStatus::Error(format!("golden file error: {err}")),
+                vec![],
+            )),
+        }
+    }
+
+    /// If the job has retries remaining, return a retry that will report to a copy of this
+    /// visitor.
+    fn retry(&self) -> Option<FollowUpJob> {
+        let spec = self.retry_spec.clone()?;
+        if self.retries_remaining == 0 {
+            return None;
+        }
+        let mut visitor = self.clone();
+        visitor.retries_remaining -= 1;
+        visitor.failed_attempts += 1;
+        Some(FollowUpJob {
+            spec,
+            handler: Box::new(move |res| visitor.job_finished(res)),
+        })
+    }
+
+    fn cleanup_job(&self) -> Option<FollowUpJob> {
+        let spec = self.cleanup_spec.clone()?;
+        let tracker = self.tracker.clone();
+        let ui = self.ui.clone();
+        let case_str = self.case_str.clone();
+        Some(FollowUpJob {
+            spec,
+            handler: Box::new(move |res| {
+                if let Some(error) = cleanup_error(res) {
+                    ui.log_message(format!("cleanup for {case_str} failed: {error}"));
+                }
+                tracker.cleanup_finished();
+            }),
+        })
+    }
+
     pub fn job_finished(&self, res: Result<(ClientJobId, JobOutcomeResult)>) {
-        let test_status: UiJobStatus;
+        let mut test_status: UiJobStatus;
         let mut test_output_stderr: Vec<String> = vec![];
         let mut test_output_stdout: Vec<String> = vec![];
         let mut test_duration = None;
+        let mut test_worker = None;
         let exit_code = match res {
             Ok((
                 cjid,
@@ -190,12 +521,14 @@ where
                             stdout,
                             stderr,
                             duration,
+                            worker,
                         },
                 })),
             )) => {
                 test_duration = Some(duration);
+                test_worker = worker;
                 let mut job_failed = true;
-                let exit_code = match status {
+                let mut exit_code = match status {
                     JobStatus::Exited(code) => {
                         test_status = if code == 0 {
                             job_failed = false;
@@ -211,33 +544,32 @@ where
                         ExitCode::FAILURE
                     }
                 };
-                if job_failed {
+                if !job_failed {
+                    if let Some((status, stdout)) = self.check_golden_file(&stdout) {
+                        test_status = status;
+                        test_output_stdout = stdout;
+                        exit_code = ExitCode::FAILURE;
+                    }
+                } else {
                     test_output_stdout.extend(format_test_output(
                         &stdout,
                         "stdout",
                         cjid,
-                        &self.case_str,
+                        &self.case,
                         &self.remove_fixture_output,
                     ));
                     test_output_stderr.extend(format_test_output(
                         &stderr,
                         "stderr",
                         cjid,
-                        &self.case_str,
+                        &self.case,
                         &self.remove_fixture_output,
                     ));
                 }
-                self.test_listing
-                    .lock()
-                    .unwrap()
-                    .as_mut()
-                    .unwrap()
-                    .add_timing(
-                        self.package.as_str(),
-                        self.artifact.clone(),
-                        self.case.as_str(),
-                        duration,
-                    );
+                if job_failed {
+                    self.record_failure();
+                }
+                self.record_timing(duration);
                 exit_code
             }
             Ok((
@@ -246,35 +578,28 @@ where
                     stdout,
                     stderr,
                     duration,
+                    worker,
                 })),
             )) => {
                 test_duration = Some(duration);
+                test_worker = worker;
                 test_status = UiJobStatus::TimedOut;
                 test_output_stdout.extend(format_test_output(
                     &stdout,
                     "stdout",
                     cjid,
-                    &self.case_str,
+                    &self.case,
                     &self.remove_fixture_output,
                 ));
                 test_output_stderr.extend(format_test_output(
                     &stderr,
                     "stderr",
                     cjid,
-                    &self.case_str,
+                    &self.case,
                     &self.remove_fixture_output,
                 ));
-                self.test_listing
-                    .lock()
-                    .unwrap()
-                    .as_mut()
-                    .unwrap()
-                    .add_timing(
-                        self.package.as_str(),
-                        self.artifact.clone(),
-                        self.case.as_str(),
-                        duration,
-                    );
+                self.record_failure();
+                self.record_timing(duration);
                 ExitCode::FAILURE
             }
             Ok((_, Err(JobError::Execution(err)))) => {
@@ -291,28 +616,445 @@ where
             }
         };
 
-        self.ui.job_finished(UiJobResult {
+        if let Some(cleanup) = self.cleanup_job() {
+            self.tracker.cleanup_queued(cleanup);
+        }
+
+        if exit_code != ExitCode::SUCCESS {
+            if let Some(retry) = self.retry() {
+                self.ui.log_message(format!(
+                    "retrying {} ({} retries left)",
+                    self.case_str,
+                    self.retries_remaining - 1
+                ));
+                self.tracker.job_retrying(retry);
+                return;
+            }
+        }
+
+        let result = UiJobResult {
             name: self.case_str.clone(),
             status: test_status,
             duration: test_duration,
             stdout: test_output_stdout,
             stderr: test_output_stderr,
-        });
+            worker: test_worker,
+        };
+        self.record_junit_case(&result);
+        self.ui.job_finished(result);
 
         // This call unblocks main thread, so it must go last
-        self.tracker.job_exited(self.case_str.clone(), exit_code);
+        if exit_code == ExitCode::SUCCESS && self.failed_attempts > 0 {
+            self.tracker
+                .job_flaky(self.package.clone(), self.case_str.clone());
+        } else {
+            self.tracker
+                .job_exited(self.package.clone(), self.case_str.clone(), exit_code);
+        }
     }
 
     pub fn job_ignored(&self) {
-        self.ui.job_finished(UiJobResult {
+        let result = UiJobResult {
             name: self.case_str.clone(),
             status: UiJobStatus::Ignored,
             duration: None,
             stdout: vec![],
             stderr: vec![],
-        });
+            worker: None,
+        };
+        self.record_junit_case(&result);
+        self.ui.job_finished(result);
 
         // This call unblocks main thread, so it must go last
-        self.tracker.job_ignored(self.case_str.clone());
+        self.tracker.job_skipped(
+            self.package.clone(),
+            self.case_str.clone(),
+            SkipReason::Ignored,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{golden::GoldenStore, ui::UiMessage, NoCaseMetadata, StringArtifactKey};
+    use assert_matches::assert_matches;
+    use maelstrom_base::WorkerId;
+    use pretty_assertions::assert_eq;
+    use std::{collections::HashMap, sync::mpsc};
+
+    #[test]
+    fn package_summaries() {
+        let tracker = JobStatusTracker::default();
+        for _ in 0..7 {
+            tracker.add_outstanding();
+        }
+        tracker.job_exited("foo".into(), "foo test_a".into(), ExitCode::SUCCESS);
+        tracker.job_exited("bar".into(), "bar test_a".into(), ExitCode::FAILURE);
+        tracker.job_skipped("foo".into(), "foo test_b".into(), SkipReason::Ignored);
+        tracker.job_skipped("bar".into(), "bar test_c".into(), SkipReason::Quarantined);
+        tracker.job_exited("foo".into(), "foo test_c".into(), ExitCode::from(2));
+        tracker.job_exited("bar".into(), "bar test_b".into(), ExitCode::SUCCESS);
+        tracker.job_exited("foo".into(), "foo test_d".into(), ExitCode::SUCCESS);
+        tracker.wait_for_outstanding();
+
+        assert_eq!(
+            tracker.package_summaries(),
+            BTreeMap::from([
+                (
+                    "bar".into(),
+                    PackageSummary {
+                        succeeded: 1,
+                        failed: 1,
+                        flaky: 0,
+                        skipped: 1,
+                    }
+                ),
+                (
+                    "foo".into(),
+                    PackageSummary {
+                        succeeded: 2,
+                        failed: 1,
+                        flaky: 0,
+                        skipped: 1,
+                    }
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn ui_summary_counts_skips_by_reason() {
+        let tracker = JobStatusTracker::default();
+        for _ in 0..5 {
+            tracker.add_outstanding();
+        }
+        tracker.job_exited("foo".into(), "foo test_a".into(), ExitCode::SUCCESS);
+        tracker.job_skipped("foo".into(), "foo test_b".into(), SkipReason::Ignored);
+        tracker.job_skipped("foo".into(), "foo test_c".into(), SkipReason::Quarantined);
+        tracker.job_skipped("bar".into(), "bar test_a".into(), SkipReason::Quarantined);
+        tracker.job_exited("bar".into(), "bar test_b".into(), ExitCode::FAILURE);
+
+        let summary = tracker.ui_summary();
+        assert_eq!(summary.succeeded, 1);
+        assert_eq!(summary.failed, vec!["bar test_b".to_owned()]);
+        assert_eq!(summary.ignored, vec!["foo test_b".to_owned()]);
+        assert_eq!(
+            summary.skipped,
+            BTreeMap::from([(SkipReason::Ignored, 1), (SkipReason::Quarantined, 2)])
+        );
+        assert_eq!(tracker.exit_code(), ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn package_summaries_empty() {
+        assert_eq!(
+            JobStatusTracker::default().package_summaries(),
+            BTreeMap::new()
+        );
+    }
+
+    /// Golden stdout, keyed by package, artifact, and case.
+    type Goldens = HashMap<(String, String, String), String>;
+
+    #[derive(Clone, Default)]
+    struct FakeGoldenStore(Arc<Mutex<Goldens>>);
+
+    impl FakeGoldenStore {
+        fn with(self, package: &str, artifact: &str, case: &str, stdout: &str) -> Self {
+            self.write(package, artifact, case, stdout).unwrap();
+            self
+        }
+
+        fn get(&self, package: &str, artifact: &str, case: &str) -> Option<String> {
+            self.read(package, artifact, case).unwrap()
+        }
+    }
+
+    impl GoldenStore for FakeGoldenStore {
+        fn read(&self, package: &str, artifact: &str, case: &str) -> Result<Option<String>> {
+            let goldens = self.0.lock().unwrap();
+            Ok(goldens
+                .get(&(package.into(), artifact.into(), case.into()))
+                .cloned())
+        }
+
+        fn write(&self, package: &str, artifact: &str, case: &str, stdout: &str) -> Result<()> {
+            let mut goldens = self.0.lock().unwrap();
+            goldens.insert(
+                (package.into(), artifact.into(), case.into()),
+                stdout.into(),
+            );
+            Ok(())
+        }
+    }
+
+    type TestVisitor =
+        JobStatusVisitor<StringArtifactKey, NoCaseMetadata, fn(&str, Vec<String>) -> Vec<String>>;
+
+    /// Create a visitor for "foo test_a" that reports to `tracker`, which gets a new outstanding
+    /// job.
+    fn visitor(
+        tracker: &Arc<JobStatusTracker>,
+        ui: mpsc::Sender<UiMessage>,
+        golden_files: Option<GoldenFiles>,
+    ) -> TestVisitor {
+        tracker.add_outstanding();
+        let mut listing = TestListing::<StringArtifactKey, NoCaseMetadata>::default();
+        listing.update_artifact_cases("foo", "foo.library", [("test_a", NoCaseMetadata)]);
+        JobStatusVisitor::new(
+            tracker.clone(),
+            Arc::new(Mutex::new(Some(listing))),
+            "foo".into(),
+            StringArtifactKey::from("foo.library"),
+            "test_a".into(),
+            "foo test_a".into(),
+            UiSender::new(ui),
+            |_, lines| lines,
+            Arc::new(NoOpTimingSink),
+            golden_files,
+            None,
+        )
+    }
+
+    fn exited(code: u8, stdout: &str) -> Result<(ClientJobId, JobOutcomeResult)> {
+        Ok((
+            ClientJobId::from(1),
+            Ok(JobOutcome::Completed(JobCompleted {
+                status: JobStatus::Exited(code),
+                effects: JobEffects {
+                    stdout: JobOutputResult::Inline(stdout.as_bytes().into()),
+                    stderr: JobOutputResult::None,
+                    duration: Duration::from_secs(1),
+                    worker: None,
+                },
+            })),
+        ))
+    }
+
+    /// Run a job that exits successfully with the given stdout through a visitor that compares
+    /// it against `golden_files`. Returns the reported status and stdout, and the exit code.
+    fn finish_passing_job(
+        golden_files: GoldenFiles,
+        stdout: &str,
+    ) -> (UiJobStatus, Vec<String>, ExitCode) {
+        let tracker = Arc::new(JobStatusTracker::default());
+        let (ui_send, ui_recv) = mpsc::channel();
+        visitor(&tracker, ui_send, Some(golden_files)).job_finished(exited(0, stdout));
+        let Ok(UiMessage::JobFinished(result)) = ui_recv.try_recv() else {
+            panic!("expected a finished job");
+        };
+        (result.status, result.stdout, tracker.exit_code())
+    }
+
+    #[test]
+    fn golden_file_matches() {
+        let store =
+            FakeGoldenStore::default().with("foo", "foo.library", "test_a", "hello\nworld\n");
+        let (status, stdout, exit_code) =
+            finish_passing_job(GoldenFiles::new(store), "hello\nworld\n");
+        assert_matches!(status, UiJobStatus::Ok);
+        assert_eq!(stdout, Vec::<String>::new());
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn golden_file_mismatch_fails_case() {
+        let store =
+            FakeGoldenStore::default().with("foo", "foo.library", "test_a", "hello\nworld\n");
+        let (status, stdout, exit_code) =
+            finish_passing_job(GoldenFiles::new(store), "hello\nthere\n");
+        assert_matches!(status, UiJobStatus::Failure(Some(reason))
+            if reason == "stdout doesn't match golden file");
+        assert!(!stdout.is_empty());
+        assert_eq!(exit_code, ExitCode::FAILURE);
+    }
+
+    #[test]
+    fn golden_file_of_other_artifact_is_not_used() {
+        let store = FakeGoldenStore::default().with("foo", "foo.binary", "test_a", "hello\n");
+        let (status, _, exit_code) = finish_passing_job(GoldenFiles::new(store), "goodbye\n");
+        assert_matches!(status, UiJobStatus::Ok);
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn fixture_output_is_removed_using_case_name() {
+        let tracker = Arc::new(JobStatusTracker::default());
+        tracker.add_outstanding();
+        let (ui_send, ui_recv) = mpsc::channel();
+        let mut listing = TestListing::<StringArtifactKey, NoCaseMetadata>::default();
+        listing.update_artifact_cases("foo", "foo.library", [("test_a[x y]", NoCaseMetadata)]);
+        let visitor: TestVisitor = JobStatusVisitor::new(
+            tracker.clone(),
+            Arc::new(Mutex::new(Some(listing))),
+            "foo".into(),
+            StringArtifactKey::from("foo.library"),
+            "test_a[x y]".into(),
+            "foo test_a[x y]".into(),
+            UiSender::new(ui_send),
+            |case, lines| {
+                assert_eq!(case, "test_a[x y]");
+                lines
+            },
+            Arc::new(NoOpTimingSink),
+            None,
+            None,
+        );
+        visitor.job_finished(exited(1, "output"));
+        let Ok(UiMessage::JobFinished(result)) = ui_recv.try_recv() else {
+            panic!("expected a finished job");
+        };
+        assert_eq!(result.stdout, vec!["output".to_owned()]);
+    }
+
+    #[test]
+    fn missing_golden_file_passes_without_creating_it() {
+        let store = FakeGoldenStore::default();
+        let (status, _, exit_code) = finish_passing_job(GoldenFiles::new(store.clone()), "hello\n");
+        assert_matches!(status, UiJobStatus::Ok);
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+        assert_eq!(store.get("foo", "foo.library", "test_a"), None);
+    }
+
+    #[test]
+    fn missing_golden_file_is_created_when_configured() {
+        let store = FakeGoldenStore::default();
+        let golden_files = GoldenFiles::new(store.clone()).create_missing(true);
+        let (status, _, exit_code) = finish_passing_job(golden_files, "hello\n");
+        assert_matches!(status, UiJobStatus::Ok);
+        assert_eq!(exit_code, ExitCode::SUCCESS);
+        assert_eq!(
+            store.get("foo", "foo.library", "test_a"),
+            Some("hello\n".into())
+        );
+    }
+
+    #[test]
+    fn case_result_reports_worker() {
+        let tracker = Arc::new(JobStatusTracker::default());
+        let (ui_send, ui_recv) = mpsc::channel();
+        let mut res = exited(1, "");
+        if let Ok((_, Ok(outcome))) = &mut res {
+            outcome.effects_mut().worker = Some(WorkerId::from(7));
+        }
+        visitor(&tracker, ui_send, None).job_finished(res);
+        let Ok(UiMessage::JobFinished(result)) = ui_recv.try_recv() else {
+            panic!("expected a finished job");
+        };
+        assert_eq!(result.worker, Some(WorkerId::from(7)));
+
+        let (ui_send, ui_recv) = mpsc::channel();
+        visitor(&tracker, ui_send, None).job_finished(exited(1, ""));
+        let Ok(UiMessage::JobFinished(result)) = ui_recv.try_recv() else {
+            panic!("expected a finished job");
+        };
+        assert_eq!(result.worker, None);
+    }
+
+    #[test]
+    fn case_that_passes_on_retry_is_flaky() {
+        let tracker = Arc::new(JobStatusTracker::default());
+        let (ui_send, ui_recv) = mpsc::channel();
+        let spec = JobSpec::new("test_a", vec![]);
+        visitor(&tracker, ui_send, None)
+            .retries(spec.clone(), 2)
+            .job_finished(exited(1, ""));
+        let Ok(UiMessage::LogMessage(message)) = ui_recv.try_recv() else {
+            panic!("expected a log message");
+        };
+        assert_eq!(message, "retrying foo test_a (1 retries left)");
+        assert!(ui_recv.try_recv().is_err());
+
+        let retry = tracker.wait_for_follow_up().unwrap();
+        assert_eq!(retry.spec, spec);
+        (retry.handler)(exited(0, ""));
+        let Ok(UiMessage::JobFinished(result)) = ui_recv.try_recv() else {
+            panic!("expected a finished job");
+        };
+        assert_matches!(result.status, UiJobStatus::Ok);
+        assert!(tracker.wait_for_follow_up().is_none());
+
+        let summary = tracker.ui_summary();
+        assert_eq!(summary.succeeded, 0);
+        assert_eq!(summary.failed, Vec::<String>::new());
+        assert_eq!(summary.flaky, vec!["foo test_a".to_owned()]);
+        assert_eq!(
+            tracker.package_summaries(),
+            BTreeMap::from([(
+                "foo".into(),
+                PackageSummary {
+                    flaky: 1,
+                    ..Default::default()
+                }
+            )])
+        );
+        assert_eq!(tracker.exit_code(), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn case_that_fails_every_retry_fails() {
+        let tracker = Arc::new(JobStatusTracker::default());
+        let (ui_send, ui_recv) = mpsc::channel();
+        visitor(&tracker, ui_send, None)
+            .retries(JobSpec::new("test_a", vec![]), 1)
+            .job_finished(exited(1, ""));
+        (tracker.wait_for_follow_up().unwrap().handler)(exited(2, ""));
+        assert!(tracker.wait_for_follow_up().is_none());
+
+        assert!(matches!(ui_recv.try_recv(), Ok(UiMessage::LogMessage(_))));
+        let Ok(UiMessage::JobFinished(result)) = ui_recv.try_recv() else {
+            panic!("expected a finished job");
+        };
+        assert_matches!(result.status, UiJobStatus::Failure(None));
+        let summary = tracker.ui_summary();
+        assert_eq!(summary.failed, vec!["foo test_a".to_owned()]);
+        assert_eq!(summary.flaky, Vec::<String>::new());
+        assert_eq!(tracker.exit_code(), ExitCode::from(2));
+    }
+
+    #[test]
+    fn cleanup_is_queued_after_passing_case() {
+        let tracker = Arc::new(JobStatusTracker::default());
+        let (ui_send, ui_recv) = mpsc::channel();
+        let cleanup = JobSpec::new("cleanup", vec![]);
+        visitor(&tracker, ui_send, None)
+            .cleanup(Some(cleanup.clone()))
+            .job_finished(exited(0, ""));
+        let Ok(UiMessage::JobFinished(result)) = ui_recv.try_recv() else {
+            panic!("expected a finished job");
+        };
+        assert_matches!(result.status, UiJobStatus::Ok);
+
+        let follow_up = tracker.wait_for_follow_up().unwrap();
+        assert_eq!(follow_up.spec, cleanup);
+        (follow_up.handler)(exited(0, ""));
+        assert!(tracker.wait_for_follow_up().is_none());
+        assert!(ui_recv.try_recv().is_err());
+        assert_eq!(tracker.exit_code(), ExitCode::SUCCESS);
+    }
+
+    #[test]
+    fn cleanup_is_queued_after_failing_case() {
+        let tracker = Arc::new(JobStatusTracker::default());
+        let (ui_send, ui_recv) = mpsc::channel();
+        let cleanup = JobSpec::new("cleanup", vec![]);
+        visitor(&tracker, ui_send, None)
+            .cleanup(Some(cleanup.clone()))
+            .job_finished(exited(1, ""));
+        let Ok(UiMessage::JobFinished(result)) = ui_recv.try_recv() else {
+            panic!("expected a finished job");
+        };
+        assert_matches!(result.status, UiJobStatus::Failure(None));
+
+        let follow_up = tracker.wait_for_follow_up().unwrap();
+        assert_eq!(follow_up.spec, cleanup);
+        (follow_up.handler)(exited(3, ""));
+        let Ok(UiMessage::LogMessage(message)) = ui_recv.try_recv() else {
+            panic!("expected a log message");
+        };
+        assert_eq!(message, "cleanup for foo test_a failed: exited with code 3");
+        assert!(tracker.wait_for_follow_up().is_none());
+        assert_eq!(tracker.exit_code(), ExitCode::from(1));
     }
 }
@@ -77,6 +77,7 @@ macro_rules! completed {
                 stdout: maelstrom_base::JobOutputResult::None,
                 stderr: maelstrom_base::JobOutputResult::None,
                 duration: std::time::Duration::from_secs(1),
+                worker: None,
             }
         }
     };
@@ -87,6 +88,7 @@ macro_rules! completed {
                 stdout: maelstrom_base::JobOutputResult::None,
                 stderr: maelstrom_base::JobOutputResult::None,
                 duration: std::time::Duration::from_secs(1),
+                worker: None,
             }
         }
     };
@@ -97,6 +99,7 @@ macro_rules! completed {
                 stdout: maelstrom_base::JobOutputResult::None,
                 stderr: maelstrom_base::JobOutputResult::None,
                 duration: std::time::Duration::from_secs(1),
+                worker: None,
             }
         }
     };
@@ -107,6 +110,7 @@ macro_rules! completed {
                 stdout: maelstrom_base::JobOutputResult::None,
                 stderr: maelstrom_base::JobOutputResult::None,
                 duration: std::time::Duration::from_secs(1),
+                worker: None,
             }
         }
     };
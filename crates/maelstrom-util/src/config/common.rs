@@ -247,6 +247,15 @@ impl Slots {
     pub fn into_inner(self) -> u16 {
         self.0
     }
+
+    /// The number of slots to use to keep every available CPU busy, as reported by
+    /// [`std::thread::available_parallelism`]. This is clamped to the range of valid slot counts,
+    /// and falls back to 1 if the available parallelism can't be determined.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_available_parallelism() -> Self {
+        let parallelism = std::thread::available_parallelism().map_or(1, usize::from);
+        Self::try_from(parallelism.clamp(1, 1000)).unwrap()
+    }
 }
 
 impl TryFrom<u16> for Slots {
@@ -543,4 +552,12 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn slots_from_available_parallelism() {
+        let parallelism = std::thread::available_parallelism().unwrap().get();
+        let slots = Slots::from_available_parallelism().into_inner();
+        assert!(slots >= 1);
+        assert_eq!(usize::from(slots), parallelism.min(1000));
+    }
 }
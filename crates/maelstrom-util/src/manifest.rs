@@ -3,25 +3,33 @@ use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use maelstrom_base::{
     manifest::{
-        ManifestEntry, ManifestEntryData, ManifestEntryMetadata, ManifestFileData, ManifestVersion,
-        Mode, UnixTimestamp,
+        Chunker, DigestAlgorithm, Manifest, ManifestEntry, ManifestEntryData,
+        ManifestEntryMetadata, ManifestFileData, ManifestVersion, Mode, UnixTimestamp,
     },
     proto, Sha256Digest, Utf8PathBuf,
 };
+use maelstrom_linux::{self as linux, Errno};
 use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::CString;
 use std::io;
+use std::os::unix::ffi::OsStrExt as _;
 use std::os::unix::fs::MetadataExt as _;
 use std::path::Path;
 use tokio::io::{
     AsyncRead, AsyncReadExt as _, AsyncSeek, AsyncSeekExt as _, AsyncWrite, AsyncWriteExt as _,
 };
 
-pub async fn decode_async<T: DeserializeOwned>(
-    mut stream: impl AsyncRead + Unpin,
-) -> io::Result<T> {
+async fn read_frame_async(mut stream: impl AsyncRead + Unpin) -> io::Result<Vec<u8>> {
     let len = stream.read_u64().await?;
     let mut buffer = vec![0; len as usize];
     stream.read_exact(&mut buffer).await?;
+    Ok(buffer)
+}
+
+pub async fn decode_async<T: DeserializeOwned>(stream: impl AsyncRead + Unpin) -> io::Result<T> {
+    let buffer = read_frame_async(stream).await?;
     proto::deserialize(&buffer).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
 }
 
@@ -40,12 +48,17 @@ pub async fn encode_async<T: Serialize>(
     Ok(())
 }
 
-pub fn decode<T: DeserializeOwned>(mut stream: impl io::Read) -> io::Result<T> {
+fn read_frame(mut stream: impl io::Read) -> io::Result<Vec<u8>> {
     use byteorder::{BigEndian, ReadBytesExt as _};
 
     let len = stream.read_u64::<BigEndian>()?;
     let mut buffer = vec![0; len as usize];
     stream.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+pub fn decode<T: DeserializeOwned>(stream: impl io::Read) -> io::Result<T> {
+    let buffer = read_frame(stream)?;
     proto::deserialize(&buffer).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
 }
 
@@ -60,9 +73,24 @@ pub fn encode<T: Serialize>(mut stream: impl io::Write, t: &T) -> io::Result<()>
     Ok(())
 }
 
+fn bad_manifest_version() -> io::Error {
+    io::Error::other("bad manifest version")
+}
+
+fn too_many_entries(max_entries: usize) -> io::Error {
+    io::Error::other(format!("manifest exceeds maximum of {max_entries} entries"))
+}
+
+fn parse_digest_algorithm(value: u32) -> io::Result<DigestAlgorithm> {
+    DigestAlgorithm::try_from(value).map_err(io::Error::other)
+}
+
 pub struct ManifestReader<ReadT> {
     r: ReadT,
     stream_end: u64,
+    digest_algorithm: DigestAlgorithm,
+    max_entries: Option<usize>,
+    entries_read: usize,
 }
 
 impl<ReadT: io::Read + io::Seek> ManifestReader<ReadT> {
@@ -73,18 +101,73 @@ impl<ReadT: io::Read + io::Seek> ManifestReader<ReadT> {
         r.seek(io::SeekFrom::Start(stream_start))?;
 
         let version: ManifestVersion = decode(&mut r)?;
-        if version != ManifestVersion::default() {
-            return Err(io::Error::new(io::ErrorKind::Other, "bad manifest version"));
-        }
+        let digest_algorithm = match version {
+            ManifestVersion::V0 => return Err(bad_manifest_version()),
+            ManifestVersion::V1 => DigestAlgorithm::Sha256,
+            ManifestVersion::V2 => parse_digest_algorithm(decode(&mut r)?)?,
+        };
 
-        Ok(Self { r, stream_end })
+        Ok(Self {
+            r,
+            stream_end,
+            digest_algorithm,
+            max_entries: None,
+            entries_read: 0,
+        })
+    }
+
+    /// Limit the number of entries that will be read. Attempting to read more than `max_entries`
+    /// entries results in an error instead of an entry.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// The algorithm used to compute the digests in the manifest's entries.
+    pub fn digest_algorithm(&self) -> DigestAlgorithm {
+        self.digest_algorithm
+    }
+
+    fn count_entry(&mut self) -> io::Result<()> {
+        if let Some(max_entries) = self.max_entries {
+            if self.entries_read >= max_entries {
+                return Err(too_many_entries(max_entries));
+            }
+        }
+        self.entries_read += 1;
+        Ok(())
     }
 
     fn next_inner(&mut self) -> io::Result<Option<ManifestEntry>> {
         if self.r.stream_position()? == self.stream_end {
             return Ok(None);
         }
-        Ok(Some(decode(&mut self.r)?))
+        self.count_entry()?;
+        let buffer = read_frame(&mut self.r)?;
+        Ok(Some(
+            ManifestEntry::decode(&buffer).map_err(io::Error::other)?,
+        ))
+    }
+
+    /// Read all of the remaining entries into memory.
+    pub fn read_manifest(self) -> io::Result<Manifest> {
+        self.collect()
+    }
+
+    /// Return the digests referenced by the remaining file entries. Entries are read one at a
+    /// time, so only the digests are held in memory, not the whole manifest.
+    pub fn referenced_digests(self) -> io::Result<HashSet<Sha256Digest>> {
+        let mut digests = HashSet::new();
+        for entry in self {
+            extend_with_digests(&mut digests, &entry?);
+        }
+        Ok(digests)
+    }
+}
+
+fn extend_with_digests(digests: &mut HashSet<Sha256Digest>, entry: &ManifestEntry) {
+    if let ManifestEntryData::File(data) = &entry.data {
+        digests.extend(data.digests().cloned());
     }
 }
 
@@ -99,6 +182,9 @@ impl<ReadT: io::Read + io::Seek> Iterator for ManifestReader<ReadT> {
 pub struct AsyncManifestReader<ReadT> {
     r: ReadT,
     stream_end: u64,
+    digest_algorithm: DigestAlgorithm,
+    max_entries: Option<usize>,
+    entries_read: usize,
 }
 
 impl<ReadT: AsyncRead + AsyncSeek + Unpin> AsyncManifestReader<ReadT> {
@@ -109,18 +195,71 @@ impl<ReadT: AsyncRead + AsyncSeek + Unpin> AsyncManifestReader<ReadT> {
         r.seek(io::SeekFrom::Start(stream_start)).await?;
 
         let version: ManifestVersion = decode_async(&mut r).await?;
-        if version != ManifestVersion::default() {
-            return Err(io::Error::new(io::ErrorKind::Other, "bad manifest version"));
-        }
+        let digest_algorithm = match version {
+            ManifestVersion::V0 => return Err(bad_manifest_version()),
+            ManifestVersion::V1 => DigestAlgorithm::Sha256,
+            ManifestVersion::V2 => parse_digest_algorithm(decode_async(&mut r).await?)?,
+        };
+
+        Ok(Self {
+            r,
+            stream_end,
+            digest_algorithm,
+            max_entries: None,
+            entries_read: 0,
+        })
+    }
 
-        Ok(Self { r, stream_end })
+    /// Limit the number of entries that will be read. Attempting to read more than `max_entries`
+    /// entries results in an error instead of an entry.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    /// The algorithm used to compute the digests in the manifest's entries.
+    pub fn digest_algorithm(&self) -> DigestAlgorithm {
+        self.digest_algorithm
+    }
+
+    fn count_entry(&mut self) -> io::Result<()> {
+        if let Some(max_entries) = self.max_entries {
+            if self.entries_read >= max_entries {
+                return Err(too_many_entries(max_entries));
+            }
+        }
+        self.entries_read += 1;
+        Ok(())
     }
 
     pub async fn next(&mut self) -> io::Result<Option<ManifestEntry>> {
         if self.r.stream_position().await? == self.stream_end {
             return Ok(None);
         }
-        Ok(Some(decode_async(&mut self.r).await?))
+        self.count_entry()?;
+        let buffer = read_frame_async(&mut self.r).await?;
+        Ok(Some(
+            ManifestEntry::decode(&buffer).map_err(io::Error::other)?,
+        ))
+    }
+
+    /// Read all of the remaining entries into memory.
+    pub async fn read_manifest(mut self) -> io::Result<Manifest> {
+        let mut entries = vec![];
+        while let Some(entry) = self.next().await? {
+            entries.push(entry);
+        }
+        Ok(Manifest::new(entries))
+    }
+
+    /// Return the digests referenced by the remaining file entries. Entries are read one at a
+    /// time, so only the digests are held in memory, not the whole manifest.
+    pub async fn referenced_digests(mut self) -> io::Result<HashSet<Sha256Digest>> {
+        let mut digests = HashSet::new();
+        while let Some(entry) = self.next().await? {
+            extend_with_digests(&mut digests, &entry);
+        }
+        Ok(digests)
     }
 }
 
@@ -129,8 +268,18 @@ pub struct ManifestWriter<WriteT> {
 }
 
 impl<WriteT: io::Write> ManifestWriter<WriteT> {
-    pub fn new(mut w: WriteT) -> io::Result<Self> {
+    pub fn new(w: WriteT) -> io::Result<Self> {
+        Self::with_digest_algorithm(w, DigestAlgorithm::default())
+    }
+
+    /// Create a writer whose header records that entry digests were computed with
+    /// `digest_algorithm`.
+    pub fn with_digest_algorithm(
+        mut w: WriteT,
+        digest_algorithm: DigestAlgorithm,
+    ) -> io::Result<Self> {
         encode(&mut w, &ManifestVersion::default())?;
+        encode(&mut w, &digest_algorithm)?;
         Ok(Self { w })
     }
 
@@ -155,8 +304,18 @@ pub struct AsyncManifestWriter<WriteT> {
 }
 
 impl<WriteT: AsyncWrite + Unpin> AsyncManifestWriter<WriteT> {
-    pub async fn new(mut w: WriteT) -> io::Result<Self> {
+    pub async fn new(w: WriteT) -> io::Result<Self> {
+        Self::with_digest_algorithm(w, DigestAlgorithm::default()).await
+    }
+
+    /// Create a writer whose header records that entry digests were computed with
+    /// `digest_algorithm`.
+    pub async fn with_digest_algorithm(
+        mut w: WriteT,
+        digest_algorithm: DigestAlgorithm,
+    ) -> io::Result<Self> {
         encode_async(&mut w, &ManifestVersion::default()).await?;
+        encode_async(&mut w, &digest_algorithm).await?;
         Ok(Self { w })
     }
 
@@ -176,10 +335,69 @@ impl<WriteT: AsyncWrite + Unpin> AsyncManifestWriter<WriteT> {
     }
 }
 
+/// Split `data` into content-defined chunks with `chunker`. Returns the
+/// [`ManifestFileData::ChunkedFile`] describing the data, along with each chunk and its digest. The
+/// chunks need to be uploaded as separate artifacts.
+pub fn chunk_file_data<'a>(
+    chunker: &Chunker,
+    data: &'a [u8],
+) -> (ManifestFileData, Vec<(Sha256Digest, &'a [u8])>) {
+    let chunks: Vec<_> = chunker
+        .chunks(data)
+        .map(|chunk| (Sha256Digest::new(Sha256::digest(chunk).into()), chunk))
+        .collect();
+    let file_data = ManifestFileData::ChunkedFile {
+        chunks: chunks.iter().map(|(digest, _)| digest.clone()).collect(),
+        size: data.len() as u64,
+    };
+    (file_data, chunks)
+}
+
 fn to_utf8_path(path: impl AsRef<Path>) -> Utf8PathBuf {
     path.as_ref().to_owned().try_into().unwrap()
 }
 
+/// Call `f`, which fills a buffer like `getxattr(2)` and `listxattr(2)` do, with a buffer large
+/// enough for its result. An empty buffer asks for the size needed, which may grow before the
+/// second call.
+fn read_xattr_buffer(f: impl Fn(&mut [u8]) -> Result<usize, Errno>) -> Result<Vec<u8>, Errno> {
+    loop {
+        let mut buffer = vec![0; f(&mut [])?];
+        match f(&mut buffer) {
+            Ok(len) => {
+                buffer.truncate(len);
+                return Ok(buffer);
+            }
+            Err(Errno::ERANGE) => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Read the extended attributes of `path`, without following a final symlink. A file system that
+/// doesn't support extended attributes has none.
+fn read_xattrs(path: &Path) -> io::Result<BTreeMap<String, Vec<u8>>> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let names = match read_xattr_buffer(|buf| linux::llistxattr(&path, buf)) {
+        Err(Errno::EOPNOTSUPP) => return Ok(BTreeMap::new()),
+        res => res?,
+    };
+    let mut xattrs = BTreeMap::new();
+    for name in names.split(|b| *b == 0).filter(|name| !name.is_empty()) {
+        let value = match read_xattr_buffer(|buf| {
+            linux::lgetxattr(&path, &CString::new(name).unwrap(), buf)
+        }) {
+            // The attribute was removed after we listed it.
+            Err(Errno::ENODATA) => continue,
+            res => res?,
+        };
+        let name = String::from_utf8(name.to_owned())
+            .map_err(|_| io::Error::other("extended attribute name is not valid UTF-8"))?;
+        xattrs.insert(name, value);
+    }
+    Ok(xattrs)
+}
+
 fn convert_metadata(meta: &async_fs::Metadata) -> ManifestEntryMetadata {
     ManifestEntryMetadata {
         size: meta.is_file().then(|| meta.size()).unwrap_or(0),
@@ -199,6 +417,9 @@ pub struct ManifestBuilder<'cb, WriteT> {
     follow_symlinks: bool,
     data_upload: Box<dyn DataUpload + 'cb>,
     inline_limit: u64,
+    /// The destination of the first file added for each (device, inode) with more than one link.
+    /// Later files with the same inode become hardlinks to it.
+    hardlink_targets: HashMap<(u64, u64), Utf8PathBuf>,
 }
 
 impl<'cb, WriteT: AsyncWrite + Unpin> ManifestBuilder<'cb, WriteT> {
@@ -214,19 +435,28 @@ impl<'cb, WriteT: AsyncWrite + Unpin> ManifestBuilder<'cb, WriteT> {
             data_upload: Box::new(data_upload),
             follow_symlinks,
             inline_limit,
+            hardlink_targets: HashMap::new(),
         })
     }
 
     async fn add_entry(
         &mut self,
         meta: &async_fs::Metadata,
+        source: &Path,
         path: impl AsRef<Path>,
         data: ManifestEntryData,
     ) -> Result<()> {
+        let source = if self.follow_symlinks {
+            self.fs.canonicalize(source).await?
+        } else {
+            source.to_owned()
+        };
+        let xattrs = tokio::task::spawn_blocking(move || read_xattrs(&source)).await??;
         let entry = ManifestEntry {
             path: to_utf8_path(path),
             metadata: convert_metadata(meta),
             data,
+            xattrs,
         };
         self.writer.write_entry(&entry).await?;
         Ok(())
@@ -243,6 +473,15 @@ impl<'cb, WriteT: AsyncWrite + Unpin> ManifestBuilder<'cb, WriteT> {
             self.fs.symlink_metadata(source.as_ref()).await?
         };
         if meta.is_file() {
+            let inode = (meta.nlink() > 1).then(|| (meta.dev(), meta.ino()));
+            if let Some(target) = inode.and_then(|inode| self.hardlink_targets.get(&inode)) {
+                let data = ManifestEntryData::Hardlink(target.clone());
+                return self.add_entry(&meta, source.as_ref(), dest, data).await;
+            }
+            if let Some(inode) = inode {
+                self.hardlink_targets
+                    .insert(inode, to_utf8_path(dest.as_ref()));
+            }
             let file_size = meta.size();
             let data = if file_size <= self.inline_limit {
                 ManifestFileData::Inline(self.fs.read(source.as_ref()).await?)
@@ -251,15 +490,21 @@ impl<'cb, WriteT: AsyncWrite + Unpin> ManifestBuilder<'cb, WriteT> {
             } else {
                 ManifestFileData::Empty
             };
-            self.add_entry(&meta, dest, ManifestEntryData::File(data))
+            self.add_entry(&meta, source.as_ref(), dest, ManifestEntryData::File(data))
                 .await
         } else if meta.is_dir() {
-            self.add_entry(&meta, dest, ManifestEntryData::Directory { opaque: false })
-                .await
+            self.add_entry(
+                &meta,
+                source.as_ref(),
+                dest,
+                ManifestEntryData::Directory { opaque: false },
+            )
+            .await
         } else if meta.is_symlink() {
             let data = self.fs.read_link(source.as_ref()).await?;
             self.add_entry(
                 &meta,
+                source.as_ref(),
                 dest,
                 ManifestEntryData::Symlink(data.into_os_string().into_encoded_bytes()),
             )
@@ -273,6 +518,8 @@ impl<'cb, WriteT: AsyncWrite + Unpin> ManifestBuilder<'cb, WriteT> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use maelstrom_base::test_support::pseudo_random_data;
+    use std::collections::HashSet;
     use std::future::Future;
     use std::path::PathBuf;
     use std::pin::Pin;
@@ -338,6 +585,11 @@ mod tests {
                 .await
                 .unwrap()
         };
+        let input_xattrs_path = if follow_symlinks {
+            fixture.fs.canonicalize(&fixture.input_path).await.unwrap()
+        } else {
+            fixture.input_path.clone()
+        };
         assert_eq!(
             actual_entries,
             vec![ManifestEntry {
@@ -347,6 +599,7 @@ mod tests {
                     ..convert_metadata(&input_meta)
                 },
                 data,
+                xattrs: read_xattrs(&input_xattrs_path).unwrap(),
             }]
         );
     }
@@ -444,4 +697,373 @@ mod tests {
         )
         .await;
     }
+
+    fn test_entry() -> ManifestEntry {
+        ManifestEntry {
+            path: "foo/bar.txt".into(),
+            metadata: ManifestEntryMetadata {
+                size: 6,
+                mode: Mode(0o444),
+                mtime: UnixTimestamp(1),
+            },
+            data: ManifestEntryData::File(ManifestFileData::Digest(42u64.into())),
+            xattrs: BTreeMap::new(),
+        }
+    }
+
+    fn test_entry_with_xattrs() -> ManifestEntry {
+        ManifestEntry {
+            xattrs: BTreeMap::from([("user.foo".into(), b"bar".to_vec())]),
+            ..test_entry()
+        }
+    }
+
+    #[test]
+    fn digest_algorithm_header_round_trip() {
+        let mut buffer = vec![];
+        let mut writer =
+            ManifestWriter::with_digest_algorithm(&mut buffer, DigestAlgorithm::Sha256).unwrap();
+        writer.write_entry(&test_entry()).unwrap();
+
+        let reader = ManifestReader::new(io::Cursor::new(buffer)).unwrap();
+        assert_eq!(reader.digest_algorithm(), DigestAlgorithm::Sha256);
+        let entries: Vec<_> = reader.map(|e| e.unwrap()).collect();
+        assert_eq!(entries, vec![test_entry()]);
+    }
+
+    #[test]
+    fn xattrs_round_trip() {
+        let mut buffer = vec![];
+        ManifestWriter::new(&mut buffer)
+            .unwrap()
+            .write_entries([&test_entry_with_xattrs(), &test_entry()])
+            .unwrap();
+
+        let entries: Vec<_> = ManifestReader::new(io::Cursor::new(buffer))
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(entries, vec![test_entry_with_xattrs(), test_entry()]);
+    }
+
+    #[tokio::test]
+    async fn xattrs_round_trip_async() {
+        let mut buffer = vec![];
+        AsyncManifestWriter::new(&mut buffer)
+            .await
+            .unwrap()
+            .write_entries([&test_entry_with_xattrs(), &test_entry()])
+            .await
+            .unwrap();
+
+        let mut reader = AsyncManifestReader::new(io::Cursor::new(buffer))
+            .await
+            .unwrap();
+        assert_eq!(reader.next().await.unwrap(), Some(test_entry_with_xattrs()));
+        assert_eq!(reader.next().await.unwrap(), Some(test_entry()));
+        assert_eq!(reader.next().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn builder_turns_repeated_inodes_into_hardlinks() {
+        let fixture = Fixture::new();
+        let root = fixture.temp_dir.path();
+        fixture.fs.write(root.join("a"), b"foobar").await.unwrap();
+        fixture
+            .fs
+            .hard_link(root.join("a"), root.join("b"))
+            .await
+            .unwrap();
+        fixture.fs.write(root.join("c"), b"foobar").await.unwrap();
+
+        let mut buffer = vec![];
+        let mut builder = ManifestBuilder::new(&mut buffer, false, TestDataUpload, 5)
+            .await
+            .unwrap();
+        for name in ["a", "b", "c"] {
+            builder
+                .add_file(root.join(name), format!("dest/{name}"))
+                .await
+                .unwrap();
+        }
+
+        let entries: Vec<_> = ManifestReader::new(io::Cursor::new(buffer))
+            .unwrap()
+            .map(|e| {
+                let e = e.unwrap();
+                (e.path.into_string(), e.data)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    "dest/a".into(),
+                    ManifestEntryData::File(ManifestFileData::Digest(42u64.into()))
+                ),
+                (
+                    "dest/b".into(),
+                    ManifestEntryData::Hardlink("dest/a".into())
+                ),
+                (
+                    "dest/c".into(),
+                    ManifestEntryData::File(ManifestFileData::Digest(42u64.into()))
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn builder_reads_xattrs() {
+        let fixture = Fixture::new();
+        fixture
+            .fs
+            .write(&fixture.input_path, b"foobar")
+            .await
+            .unwrap();
+        let path = CString::new(fixture.input_path.as_os_str().as_bytes()).unwrap();
+        match linux::setxattr(&path, c"user.maelstrom", b"value") {
+            // Not every file system that might hold the temporary directory supports user
+            // extended attributes.
+            Err(Errno::EOPNOTSUPP) => return,
+            res => res.unwrap(),
+        }
+
+        let mut buffer = vec![];
+        let mut builder = ManifestBuilder::new(&mut buffer, false, TestDataUpload, 5)
+            .await
+            .unwrap();
+        builder
+            .add_file(&fixture.input_path, "foo/bar.txt")
+            .await
+            .unwrap();
+
+        let entries: Vec<_> = ManifestReader::new(io::Cursor::new(buffer))
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(
+            entries[0].xattrs.get("user.maelstrom").map(Vec::as_slice),
+            Some(&b"value"[..])
+        );
+    }
+
+    fn manifest_referencing_digests() -> Manifest {
+        let entry = |path: &str, data| ManifestEntry {
+            path: path.into(),
+            data: ManifestEntryData::File(data),
+            ..test_entry()
+        };
+        Manifest::from_iter([
+            entry("a", ManifestFileData::Digest(1u64.into())),
+            entry("b", ManifestFileData::Inline(b"foo".to_vec())),
+            entry(
+                "c",
+                ManifestFileData::ChunkedFile {
+                    chunks: vec![2u64.into(), 3u64.into(), 1u64.into()],
+                    size: 6,
+                },
+            ),
+            ManifestEntry {
+                path: "d".into(),
+                data: ManifestEntryData::Directory { opaque: false },
+                ..test_entry()
+            },
+            entry("e", ManifestFileData::Digest(4u64.into())),
+            entry("f", ManifestFileData::Empty),
+        ])
+    }
+
+    #[test]
+    fn referenced_digests_matches_eager_read() {
+        let manifest = manifest_referencing_digests();
+        let mut buffer = vec![];
+        ManifestWriter::new(&mut buffer)
+            .unwrap()
+            .write_entries(&manifest.entries)
+            .unwrap();
+
+        let eager = ManifestReader::new(io::Cursor::new(&buffer))
+            .unwrap()
+            .read_manifest()
+            .unwrap();
+        assert_eq!(eager, manifest);
+
+        let mut reader = ManifestReader::new(io::Cursor::new(&buffer)).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), manifest.entries[0]);
+        let mut digests = reader.referenced_digests().unwrap();
+        digests.insert(1u64.into());
+        assert_eq!(digests, eager.referenced_digests());
+        assert_eq!(
+            digests,
+            HashSet::from_iter([1u64, 2, 3, 4].map(Sha256Digest::from))
+        );
+    }
+
+    #[tokio::test]
+    async fn referenced_digests_matches_eager_read_async() {
+        let manifest = manifest_referencing_digests();
+        let mut buffer = vec![];
+        AsyncManifestWriter::new(&mut buffer)
+            .await
+            .unwrap()
+            .write_entries(&manifest.entries)
+            .await
+            .unwrap();
+
+        let eager = AsyncManifestReader::new(io::Cursor::new(&buffer))
+            .await
+            .unwrap()
+            .read_manifest()
+            .await
+            .unwrap();
+        assert_eq!(eager, manifest);
+
+        let digests = AsyncManifestReader::new(io::Cursor::new(&buffer))
+            .await
+            .unwrap()
+            .referenced_digests()
+            .await
+            .unwrap();
+        assert_eq!(digests, eager.referenced_digests());
+    }
+
+    #[test]
+    fn manifest_content_digest_matches_written_bytes() {
+        let manifest = Manifest::from_iter([test_entry(), test_entry()]);
+        let mut buffer = vec![];
+        ManifestWriter::new(&mut buffer)
+            .unwrap()
+            .write_entries(&manifest.entries)
+            .unwrap();
+        assert_eq!(
+            manifest.content_digest(),
+            Sha256Digest::new(Sha256::digest(&buffer).into())
+        );
+    }
+
+    #[tokio::test]
+    async fn digest_algorithm_header_round_trip_async() {
+        let mut buffer = vec![];
+        let mut writer =
+            AsyncManifestWriter::with_digest_algorithm(&mut buffer, DigestAlgorithm::Sha256)
+                .await
+                .unwrap();
+        writer.write_entry(&test_entry()).await.unwrap();
+
+        let mut reader = AsyncManifestReader::new(io::Cursor::new(buffer))
+            .await
+            .unwrap();
+        assert_eq!(reader.digest_algorithm(), DigestAlgorithm::Sha256);
+        assert_eq!(reader.next().await.unwrap(), Some(test_entry()));
+        assert_eq!(reader.next().await.unwrap(), None);
+    }
+
+    #[test]
+    fn v1_manifest_has_implicit_sha256() {
+        let mut buffer = vec![];
+        encode(&mut buffer, &ManifestVersion::V1).unwrap();
+        encode(&mut buffer, &test_entry()).unwrap();
+
+        let reader = ManifestReader::new(io::Cursor::new(buffer)).unwrap();
+        assert_eq!(reader.digest_algorithm(), DigestAlgorithm::Sha256);
+        let entries: Vec<_> = reader.map(|e| e.unwrap()).collect();
+        assert_eq!(entries, vec![test_entry()]);
+    }
+
+    #[test]
+    fn unknown_digest_algorithm_header() {
+        let mut buffer = vec![];
+        encode(&mut buffer, &ManifestVersion::V2).unwrap();
+        encode(&mut buffer, &7u32).unwrap();
+
+        let Err(err) = ManifestReader::new(io::Cursor::new(buffer)) else {
+            panic!("expected an error");
+        };
+        assert_eq!(err.to_string(), "unknown manifest digest algorithm 7");
+    }
+
+    fn manifest_with_entries(count: usize) -> Vec<u8> {
+        let mut buffer = vec![];
+        let mut writer = ManifestWriter::new(&mut buffer).unwrap();
+        for _ in 0..count {
+            writer.write_entry(&test_entry()).unwrap();
+        }
+        buffer
+    }
+
+    #[test]
+    fn max_entries_not_exceeded() {
+        let reader = ManifestReader::new(io::Cursor::new(manifest_with_entries(3)))
+            .unwrap()
+            .with_max_entries(3);
+        let entries: Vec<_> = reader.map(|e| e.unwrap()).collect();
+        assert_eq!(entries, vec![test_entry(); 3]);
+    }
+
+    #[test]
+    fn max_entries_exceeded() {
+        let mut reader = ManifestReader::new(io::Cursor::new(manifest_with_entries(4)))
+            .unwrap()
+            .with_max_entries(3);
+        for _ in 0..3 {
+            assert_eq!(reader.next().unwrap().unwrap(), test_entry());
+        }
+        let err = reader.next().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "manifest exceeds maximum of 3 entries");
+    }
+
+    #[tokio::test]
+    async fn max_entries_exceeded_async() {
+        let mut reader = AsyncManifestReader::new(io::Cursor::new(manifest_with_entries(2)))
+            .await
+            .unwrap()
+            .with_max_entries(1);
+        assert_eq!(reader.next().await.unwrap(), Some(test_entry()));
+        let err = reader.next().await.unwrap_err();
+        assert_eq!(err.to_string(), "manifest exceeds maximum of 1 entries");
+    }
+
+    fn chunk_digests(chunker: &Chunker, data: &[u8]) -> Vec<Sha256Digest> {
+        let (file_data, chunks) = chunk_file_data(chunker, data);
+        let ManifestFileData::ChunkedFile {
+            chunks: digests,
+            size,
+        } = file_data
+        else {
+            panic!("expected chunked file data");
+        };
+        assert_eq!(size, data.len() as u64);
+        assert_eq!(
+            digests,
+            chunks.iter().map(|(d, _)| d.clone()).collect::<Vec<_>>()
+        );
+        digests
+    }
+
+    #[test]
+    fn chunk_file_data_is_deterministic() {
+        let data = pseudo_random_data(200_000);
+        let chunker = Chunker::new(256, 2048, 8192);
+        let digests = chunk_digests(&chunker, &data);
+        assert!(digests.len() > 10);
+        assert_eq!(digests, chunk_digests(&chunker, &data));
+    }
+
+    #[test]
+    fn chunk_file_data_small_edit_shares_most_chunks() {
+        let data = pseudo_random_data(200_000);
+        let mut edited = data.clone();
+        edited.splice(100_000..100_010, b"edited!".iter().copied());
+        let chunker = Chunker::new(256, 2048, 8192);
+
+        let before: HashSet<_> = chunk_digests(&chunker, &data).into_iter().collect();
+        let after = chunk_digests(&chunker, &edited);
+        let shared = after.iter().filter(|d| before.contains(*d)).count();
+        assert!(
+            shared + 3 >= after.len(),
+            "only {shared} of {} chunks shared",
+            after.len()
+        );
+    }
 }
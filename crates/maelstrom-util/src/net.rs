@@ -12,53 +12,46 @@ use tokio::{
     sync::mpsc::{UnboundedReceiver, UnboundedSender},
 };
 
-fn write_message_to_vec(msg: impl Serialize) -> Result<Vec<u8>> {
-    let msg_len = proto::serialized_size(&msg)? as u32;
-    let mut buf = Vec::<u8>::with_capacity(msg_len as usize + 4);
-    Write::write_all(&mut buf, &msg_len.to_be_bytes())?;
-    proto::serialize_into(&mut buf, &msg)?;
-    Ok(buf)
-}
-
 /// Write a message to a normal (threaded) writer. Each message is framed by sending a leading
-/// 4-byte, little-endian message size.
+/// 4-byte, big-endian message size. See [`proto::write_framed`].
 pub fn write_message_to_socket(stream: &mut impl Write, msg: impl Serialize) -> Result<()> {
-    Ok(stream.write_all(&write_message_to_vec(msg)?)?)
+    Ok(stream.write_all(&proto::frame(&msg)?)?)
 }
 
 /// Write a message to a Tokio output stream. Each message is framed by sending a leading 4-byte,
-/// little-endian message size.
+/// big-endian message size. See [`proto::write_framed`].
 pub async fn write_message_to_async_socket(
     stream: &mut (impl AsyncWrite + Unpin),
     msg: impl Serialize,
 ) -> Result<()> {
-    Ok(stream.write_all(&write_message_to_vec(msg)?).await?)
+    Ok(stream.write_all(&proto::frame(&msg)?).await?)
 }
 
 /// Read a message from a normal (threaded) reader. The framing must match that of
-/// [`write_message_to_socket`] and [`write_message_to_async_socket`].
+/// [`write_message_to_socket`] and [`write_message_to_async_socket`]. Messages larger than
+/// [`proto::DEFAULT_MAX_MESSAGE_BYTES`] are rejected.
 pub fn read_message_from_socket<MessageT>(stream: &mut impl Read) -> Result<MessageT>
 where
     MessageT: DeserializeOwned,
 {
-    let mut msg_len: [u8; 4] = [0; 4];
-    stream.read_exact(&mut msg_len)?;
-    let mut buf = vec![0; u32::from_be_bytes(msg_len) as usize];
-    stream.read_exact(&mut buf)?;
-    Ok(proto::deserialize_from(&mut &buf[..])?)
+    Ok(proto::read_framed(
+        stream,
+        proto::DEFAULT_MAX_MESSAGE_BYTES,
+    )?)
 }
 
 /// Read a message from a Tokio input stream. The framing must match that of
-/// [`write_message_to_socket`] and [`write_message_to_async_socket`].
+/// [`write_message_to_socket`] and [`write_message_to_async_socket`]. Messages larger than
+/// [`proto::DEFAULT_MAX_MESSAGE_BYTES`] are rejected.
 pub async fn read_message_from_async_socket<MessageT>(
     stream: &mut (impl AsyncRead + Unpin),
 ) -> Result<MessageT>
 where
     MessageT: DeserializeOwned,
 {
-    let mut msg_len: [u8; 4] = [0; 4];
+    let mut msg_len = [0; proto::FRAME_LENGTH_SIZE];
     stream.read_exact(&mut msg_len).await?;
-    let mut buf = vec![0; u32::from_be_bytes(msg_len) as usize];
+    let mut buf = vec![0; proto::frame_length(msg_len, proto::DEFAULT_MAX_MESSAGE_BYTES)?];
     stream.read_exact(&mut buf).await?;
     Ok(proto::deserialize_from(&mut &buf[..])?)
 }
@@ -47,7 +47,10 @@ fn ident_invalid() {
 }
 
 #[derive(Default)]
-pub struct TemplateVars(HashMap<Ident, String>);
+pub struct TemplateVars {
+    vars: HashMap<Ident, String>,
+    env: HashMap<String, String>,
+}
 
 impl TemplateVars {
     pub fn new() -> Self {
@@ -60,12 +63,31 @@ impl TemplateVars {
         value: impl Into<String>,
     ) -> Result<Self> {
         let key = key.try_into()?;
-        if self.0.contains_key(&key) {
+        if self.vars.contains_key(&key) {
             bail!("duplicate key {key:?}")
         }
-        self.0.insert(key, value.into());
+        self.vars.insert(key, value.into());
         Ok(self)
     }
+
+    /// Make the given environment variables available to `${env:VAR}` expressions.
+    pub fn with_env(
+        mut self,
+        env: impl IntoIterator<Item = (impl Into<String>, impl Into<String>)>,
+    ) -> Self {
+        self.env
+            .extend(env.into_iter().map(|(k, v)| (k.into(), v.into())));
+        self
+    }
+
+    /// Make the environment of the current process available to `${env:VAR}` expressions.
+    /// Variables whose names or values aren't valid UTF-8 are skipped.
+    pub fn with_host_env(self) -> Self {
+        self.with_env(
+            std::env::vars_os()
+                .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?))),
+        )
+    }
 }
 
 pub fn replace_template_vars(input: &str, vars: &TemplateVars) -> Result<String> {
@@ -74,6 +96,9 @@ pub fn replace_template_vars(input: &str, vars: &TemplateVars) -> Result<String>
         (?:
             # opening angle braces followed by identifier ending with a single closing angle brace
             (?<var><+[a-zA-Z-][a-zA-Z0-9-]*>)
+            |
+            # environment variable with an optional default: ${env:NAME} or ${env:NAME:-default}
+            (?<env>\\$\\{env:(?<env_name>[a-zA-Z_][a-zA-Z0-9_]*)(?::-(?<env_default>[^}]*))?\\})
         )
     ",
     )
@@ -88,7 +113,7 @@ pub fn replace_template_vars(input: &str, vars: &TemplateVars) -> Result<String>
                 let ident = &m_str[starting_angle..(m_str.len() - 1)];
                 let new_angle = "<".repeat((starting_angle - 1) / 2);
                 let value = vars
-                    .0
+                    .vars
                     .get(ident)
                     .ok_or_else(|| anyhow!("unknown template variable {ident:?}"))?
                     .as_str();
@@ -100,6 +125,14 @@ pub fn replace_template_vars(input: &str, vars: &TemplateVars) -> Result<String>
                     "<".repeat(starting_angle / 2).to_string(),
                 )
             }
+        } else if let Some(m) = cap.name("env") {
+            let name = &cap["env_name"];
+            let value = match (vars.env.get(name), cap.name("env_default")) {
+                (Some(value), _) => value.clone(),
+                (None, Some(default)) => default.as_str().to_owned(),
+                (None, None) => bail!("environment variable {name:?} is not set"),
+            };
+            (m.range(), value)
         } else {
             unreachable!()
         };
@@ -177,3 +210,34 @@ fn template_escaping() {
         "/he<llo>/bar/bar",
     );
 }
+
+#[test]
+fn template_env_var_set() {
+    let vars = TemplateVars::new().with_env([("HOME_DIR", "/home/bob")]);
+    let actual = replace_template_vars("${env:HOME_DIR}/.config", &vars).unwrap();
+    assert_eq!("/home/bob/.config", &actual);
+
+    // The default is ignored when the variable is set.
+    let actual = replace_template_vars("${env:HOME_DIR:-/root}/.config", &vars).unwrap();
+    assert_eq!("/home/bob/.config", &actual);
+}
+
+#[test]
+fn template_env_var_unset_with_default() {
+    let vars = TemplateVars::new().with_var("foo", "bar").unwrap();
+    let actual = replace_template_vars("<foo>/${env:DATA_DIR:-data}", &vars).unwrap();
+    assert_eq!("bar/data", &actual);
+
+    let actual = replace_template_vars("<foo>/${env:DATA_DIR:-}", &vars).unwrap();
+    assert_eq!("bar/", &actual);
+}
+
+#[test]
+fn template_env_var_unset_without_default() {
+    let vars = TemplateVars::new();
+    let err = replace_template_vars("${env:DATA_DIR}/baz", &vars).unwrap_err();
+    assert_eq!(
+        "environment variable \"DATA_DIR\" is not set",
+        err.to_string()
+    );
+}
@@ -8,7 +8,7 @@
 
 use core::{cell::UnsafeCell, ffi::CStr, fmt::Write as _, result};
 use maelstrom_linux::{
-    self as linux, AccessMode, CloseRangeFirst, CloseRangeFlags, CloseRangeLast, Errno, Fd,
+    self as linux, AccessMode, CloseRangeFirst, CloseRangeFlags, CloseRangeLast, CpuSet, Errno, Fd,
     FileMode, FsconfigCommand, FsmountFlags, FsopenFlags, Gid, MountAttrs, MountFlags,
     MoveMountFlags, OpenFlags, OpenTreeFlags, OwnedFd, Sockaddr, SocketDomain, SocketProtocol,
     SocketType, Uid, UmountFlags,
@@ -163,11 +163,19 @@ pub enum Syscall<'a> {
         fd: FdSlot<'a>,
         buf: &'a mut [u8],
     },
+    SchedSetaffinity {
+        cpu_set: &'a CpuSet,
+    },
     SendMsg {
         buf: &'a [u8],
         fd_to_send: FdSlot<'a>,
     },
     SetSid,
+    Setxattr {
+        path: &'a CStr,
+        name: &'a CStr,
+        value: &'a [u8],
+    },
     Socket {
         domain: SocketDomain,
         type_: SocketType,
@@ -284,12 +292,14 @@ impl<'a> Syscall<'a> {
             }
             Syscall::PivotRoot { new_root, put_old } => linux::pivot_root(new_root, put_old),
             Syscall::Read { fd, buf } => linux::read(fd, buf).map(drop),
+            Syscall::SchedSetaffinity { cpu_set } => linux::sched_setaffinity(cpu_set),
             Syscall::SendMsg { buf, fd_to_send } => {
                 let count = write_sock.send_with_fd(buf, fd_to_send.get())?;
                 assert_eq!(count, buf.len());
                 Ok(())
             }
             Syscall::SetSid => linux::setsid(),
+            Syscall::Setxattr { path, name, value } => linux::setxattr(path, name, value),
             Syscall::Socket {
                 domain,
                 type_,
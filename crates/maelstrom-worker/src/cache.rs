@@ -1,24 +1,29 @@
 //! Manage downloading, extracting, and storing of artifacts specified by jobs.
 
 use bytesize::ByteSize;
-use maelstrom_base::{JobId, Sha256Digest};
+use maelstrom_base::{stats::CacheStats, JobId, Sha256Digest};
 use maelstrom_util::{
     config::common::CacheSize,
     heap::{Heap, HeapDeps, HeapIndex},
     root::RootBuf,
 };
+use serde::Serialize;
 use slog::{debug, Logger};
 use std::{
     cmp::Ordering,
-    collections::{hash_map::Entry as HashEntry, HashMap},
-    fmt, fs, mem,
+    collections::{hash_map::Entry as HashEntry, HashMap, VecDeque},
+    error, fmt, fs, mem,
     num::NonZeroU32,
     ops::{Deref, DerefMut},
     path::{Path, PathBuf},
     thread,
+    time::{Duration, Instant},
 };
 
-/// Dependencies that [Cache] has on the file system.
+/// The storage backend for [Cache]. This is where the bytes of the artifacts live. The [Cache]
+/// itself only does the bookkeeping: reference counting, LRU ordering, and deciding when to evict.
+/// It only ever touches storage through this trait, so it can be backed by something other than
+/// the local file system, like the in-memory one used by the tests.
 pub trait Fs {
     /// Return a random u64. This is used for creating unique path names in the directory removal
     /// code path.
@@ -45,6 +50,9 @@ pub trait Fs {
     /// Return and iterator that will yield all of the children of a directory. Panic on file
     /// system error or if `path` doesn't exist or isn't a directory.
     fn read_dir(&self, path: &Path) -> Box<dyn Iterator<Item = PathBuf>>;
+
+    /// Return the current time. This is used to decide when deferred removals are due.
+    fn now(&self) -> Instant;
 }
 
 /// The standard implementation of CacheFs that uses [std] and [rand].
@@ -80,6 +88,10 @@ impl Fs for StdFs {
     fn read_dir(&self, path: &Path) -> Box<dyn Iterator<Item = PathBuf>> {
         Box::new(fs::read_dir(path).unwrap().map(|de| de.unwrap().path()))
     }
+
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
 }
 
 /// Type returned from [Cache::get_artifact].
@@ -102,6 +114,29 @@ pub enum GetArtifact {
     Get(PathBuf),
 }
 
+/// Error returned from [Cache::reserve] when the requested space can't be freed because too much of
+/// the cache is being used by jobs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheFull {
+    pub bytes_requested: u64,
+    pub bytes_in_use: u64,
+    pub bytes_used_target: u64,
+}
+
+impl fmt::Display for CacheFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cache full: {} requested, but {} of {} is in use",
+            ByteSize::b(self.bytes_requested),
+            ByteSize::b(self.bytes_in_use),
+            ByteSize::b(self.bytes_used_target),
+        )
+    }
+}
+
+impl error::Error for CacheFull {}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, strum::EnumIter)]
 pub enum EntryKind {
     Blob,
@@ -209,6 +244,19 @@ pub struct CacheDir;
 /// Manage a directory of downloaded, extracted artifacts. Coordinate fetching of these artifacts,
 /// and removing them when they are no longer in use and the amount of space used by the directory
 /// has grown too large.
+/// A callback invoked by the [Cache] every time it evicts an entry. It is passed the entry's kind,
+/// digest, and the number of bytes the entry was using.
+pub type EvictionListener = Box<dyn FnMut(EntryKind, &Sha256Digest, u64) + Send>;
+
+/// The effective configuration of a [Cache], along with how many bytes it is currently using. See
+/// [Cache::config_snapshot].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CacheConfigSnapshot {
+    pub bytes_used: u64,
+    pub bytes_used_target: u64,
+    pub removal_grace_period: Duration,
+}
+
 pub struct Cache<FsT> {
     fs: FsT,
     root: PathBuf,
@@ -216,19 +264,26 @@ pub struct Cache<FsT> {
     heap: Heap<Map>,
     next_priority: u64,
     bytes_used: u64,
+    bytes_in_heap: u64,
     bytes_used_target: u64,
+    eviction_listener: Option<EvictionListener>,
+    removal_grace_period: Duration,
+    pending_removals: VecDeque<(Instant, PathBuf)>,
+    expirations: HashMap<Key, Instant>,
+    stats: CacheStats,
     log: Logger,
 }
 
 impl<FsT: Fs> Cache<FsT> {
     /// Create a new [Cache] rooted at `root`. The directory `root` and all necessary ancestors
-    /// will be created, along with `{root}/removing` and `{root}/{kind}/sha256`. Any pre-existing
-    /// entries in `{root}/removing` and `{root}/{kind}/sha256` will be removed. That implies that
+    /// will be created, along with `{root}/removing`, `{root}/{kind}/sha256`, and `{root}/output`.
+    /// Any pre-existing entries in those directories will be removed. That implies that
     /// the [Cache] doesn't currently keep data stored across invocations.
     ///
-    /// `bytes_used_target` is the goal on-disk size for the cache. The cache will periodically grow
-    /// larger than this size, but then shrink back down to this size. Ideally, the cache would use
-    /// this as a hard upper bound, but that's not how it currently works.
+    /// `bytes_used_target` is the goal on-disk size for the cache. Unreferenced entries are evicted
+    /// to stay under this size. Callers can use [Cache::reserve] to make room before adding to the
+    /// cache, so that it doesn't overshoot. Otherwise, the cache will periodically grow larger
+    /// than this size, but then shrink back down to this size.
     pub fn new(mut fs: FsT, root: RootBuf<CacheDir>, size: CacheSize, log: Logger) -> Self {
         let root = root.into_path_buf();
         let mut path = root.clone();
@@ -250,6 +305,13 @@ impl<FsT: Fs> Cache<FsT> {
             fs.mkdir_recursively(&path);
         }
 
+        path.push("output");
+        if fs.file_exists(&path) {
+            Self::remove_in_background(&mut fs, &root, &path);
+        }
+        fs.mkdir_recursively(&path);
+        path.pop();
+
         Cache {
             fs,
             root,
@@ -257,11 +319,109 @@ impl<FsT: Fs> Cache<FsT> {
             heap: Heap::default(),
             next_priority: 0,
             bytes_used: 0,
+            bytes_in_heap: 0,
             bytes_used_target: size.as_bytes(),
+            eviction_listener: None,
+            removal_grace_period: Duration::ZERO,
+            pending_removals: VecDeque::new(),
+            expirations: HashMap::new(),
+            stats: CacheStats::default(),
             log,
         }
     }
 
+    /// Call `listener` every time an entry is evicted from the cache.
+    pub fn eviction_listener(
+        mut self,
+        listener: impl FnMut(EntryKind, &Sha256Digest, u64) + Send + 'static,
+    ) -> Self {
+        self.eviction_listener = Some(Box::new(listener));
+        self
+    }
+
+    /// Wait at least `removal_grace_period` before removing the directories of evicted entries.
+    /// Evicted directories are still moved into `{root}/removing` immediately, but they are only
+    /// removed by the first call into the cache after the grace period has elapsed. Any that are
+    /// still around when the cache is dropped will be removed the next time a [Cache] is created
+    /// with the same root. This gives other processes that share the cache directory time to stop
+    /// using them. By default, there is no grace period.
+    pub fn removal_grace_period(mut self, removal_grace_period: Duration) -> Self {
+        self.removal_grace_period = removal_grace_period;
+        self
+    }
+
+    /// Remove the evicted directories whose grace period has elapsed. This is called at the start
+    /// of every other operation on the cache, but may also be called directly.
+    pub fn remove_expired(&mut self) {
+        if self.pending_removals.is_empty() {
+            return;
+        }
+        let now = self.fs.now();
+        while let Some((deadline, _)) = self.pending_removals.front() {
+            if *deadline > now {
+                break;
+            }
+            let (_, path) = self.pending_removals.pop_front().unwrap();
+            self.fs.remove_recursively_on_thread(path);
+        }
+    }
+
+    /// Return the cache's effective configuration and current size.
+    pub fn config_snapshot(&self) -> CacheConfigSnapshot {
+        CacheConfigSnapshot {
+            bytes_used: self.bytes_used,
+            bytes_used_target: self.bytes_used_target,
+            removal_grace_period: self.removal_grace_period,
+        }
+    }
+
+    /// Return the hit, miss, and eviction counts accumulated since the cache was created.
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Return the entries that aren't in use, in the order they would be evicted, without evicting
+    /// anything. Entries that are in use or still being fetched can't be evicted, so they aren't
+    /// included.
+    pub fn eviction_order(&self) -> Vec<(EntryKind, Sha256Digest)> {
+        let mut in_heap =
+            Vec::from_iter(self.entries.iter().filter_map(|(key, entry)| match entry {
+                Entry::InHeap { priority, .. } => Some((*priority, key)),
+                _ => None,
+            }));
+        in_heap.sort_unstable_by_key(|(priority, _)| *priority);
+        in_heap
+            .into_iter()
+            .map(|(_, key)| (key.kind, key.digest.clone()))
+            .collect()
+    }
+
+    /// Evict unreferenced entries until there is room for `bytes` more without going over the
+    /// target size. If that isn't possible because too many bytes are used by entries that are
+    /// in use, evict nothing and return [CacheFull].
+    pub fn reserve(&mut self, bytes: u64) -> Result<(), CacheFull> {
+        self.remove_expired();
+        let bytes_in_use = self.bytes_used - self.bytes_in_heap;
+        if bytes_in_use.saturating_add(bytes) > self.bytes_used_target {
+            return Err(CacheFull {
+                bytes_requested: bytes,
+                bytes_in_use,
+                bytes_used_target: self.bytes_used_target,
+            });
+        }
+        self.remove_while_over(self.bytes_used_target - bytes);
+        Ok(())
+    }
+
+    /// Return the number of bytes used by the given artifact, if it has been fetched and hasn't
+    /// been evicted yet.
+    pub fn bytes_used(&self, kind: EntryKind, digest: &Sha256Digest) -> Option<u64> {
+        match self.entries.get(&Key::new(kind, digest.clone()))? {
+            Entry::DownloadingAndExtracting(_) => None,
+            Entry::InUse { bytes_used, .. } | Entry::InHeap { bytes_used, .. } => Some(*bytes_used),
+        }
+    }
+
     /// Attempt to fetch `artifact` from the cache. See [GetArtifact] for the meaning of the return
     /// values.
     pub fn get_artifact(
@@ -270,11 +430,14 @@ impl<FsT: Fs> Cache<FsT> {
         digest: Sha256Digest,
         jid: JobId,
     ) -> GetArtifact {
+        self.remove_expired();
+        self.evict_expired_entries();
         let key = Key::new(kind, digest);
         let cache_path = Self::cache_path(&self.root, &key);
         match self.entries.entry(key) {
             HashEntry::Vacant(entry) => {
                 entry.insert(Entry::DownloadingAndExtracting(vec![jid]));
+                self.stats.misses += 1;
                 GetArtifact::Get(cache_path)
             }
             HashEntry::Occupied(entry) => {
@@ -286,6 +449,7 @@ impl<FsT: Fs> Cache<FsT> {
                     }
                     Entry::InUse { ref_count, .. } => {
                         *ref_count = ref_count.checked_add(1).unwrap();
+                        self.stats.hits += 1;
                         GetArtifact::Success(cache_path)
                     }
                     Entry::InHeap {
@@ -294,11 +458,13 @@ impl<FsT: Fs> Cache<FsT> {
                         ..
                     } => {
                         let heap_index = *heap_index;
+                        self.bytes_in_heap = self.bytes_in_heap.checked_sub(*bytes_used).unwrap();
                         *entry = Entry::InUse {
                             ref_count: NonZeroU32::new(1).unwrap(),
                             bytes_used: *bytes_used,
                         };
                         self.heap.remove(&mut self.entries, heap_index);
+                        self.stats.hits += 1;
                         GetArtifact::Success(cache_path)
                     }
                 }
@@ -309,6 +475,7 @@ impl<FsT: Fs> Cache<FsT> {
     /// Notify the cache that an artifact fetch has failed. The returned vector lists the jobs that
     /// are affected and that need to be canceled.
     pub fn got_artifact_failure(&mut self, kind: EntryKind, digest: &Sha256Digest) -> Vec<JobId> {
+        self.remove_expired();
         let key = Key::new(kind, digest.clone());
         let Some(Entry::DownloadingAndExtracting(jobs)) = self.entries.remove(&key) else {
             panic!("Got got_artifact in unexpected state");
@@ -328,6 +495,31 @@ impl<FsT: Fs> Cache<FsT> {
         digest: &Sha256Digest,
         bytes_used: u64,
     ) -> (PathBuf, Vec<JobId>) {
+        self.got_artifact_success_inner(kind, digest, bytes_used, None)
+    }
+
+    /// Like [Cache::got_artifact_success], but the entry expires `ttl` after it is added. Once it
+    /// has expired, it is evicted as soon as no jobs are using it, even if the cache is under its
+    /// target size.
+    pub fn got_artifact_success_with_ttl(
+        &mut self,
+        kind: EntryKind,
+        digest: &Sha256Digest,
+        bytes_used: u64,
+        ttl: Duration,
+    ) -> (PathBuf, Vec<JobId>) {
+        let expires_at = self.fs.now() + ttl;
+        self.got_artifact_success_inner(kind, digest, bytes_used, Some(expires_at))
+    }
+
+    fn got_artifact_success_inner(
+        &mut self,
+        kind: EntryKind,
+        digest: &Sha256Digest,
+        bytes_used: u64,
+        expires_at: Option<Instant>,
+    ) -> (PathBuf, Vec<JobId>) {
+        self.remove_expired();
         let key = Key::new(kind, digest.clone());
         let entry = self
             .entries
@@ -344,6 +536,9 @@ impl<FsT: Fs> Cache<FsT> {
             ref_count: NonZeroU32::new(ref_count).unwrap(),
         };
         self.bytes_used = self.bytes_used.checked_add(bytes_used).unwrap();
+        if let Some(expires_at) = expires_at {
+            self.expirations.insert(key.clone(), expires_at);
+        }
         debug!(self.log, "cache added artifact";
             "kind" => ?kind,
             "digest" => %digest,
@@ -358,6 +553,7 @@ impl<FsT: Fs> Cache<FsT> {
 
     /// Notify the cache that a reference to an artifact is no longer needed.
     pub fn decrement_ref_count(&mut self, kind: EntryKind, digest: &Sha256Digest) {
+        self.remove_expired();
         let key = Key::new(kind, digest.clone());
         let entry = self
             .entries
@@ -373,6 +569,7 @@ impl<FsT: Fs> Cache<FsT> {
         match NonZeroU32::new(ref_count.get() - 1) {
             Some(new_ref_count) => *ref_count = new_ref_count,
             None => {
+                self.bytes_in_heap = self.bytes_in_heap.checked_add(*bytes_used).unwrap();
                 *entry = Entry::InHeap {
                     bytes_used: *bytes_used,
                     priority: self.next_priority,
@@ -385,8 +582,61 @@ impl<FsT: Fs> Cache<FsT> {
         }
     }
 
+    /// Return the directory where jobs should store output that is too large to be sent inline.
+    /// Files in this directory should be named after the digest of their contents, and be handed
+    /// to the cache with [Cache::got_external_output] once they are complete.
+    pub fn external_output_dir(root: &Path) -> PathBuf {
+        root.join("output")
+    }
+
+    /// Notify the cache that a job stored `bytes_used` bytes of output with the given digest in
+    /// the [Cache::external_output_dir]. The output is moved into the cache as an unreferenced
+    /// blob, where it counts against the target size and is evicted like any other entry. Until
+    /// then, it can be found at `{root}/blob/sha256/{digest}`. If there already is a blob with the
+    /// same digest, the new copy is just removed.
+    pub fn got_external_output(&mut self, digest: &Sha256Digest, bytes_used: u64) {
+        self.remove_expired();
+        let source = Self::external_output_dir(&self.root).join(digest.to_string());
+        if !self.fs.file_exists(&source) {
+            // Another job with the same output already handed it to us.
+            return;
+        }
+        let key = Key::new(EntryKind::Blob, digest.clone());
+        if self.entries.contains_key(&key) {
+            Self::remove_in_background(&mut self.fs, &self.root, &source);
+            return;
+        }
+        self.fs.rename(&source, &Self::cache_path(&self.root, &key));
+        self.bytes_used = self.bytes_used.checked_add(bytes_used).unwrap();
+        self.bytes_in_heap = self.bytes_in_heap.checked_add(bytes_used).unwrap();
+        self.entries.insert(
+            key.clone(),
+            Entry::InHeap {
+                bytes_used,
+                priority: self.next_priority,
+                heap_index: HeapIndex::default(),
+            },
+        );
+        self.heap.push(&mut self.entries, key);
+        self.next_priority = self.next_priority.checked_add(1).unwrap();
+        debug!(self.log, "cache added external output";
+            "digest" => %digest,
+            "artifact_bytes_used" => %ByteSize::b(bytes_used),
+            "entries" => %self.entries.len(),
+            "bytes_used" => %ByteSize::b(self.bytes_used),
+            "byte_used_target" => %ByteSize::b(self.bytes_used_target)
+        );
+        self.possibly_remove_some();
+    }
+
     /// Remove all files and directories rooted in `source` in a separate thread.
     fn remove_in_background(fs: &mut impl Fs, root: &Path, source: &Path) {
+        let target = Self::move_to_removing(fs, root, source);
+        fs.remove_recursively_on_thread(target);
+    }
+
+    /// Move `source` into a uniquely-named child of `{root}/removing`, and return the new path.
+    fn move_to_removing(fs: &mut impl Fs, root: &Path, source: &Path) -> PathBuf {
         let mut target = root.to_owned();
         target.push("removing");
         loop {
@@ -399,7 +649,19 @@ impl<FsT: Fs> Cache<FsT> {
             }
         }
         fs.rename(source, &target);
-        fs.remove_recursively_on_thread(target);
+        target
+    }
+
+    /// Remove the directory of an evicted entry, after the removal grace period if there is one.
+    fn remove_evicted(&mut self, key: &Key) {
+        let cache_path = Self::cache_path(&self.root, key);
+        if self.removal_grace_period.is_zero() {
+            Self::remove_in_background(&mut self.fs, &self.root, &cache_path);
+        } else {
+            let target = Self::move_to_removing(&mut self.fs, &self.root, &cache_path);
+            let deadline = self.fs.now() + self.removal_grace_period;
+            self.pending_removals.push_back((deadline, target));
+        }
     }
 
     /// Return the directory path for the artifact referenced by `digest`.
@@ -411,29 +673,67 @@ impl<FsT: Fs> Cache<FsT> {
         path
     }
 
-    /// Check to see if the cache is over its goal size, and if so, try to remove the least
-    /// recently used artifacts.
+    /// Remove any unused artifacts whose TTL has elapsed. Then check to see if the cache is over
+    /// its goal size, and if so, try to remove the least recently used artifacts.
     fn possibly_remove_some(&mut self) {
-        while self.bytes_used > self.bytes_used_target {
+        self.evict_expired_entries();
+        self.remove_while_over(self.bytes_used_target);
+    }
+
+    /// Evict every artifact whose TTL has elapsed and that isn't currently in use. Artifacts that
+    /// are in use are evicted once they are no longer in use.
+    fn evict_expired_entries(&mut self) {
+        if self.expirations.is_empty() {
+            return;
+        }
+        let now = self.fs.now();
+        let expired = Vec::from_iter(
+            self.expirations
+                .iter()
+                .filter(|(_, expires_at)| **expires_at <= now)
+                .map(|(key, _)| key.clone()),
+        );
+        for key in expired {
+            let Some(Entry::InHeap { heap_index, .. }) = self.entries.get(&key) else {
+                continue;
+            };
+            let heap_index = *heap_index;
+            self.heap.remove(&mut self.entries, heap_index);
+            self.evict(key);
+        }
+    }
+
+    /// Remove the least recently used artifacts until the cache is using at most `limit` bytes, or
+    /// there is nothing left that can be removed.
+    fn remove_while_over(&mut self, limit: u64) {
+        while self.bytes_used > limit {
             let Some(key) = self.heap.pop(&mut self.entries) else {
                 break;
             };
-            let Some(Entry::InHeap { bytes_used, .. }) = self.entries.remove(&key) else {
-                panic!("Entry popped off of heap was in unexpected state");
-            };
-            Self::remove_in_background(
-                &mut self.fs,
-                &self.root,
-                &Self::cache_path(&self.root, &key),
-            );
-            self.bytes_used = self.bytes_used.checked_sub(bytes_used).unwrap();
-            debug!(self.log, "cache removed artifact";
-                "key" => ?key,
-                "artifact_bytes_used" => %ByteSize::b(bytes_used),
-                "entries" => %self.entries.len(),
-                "bytes_used" => %ByteSize::b(self.bytes_used),
-                "byte_used_target" => %ByteSize::b(self.bytes_used_target)
-            );
+            self.evict(key);
+        }
+    }
+
+    /// Remove an entry that has just been taken off of the heap.
+    fn evict(&mut self, key: Key) {
+        let Some(Entry::InHeap { bytes_used, .. }) = self.entries.remove(&key) else {
+            panic!("Entry popped off of heap was in unexpected state");
+        };
+        self.expirations.remove(&key);
+        self.remove_evicted(&key);
+        self.bytes_used = self.bytes_used.checked_sub(bytes_used).unwrap();
+        self.bytes_in_heap = self.bytes_in_heap.checked_sub(bytes_used).unwrap();
+        self.stats.evictions += 1;
+        self.stats.bytes_evicted += bytes_used;
+        debug!(self.log, "cache removed artifact";
+            "key" => ?key,
+            "artifact_bytes_used" => %ByteSize::b(bytes_used),
+            "entries" => %self.entries.len(),
+            "bytes_used" => %ByteSize::b(self.bytes_used),
+            "byte_used_target" => %ByteSize::b(self.bytes_used_target)
+        );
+        if let Some(listener) = &mut self.eviction_listener {
+            listener(key.kind, &key.digest, bytes_used);
         }
     }
 }
@@ -452,9 +752,90 @@ mod tests {
     use itertools::Itertools;
     use maelstrom_test::*;
     use slog::{o, Discard};
-    use std::{cell::RefCell, collections::HashSet, rc::Rc};
+    use std::{
+        cell::{Cell, RefCell},
+        collections::BTreeSet,
+        rc::Rc,
+        sync::{Arc, Mutex},
+    };
     use TestMessage::*;
 
+    /// An implementation of [Fs] that keeps the directory tree in memory. No artifact data is stored,
+    /// only which paths exist. Removals happen immediately instead of on a separate thread, and
+    /// [Fs::rand_u64] returns consecutive numbers starting at 1.
+    #[derive(Default)]
+    struct MemFs {
+        paths: BTreeSet<PathBuf>,
+        last_random_number: u64,
+    }
+
+    impl MemFs {
+        fn descendants(&self, path: &Path) -> Vec<PathBuf> {
+            self.paths
+                .range(path.to_owned()..)
+                .take_while(|p| p.starts_with(path))
+                .cloned()
+                .collect()
+        }
+    }
+
+    impl Fs for MemFs {
+        fn rand_u64(&mut self) -> u64 {
+            self.last_random_number += 1;
+            self.last_random_number
+        }
+
+        fn file_exists(&self, path: &Path) -> bool {
+            self.paths.contains(path)
+        }
+
+        fn rename(&mut self, source: &Path, destination: &Path) {
+            assert!(self.paths.contains(source), "{source:?} doesn't exist");
+            assert!(
+                !self.paths.contains(destination),
+                "{destination:?} already exists"
+            );
+            if let Some(parent) = destination.parent() {
+                assert!(self.paths.contains(parent), "{parent:?} doesn't exist");
+            }
+            for path in self.descendants(source) {
+                self.paths.remove(&path);
+                let mut new_path = destination.to_owned();
+                new_path.push(path.strip_prefix(source).unwrap());
+                self.paths.insert(new_path);
+            }
+        }
+
+        fn remove_recursively_on_thread(&mut self, path: PathBuf) {
+            assert!(self.paths.contains(&path), "{path:?} doesn't exist");
+            for path in self.descendants(&path) {
+                self.paths.remove(&path);
+            }
+        }
+
+        fn mkdir_recursively(&mut self, path: &Path) {
+            self.paths.extend(
+                path.ancestors()
+                    .filter(|p| !p.as_os_str().is_empty())
+                    .map(Path::to_owned),
+            );
+        }
+
+        fn read_dir(&self, path: &Path) -> Box<dyn Iterator<Item = PathBuf>> {
+            assert!(self.paths.contains(path), "{path:?} doesn't exist");
+            let children: Vec<_> = self
+                .descendants(path)
+                .into_iter()
+                .filter(|p| p.parent() == Some(path))
+                .collect();
+            Box::new(children.into_iter())
+        }
+
+        fn now(&self) -> Instant {
+            Instant::now()
+        }
+    }
+
     #[derive(Clone, Debug, PartialEq)]
     enum TestMessage {
         FileExists(PathBuf),
@@ -464,80 +845,109 @@ mod tests {
         ReadDir(PathBuf),
     }
 
-    #[derive(Default)]
+    /// A [MemFs] that records every call made to it. Its clock only moves when told to.
     struct TestFs {
         messages: Rc<RefCell<Vec<TestMessage>>>,
-        existing_files: HashSet<PathBuf>,
-        directories: HashMap<PathBuf, Vec<PathBuf>>,
-        last_random_number: u64,
+        fs: Rc<RefCell<MemFs>>,
+        now: Rc<Cell<Instant>>,
+    }
+
+    impl Default for TestFs {
+        fn default() -> Self {
+            Self {
+                messages: Default::default(),
+                fs: Default::default(),
+                now: Rc::new(Cell::new(Instant::now())),
+            }
+        }
+    }
+
+    impl TestFs {
+        fn mkdir(&self, path: PathBuf) {
+            self.fs.borrow_mut().mkdir_recursively(&path);
+        }
     }
 
     impl Fs for TestFs {
         fn rand_u64(&mut self) -> u64 {
-            self.last_random_number += 1;
-            self.last_random_number
+            self.fs.borrow_mut().rand_u64()
         }
 
         fn file_exists(&self, path: &Path) -> bool {
             self.messages.borrow_mut().push(FileExists(path.to_owned()));
-            self.existing_files.contains(path)
+            self.fs.borrow().file_exists(path)
         }
 
         fn rename(&mut self, source: &Path, destination: &Path) {
             self.messages
                 .borrow_mut()
                 .push(Rename(source.to_owned(), destination.to_owned()));
+            self.fs.borrow_mut().rename(source, destination);
         }
 
         fn remove_recursively_on_thread(&mut self, path: PathBuf) {
             self.messages
                 .borrow_mut()
                 .push(RemoveRecursively(path.to_owned()));
+            self.fs.borrow_mut().remove_recursively_on_thread(path);
         }
 
         fn mkdir_recursively(&mut self, path: &Path) {
             self.messages
                 .borrow_mut()
                 .push(MkdirRecursively(path.to_owned()));
+            self.fs.borrow_mut().mkdir_recursively(path);
         }
 
         fn read_dir(&self, path: &Path) -> Box<dyn Iterator<Item = PathBuf>> {
             self.messages.borrow_mut().push(ReadDir(path.to_owned()));
-            Box::new(
-                self.directories
-                    .get(path)
-                    .unwrap_or(&vec![])
-                    .clone()
-                    .into_iter(),
-            )
+            self.fs.borrow().read_dir(path)
+        }
+
+        fn now(&self) -> Instant {
+            self.now.get()
         }
     }
 
     struct Fixture {
         messages: Rc<RefCell<Vec<TestMessage>>>,
+        fs: Rc<RefCell<MemFs>>,
+        now: Rc<Cell<Instant>>,
         cache: Cache<TestFs>,
     }
 
     impl Fixture {
-        fn new_with_fs_and_clear_messages(test_cache_fs: TestFs, bytes_used_target: u64) -> Self {
-            let mut fixture = Fixture::new(test_cache_fs, bytes_used_target);
+        fn new_and_clear_messages(bytes_used_target: u64) -> Self {
+            let mut fixture = Fixture::new(TestFs::default(), bytes_used_target);
             fixture.clear_messages();
             fixture
         }
 
-        fn new_and_clear_messages(bytes_used_target: u64) -> Self {
-            Self::new_with_fs_and_clear_messages(TestFs::default(), bytes_used_target)
-        }
-
         fn new(test_cache_fs: TestFs, bytes_used_target: u64) -> Self {
             let messages = test_cache_fs.messages.clone();
+            let fs = test_cache_fs.fs.clone();
+            let now = test_cache_fs.now.clone();
             let cache = Cache::new(
                 test_cache_fs,
                 "/z".parse().unwrap(),
                 ByteSize::b(bytes_used_target).into(),
                 Logger::root(Discard, o!()),
             );
-            Fixture { messages, cache }
+            Fixture {
+                messages,
+                fs,
+                now,
+                cache,
+            }
+        }
+
+        fn removal_grace_period(mut self, removal_grace_period: Duration) -> Self {
+            self.cache = self.cache.removal_grace_period(removal_grace_period);
+            self
+        }
+
+        fn advance_clock(&self, duration: Duration) {
+            self.now.set(self.now.get() + duration);
         }
 
         fn expect_messages_in_any_order(&mut self, expected: Vec<TestMessage>) {
@@ -573,6 +983,19 @@ mod tests {
             self.messages.borrow_mut().clear();
         }
 
+        /// Create a path in the backing store without going through the cache, like the fetcher
+        /// does when it downloads an artifact.
+        fn mkdir(&self, path: PathBuf) {
+            self.fs.borrow_mut().mkdir_recursively(&path);
+        }
+
+        fn fetch_artifact(&self, digest: &Sha256Digest) {
+            self.mkdir(Cache::<TestFs>::cache_path(
+                Path::new("/z"),
+                &Key::new(EntryKind::Blob, digest.clone()),
+            ));
+        }
+
         fn get_artifact(&mut self, digest: Sha256Digest, jid: JobId, expected: GetArtifact) {
             let result = self.cache.get_artifact(EntryKind::Blob, digest, jid);
             assert_eq!(result, expected);
@@ -591,6 +1014,7 @@ mod tests {
             expected: (PathBuf, Vec<JobId>),
             expected_fs_operations: Vec<TestMessage>,
         ) {
+            self.fetch_artifact(&digest);
             let result = self
                 .cache
                 .got_artifact_success(EntryKind::Blob, &digest, bytes_used);
@@ -610,6 +1034,7 @@ mod tests {
         }
 
         fn got_artifact_success_ign(&mut self, digest: Sha256Digest, bytes_used: u64) {
+            self.fetch_artifact(&digest);
             self.cache
                 .got_artifact_success(EntryKind::Blob, &digest, bytes_used);
             self.clear_messages();
@@ -668,6 +1093,215 @@ mod tests {
         );
     }
 
+    #[test]
+    fn got_external_output_adds_unreferenced_blob() {
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+
+        fixture.mkdir(long_path!("/z/output", 42));
+        fixture.cache.got_external_output(&digest!(42), 100);
+        fixture.expect_messages_in_specific_order(vec![
+            FileExists(long_path!("/z/output", 42)),
+            Rename(
+                long_path!("/z/output", 42),
+                long_path!("/z/blob/sha256", 42),
+            ),
+        ]);
+        assert_eq!(
+            fixture.cache.bytes_used(EntryKind::Blob, &digest!(42)),
+            Some(100)
+        );
+        assert_eq!(fixture.cache.config_snapshot().bytes_used, 100);
+        assert_eq!(
+            fixture.cache.eviction_order(),
+            vec![(EntryKind::Blob, digest!(42))]
+        );
+
+        fixture.get_artifact(
+            digest!(42),
+            jid!(1),
+            GetArtifact::Success(long_path!("/z/blob/sha256", 42)),
+        );
+    }
+
+    #[test]
+    fn got_external_output_is_evicted_when_over_target() {
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+
+        fixture.mkdir(long_path!("/z/output", 42));
+        fixture.cache.got_external_output(&digest!(42), 10000);
+        fixture.expect_messages_in_specific_order(vec![
+            FileExists(long_path!("/z/output", 42)),
+            Rename(
+                long_path!("/z/output", 42),
+                long_path!("/z/blob/sha256", 42),
+            ),
+            FileExists(short_path!("/z/removing", 1)),
+            Rename(
+                long_path!("/z/blob/sha256", 42),
+                short_path!("/z/removing", 1),
+            ),
+            RemoveRecursively(short_path!("/z/removing", 1)),
+        ]);
+        assert_eq!(fixture.cache.config_snapshot().bytes_used, 0);
+    }
+
+    #[test]
+    fn got_external_output_for_existing_blob_removes_copy() {
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+
+        fixture.get_artifact_ign(digest!(42), jid!(1));
+        fixture.got_artifact_success_ign(digest!(42), 100);
+
+        fixture.mkdir(long_path!("/z/output", 42));
+        fixture.cache.got_external_output(&digest!(42), 100);
+        fixture.expect_messages_in_specific_order(vec![
+            FileExists(long_path!("/z/output", 42)),
+            FileExists(short_path!("/z/removing", 1)),
+            Rename(long_path!("/z/output", 42), short_path!("/z/removing", 1)),
+            RemoveRecursively(short_path!("/z/removing", 1)),
+        ]);
+        assert_eq!(fixture.cache.config_snapshot().bytes_used, 100);
+    }
+
+    #[test]
+    fn got_external_output_already_handed_over_does_nothing() {
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+
+        fixture.cache.got_external_output(&digest!(42), 100);
+        fixture.expect_messages_in_specific_order(vec![FileExists(long_path!("/z/output", 42))]);
+        assert_eq!(fixture.cache.config_snapshot().bytes_used, 0);
+    }
+
+    #[test]
+    fn config_snapshot_reflects_construction() {
+        let mut fixture =
+            Fixture::new_and_clear_messages(1000).removal_grace_period(Duration::from_secs(10));
+        assert_eq!(
+            fixture.cache.config_snapshot(),
+            CacheConfigSnapshot {
+                bytes_used: 0,
+                bytes_used_target: 1000,
+                removal_grace_period: Duration::from_secs(10),
+            }
+        );
+
+        fixture.get_artifact_ign(digest!(42), jid!(1));
+        fixture.got_artifact_success_ign(digest!(42), 100);
+        assert_eq!(fixture.cache.config_snapshot().bytes_used, 100);
+    }
+
+    #[test]
+    fn eviction_removal_is_deferred_until_grace_period_elapses() {
+        let mut fixture =
+            Fixture::new_and_clear_messages(1000).removal_grace_period(Duration::from_secs(10));
+
+        fixture.get_artifact_ign(digest!(42), jid!(1));
+        fixture.got_artifact_success_ign(digest!(42), 10000);
+        fixture.decrement_ref_count(
+            digest!(42),
+            vec![
+                FileExists(short_path!("/z/removing", 1)),
+                Rename(
+                    long_path!("/z/blob/sha256", 42),
+                    short_path!("/z/removing", 1),
+                ),
+            ],
+        );
+
+        fixture.advance_clock(Duration::from_secs(9));
+        fixture.cache.remove_expired();
+        fixture.expect_messages_in_any_order(vec![]);
+
+        fixture.advance_clock(Duration::from_secs(1));
+        fixture
+            .cache
+            .get_artifact(EntryKind::Blob, digest!(43), jid!(2));
+        fixture
+            .expect_messages_in_any_order(vec![RemoveRecursively(short_path!("/z/removing", 1))]);
+    }
+
+    #[test]
+    fn entry_past_ttl_is_evicted_even_when_under_size_target() {
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+
+        fixture.get_artifact_ign(digest!(42), jid!(1));
+        fixture.fetch_artifact(&digest!(42));
+        fixture.cache.got_artifact_success_with_ttl(
+            EntryKind::Blob,
+            &digest!(42),
+            100,
+            Duration::from_secs(10),
+        );
+        fixture.decrement_ref_count(digest!(42), vec![]);
+
+        fixture.advance_clock(Duration::from_secs(9));
+        fixture.get_artifact(
+            digest!(43),
+            jid!(2),
+            GetArtifact::Get(long_path!("/z/blob/sha256", 43)),
+        );
+
+        fixture.advance_clock(Duration::from_secs(1));
+        let result = fixture
+            .cache
+            .get_artifact(EntryKind::Blob, digest!(42), jid!(3));
+        assert_eq!(result, GetArtifact::Get(long_path!("/z/blob/sha256", 42)));
+        fixture.expect_messages_in_any_order(vec![
+            FileExists(short_path!("/z/removing", 1)),
+            Rename(
+                long_path!("/z/blob/sha256", 42),
+                short_path!("/z/removing", 1),
+            ),
+            RemoveRecursively(short_path!("/z/removing", 1)),
+        ]);
+        assert_eq!(fixture.cache.config_snapshot().bytes_used, 0);
+    }
+
+    #[test]
+    fn entry_past_ttl_is_not_evicted_until_no_longer_in_use() {
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+
+        fixture.get_artifact_ign(digest!(42), jid!(1));
+        fixture.get_artifact_ign(digest!(42), jid!(2));
+        fixture.fetch_artifact(&digest!(42));
+        fixture.cache.got_artifact_success_with_ttl(
+            EntryKind::Blob,
+            &digest!(42),
+            100,
+            Duration::from_secs(10),
+        );
+
+        fixture.advance_clock(Duration::from_secs(20));
+        fixture.decrement_ref_count(digest!(42), vec![]);
+        fixture.decrement_ref_count(
+            digest!(42),
+            vec![
+                FileExists(short_path!("/z/removing", 1)),
+                Rename(
+                    long_path!("/z/blob/sha256", 42),
+                    short_path!("/z/removing", 1),
+                ),
+                RemoveRecursively(short_path!("/z/removing", 1)),
+            ],
+        );
+    }
+
+    #[test]
+    fn entry_without_ttl_is_not_evicted_when_under_size_target() {
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+
+        fixture.get_artifact_ign(digest!(42), jid!(1));
+        fixture.got_artifact_success_ign(digest!(42), 100);
+        fixture.decrement_ref_count(digest!(42), vec![]);
+
+        fixture.advance_clock(Duration::from_secs(3600));
+        fixture.get_artifact(
+            digest!(42),
+            jid!(2),
+            GetArtifact::Success(long_path!("/z/blob/sha256", 42)),
+        );
+    }
+
     #[test]
     fn cache_entries_are_removed_in_lru_order() {
         let mut fixture = Fixture::new_and_clear_messages(10);
@@ -713,6 +1347,180 @@ mod tests {
         fixture.decrement_ref_count(digest!(4), vec![]);
     }
 
+    #[test]
+    fn reserve_evicts_lru_entries_up_front() {
+        let mut fixture = Fixture::new_and_clear_messages(10);
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.got_artifact_success_ign(digest!(1), 4);
+        fixture.decrement_ref_count(digest!(1), vec![]);
+
+        fixture.get_artifact_ign(digest!(2), jid!(2));
+        fixture.got_artifact_success_ign(digest!(2), 4);
+        fixture.decrement_ref_count(digest!(2), vec![]);
+
+        assert_eq!(fixture.cache.reserve(2), Ok(()));
+        fixture.expect_messages_in_any_order(vec![]);
+
+        assert_eq!(fixture.cache.reserve(4), Ok(()));
+        fixture.expect_messages_in_any_order(vec![
+            FileExists(short_path!("/z/removing", 1)),
+            Rename(
+                long_path!("/z/blob/sha256", 1),
+                short_path!("/z/removing", 1),
+            ),
+            RemoveRecursively(short_path!("/z/removing", 1)),
+        ]);
+        assert_eq!(fixture.cache.config_snapshot().bytes_used, 4);
+    }
+
+    #[test]
+    fn reserve_counts_entries_taken_back_out_of_heap_as_in_use() {
+        let mut fixture = Fixture::new_and_clear_messages(10);
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.got_artifact_success_ign(digest!(1), 4);
+        fixture.decrement_ref_count(digest!(1), vec![]);
+        fixture.get_artifact_ign(digest!(1), jid!(2));
+
+        assert_eq!(
+            fixture.cache.reserve(7),
+            Err(CacheFull {
+                bytes_requested: 7,
+                bytes_in_use: 4,
+                bytes_used_target: 10,
+            })
+        );
+    }
+
+    #[test]
+    fn bytes_used_is_only_known_once_artifact_is_fetched() {
+        let mut fixture = Fixture::new_and_clear_messages(10);
+
+        assert_eq!(fixture.cache.bytes_used(EntryKind::Blob, &digest!(1)), None);
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        assert_eq!(fixture.cache.bytes_used(EntryKind::Blob, &digest!(1)), None);
+        fixture.got_artifact_success_ign(digest!(1), 4);
+        assert_eq!(
+            fixture.cache.bytes_used(EntryKind::Blob, &digest!(1)),
+            Some(4)
+        );
+        fixture.decrement_ref_count(digest!(1), vec![]);
+        assert_eq!(
+            fixture.cache.bytes_used(EntryKind::Blob, &digest!(1)),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn reserve_fails_without_evicting_when_in_use_entries_fill_cache() {
+        let mut fixture = Fixture::new_and_clear_messages(10);
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.got_artifact_success_ign(digest!(1), 4);
+
+        fixture.get_artifact_ign(digest!(2), jid!(2));
+        fixture.got_artifact_success_ign(digest!(2), 4);
+        fixture.decrement_ref_count(digest!(2), vec![]);
+
+        assert_eq!(
+            fixture.cache.reserve(7),
+            Err(CacheFull {
+                bytes_requested: 7,
+                bytes_in_use: 4,
+                bytes_used_target: 10,
+            })
+        );
+        fixture.expect_messages_in_any_order(vec![]);
+        assert_eq!(fixture.cache.config_snapshot().bytes_used, 8);
+
+        assert_eq!(fixture.cache.reserve(6), Ok(()));
+        fixture.expect_messages_in_any_order(vec![
+            FileExists(short_path!("/z/removing", 1)),
+            Rename(
+                long_path!("/z/blob/sha256", 2),
+                short_path!("/z/removing", 1),
+            ),
+            RemoveRecursively(short_path!("/z/removing", 1)),
+        ]);
+    }
+
+    #[test]
+    fn stats_count_hits_misses_and_evictions() {
+        let mut fixture = Fixture::new_and_clear_messages(10);
+        assert_eq!(fixture.cache.stats(), CacheStats::default());
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.got_artifact_success_ign(digest!(1), 4);
+        fixture.get_artifact_ign(digest!(1), jid!(2));
+        fixture.decrement_ref_count_ign(digest!(1));
+        fixture.decrement_ref_count_ign(digest!(1));
+        fixture.get_artifact_ign(digest!(1), jid!(3));
+        fixture.decrement_ref_count_ign(digest!(1));
+
+        fixture.get_artifact_ign(digest!(2), jid!(4));
+        fixture.got_artifact_success_ign(digest!(2), 5);
+        fixture.decrement_ref_count_ign(digest!(2));
+
+        // Waiting on an in-progress fetch is neither a hit nor a miss.
+        fixture.get_artifact_ign(digest!(3), jid!(5));
+        fixture.get_artifact_ign(digest!(3), jid!(6));
+        assert_eq!(
+            fixture.cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 3,
+                evictions: 0,
+                bytes_evicted: 0,
+            }
+        );
+
+        fixture.got_artifact_success_ign(digest!(3), 6);
+        assert_eq!(
+            fixture.cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 3,
+                evictions: 2,
+                bytes_evicted: 9,
+            }
+        );
+    }
+
+    #[test]
+    fn eviction_listener_called_for_each_evicted_entry() {
+        let evicted = Arc::new(Mutex::new(vec![]));
+        let mut fixture = Fixture::new_and_clear_messages(10);
+        fixture.cache = fixture.cache.eviction_listener({
+            let evicted = evicted.clone();
+            move |kind, digest, bytes_used| {
+                evicted
+                    .lock()
+                    .unwrap()
+                    .push((kind, digest.clone(), bytes_used))
+            }
+        });
+
+        fixture.get_artifact_ign(digest!(1), jid!(1));
+        fixture.got_artifact_success_ign(digest!(1), 4);
+        fixture.decrement_ref_count(digest!(1), vec![]);
+
+        fixture.get_artifact_ign(digest!(2), jid!(2));
+        fixture.got_artifact_success_ign(digest!(2), 5);
+        fixture.decrement_ref_count(digest!(2), vec![]);
+        assert_eq!(*evicted.lock().unwrap(), vec![]);
+
+        fixture.get_artifact_ign(digest!(3), jid!(3));
+        fixture.got_artifact_success_ign(digest!(3), 6);
+        assert_eq!(
+            *evicted.lock().unwrap(),
+            vec![
+                (EntryKind::Blob, digest!(1), 4),
+                (EntryKind::Blob, digest!(2), 5)
+            ]
+        );
+    }
+
     #[test]
     fn lru_order_augmented_by_last_use() {
         let mut fixture = Fixture::new_and_clear_messages(10);
@@ -746,6 +1554,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn eviction_order_follows_lru_priority_without_evicting() {
+        let mut fixture = Fixture::new_and_clear_messages(100);
+
+        for i in 1..=5 {
+            fixture.get_artifact_ign(digest!(i), jid!(i));
+            fixture.got_artifact_success_ign(digest!(i), 3);
+        }
+
+        fixture.decrement_ref_count(digest!(4), vec![]);
+        fixture.decrement_ref_count(digest!(2), vec![]);
+        fixture.decrement_ref_count(digest!(5), vec![]);
+        fixture.decrement_ref_count(digest!(1), vec![]);
+
+        let expected = vec![
+            (EntryKind::Blob, digest!(4)),
+            (EntryKind::Blob, digest!(2)),
+            (EntryKind::Blob, digest!(5)),
+            (EntryKind::Blob, digest!(1)),
+        ];
+        assert_eq!(fixture.cache.eviction_order(), expected);
+        assert_eq!(fixture.cache.eviction_order(), expected);
+        assert_eq!(fixture.cache.stats().evictions, 0);
+
+        // Using an entry again moves it to the back of the line.
+        fixture.get_artifact_ign(digest!(4), jid!(6));
+        fixture.decrement_ref_count(digest!(4), vec![]);
+        assert_eq!(
+            fixture.cache.eviction_order(),
+            vec![
+                (EntryKind::Blob, digest!(2)),
+                (EntryKind::Blob, digest!(5)),
+                (EntryKind::Blob, digest!(1)),
+                (EntryKind::Blob, digest!(4)),
+            ]
+        );
+    }
+
     #[test]
     fn multiple_get_requests_for_empty() {
         let mut fixture = Fixture::new_and_clear_messages(1000);
@@ -877,11 +1723,8 @@ mod tests {
 
     #[test]
     fn preexisting_directories_do_not_affect_get_request() {
-        let mut test_cache_fs = TestFs::default();
-        test_cache_fs
-            .existing_files
-            .insert(long_path!("/z/blob/sha256", 42));
-        let mut fixture = Fixture::new_with_fs_and_clear_messages(test_cache_fs, 1000);
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+        fixture.mkdir(long_path!("/z/blob/sha256", 42));
 
         fixture.get_artifact(
             digest!(42),
@@ -892,11 +1735,8 @@ mod tests {
 
     #[test]
     fn get_request_for_empty_with_download_and_extract_failure_and_files_created() {
-        let mut test_cache_fs = TestFs::default();
-        test_cache_fs
-            .existing_files
-            .insert(long_path!("/z/blob/sha256", 42));
-        let mut fixture = Fixture::new_with_fs_and_clear_messages(test_cache_fs, 1000);
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+        fixture.mkdir(long_path!("/z/blob/sha256", 42));
 
         fixture.get_artifact_ign(digest!(42), jid!(1));
 
@@ -917,11 +1757,8 @@ mod tests {
 
     #[test]
     fn multiple_get_requests_for_empty_with_download_and_extract_failure() {
-        let mut test_cache_fs = TestFs::default();
-        test_cache_fs
-            .existing_files
-            .insert(long_path!("/z/blob/sha256", 42));
-        let mut fixture = Fixture::new_with_fs_and_clear_messages(test_cache_fs, 1000);
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+        fixture.mkdir(long_path!("/z/blob/sha256", 42));
 
         fixture.get_artifact_ign(digest!(42), jid!(1));
         fixture.get_artifact_ign(digest!(42), jid!(2));
@@ -963,20 +1800,11 @@ mod tests {
 
     #[test]
     fn rename_retries_until_unique_path_name() {
-        let mut test_cache_fs = TestFs::default();
-        test_cache_fs
-            .existing_files
-            .insert(long_path!("/z/blob/sha256", 42));
-        test_cache_fs
-            .existing_files
-            .insert(short_path!("/z/removing", 1));
-        test_cache_fs
-            .existing_files
-            .insert(short_path!("/z/removing", 2));
-        test_cache_fs
-            .existing_files
-            .insert(short_path!("/z/removing", 3));
-        let mut fixture = Fixture::new_with_fs_and_clear_messages(test_cache_fs, 1000);
+        let mut fixture = Fixture::new_and_clear_messages(1000);
+        fixture.mkdir(long_path!("/z/blob/sha256", 42));
+        fixture.mkdir(short_path!("/z/removing", 1));
+        fixture.mkdir(short_path!("/z/removing", 2));
+        fixture.mkdir(short_path!("/z/removing", 3));
 
         fixture.get_artifact_ign(digest!(42), jid!(1));
 
@@ -1010,19 +1838,16 @@ mod tests {
             MkdirRecursively(path_buf!("/z/bottom_fs_layer/sha256")),
             FileExists(path_buf!("/z/upper_fs_layer/sha256")),
             MkdirRecursively(path_buf!("/z/upper_fs_layer/sha256")),
+            FileExists(path_buf!("/z/output")),
+            MkdirRecursively(path_buf!("/z/output")),
         ]);
     }
 
     #[test]
     fn new_restarts_old_removes() {
-        let mut test_cache_fs = TestFs::default();
-        test_cache_fs.directories.insert(
-            path_buf!("/z/removing"),
-            vec![
-                short_path!("/z/removing", 10),
-                short_path!("/z/removing", 20),
-            ],
-        );
+        let test_cache_fs = TestFs::default();
+        test_cache_fs.mkdir(short_path!("/z/removing", 10));
+        test_cache_fs.mkdir(short_path!("/z/removing", 20));
         let mut fixture = Fixture::new(test_cache_fs, 1000);
         fixture.expect_messages_in_specific_order(vec![
             MkdirRecursively(path_buf!("/z/removing")),
@@ -1035,21 +1860,18 @@ mod tests {
             MkdirRecursively(path_buf!("/z/bottom_fs_layer/sha256")),
             FileExists(path_buf!("/z/upper_fs_layer/sha256")),
             MkdirRecursively(path_buf!("/z/upper_fs_layer/sha256")),
+            FileExists(path_buf!("/z/output")),
+            MkdirRecursively(path_buf!("/z/output")),
         ]);
     }
 
     #[test]
     fn new_removes_old_sha256_if_it_exists() {
-        let mut test_cache_fs = TestFs::default();
-        test_cache_fs
-            .existing_files
-            .insert(path_buf!("/z/blob/sha256"));
-        test_cache_fs
-            .existing_files
-            .insert(path_buf!("/z/bottom_fs_layer/sha256"));
-        test_cache_fs
-            .existing_files
-            .insert(path_buf!("/z/upper_fs_layer/sha256"));
+        let test_cache_fs = TestFs::default();
+        test_cache_fs.mkdir(path_buf!("/z/blob/sha256"));
+        test_cache_fs.mkdir(path_buf!("/z/bottom_fs_layer/sha256"));
+        test_cache_fs.mkdir(path_buf!("/z/upper_fs_layer/sha256"));
+        test_cache_fs.mkdir(path_buf!("/z/output"));
         let mut fixture = Fixture::new(test_cache_fs, 1000);
         fixture.expect_messages_in_specific_order(vec![
             MkdirRecursively(path_buf!("/z/removing")),
@@ -1075,6 +1897,34 @@ mod tests {
             ),
             RemoveRecursively(short_path!("/z/removing", 3)),
             MkdirRecursively(path_buf!("/z/upper_fs_layer/sha256")),
+            FileExists(path_buf!("/z/output")),
+            FileExists(short_path!("/z/removing", 4)),
+            Rename(path_buf!("/z/output"), short_path!("/z/removing", 4)),
+            RemoveRecursively(short_path!("/z/removing", 4)),
+            MkdirRecursively(path_buf!("/z/output")),
         ]);
     }
+
+    #[test]
+    fn mem_fs_rename_and_remove_move_descendants() {
+        let mut fs = MemFs::default();
+        fs.mkdir_recursively(Path::new("/a/b/c"));
+        fs.mkdir_recursively(Path::new("/a/b/d"));
+        fs.mkdir_recursively(Path::new("/e"));
+
+        fs.rename(Path::new("/a/b"), Path::new("/e/f"));
+        assert!(!fs.file_exists(Path::new("/a/b")));
+        assert!(!fs.file_exists(Path::new("/a/b/c")));
+        assert_eq!(
+            fs.read_dir(Path::new("/e/f")).collect::<Vec<_>>(),
+            vec![path_buf!("/e/f/c"), path_buf!("/e/f/d")],
+        );
+
+        fs.remove_recursively_on_thread(path_buf!("/e"));
+        assert!(!fs.file_exists(Path::new("/e/f/c")));
+        assert_eq!(
+            fs.read_dir(Path::new("/")).collect::<Vec<_>>(),
+            vec![path_buf!("/a")],
+        );
+    }
 }
@@ -1,9 +1,10 @@
-use crate::WorkerCacheDir;
+use crate::{dispatcher::SchedulingPolicy, env_filter::EnvPatterns, WorkerCacheDir};
 use maelstrom_macro::Config;
 use maelstrom_util::{
     config::common::{BrokerAddr, CacheSize, InlineLimit, LogLevel, Slots},
     root::RootBuf,
 };
+use std::num::NonZeroUsize;
 use xdg::BaseDirectories;
 
 #[derive(Config, Debug)]
@@ -28,15 +29,56 @@ pub struct Config {
     )]
     pub cache_root: RootBuf<WorkerCacheDir>,
 
-    /// The target amount of disk space to use for the cache. This bound won't be followed
-    /// strictly, so it's best to be conservative. SI and binary suffixes are supported.
+    /// The target amount of disk space to use for the cache. Space is reserved before artifacts
+    /// are downloaded, but small entries may still go over, so it's best to be conservative. SI
+    /// and binary suffixes are supported.
     #[config(value_name = "BYTES", default = "CacheSize::default()")]
     pub cache_size: CacheSize,
 
+    /// The number of seconds to wait before removing the directories of evicted cache entries.
+    /// This gives other processes that share the cache directory time to stop using them.
+    #[config(value_name = "SECONDS", default = "0")]
+    pub cache_removal_grace_period: u64,
+
     /// The maximum amount of bytes to return inline for captured stdout and stderr.
     #[config(value_name = "BYTES", default = "InlineLimit::default()")]
     pub inline_limit: InlineLimit,
 
+    /// Store captured stdout and stderr that exceed the inline limit in the cache directory,
+    /// returning its digest along with the first inline-limit bytes, instead of truncating it.
+    #[config(flag)]
+    pub external_output: bool,
+
+    /// Check that each downloaded artifact matches its digest before handing it to jobs.
+    #[config(flag)]
+    pub verify_artifacts: bool,
+
+    /// Only pass jobs the environment variables whose names match one of these comma-separated
+    /// patterns. In a pattern, `*` matches any sequence of characters.
+    #[config(option, value_name = "PATTERNS", default = r#""all variables""#)]
+    pub environment_allow: Option<EnvPatterns>,
+
+    /// Never pass jobs the environment variables whose names match one of these comma-separated
+    /// patterns, even if they match `environment-allow`.
+    #[config(option, value_name = "PATTERNS", default = r#""no variables""#)]
+    pub environment_deny: Option<EnvPatterns>,
+
+    /// The maximum number of artifact fetches and manifest reads to have in progress at once.
+    #[config(option, value_name = "N", default = r#""no limit""#)]
+    pub max_outstanding_fetches: Option<NonZeroUsize>,
+
+    /// The maximum number of bottom and upper FS layer builds to have in progress at once.
+    #[config(option, value_name = "N", default = r#""no limit""#)]
+    pub max_outstanding_layer_builds: Option<NonZeroUsize>,
+
+    /// How to choose which job to start next when a slot frees up.
+    #[config(value_name = "POLICY", default = r#""lpt""#)]
+    pub scheduling_policy: SchedulingPolicy,
+
+    /// The number of times to retry a job that fails with a system error before reporting it.
+    #[config(value_name = "N", default = "0")]
+    pub max_system_error_retries: u32,
+
     /// Minimum log level to output.
     #[config(short = 'l', value_name = "LEVEL", default = r#""info""#)]
     pub log_level: LogLevel,
@@ -5,17 +5,23 @@ mod tracker;
 
 use crate::cache::{self, GetArtifact};
 use anyhow::{Error, Result};
+use clap::ValueEnum;
 use maelstrom_base::{
     proto::{BrokerToWorker, WorkerToBroker},
-    ArtifactType, JobCompleted, JobError, JobId, JobOutcome, JobResult, JobSpec, Sha256Digest,
+    ArtifactType, ClientId, JobCompleted, JobError, JobId, JobOutcome, JobOutputResult, JobResult,
+    JobSpec, Sha256Digest, WindowSize,
 };
 use maelstrom_util::{config::common::Slots, duration, ext::OptionExt as _};
+use serde::{Deserialize, Serialize};
 use std::{
     cmp::Ordering,
-    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet},
+    collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet, VecDeque},
+    mem,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     time::Duration,
 };
+use strum::EnumString;
 use tracker::{FetcherResult, LayerTracker};
 
 /*              _     _ _
@@ -39,6 +45,10 @@ pub trait Deps {
     /// completes.
     fn start_job(&mut self, jid: JobId, spec: JobSpec, path: PathBuf) -> Self::JobHandle;
 
+    /// Apply a new window size to the TTY of the job with the given handle. This should do nothing
+    /// if the job doesn't have a TTY.
+    fn resize_job_tty(&mut self, handle: &Self::JobHandle, window_size: WindowSize);
+
     /// The timer handle should cancel an outstanding timer when it is dropped. It must be safe to
     /// drop this handle after the timer has completed. Dropping this handle may or may not result
     /// in no [`Message::JobTimer`] message. The dispatcher must be prepared to handle the case
@@ -76,6 +86,14 @@ pub trait Deps {
 pub trait ArtifactFetcher {
     /// Start a thread that will download an artifact from the broker and extract it into `path`.
     fn start_artifact_fetch(&mut self, digest: Sha256Digest, path: PathBuf);
+
+    /// Answer a fetch's [`Message::ReserveArtifactSpace`]. If the space couldn't be reserved, the
+    /// fetch should fail with the given error without writing anything.
+    fn artifact_space_reserved(
+        &mut self,
+        digest: Sha256Digest,
+        result: Result<(), cache::CacheFull>,
+    );
 }
 
 /// The broker sender is split out of [`Deps`] for convenience. The rest of [`Deps`] can stay
@@ -108,6 +126,11 @@ pub trait Cache {
         bytes_used: u64,
     ) -> (PathBuf, Vec<JobId>);
     fn decrement_ref_count(&mut self, kind: cache::EntryKind, digest: &Sha256Digest);
+    fn got_external_output(&mut self, digest: &Sha256Digest, bytes_used: u64);
+    fn reserve(&mut self, bytes: u64) -> Result<(), cache::CacheFull>;
+    fn bytes_used(&self, kind: cache::EntryKind, digest: &Sha256Digest) -> Option<u64>;
+    fn config_snapshot(&self) -> cache::CacheConfigSnapshot;
+    fn remove_expired(&mut self);
 }
 
 /// The standard implementation of [`Cache`] that just calls into [`cache::Cache`].
@@ -141,19 +164,125 @@ impl<FsT: cache::Fs> Cache for cache::Cache<FsT> {
     fn decrement_ref_count(&mut self, kind: cache::EntryKind, digest: &Sha256Digest) {
         self.decrement_ref_count(kind, digest)
     }
+
+    fn got_external_output(&mut self, digest: &Sha256Digest, bytes_used: u64) {
+        self.got_external_output(digest, bytes_used)
+    }
+
+    fn reserve(&mut self, bytes: u64) -> Result<(), cache::CacheFull> {
+        self.reserve(bytes)
+    }
+
+    fn bytes_used(&self, kind: cache::EntryKind, digest: &Sha256Digest) -> Option<u64> {
+        self.bytes_used(kind, digest)
+    }
+
+    fn config_snapshot(&self) -> cache::CacheConfigSnapshot {
+        self.config_snapshot()
+    }
+
+    fn remove_expired(&mut self) {
+        self.remove_expired()
+    }
+}
+
+/// The effective configuration of a worker, as seen by its [`Dispatcher`] and [`Cache`]. This is
+/// meant for debugging deployments. Limits that weren't set are `None`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct WorkerConfigSnapshot {
+    pub slots: usize,
+    pub scheduling_policy: SchedulingPolicy,
+    pub max_outstanding_fetches: Option<usize>,
+    pub max_outstanding_layer_builds: Option<usize>,
+    pub max_system_error_retries: u32,
+    pub default_estimated_duration: Duration,
+    pub cache: cache::CacheConfigSnapshot,
+}
+
+/// Every job the [`Dispatcher`] knows about, grouped by state, along with the sizes of its fetch
+/// and layer-build queues. This is meant for post-mortem debugging of a stuck worker. All job
+/// lists are sorted by [`JobId`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DispatcherSnapshot {
+    /// Jobs waiting on layers, along with the digests each is still waiting on.
+    pub awaiting_layers: Vec<(JobId, Vec<Sha256Digest>)>,
+    /// Jobs that have all of their layers and are waiting for a slot.
+    pub available: Vec<JobId>,
+    pub executing: Vec<(JobId, ExecutingJobStatus)>,
+    pub outstanding_fetches: usize,
+    pub queued_manifest_reads: usize,
+    pub queued_artifact_fetches: usize,
+    pub artifact_fetches_awaiting_cache_space: usize,
+    pub outstanding_layer_builds: usize,
+    pub queued_layer_builds: usize,
+}
+
+/// The jobs a [`Dispatcher`] has been given but hasn't started executing yet, either because
+/// they're waiting on layers or waiting for a slot. Unlike [`DispatcherSnapshot`], this is meant to
+/// be handed to a replacement worker, which can resume the jobs with
+/// [`Dispatcher::requeue_jobs`]. Executing jobs can't be migrated, so they aren't included. The
+/// jobs are sorted by [`JobId`].
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq, Serialize)]
+pub struct QueuedJobsSnapshot {
+    pub jobs: Vec<(JobId, JobSpec)>,
+}
+
+/// The state of an executing job, as reported in a [`DispatcherSnapshot`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExecutingJobStatus {
+    /// The job is running normally.
+    Nominal,
+    /// The job has been canceled, and we're waiting for it to terminate.
+    Canceled,
+    /// The job's timer has expired, and we're waiting for it to terminate.
+    TimedOut,
+}
+
+/// How the dispatcher chooses which available job to start next when a slot frees up.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, EnumString, PartialEq, Eq, Serialize, ValueEnum,
+)]
+#[clap(rename_all = "kebab_case")]
+#[serde(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum SchedulingPolicy {
+    /// Start the highest priority, longest job among all available jobs, regardless of which
+    /// client submitted it.
+    #[default]
+    Lpt,
+
+    /// Rotate between clients that have available jobs, starting the highest priority, longest
+    /// job of the next client in the rotation. This keeps one client with a lot of long jobs from
+    /// starving the others.
+    FairAcrossClients,
 }
 
 /// An input message for the dispatcher. These come from the broker, an executor, or an artifact
 /// fetcher.
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum Message {
     Broker(BrokerToWorker),
     JobCompleted(JobId, JobResult<JobCompleted, String>),
+    JobProgress(JobId, u64, Option<u64>),
     JobTimer(JobId),
+    /// An artifact fetch has learned how big its artifact is, and is waiting for that much cache
+    /// space to be reserved before it writes anything. The dispatcher answers with
+    /// [`ArtifactFetcher::artifact_space_reserved`]. Fetches that don't write into the cache needn't
+    /// send this.
+    ReserveArtifactSpace(Sha256Digest, u64),
     ArtifactFetchCompleted(Sha256Digest, Result<u64>),
     BuiltBottomFsLayer(Sha256Digest, Result<u64>),
     BuiltUpperFsLayer(Sha256Digest, Result<u64>),
     ReadManifestDigests(Sha256Digest, JobId, Result<HashSet<Sha256Digest>>),
+    /// Remove the cache's evicted directories whose removal grace period has elapsed. The cache
+    /// does this on every other operation, but this is also sent periodically so that it happens
+    /// while the worker is idle.
+    RemoveExpiredCacheEntries,
+    /// Stop taking on jobs, but let the executing ones finish and report their results. The
+    /// drain is complete once [`Dispatcher::num_executing`] drops to zero.
+    Drain,
     Shutdown(Error),
 }
 
@@ -165,14 +294,15 @@ where
     BrokerSenderT: BrokerSender,
     CacheT: Cache,
 {
-    /// Create a new dispatcher with the provided slot count. The slot count must be a positive
-    /// number.
+    /// Create a new dispatcher with the provided slot count and scheduling policy. The slot count
+    /// must be a positive number.
     pub fn new(
         deps: DepsT,
         artifact_fetcher: ArtifactFetcherT,
         broker_sender: BrokerSenderT,
         cache: CacheT,
         slots: Slots,
+        scheduling_policy: SchedulingPolicy,
     ) -> Self {
         Dispatcher {
             deps,
@@ -181,8 +311,65 @@ where
             cache,
             slots: slots.into_inner().into(),
             awaiting_layers: HashMap::default(),
-            available: BinaryHeap::default(),
+            available: AvailableJobs::new(scheduling_policy),
             executing: HashMap::default(),
+            fetch_queue: FetchQueue::default(),
+            layer_build_queue: LayerBuildQueue::default(),
+            max_system_error_retries: 0,
+            system_error_retries_remaining: HashMap::default(),
+            default_estimated_duration: Duration::ZERO,
+            draining: false,
+            cache_reservations: HashMap::default(),
+        }
+    }
+
+    /// Limit the number of artifact fetches and manifest reads that may be outstanding at once.
+    /// The rest are queued until outstanding ones complete. Queued manifest reads are always
+    /// started before queued artifact fetches, since they are cheap and they unblock the discovery
+    /// of more artifacts to fetch. By default, there is no limit.
+    pub fn max_outstanding_fetches(mut self, max_outstanding_fetches: NonZeroUsize) -> Self {
+        self.fetch_queue.max_outstanding = Some(max_outstanding_fetches.get());
+        self
+    }
+
+    /// Limit the number of bottom and upper FS layer builds that may be running at once. The rest
+    /// are queued, and are started in the order they were requested as running builds complete.
+    /// By default, there is no limit.
+    pub fn max_outstanding_layer_builds(
+        mut self,
+        max_outstanding_layer_builds: NonZeroUsize,
+    ) -> Self {
+        self.layer_build_queue.max_outstanding = Some(max_outstanding_layer_builds.get());
+        self
+    }
+
+    /// Run a job up to `max_system_error_retries` more times if it fails with a
+    /// [`JobError::System`], instead of reporting the failure to the broker. The job is enqueued
+    /// again from scratch, so it reacquires its layers. Only the final failure, if there is one, is
+    /// reported. Jobs that fail with a [`JobError::Execution`] are never retried. By default, jobs
+    /// aren't retried.
+    pub fn max_system_error_retries(mut self, max_system_error_retries: u32) -> Self {
+        self.max_system_error_retries = max_system_error_retries;
+        self
+    }
+
+    /// The duration [`estimated_remaining_work`](Self::estimated_remaining_work) assumes for jobs
+    /// that don't have an estimated duration. By default, it is zero.
+    pub fn default_estimated_duration(mut self, default_estimated_duration: Duration) -> Self {
+        self.default_estimated_duration = default_estimated_duration;
+        self
+    }
+
+    /// Return the effective configuration of the dispatcher and its cache.
+    pub fn config_snapshot(&self) -> WorkerConfigSnapshot {
+        WorkerConfigSnapshot {
+            slots: self.slots,
+            scheduling_policy: self.available.policy(),
+            max_outstanding_fetches: self.fetch_queue.max_outstanding,
+            max_outstanding_layer_builds: self.layer_build_queue.max_outstanding,
+            max_system_error_retries: self.max_system_error_retries,
+            default_estimated_duration: self.default_estimated_duration,
+            cache: self.cache.config_snapshot(),
         }
     }
 
@@ -190,12 +377,20 @@ where
     /// [Message] for more information.
     pub fn receive_message(&mut self, msg: Message) {
         match msg {
+            Message::Broker(BrokerToWorker::EnqueueJob(..)) if self.draining => {}
             Message::Broker(BrokerToWorker::EnqueueJob(jid, spec)) => {
                 self.receive_enqueue_job(jid, spec)
             }
             Message::Broker(BrokerToWorker::CancelJob(jid)) => self.receive_cancel_job(jid),
+            Message::Broker(BrokerToWorker::ResizeJobTty(jid, window_size)) => {
+                self.receive_resize_job_tty(jid, window_size)
+            }
             Message::JobCompleted(jid, result) => self.receive_job_completed(jid, result),
+            Message::JobProgress(jid, done, total) => self.receive_job_progress(jid, done, total),
             Message::JobTimer(jid) => self.receive_job_timer(jid),
+            Message::ReserveArtifactSpace(digest, bytes) => {
+                self.fetch_queue.awaiting_space.push_back((digest, bytes))
+            }
             Message::ArtifactFetchCompleted(digest, Err(err)) => {
                 self.receive_artifact_failure(digest, err)
             }
@@ -220,8 +415,13 @@ where
             Message::ReadManifestDigests(digest, jid, Err(err)) => {
                 self.receive_read_manifest_digests_failure(digest, jid, err)
             }
+            Message::RemoveExpiredCacheEntries => self.cache.remove_expired(),
+            Message::Drain => self.receive_drain(),
             Message::Shutdown(_) => self.receive_shutdown(),
         }
+        self.possibly_reserve_artifact_space();
+        self.possibly_start_fetches();
+        self.possibly_start_layer_builds();
     }
 }
 
@@ -255,9 +455,7 @@ struct AvailableJob {
 
 impl PartialEq for AvailableJob {
     fn eq(&self, other: &Self) -> bool {
-        self.spec
-            .estimated_duration
-            .eq(&other.spec.estimated_duration)
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -269,12 +467,195 @@ impl PartialOrd for AvailableJob {
 
 impl Eq for AvailableJob {}
 
+/// Jobs with higher priorities are ordered greater, so they're popped from the heap first. Among
+/// jobs with the same priority, those with longer estimated durations are ordered greater. Ties are
+/// broken by [`JobId`], with lower job ids ordered greater, so that the order in which jobs are
+/// popped is deterministic.
 impl Ord for AvailableJob {
     fn cmp(&self, other: &Self) -> Ordering {
-        duration::cmp(
-            &self.spec.estimated_duration,
-            &other.spec.estimated_duration,
-        )
+        self.spec
+            .priority
+            .cmp(&other.spec.priority)
+            .then_with(|| {
+                duration::cmp(
+                    &self.spec.estimated_duration,
+                    &other.spec.estimated_duration,
+                )
+            })
+            .then_with(|| other.jid.cmp(&self.jid))
+    }
+}
+
+/// The jobs that have all of their layers and are waiting for a slot. Depending on the
+/// [`SchedulingPolicy`], either all jobs share one heap, or each client gets its own heap and the
+/// clients take turns.
+enum AvailableJobs {
+    Shared(BinaryHeap<AvailableJob>),
+    PerClient {
+        heaps: HashMap<ClientId, BinaryHeap<AvailableJob>>,
+        rotation: VecDeque<ClientId>,
+    },
+}
+
+impl AvailableJobs {
+    fn new(policy: SchedulingPolicy) -> Self {
+        match policy {
+            SchedulingPolicy::Lpt => Self::Shared(BinaryHeap::default()),
+            SchedulingPolicy::FairAcrossClients => Self::PerClient {
+                heaps: HashMap::default(),
+                rotation: VecDeque::default(),
+            },
+        }
+    }
+
+    fn policy(&self) -> SchedulingPolicy {
+        match self {
+            Self::Shared(_) => SchedulingPolicy::Lpt,
+            Self::PerClient { .. } => SchedulingPolicy::FairAcrossClients,
+        }
+    }
+
+    fn push(&mut self, job: AvailableJob) {
+        match self {
+            Self::Shared(heap) => heap.push(job),
+            Self::PerClient { heaps, rotation } => {
+                let cid = job.jid.cid;
+                heaps
+                    .entry(cid)
+                    .or_insert_with(|| {
+                        rotation.push_back(cid);
+                        BinaryHeap::default()
+                    })
+                    .push(job);
+            }
+        }
+    }
+
+    /// Remove and return the next job to start. For per-client scheduling, the client at the front
+    /// of the rotation gives up its best job and moves to the back, if it has any jobs left.
+    fn pop(&mut self) -> Option<AvailableJob> {
+        match self {
+            Self::Shared(heap) => heap.pop(),
+            Self::PerClient { heaps, rotation } => {
+                let cid = rotation.pop_front()?;
+                let Entry::Occupied(mut entry) = heaps.entry(cid) else {
+                    panic!("client {cid:?} in rotation without any available jobs");
+                };
+                let job = entry.get_mut().pop().unwrap();
+                if entry.get().is_empty() {
+                    entry.remove();
+                } else {
+                    rotation.push_back(cid);
+                }
+                Some(job)
+            }
+        }
+    }
+
+    fn retain(&mut self, mut f: impl FnMut(&AvailableJob) -> bool) {
+        match self {
+            Self::Shared(heap) => heap.retain(f),
+            Self::PerClient { heaps, rotation } => {
+                heaps.retain(|_, heap| {
+                    heap.retain(&mut f);
+                    !heap.is_empty()
+                });
+                rotation.retain(|cid| heaps.contains_key(cid));
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Self::new(self.policy());
+    }
+
+    fn contains(&self, jid: JobId) -> bool {
+        match self {
+            Self::Shared(heap) => heap.iter().any(|job| job.jid == jid),
+            Self::PerClient { heaps, .. } => heaps
+                .get(&jid.cid)
+                .is_some_and(|heap| heap.iter().any(|job| job.jid == jid)),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = &AvailableJob> + '_> {
+        match self {
+            Self::Shared(heap) => Box::new(heap.iter()),
+            Self::PerClient { heaps, .. } => Box::new(heaps.values().flatten()),
+        }
+    }
+
+    fn jids(&self) -> Vec<JobId> {
+        self.iter().map(|job| job.jid).collect()
+    }
+}
+
+/// Artifact fetches and manifest reads that have been requested but not yet started.
+#[derive(Default)]
+struct FetchQueue {
+    max_outstanding: Option<usize>,
+    outstanding_manifest_reads: usize,
+    /// The artifact fetches we started. We may also hear about fetches that someone else started,
+    /// since the cache tells every waiting job when an artifact arrives, and those don't count.
+    outstanding_artifact_fetches: HashSet<Sha256Digest>,
+    manifest_reads: VecDeque<(Sha256Digest, PathBuf, JobId)>,
+    artifact_fetches: VecDeque<(Sha256Digest, PathBuf)>,
+    /// Started artifact fetches that are waiting for cache space, in the order they asked for it.
+    awaiting_space: VecDeque<(Sha256Digest, u64)>,
+}
+
+impl FetchQueue {
+    fn outstanding(&self) -> usize {
+        self.outstanding_manifest_reads + self.outstanding_artifact_fetches.len()
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.max_outstanding
+            .map_or(true, |max_outstanding| self.outstanding() < max_outstanding)
+    }
+
+    fn manifest_read_completed(&mut self) {
+        self.outstanding_manifest_reads = self.outstanding_manifest_reads.checked_sub(1).unwrap();
+    }
+
+    fn artifact_fetch_completed(&mut self, digest: &Sha256Digest) {
+        self.outstanding_artifact_fetches.remove(digest);
+    }
+}
+
+/// A bottom or upper FS layer build that has been requested but not yet started.
+enum LayerBuild {
+    Bottom {
+        digest: Sha256Digest,
+        layer_path: PathBuf,
+        artifact_type: ArtifactType,
+        artifact_path: PathBuf,
+        artifact_bytes_used: u64,
+    },
+    Upper {
+        digest: Sha256Digest,
+        layer_path: PathBuf,
+        lower_layer_path: PathBuf,
+        upper_layer_path: PathBuf,
+    },
+}
+
+/// Layer builds that have been requested but not yet started.
+#[derive(Default)]
+struct LayerBuildQueue {
+    max_outstanding: Option<usize>,
+    outstanding: usize,
+    builds: VecDeque<LayerBuild>,
+}
+
+impl LayerBuildQueue {
+    fn has_capacity(&self) -> bool {
+        self.max_outstanding
+            .map_or(true, |max_outstanding| self.outstanding < max_outstanding)
+    }
+
+    fn build_completed(&mut self) {
+        self.outstanding = self.outstanding.checked_sub(1).unwrap();
     }
 }
 
@@ -284,7 +665,7 @@ enum ExecutingJobState<DepsT: Deps> {
     /// we're going to send a `JobOutcome::Completed` result to the broker, unless it times out or
     /// is canceled in the meantime.
     Nominal {
-        _job_handle: DepsT::JobHandle,
+        job_handle: DepsT::JobHandle,
         _timer_handle: Option<DepsT::TimerHandle>,
     },
 
@@ -306,6 +687,9 @@ enum ExecutingJobState<DepsT: Deps> {
 struct ExecutingJob<DepsT: Deps> {
     state: ExecutingJobState<DepsT>,
     cache_keys: HashSet<cache::Key>,
+    /// Kept so the job can be enqueued again if it fails with a system error.
+    spec: JobSpec,
+    system_error_retries_remaining: u32,
 }
 
 /// Manage jobs based on the slot count and requests from the broker. If the broker sends more job
@@ -320,22 +704,40 @@ pub struct Dispatcher<DepsT: Deps, ArtifactFetcherT, BrokerSenderT, CacheT> {
     cache: CacheT,
     slots: usize,
     awaiting_layers: HashMap<JobId, AwaitingLayersJob>,
-    available: BinaryHeap<AvailableJob>,
+    available: AvailableJobs,
     executing: HashMap<JobId, ExecutingJob<DepsT>>,
+    fetch_queue: FetchQueue,
+    layer_build_queue: LayerBuildQueue,
+    max_system_error_retries: u32,
+    /// Retry counts for jobs that failed with a system error and were enqueued again. A job's
+    /// entry is removed when it starts executing again, or if it is canceled or fails first.
+    system_error_retries_remaining: HashMap<JobId, u32>,
+    default_estimated_duration: Duration,
+    draining: bool,
+    /// Cache space reserved for artifact fetches and bottom FS layer builds that haven't finished
+    /// yet. The cache doesn't know about these bytes until they're added to it, so we count them
+    /// against its target ourselves.
+    cache_reservations: HashMap<cache::Key, u64>,
+}
+
+/// The outcome of trying to reserve cache space.
+enum CacheSpace {
+    Reserved,
+    /// There isn't enough space right now, but something in progress may release some.
+    Wait,
+    /// There isn't enough space, and nothing in progress could release enough.
+    Full(cache::CacheFull),
 }
 
-struct Fetcher<'dispatcher, DepsT, ArtifactFetcherT, CacheT> {
-    deps: &'dispatcher mut DepsT,
-    artifact_fetcher: &'dispatcher mut ArtifactFetcherT,
+struct Fetcher<'dispatcher, CacheT> {
+    fetch_queue: &'dispatcher mut FetchQueue,
+    layer_build_queue: &'dispatcher mut LayerBuildQueue,
     cache: &'dispatcher mut CacheT,
     jid: JobId,
 }
 
-impl<'dispatcher, DepsT, ArtifactFetcherT, CacheT> tracker::Fetcher
-    for Fetcher<'dispatcher, DepsT, ArtifactFetcherT, CacheT>
+impl<'dispatcher, CacheT> tracker::Fetcher for Fetcher<'dispatcher, CacheT>
 where
-    DepsT: Deps,
-    ArtifactFetcherT: ArtifactFetcher,
     CacheT: Cache,
 {
     fn fetch_artifact(&mut self, digest: &Sha256Digest) -> FetcherResult {
@@ -346,8 +748,9 @@ where
             GetArtifact::Success(path) => FetcherResult::Got(path),
             GetArtifact::Wait => FetcherResult::Pending,
             GetArtifact::Get(path) => {
-                self.artifact_fetcher
-                    .start_artifact_fetch(digest.clone(), path);
+                self.fetch_queue
+                    .artifact_fetches
+                    .push_back((digest.clone(), path));
                 FetcherResult::Pending
             }
         }
@@ -365,13 +768,19 @@ where
         {
             GetArtifact::Success(path) => FetcherResult::Got(path),
             GetArtifact::Wait => FetcherResult::Pending,
-            GetArtifact::Get(path) => {
-                self.deps.build_bottom_fs_layer(
-                    digest.clone(),
-                    path,
+            GetArtifact::Get(layer_path) => {
+                // A bottom layer takes up about as much space as the artifact it's built from.
+                let artifact_bytes_used = self
+                    .cache
+                    .bytes_used(cache::EntryKind::Blob, digest)
+                    .unwrap_or_default();
+                self.layer_build_queue.builds.push_back(LayerBuild::Bottom {
+                    digest: digest.clone(),
+                    layer_path,
                     artifact_type,
-                    artifact_path.into(),
-                );
+                    artifact_path: artifact_path.into(),
+                    artifact_bytes_used,
+                });
                 FetcherResult::Pending
             }
         }
@@ -389,21 +798,22 @@ where
         {
             GetArtifact::Success(path) => FetcherResult::Got(path),
             GetArtifact::Wait => FetcherResult::Pending,
-            GetArtifact::Get(path) => {
-                self.deps.build_upper_fs_layer(
-                    digest.clone(),
-                    path,
-                    lower_layer_path.into(),
-                    upper_layer_path.into(),
-                );
+            GetArtifact::Get(layer_path) => {
+                self.layer_build_queue.builds.push_back(LayerBuild::Upper {
+                    digest: digest.clone(),
+                    layer_path,
+                    lower_layer_path: lower_layer_path.into(),
+                    upper_layer_path: upper_layer_path.into(),
+                });
                 FetcherResult::Pending
             }
         }
     }
 
     fn fetch_manifest_digests(&mut self, digest: &Sha256Digest, path: &Path) {
-        self.deps
-            .read_manifest_digests(digest.clone(), path.into(), self.jid);
+        self.fetch_queue
+            .manifest_reads
+            .push_back((digest.clone(), path.into(), self.jid));
     }
 }
 
@@ -433,17 +843,167 @@ where
         let timer_handle = spec
             .timeout
             .map(|timeout| self.deps.start_timer(jid, Duration::from(timeout)));
-        let job_handle = self.deps.start_job(jid, spec, path);
+        let job_handle = self.deps.start_job(jid, spec.clone(), path);
+        let system_error_retries_remaining = self
+            .system_error_retries_remaining
+            .remove(&jid)
+            .unwrap_or(self.max_system_error_retries);
         let executing_job = ExecutingJob {
             state: ExecutingJobState::Nominal {
-                _job_handle: job_handle,
+                job_handle,
                 _timer_handle: timer_handle,
             },
             cache_keys,
+            spec,
+            system_error_retries_remaining,
         };
         self.executing.insert(jid, executing_job).assert_is_none();
     }
 
+    /// Start as many queued fetches as the limit allows, manifest reads first.
+    fn possibly_start_fetches(&mut self) {
+        while self.fetch_queue.has_capacity() {
+            if let Some((digest, path, jid)) = self.fetch_queue.manifest_reads.pop_front() {
+                self.fetch_queue.outstanding_manifest_reads += 1;
+                self.deps.read_manifest_digests(digest, path, jid);
+            } else if let Some((digest, path)) = self.fetch_queue.artifact_fetches.pop_front() {
+                // We don't learn how big an artifact is until the broker starts sending it. The
+                // fetch will ask us to reserve space for it at that point.
+                self.fetch_queue
+                    .outstanding_artifact_fetches
+                    .insert(digest.clone());
+                self.artifact_fetcher.start_artifact_fetch(digest, path);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Answer the artifact fetches waiting for cache space, in order, until one has to keep
+    /// waiting.
+    fn possibly_reserve_artifact_space(&mut self) {
+        while let Some((digest, bytes)) = self.fetch_queue.awaiting_space.front().cloned() {
+            let result = match self.reserve_cache_space(cache::EntryKind::Blob, &digest, bytes) {
+                CacheSpace::Reserved => Ok(()),
+                CacheSpace::Wait => break,
+                CacheSpace::Full(err) => Err(err),
+            };
+            self.fetch_queue.awaiting_space.pop_front();
+            self.artifact_fetcher
+                .artifact_space_reserved(digest, result);
+        }
+    }
+
+    /// Make room in the cache for `bytes` more, and hold them for the given entry until it is
+    /// filled or fails. Reservations that haven't been released count against the cache's target,
+    /// so the cache never goes over it.
+    ///
+    /// Space is only released when entries stop being used, or when another reservation turns out
+    /// to have been too big. If nothing in progress could do that, waiting would deadlock, so the
+    /// reservation fails instead.
+    fn reserve_cache_space(
+        &mut self,
+        kind: cache::EntryKind,
+        digest: &Sha256Digest,
+        bytes: u64,
+    ) -> CacheSpace {
+        let reserved = self.cache_reservations.values().sum::<u64>();
+        match self.cache.reserve(reserved.saturating_add(bytes)) {
+            Ok(()) => {
+                self.cache_reservations
+                    .insert(cache::Key::new(kind, digest.clone()), bytes);
+                CacheSpace::Reserved
+            }
+            Err(err) if bytes <= err.bytes_used_target && self.cache_space_may_be_released() => {
+                CacheSpace::Wait
+            }
+            Err(err) => CacheSpace::Full(cache::CacheFull {
+                bytes_requested: bytes,
+                ..err
+            }),
+        }
+    }
+
+    /// Return whether anything in progress may release cache space when it finishes. Fetches
+    /// waiting for space won't make progress until they get it, so they don't count.
+    fn cache_space_may_be_released(&self) -> bool {
+        !self.executing.is_empty()
+            || self.layer_build_queue.outstanding > 0
+            || self.fetch_queue.outstanding() > self.fetch_queue.awaiting_space.len()
+    }
+
+    fn release_cache_space(&mut self, kind: cache::EntryKind, digest: &Sha256Digest) {
+        self.cache_reservations
+            .remove(&cache::Key::new(kind, digest.clone()));
+    }
+
+    /// Start as many queued layer builds as the limit allows, in the order they were requested.
+    fn possibly_start_layer_builds(&mut self) {
+        while self.layer_build_queue.has_capacity() {
+            match self.layer_build_queue.builds.pop_front() {
+                Some(LayerBuild::Bottom {
+                    digest,
+                    layer_path,
+                    artifact_type,
+                    artifact_path,
+                    artifact_bytes_used,
+                }) => {
+                    // We use the size of the artifact as an estimate of the size of the layer.
+                    match self.reserve_cache_space(
+                        cache::EntryKind::BottomFsLayer,
+                        &digest,
+                        artifact_bytes_used,
+                    ) {
+                        CacheSpace::Reserved => {}
+                        CacheSpace::Wait => {
+                            self.layer_build_queue
+                                .builds
+                                .push_front(LayerBuild::Bottom {
+                                    digest,
+                                    layer_path,
+                                    artifact_type,
+                                    artifact_path,
+                                    artifact_bytes_used,
+                                });
+                            break;
+                        }
+                        CacheSpace::Full(err) => {
+                            let msg = "Failed to build bottom FS layer";
+                            self.cache_fill_failure(
+                                cache::EntryKind::BottomFsLayer,
+                                digest,
+                                msg,
+                                err.into(),
+                            );
+                            continue;
+                        }
+                    }
+                    self.deps.build_bottom_fs_layer(
+                        digest,
+                        layer_path,
+                        artifact_type,
+                        artifact_path,
+                    );
+                }
+                Some(LayerBuild::Upper {
+                    digest,
+                    layer_path,
+                    lower_layer_path,
+                    upper_layer_path,
+                }) => {
+                    self.deps.build_upper_fs_layer(
+                        digest,
+                        layer_path,
+                        lower_layer_path,
+                        upper_layer_path,
+                    );
+                }
+                None => break,
+            }
+            self.layer_build_queue.outstanding += 1;
+        }
+    }
+
     /// Put a job on the available jobs queue. At this point, it must have all of its artifacts.
     fn make_job_available(&mut self, jid: JobId, spec: JobSpec, tracker: LayerTracker) {
         let (path, cache_keys) = tracker.into_path_and_cache_keys();
@@ -457,9 +1017,17 @@ where
     }
 
     fn receive_enqueue_job(&mut self, jid: JobId, spec: JobSpec) {
+        // The broker may send us a job we already have, for example after a reconnect. We'll send
+        // the job's result when it completes, so there's nothing more to do.
+        if self.awaiting_layers.contains_key(&jid)
+            || self.available.contains(jid)
+            || self.executing.contains_key(&jid)
+        {
+            return;
+        }
         let mut fetcher = Fetcher {
-            deps: &mut self.deps,
-            artifact_fetcher: &mut self.artifact_fetcher,
+            fetch_queue: &mut self.fetch_queue,
+            layer_build_queue: &mut self.layer_build_queue,
             cache: &mut self.cache,
             jid,
         };
@@ -474,6 +1042,7 @@ where
     }
 
     fn receive_cancel_job(&mut self, jid: JobId) {
+        self.system_error_retries_remaining.remove(&jid);
         if let Some(entry) = self.awaiting_layers.remove(&jid) {
             // We may have already gotten some layers. Make sure we release those.
             for cache::Key { kind, digest } in entry.tracker.into_cache_keys() {
@@ -503,20 +1072,64 @@ where
         }
     }
 
+    fn receive_resize_job_tty(&mut self, jid: JobId, window_size: WindowSize) {
+        // The resize can race with the job completing, being canceled, or timing out. In those
+        // cases, there's nothing left to resize, so we just drop the message.
+        if let Some(ExecutingJob {
+            state: ExecutingJobState::Nominal { job_handle, .. },
+            ..
+        }) = self.executing.get(&jid)
+        {
+            self.deps.resize_job_tty(job_handle, window_size);
+        }
+    }
+
     fn receive_job_completed(&mut self, jid: JobId, result: JobResult<JobCompleted, String>) {
-        let Some(ExecutingJob { state, cache_keys }) = self.executing.remove(&jid) else {
+        let Some(ExecutingJob {
+            state,
+            cache_keys,
+            spec,
+            system_error_retries_remaining,
+        }) = self.executing.remove(&jid)
+        else {
             panic!("missing entry for {jid:?}");
         };
 
+        if let Ok(JobCompleted { effects, .. }) = &result {
+            for output in [&effects.stdout, &effects.stderr] {
+                if let JobOutputResult::External { digest, size, .. } = output {
+                    self.cache.got_external_output(digest, *size);
+                }
+            }
+        }
+
         match state {
+            ExecutingJobState::Nominal { .. }
+                if matches!(result, Err(JobError::System(_)))
+                    && system_error_retries_remaining > 0
+                    && !self.draining =>
+            {
+                // Enqueue the job again before releasing its layers, so that they aren't evicted
+                // from the cache in between.
+                self.system_error_retries_remaining
+                    .insert(jid, system_error_retries_remaining - 1);
+                self.receive_enqueue_job(jid, spec);
+            }
             ExecutingJobState::Nominal { .. } => {
                 self.broker_sender
-                    .send_message_to_broker(WorkerToBroker(jid, result.map(JobOutcome::Completed)));
+                    .send_message_to_broker(WorkerToBroker::JobResponse {
+                        jid,
+                        result: result.map(JobOutcome::Completed),
+                    });
             }
             ExecutingJobState::Canceled => {}
-            ExecutingJobState::TimedOut => self.broker_sender.send_message_to_broker(
-                WorkerToBroker(jid, result.map(|c| JobOutcome::TimedOut(c.effects))),
-            ),
+            ExecutingJobState::TimedOut => {
+                self.broker_sender
+                    .send_message_to_broker(WorkerToBroker::JobResponse {
+                        jid,
+                        result: result.map(|c| JobOutcome::TimedOut(c.effects)),
+                    })
+            }
         }
 
         for cache::Key { kind, digest } in cache_keys {
@@ -525,12 +1138,22 @@ where
         self.possibly_start_job();
     }
 
+    fn receive_job_progress(&mut self, jid: JobId, done: u64, total: Option<u64>) {
+        // Progress can race with the job completing, being canceled, or timing out. In those
+        // cases, the broker either isn't interested or has already heard the last of the job, so
+        // we just drop the message.
+        if let Some(ExecutingJob {
+            state: ExecutingJobState::Nominal { .. },
+            ..
+        }) = self.executing.get(&jid)
+        {
+            self.broker_sender
+                .send_message_to_broker(WorkerToBroker::JobProgress(jid, done, total));
+        }
+    }
+
     fn receive_job_timer(&mut self, jid: JobId) {
-        let Some(&mut ExecutingJob {
-            ref mut state,
-            cache_keys: _,
-        }) = self.executing.get_mut(&jid)
-        else {
+        let Some(&mut ExecutingJob { ref mut state, .. }) = self.executing.get_mut(&jid) else {
             return;
         };
         // We kill the job, but we wait around until it's actually
@@ -555,10 +1178,12 @@ where
             //
             // Otherwise, it means that there were previous errors for this entry, or it was
             // canceled, and there's nothing to do here.
-            self.broker_sender.send_message_to_broker(WorkerToBroker(
-                jid,
-                Err(JobError::System(format!("{msg} {digest}: {err:?}"))),
-            ));
+            self.system_error_retries_remaining.remove(&jid);
+            self.broker_sender
+                .send_message_to_broker(WorkerToBroker::JobResponse {
+                    jid,
+                    result: Err(JobError::System(format!("{msg} {digest}: {err:?}"))),
+                });
             for cache::Key { kind, digest } in entry.tracker.into_cache_keys() {
                 self.cache.decrement_ref_count(kind, &digest);
             }
@@ -578,6 +1203,8 @@ where
     }
 
     fn receive_artifact_failure(&mut self, digest: Sha256Digest, err: Error) {
+        self.fetch_queue.artifact_fetch_completed(&digest);
+        self.release_cache_space(cache::EntryKind::Blob, &digest);
         let msg = "Failed to download and extract layer artifact";
         self.cache_fill_failure(cache::EntryKind::Blob, digest, msg, err)
     }
@@ -587,11 +1214,7 @@ where
         jid: JobId,
         kind: cache::EntryKind,
         digest: &Sha256Digest,
-        cb: impl FnOnce(
-            &mut LayerTracker,
-            &Sha256Digest,
-            &mut Fetcher<'_, DepsT, ArtifactFetcherT, CacheT>,
-        ),
+        cb: impl FnOnce(&mut LayerTracker, &Sha256Digest, &mut Fetcher<'_, CacheT>),
     ) {
         match self.awaiting_layers.entry(jid) {
             Entry::Vacant(_) => {
@@ -603,8 +1226,8 @@ where
                 // So far all is good. We then need to check if we've gotten all layers. If we
                 // have, then we can go ahead and schedule the job.
                 let mut fetcher = Fetcher {
-                    deps: &mut self.deps,
-                    artifact_fetcher: &mut self.artifact_fetcher,
+                    fetch_queue: &mut self.fetch_queue,
+                    layer_build_queue: &mut self.layer_build_queue,
                     cache: &mut self.cache,
                     jid,
                 };
@@ -622,12 +1245,7 @@ where
         kind: cache::EntryKind,
         digest: Sha256Digest,
         bytes_used: u64,
-        cb: impl Fn(
-            &mut LayerTracker,
-            &Sha256Digest,
-            PathBuf,
-            &mut Fetcher<'_, DepsT, ArtifactFetcherT, CacheT>,
-        ),
+        cb: impl Fn(&mut LayerTracker, &Sha256Digest, PathBuf, &mut Fetcher<'_, CacheT>),
     ) {
         let (path, jobs) = self.cache.got_artifact_success(kind, &digest, bytes_used);
         for jid in jobs {
@@ -638,6 +1256,8 @@ where
     }
 
     fn receive_artifact_success(&mut self, digest: Sha256Digest, bytes_used: u64) {
+        self.fetch_queue.artifact_fetch_completed(&digest);
+        self.release_cache_space(cache::EntryKind::Blob, &digest);
         self.cache_fill_success(
             cache::EntryKind::Blob,
             digest,
@@ -647,6 +1267,8 @@ where
     }
 
     fn receive_build_bottom_fs_layer_success(&mut self, digest: Sha256Digest, bytes_used: u64) {
+        self.layer_build_queue.build_completed();
+        self.release_cache_space(cache::EntryKind::BottomFsLayer, &digest);
         self.cache_fill_success(
             cache::EntryKind::BottomFsLayer,
             digest,
@@ -656,11 +1278,14 @@ where
     }
 
     fn receive_build_bottom_fs_layer_failure(&mut self, digest: Sha256Digest, err: Error) {
+        self.layer_build_queue.build_completed();
+        self.release_cache_space(cache::EntryKind::BottomFsLayer, &digest);
         let msg = "Failed to build bottom FS layer";
         self.cache_fill_failure(cache::EntryKind::BottomFsLayer, digest, msg, err)
     }
 
     fn receive_build_upper_fs_layer_success(&mut self, digest: Sha256Digest, bytes_used: u64) {
+        self.layer_build_queue.build_completed();
         self.cache_fill_success(
             cache::EntryKind::UpperFsLayer,
             digest,
@@ -670,6 +1295,7 @@ where
     }
 
     fn receive_build_upper_fs_layer_failure(&mut self, digest: Sha256Digest, err: Error) {
+        self.layer_build_queue.build_completed();
         let msg = "Failed to build upper FS layer";
         self.cache_fill_failure(cache::EntryKind::UpperFsLayer, digest, msg, err)
     }
@@ -680,6 +1306,7 @@ where
         jid: JobId,
         digests: HashSet<Sha256Digest>,
     ) {
+        self.fetch_queue.manifest_read_completed();
         self.advance_job(
             jid,
             cache::EntryKind::Blob,
@@ -696,14 +1323,45 @@ where
         jid: JobId,
         err: Error,
     ) {
+        self.fetch_queue.manifest_read_completed();
         self.job_failure(&digest, jid, "failed to read manifest", &err);
     }
 
+    /// Stop taking on work, but keep the connection to the broker open so that the executing
+    /// jobs can report their results. Jobs that haven't started executing yet are dropped, along
+    /// with their references to cache entries, and failed jobs are no longer retried. The broker
+    /// isn't told about any of these jobs, or about jobs it enqueues while we're draining: it
+    /// requeues everything still pending on this worker once the worker disconnects.
+    fn receive_drain(&mut self) {
+        self.draining = true;
+        for (_, entry) in mem::take(&mut self.awaiting_layers) {
+            for cache::Key { kind, digest } in entry.tracker.into_cache_keys() {
+                self.cache.decrement_ref_count(kind, &digest);
+            }
+        }
+        let mut keys_to_drop = vec![];
+        self.available.retain(|entry| {
+            keys_to_drop.extend(entry.cache_keys.iter().cloned());
+            false
+        });
+        for cache::Key { kind, digest } in keys_to_drop {
+            self.cache.decrement_ref_count(kind, &digest);
+        }
+        self.fetch_queue.manifest_reads = Default::default();
+        self.fetch_queue.artifact_fetches = Default::default();
+        self.layer_build_queue.builds = Default::default();
+        self.system_error_retries_remaining = Default::default();
+    }
+
     /// Close our connection to the broker, drop pending work, and cancel all jobs.
     fn receive_shutdown(&mut self) {
         self.broker_sender.close();
         self.awaiting_layers = Default::default();
-        self.available = Default::default();
+        self.available.clear();
+        self.fetch_queue.manifest_reads = Default::default();
+        self.fetch_queue.artifact_fetches = Default::default();
+        self.layer_build_queue.builds = Default::default();
+        self.system_error_retries_remaining = Default::default();
 
         for jid in self.executing.keys().cloned().collect::<Vec<_>>() {
             self.receive_cancel_job(jid);
@@ -714,6 +1372,90 @@ where
     pub fn num_executing(&self) -> usize {
         self.executing.len()
     }
+
+    /// The sum of the estimated durations of the jobs that are executing or waiting for a slot.
+    /// Jobs without an estimated duration count as the
+    /// [`default_estimated_duration`](Self::default_estimated_duration). Jobs that are still
+    /// waiting on layers aren't counted. This gives a rough sense of how backed up the worker is.
+    pub fn estimated_remaining_work(&self) -> Duration {
+        self.available
+            .iter()
+            .map(|job| &job.spec)
+            .chain(self.executing.values().map(|job| &job.spec))
+            .map(|spec| {
+                spec.estimated_duration
+                    .unwrap_or(self.default_estimated_duration)
+            })
+            .sum()
+    }
+
+    /// Whether the dispatcher has received a [`Message::Drain`]. Once it has, and
+    /// [`num_executing`](Self::num_executing) is zero, the worker can be shut down without
+    /// losing any results.
+    pub fn is_draining(&self) -> bool {
+        self.draining
+    }
+
+    /// Returns, for each job that is waiting on layers, the digests it is still waiting on. This
+    /// is useful for figuring out why a job isn't making progress. The jobs are sorted by
+    /// [`JobId`].
+    pub fn awaiting_layers_detail(&self) -> Vec<(JobId, Vec<Sha256Digest>)> {
+        let mut detail = Vec::from_iter(
+            self.awaiting_layers
+                .iter()
+                .map(|(jid, entry)| (*jid, entry.tracker.pending_digests())),
+        );
+        detail.sort_by_key(|(jid, _)| *jid);
+        detail
+    }
+
+    /// Returns a snapshot of every job in the dispatcher, grouped by state. See
+    /// [`DispatcherSnapshot`].
+    pub fn debug_snapshot(&self) -> DispatcherSnapshot {
+        let mut available = self.available.jids();
+        available.sort();
+        let mut executing = Vec::from_iter(self.executing.iter().map(|(jid, job)| {
+            let status = match job.state {
+                ExecutingJobState::Nominal { .. } => ExecutingJobStatus::Nominal,
+                ExecutingJobState::Canceled => ExecutingJobStatus::Canceled,
+                ExecutingJobState::TimedOut => ExecutingJobStatus::TimedOut,
+            };
+            (*jid, status)
+        }));
+        executing.sort_by_key(|(jid, _)| *jid);
+        DispatcherSnapshot {
+            awaiting_layers: self.awaiting_layers_detail(),
+            available,
+            executing,
+            outstanding_fetches: self.fetch_queue.outstanding(),
+            queued_manifest_reads: self.fetch_queue.manifest_reads.len(),
+            queued_artifact_fetches: self.fetch_queue.artifact_fetches.len(),
+            artifact_fetches_awaiting_cache_space: self.fetch_queue.awaiting_space.len(),
+            outstanding_layer_builds: self.layer_build_queue.outstanding,
+            queued_layer_builds: self.layer_build_queue.builds.len(),
+        }
+    }
+
+    /// Returns the jobs that are waiting on layers or waiting for a slot, so they can be resumed
+    /// by another dispatcher. See [`QueuedJobsSnapshot`].
+    pub fn queued_jobs_snapshot(&self) -> QueuedJobsSnapshot {
+        let mut jobs = Vec::from_iter(
+            self.awaiting_layers
+                .iter()
+                .map(|(jid, entry)| (*jid, entry.spec.clone()))
+                .chain(self.available.iter().map(|job| (job.jid, job.spec.clone()))),
+        );
+        jobs.sort_by_key(|(jid, _)| *jid);
+        QueuedJobsSnapshot { jobs }
+    }
+
+    /// Enqueue the jobs from a [`QueuedJobsSnapshot`] taken from another dispatcher. Each job is
+    /// treated as if it had just been received from the broker.
+    pub fn requeue_jobs(&mut self, snapshot: QueuedJobsSnapshot) {
+        for (jid, spec) in snapshot.jobs {
+            self.receive_message(Message::Broker(BrokerToWorker::EnqueueJob(jid, spec)));
+        }
+    }
 }
 
 /*  _            _
@@ -739,6 +1481,7 @@ mod tests {
         StartJob(JobId, JobSpec, PathBuf),
         SendMessageToBroker(WorkerToBroker),
         StartArtifactFetch(Sha256Digest, PathBuf),
+        ArtifactSpaceReserved(Sha256Digest, Result<(), String>),
         BuildBottomFsLayer(Sha256Digest, PathBuf, ArtifactType, PathBuf),
         BuildUpperFsLayer(Sha256Digest, PathBuf, PathBuf, PathBuf),
         ReadManifestDigests(Sha256Digest, PathBuf, JobId),
@@ -746,7 +1489,10 @@ mod tests {
         CacheGotArtifactSuccess(cache::EntryKind, Sha256Digest, u64),
         CacheGotArtifactFailure(cache::EntryKind, Sha256Digest),
         CacheDecrementRefCount(cache::EntryKind, Sha256Digest),
+        CacheGotExternalOutput(Sha256Digest, u64),
+        CacheRemoveExpired,
         JobHandleDropped(JobId),
+        JobTtyResized(JobId, WindowSize),
         StartTimer(JobId, Duration),
         TimerHandleDropped(JobId),
     }
@@ -755,9 +1501,15 @@ mod tests {
 
     struct TestState {
         messages: Vec<TestMessage>,
-        get_artifact_returns: HashMap<cache::Key, GetArtifact>,
+        /// What each call to `get_artifact` returns, in order. A key may be listed more than once if
+        /// the test expects it to be asked for more than once.
+        get_artifact_returns: HashMap<cache::Key, VecDeque<GetArtifact>>,
         got_artifact_success_returns: HashMap<cache::Key, (PathBuf, Vec<JobId>)>,
         got_artifact_failure_returns: HashMap<cache::Key, Vec<JobId>>,
+        cache_config_snapshot: cache::CacheConfigSnapshot,
+        cache_full: Option<cache::CacheFull>,
+        cache_bytes_used: HashMap<cache::Key, u64>,
+        cache_reservations: Vec<u64>,
         closed: bool,
     }
 
@@ -778,6 +1530,15 @@ mod tests {
             TestHandle(TestMessage::JobHandleDropped(jid), self.clone())
         }
 
+        fn resize_job_tty(&mut self, handle: &Self::JobHandle, window_size: WindowSize) {
+            let JobHandleDropped(jid) = handle.0 else {
+                panic!("unexpected job handle {:?}", handle.0);
+            };
+            self.borrow_mut()
+                .messages
+                .push(JobTtyResized(jid, window_size));
+        }
+
         type TimerHandle = TestHandle;
 
         fn start_timer(&mut self, jid: JobId, duration: Duration) -> Self::TimerHandle {
@@ -828,6 +1589,17 @@ mod tests {
                 .messages
                 .push(StartArtifactFetch(digest, path));
         }
+
+        fn artifact_space_reserved(
+            &mut self,
+            digest: Sha256Digest,
+            result: Result<(), cache::CacheFull>,
+        ) {
+            self.borrow_mut().messages.push(ArtifactSpaceReserved(
+                digest,
+                result.map_err(|err| err.to_string()),
+            ));
+        }
     }
 
     impl BrokerSender for Rc<RefCell<TestState>> {
@@ -856,7 +1628,8 @@ mod tests {
                 .push(CacheGetArtifact(kind, digest.clone(), jid));
             self.borrow_mut()
                 .get_artifact_returns
-                .remove(&cache::Key::new(kind, digest.clone()))
+                .get_mut(&cache::Key::new(kind, digest.clone()))
+                .and_then(VecDeque::pop_front)
                 .unwrap_or_else(|| panic!("unexpected get_artifact of {kind:?} {digest}"))
         }
 
@@ -896,6 +1669,32 @@ mod tests {
                 .messages
                 .push(CacheDecrementRefCount(kind, digest.clone()))
         }
+
+        fn got_external_output(&mut self, digest: &Sha256Digest, bytes_used: u64) {
+            self.borrow_mut()
+                .messages
+                .push(CacheGotExternalOutput(digest.clone(), bytes_used))
+        }
+
+        fn reserve(&mut self, bytes: u64) -> Result<(), cache::CacheFull> {
+            self.borrow_mut().cache_reservations.push(bytes);
+            self.borrow().cache_full.clone().map_or(Ok(()), Err)
+        }
+
+        fn bytes_used(&self, kind: cache::EntryKind, digest: &Sha256Digest) -> Option<u64> {
+            self.borrow()
+                .cache_bytes_used
+                .get(&cache::Key::new(kind, digest.clone()))
+                .copied()
+        }
+
+        fn config_snapshot(&self) -> cache::CacheConfigSnapshot {
+            self.borrow().cache_config_snapshot.clone()
+        }
+
+        fn remove_expired(&mut self) {
+            self.borrow_mut().messages.push(CacheRemoveExpired)
+        }
     }
 
     struct Fixture {
@@ -915,11 +1714,38 @@ mod tests {
             got_artifact_success_returns: [(cache::Key, (PathBuf, Vec<JobId>)); M],
             got_artifact_failure_returns: [(cache::Key, Vec<JobId>); N],
         ) -> Self {
+            Self::with_scheduling_policy(
+                SchedulingPolicy::default(),
+                slots,
+                get_artifact_returns,
+                got_artifact_success_returns,
+                got_artifact_failure_returns,
+            )
+        }
+
+        fn with_scheduling_policy<const L: usize, const M: usize, const N: usize>(
+            scheduling_policy: SchedulingPolicy,
+            slots: u16,
+            get_artifact_returns: [(cache::Key, GetArtifact); L],
+            got_artifact_success_returns: [(cache::Key, (PathBuf, Vec<JobId>)); M],
+            got_artifact_failure_returns: [(cache::Key, Vec<JobId>); N],
+        ) -> Self {
+            let mut get_artifact_returns_by_key = HashMap::<_, VecDeque<_>>::new();
+            for (key, get_artifact_return) in get_artifact_returns {
+                get_artifact_returns_by_key
+                    .entry(key)
+                    .or_default()
+                    .push_back(get_artifact_return);
+            }
             let test_state = Rc::new(RefCell::new(TestState {
                 messages: Vec::default(),
-                get_artifact_returns: HashMap::from(get_artifact_returns),
+                get_artifact_returns: get_artifact_returns_by_key,
                 got_artifact_success_returns: HashMap::from(got_artifact_success_returns),
                 got_artifact_failure_returns: HashMap::from(got_artifact_failure_returns),
+                cache_config_snapshot: cache::CacheConfigSnapshot::default(),
+                cache_full: None,
+                cache_bytes_used: HashMap::default(),
+                cache_reservations: vec![],
                 closed: false,
             }));
             let dispatcher = Dispatcher::new(
@@ -928,6 +1754,7 @@ mod tests {
                 test_state.clone(),
                 test_state.clone(),
                 Slots::try_from(slots).unwrap(),
+                scheduling_policy,
             );
             Fixture {
                 test_state,
@@ -935,6 +1762,39 @@ mod tests {
             }
         }
 
+        fn max_system_error_retries(mut self, max_system_error_retries: u32) -> Self {
+            self.dispatcher = self
+                .dispatcher
+                .max_system_error_retries(max_system_error_retries);
+            self
+        }
+
+        fn default_estimated_duration(mut self, default_estimated_duration: Duration) -> Self {
+            self.dispatcher = self
+                .dispatcher
+                .default_estimated_duration(default_estimated_duration);
+            self
+        }
+
+        fn max_outstanding_fetches(mut self, max_outstanding_fetches: usize) -> Self {
+            self.dispatcher = self
+                .dispatcher
+                .max_outstanding_fetches(NonZeroUsize::new(max_outstanding_fetches).unwrap());
+            self
+        }
+
+        fn max_outstanding_layer_builds(mut self, max_outstanding_layer_builds: usize) -> Self {
+            self.dispatcher = self.dispatcher.max_outstanding_layer_builds(
+                NonZeroUsize::new(max_outstanding_layer_builds).unwrap(),
+            );
+            self
+        }
+
+        fn cache_full(self, cache_full: cache::CacheFull) -> Self {
+            self.test_state.borrow_mut().cache_full = Some(cache_full);
+            self
+        }
+
         fn expect_messages_in_any_order(&mut self, mut expected: Vec<TestMessage>) {
             expected.sort();
             let messages = &mut self.test_state.borrow_mut().messages;
@@ -1009,6 +1869,14 @@ mod tests {
         };
     }
 
+    script_test! {
+        remove_expired_cache_entries,
+        Fixture::new(1, [], [], []),
+        RemoveExpiredCacheEntries => {
+            CacheRemoveExpired,
+        };
+    }
+
     script_test! {
         enqueue_mixed_artifacts_no_error_slots_available,
         Fixture::new(1, [
@@ -1038,11 +1906,15 @@ mod tests {
             (cache_key!(Blob, 3), GetArtifact::Success(path_buf!("/c"))),
             (cache_key!(Blob, 4), GetArtifact::Success(path_buf!("/d"))),
             (cache_key!(Blob, 5), GetArtifact::Success(path_buf!("/e"))),
+            (cache_key!(Blob, 6), GetArtifact::Success(path_buf!("/f"))),
+            (cache_key!(Blob, 7), GetArtifact::Success(path_buf!("/g"))),
             (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
             (cache_key!(BottomFsLayer, 2), GetArtifact::Success(path_buf!("/b"))),
             (cache_key!(BottomFsLayer, 3), GetArtifact::Success(path_buf!("/c"))),
             (cache_key!(BottomFsLayer, 4), GetArtifact::Success(path_buf!("/d"))),
             (cache_key!(BottomFsLayer, 5), GetArtifact::Success(path_buf!("/e"))),
+            (cache_key!(BottomFsLayer, 6), GetArtifact::Success(path_buf!("/f"))),
+            (cache_key!(BottomFsLayer, 7), GetArtifact::Success(path_buf!("/g"))),
         ], [], []),
         Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
             CacheGetArtifact(Blob, digest!(1), jid!(1)),
@@ -1093,28 +1965,189 @@ mod tests {
             CacheDecrementRefCount(BottomFsLayer, digest!(5)),
             StartJob(jid!(3), spec!(3, Tar).estimated_duration(Some(millis!(10))), path_buf!("/c")),
         };
-    }
 
-    script_test! {
-        cancel_awaiting_layers,
-        Fixture::new(1, [
-            (cache_key!(Blob, 41), GetArtifact::Success(path_buf!("/a"))),
-            (cache_key!(BottomFsLayer, 41), GetArtifact::Success(path_buf!("/a"))),
-            (cache_key!(Blob, 42), GetArtifact::Wait),
-        ], [], []),
-        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]))) => {
-            CacheGetArtifact(Blob, digest!(41), jid!(1)),
-            CacheGetArtifact(Blob, digest!(42), jid!(1)),
-            CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
+        // A higher priority job starts before a longer job, even though it was enqueued later.
+        Broker(EnqueueJob(jid!(6), spec!(6, Tar).estimated_duration(Some(millis!(100))))) => {
+            CacheGetArtifact(Blob, digest!(6), jid!(6)),
+            CacheGetArtifact(BottomFsLayer, digest!(6), jid!(6)),
         };
-        Broker(CancelJob(jid!(1))) => {
-            CacheDecrementRefCount(BottomFsLayer, digest!(41)),
-            CacheDecrementRefCount(Blob, digest!(41)),
+        Broker(EnqueueJob(jid!(7), spec!(7, Tar).estimated_duration(Some(millis!(1))).priority(1))) => {
+            CacheGetArtifact(Blob, digest!(7), jid!(7)),
+            CacheGetArtifact(BottomFsLayer, digest!(7), jid!(7)),
+        };
+
+        Broker(CancelJob(jid!(4))) => {
+            JobHandleDropped(jid!(4)),
+        };
+        Message::JobCompleted(jid!(4), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(4)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(4)),
+            StartJob(jid!(7), spec!(7, Tar).estimated_duration(Some(millis!(1))).priority(1), path_buf!("/g")),
+        };
+
+        Broker(CancelJob(jid!(3))) => {
+            JobHandleDropped(jid!(3)),
+        };
+        Message::JobCompleted(jid!(3), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(3)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(3)),
+            StartJob(jid!(6), spec!(6, Tar).estimated_duration(Some(millis!(100))), path_buf!("/f")),
         };
     }
 
     script_test! {
-        cancel_executing,
+        fair_across_clients_alternates_between_clients,
+        Fixture::with_scheduling_policy(SchedulingPolicy::FairAcrossClients, 1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/b"))),
+            (cache_key!(Blob, 3), GetArtifact::Success(path_buf!("/c"))),
+            (cache_key!(Blob, 4), GetArtifact::Success(path_buf!("/d"))),
+            (cache_key!(Blob, 5), GetArtifact::Success(path_buf!("/e"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 2), GetArtifact::Success(path_buf!("/b"))),
+            (cache_key!(BottomFsLayer, 3), GetArtifact::Success(path_buf!("/c"))),
+            (cache_key!(BottomFsLayer, 4), GetArtifact::Success(path_buf!("/d"))),
+            (cache_key!(BottomFsLayer, 5), GetArtifact::Success(path_buf!("/e"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1, 1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1, 1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1, 1)),
+            StartJob(jid!(1, 1), spec!(1, Tar), path_buf!("/a")),
+        };
+
+        // Client 1's jobs are longer, so strict LPT would start both of them before client 2's.
+        Broker(EnqueueJob(jid!(1, 2), spec!(2, Tar).estimated_duration(Some(millis!(100))))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(1, 2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(1, 2)),
+        };
+        Broker(EnqueueJob(jid!(1, 3), spec!(3, Tar).estimated_duration(Some(millis!(100))))) => {
+            CacheGetArtifact(Blob, digest!(3), jid!(1, 3)),
+            CacheGetArtifact(BottomFsLayer, digest!(3), jid!(1, 3)),
+        };
+        Broker(EnqueueJob(jid!(2, 4), spec!(4, Tar))) => {
+            CacheGetArtifact(Blob, digest!(4), jid!(2, 4)),
+            CacheGetArtifact(BottomFsLayer, digest!(4), jid!(2, 4)),
+        };
+        Broker(EnqueueJob(jid!(2, 5), spec!(5, Tar))) => {
+            CacheGetArtifact(Blob, digest!(5), jid!(2, 5)),
+            CacheGetArtifact(BottomFsLayer, digest!(5), jid!(2, 5)),
+        };
+
+        Broker(CancelJob(jid!(1, 1))) => {
+            JobHandleDropped(jid!(1, 1)),
+        };
+        Message::JobCompleted(jid!(1, 1), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            StartJob(jid!(1, 2), spec!(2, Tar).estimated_duration(Some(millis!(100))), path_buf!("/b")),
+        };
+
+        Broker(CancelJob(jid!(1, 2))) => {
+            JobHandleDropped(jid!(1, 2)),
+        };
+        Message::JobCompleted(jid!(1, 2), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(2)),
+            StartJob(jid!(2, 4), spec!(4, Tar), path_buf!("/d")),
+        };
+
+        Broker(CancelJob(jid!(2, 4))) => {
+            JobHandleDropped(jid!(2, 4)),
+        };
+        Message::JobCompleted(jid!(2, 4), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(4)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(4)),
+            StartJob(jid!(1, 3), spec!(3, Tar).estimated_duration(Some(millis!(100))), path_buf!("/c")),
+        };
+
+        Broker(CancelJob(jid!(1, 3))) => {
+            JobHandleDropped(jid!(1, 3)),
+        };
+        Message::JobCompleted(jid!(1, 3), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(3)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(3)),
+            StartJob(jid!(2, 5), spec!(5, Tar), path_buf!("/e")),
+        };
+    }
+
+    script_test! {
+        equal_estimated_durations_start_in_job_id_order,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/b"))),
+            (cache_key!(Blob, 3), GetArtifact::Success(path_buf!("/c"))),
+            (cache_key!(Blob, 4), GetArtifact::Success(path_buf!("/d"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 2), GetArtifact::Success(path_buf!("/b"))),
+            (cache_key!(BottomFsLayer, 3), GetArtifact::Success(path_buf!("/c"))),
+            (cache_key!(BottomFsLayer, 4), GetArtifact::Success(path_buf!("/d"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+        };
+        Broker(EnqueueJob(jid!(4), spec!(4, Tar))) => {
+            CacheGetArtifact(Blob, digest!(4), jid!(4)),
+            CacheGetArtifact(BottomFsLayer, digest!(4), jid!(4)),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+        };
+        Broker(EnqueueJob(jid!(3), spec!(3, Tar))) => {
+            CacheGetArtifact(Blob, digest!(3), jid!(3)),
+            CacheGetArtifact(BottomFsLayer, digest!(3), jid!(3)),
+        };
+
+        Broker(CancelJob(jid!(1))) => {
+            JobHandleDropped(jid!(1)),
+        };
+        Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/b")),
+        };
+
+        Broker(CancelJob(jid!(2))) => {
+            JobHandleDropped(jid!(2)),
+        };
+        Message::JobCompleted(jid!(2), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(2)),
+            StartJob(jid!(3), spec!(3, Tar), path_buf!("/c")),
+        };
+
+        Broker(CancelJob(jid!(3))) => {
+            JobHandleDropped(jid!(3)),
+        };
+        Message::JobCompleted(jid!(3), Ok(completed!(1))) => {
+            CacheDecrementRefCount(Blob, digest!(3)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(3)),
+            StartJob(jid!(4), spec!(4, Tar), path_buf!("/d")),
+        };
+    }
+
+    script_test! {
+        cancel_awaiting_layers,
+        Fixture::new(1, [
+            (cache_key!(Blob, 41), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 41), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 42), GetArtifact::Wait),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]))) => {
+            CacheGetArtifact(Blob, digest!(41), jid!(1)),
+            CacheGetArtifact(Blob, digest!(42), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
+        };
+        Broker(CancelJob(jid!(1))) => {
+            CacheDecrementRefCount(BottomFsLayer, digest!(41)),
+            CacheDecrementRefCount(Blob, digest!(41)),
+        };
+    }
+
+    script_test! {
+        cancel_executing,
         Fixture::new(1, [
             (cache_key!(Blob, 41), GetArtifact::Success(path_buf!("/a"))),
             (cache_key!(Blob, 42), GetArtifact::Success(path_buf!("/b"))),
@@ -1196,7 +2229,7 @@ mod tests {
             CacheDecrementRefCount(UpperFsLayer, upper_digest!(41, 42, 41)),
         };
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
-            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(outcome!(1)))),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             JobHandleDropped(jid!(1)),
@@ -1256,56 +2289,922 @@ mod tests {
         };
     }
 
+    script_test! {
+        resize_executing,
+        Fixture::new(2, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/2"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(BottomFsLayer, 2), GetArtifact::Success(path_buf!("/2"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/1")),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/2")),
+        };
+        Broker(ResizeJobTty(jid!(2), WindowSize::new(40, 100))) => {
+            JobTtyResized(jid!(2), WindowSize::new(40, 100)),
+        };
+        Broker(ResizeJobTty(jid!(1), WindowSize::new(50, 120))) => {
+            JobTtyResized(jid!(1), WindowSize::new(50, 120)),
+        };
+    }
+
+    script_test! {
+        resize_unknown,
+        Fixture::new(1, [], [], []),
+        Broker(ResizeJobTty(jid!(1), WindowSize::new(40, 100))) => {};
+    }
+
+    script_test! {
+        resize_awaiting_layers_or_queued,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/2"))),
+            (cache_key!(Blob, 3), GetArtifact::Wait),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(BottomFsLayer, 2), GetArtifact::Success(path_buf!("/2"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/1")),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+        };
+        Broker(EnqueueJob(jid!(3), spec!(3, Tar))) => {
+            CacheGetArtifact(Blob, digest!(3), jid!(3)),
+        };
+        Broker(ResizeJobTty(jid!(2), WindowSize::new(40, 100))) => {};
+        Broker(ResizeJobTty(jid!(3), WindowSize::new(40, 100))) => {};
+    }
+
+    script_test! {
+        resize_completed,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/1")),
+        };
+        Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        };
+        Broker(ResizeJobTty(jid!(1), WindowSize::new(40, 100))) => {};
+    }
+
+    script_test! {
+        completed_with_external_output,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/1")),
+        };
+        Message::JobCompleted(jid!(1), Ok(base::JobCompleted {
+            status: JobStatus::Exited(0),
+            effects: JobEffects {
+                stdout: JobOutputResult::External {
+                    first: boxed_u8!(b"a"),
+                    digest: digest!(10),
+                    size: 10000,
+                },
+                stderr: JobOutputResult::External {
+                    first: boxed_u8!(b"a"),
+                    digest: digest!(11),
+                    size: 20000,
+                },
+                duration: std::time::Duration::from_secs(1),
+                worker: None,
+            }
+        })) => {
+            CacheGotExternalOutput(digest!(10), 10000),
+            CacheGotExternalOutput(digest!(11), 20000),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(JobOutcome::Completed(base::JobCompleted {
+                status: JobStatus::Exited(0),
+                effects: JobEffects {
+                    stdout: JobOutputResult::External {
+                    first: boxed_u8!(b"a"),
+                    digest: digest!(10),
+                    size: 10000,
+                },
+                    stderr: JobOutputResult::External {
+                    first: boxed_u8!(b"a"),
+                    digest: digest!(11),
+                    size: 20000,
+                },
+                    duration: std::time::Duration::from_secs(1),
+                    worker: None,
+                }
+            })) }),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        };
+    }
+
+    script_test! {
+        resize_canceled,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/1")),
+        };
+        Broker(CancelJob(jid!(1))) => { JobHandleDropped(jid!(1)) };
+        Broker(ResizeJobTty(jid!(1), WindowSize::new(40, 100))) => {};
+    }
+
+    script_test! {
+        resize_timed_out,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar).timeout(timeout!(1)), path_buf!("/1")),
+            StartTimer(jid!(1), Duration::from_secs(1))
+        };
+        JobTimer(jid!(1)) => {
+            JobHandleDropped(jid!(1)),
+            TimerHandleDropped(jid!(1)),
+        };
+        Broker(ResizeJobTty(jid!(1), WindowSize::new(40, 100))) => {};
+    }
+
+    script_test! {
+        sub_second_timeout,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/1"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar).timeout(base::Timeout::from_millis(500)))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(
+                jid!(1),
+                spec!(1, Tar).timeout(base::Timeout::from_millis(500)),
+                path_buf!("/1")
+            ),
+            StartTimer(jid!(1), Duration::from_millis(500))
+        };
+    }
+
+    fn full_cache() -> cache::CacheFull {
+        cache::CacheFull {
+            bytes_requested: 0,
+            bytes_in_use: 2000,
+            bytes_used_target: 1000,
+        }
+    }
+
+    #[test]
+    fn artifact_fetch_waits_for_cache_space() {
+        let mut fixture = Fixture::new(
+            2,
+            [
+                (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+                (
+                    cache_key!(BottomFsLayer, 1),
+                    GetArtifact::Success(path_buf!("/a")),
+                ),
+                (cache_key!(Blob, 41), GetArtifact::Get(path_buf!("/b"))),
+            ],
+            [],
+            [],
+        )
+        .cache_full(full_cache());
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+        ]);
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(2), spec!(2, [(41, Tar)]))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(41), jid!(2)),
+            StartArtifactFetch(digest!(41), path_buf!("/b")),
+        ]);
+
+        // Job 1 is still running, so it may release space. The fetch waits for it.
+        fixture
+            .dispatcher
+            .receive_message(ReserveArtifactSpace(digest!(41), 500));
+        fixture.expect_messages_in_any_order(vec![]);
+        assert_eq!(
+            fixture
+                .dispatcher
+                .debug_snapshot()
+                .artifact_fetches_awaiting_cache_space,
+            1
+        );
+
+        fixture.test_state.borrow_mut().cache_full = None;
+        fixture
+            .dispatcher
+            .receive_message(Message::JobCompleted(jid!(1), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            SendMessageToBroker(WorkerToBroker::JobResponse {
+                jid: jid!(1),
+                result: Ok(outcome!(1)),
+            }),
+            JobHandleDropped(jid!(1)),
+            ArtifactSpaceReserved(digest!(41), Ok(())),
+        ]);
+        assert_eq!(
+            fixture.test_state.borrow().cache_reservations,
+            vec![500, 500]
+        );
+    }
+
+    #[test]
+    fn artifact_fetch_fails_when_nothing_can_release_cache_space() {
+        let mut fixture = Fixture::new(
+            1,
+            [(cache_key!(Blob, 41), GetArtifact::Get(path_buf!("/a")))],
+            [],
+            [(cache_key!(Blob, 41), vec![jid!(1)])],
+        )
+        .cache_full(full_cache());
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar)]))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(41), jid!(1)),
+            StartArtifactFetch(digest!(41), path_buf!("/a")),
+        ]);
+
+        let err = cache::CacheFull {
+            bytes_requested: 500,
+            ..full_cache()
+        };
+        fixture
+            .dispatcher
+            .receive_message(ReserveArtifactSpace(digest!(41), 500));
+        fixture.expect_messages_in_any_order(vec![ArtifactSpaceReserved(
+            digest!(41),
+            Err(err.to_string()),
+        )]);
+
+        fixture
+            .dispatcher
+            .receive_message(ArtifactFetchCompleted(digest!(41), Err(err.into())));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGotArtifactFailure(Blob, digest!(41)),
+            SendMessageToBroker(WorkerToBroker::JobResponse {
+                jid: jid!(1),
+                result: Err(JobError::System(format!(
+                    "Failed to download and extract layer artifact {}: cache full: 500 B \
+                    requested, but 2.0 KB of 1.0 KB is in use",
+                    digest!(41)
+                ))),
+            }),
+        ]);
+    }
+
+    #[test]
+    fn artifact_fetch_fails_when_bigger_than_cache() {
+        let mut fixture = Fixture::new(
+            1,
+            [
+                (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+                (
+                    cache_key!(BottomFsLayer, 1),
+                    GetArtifact::Success(path_buf!("/a")),
+                ),
+                (cache_key!(Blob, 41), GetArtifact::Get(path_buf!("/b"))),
+            ],
+            [],
+            [],
+        )
+        .cache_full(full_cache());
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(2), spec!(2, [(41, Tar)]))));
+        fixture.test_state.borrow_mut().messages.clear();
+
+        // Job 1 is still running, but it could never release enough space.
+        fixture
+            .dispatcher
+            .receive_message(ReserveArtifactSpace(digest!(41), 1001));
+        fixture.expect_messages_in_any_order(vec![ArtifactSpaceReserved(
+            digest!(41),
+            Err(cache::CacheFull {
+                bytes_requested: 1001,
+                ..full_cache()
+            }
+            .to_string()),
+        )]);
+    }
+
+    #[test]
+    fn artifact_fetch_reservations_count_against_cache_target() {
+        let mut fixture = Fixture::new(
+            3,
+            [
+                (cache_key!(Blob, 41), GetArtifact::Get(path_buf!("/a"))),
+                (cache_key!(Blob, 42), GetArtifact::Get(path_buf!("/b"))),
+                (cache_key!(Blob, 43), GetArtifact::Get(path_buf!("/c"))),
+                (cache_key!(BottomFsLayer, 41), GetArtifact::Wait),
+            ],
+            [(cache_key!(Blob, 41), (path_buf!("/a"), vec![jid!(1)]))],
+            [],
+        );
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar)]))));
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(2), spec!(2, [(42, Tar)]))));
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(3), spec!(3, [(43, Tar)]))));
+        fixture.test_state.borrow_mut().messages.clear();
+
+        fixture
+            .dispatcher
+            .receive_message(ReserveArtifactSpace(digest!(41), 300));
+        fixture
+            .dispatcher
+            .receive_message(ReserveArtifactSpace(digest!(42), 200));
+        fixture
+            .dispatcher
+            .receive_message(ArtifactFetchCompleted(digest!(41), Ok(300)));
+        fixture
+            .dispatcher
+            .receive_message(ReserveArtifactSpace(digest!(43), 100));
+        assert_eq!(
+            fixture.test_state.borrow().cache_reservations,
+            vec![300, 500, 300]
+        );
+    }
+
+    #[test]
+    fn bottom_fs_layer_build_fails_when_nothing_can_release_cache_space() {
+        let mut fixture = Fixture::new(
+            1,
+            [
+                (cache_key!(Blob, 41), GetArtifact::Success(path_buf!("/a"))),
+                (
+                    cache_key!(BottomFsLayer, 41),
+                    GetArtifact::Get(path_buf!("/b")),
+                ),
+            ],
+            [],
+            [(cache_key!(BottomFsLayer, 41), vec![jid!(1)])],
+        )
+        .cache_full(full_cache());
+        fixture
+            .test_state
+            .borrow_mut()
+            .cache_bytes_used
+            .insert(cache_key!(Blob, 41), 500);
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar)]))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(41), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
+            CacheGotArtifactFailure(BottomFsLayer, digest!(41)),
+            SendMessageToBroker(WorkerToBroker::JobResponse {
+                jid: jid!(1),
+                result: Err(JobError::System(format!(
+                    "Failed to build bottom FS layer {}: cache full: 500 B requested, but 2.0 \
+                    KB of 1.0 KB is in use",
+                    digest!(41)
+                ))),
+            }),
+            CacheDecrementRefCount(Blob, digest!(41)),
+        ]);
+    }
+
+    #[test]
+    fn bottom_fs_layer_build_reserves_size_of_artifact() {
+        let mut fixture = Fixture::new(
+            1,
+            [
+                (cache_key!(Blob, 41), GetArtifact::Success(path_buf!("/a"))),
+                (
+                    cache_key!(BottomFsLayer, 41),
+                    GetArtifact::Get(path_buf!("/b")),
+                ),
+            ],
+            [],
+            [],
+        );
+        fixture
+            .test_state
+            .borrow_mut()
+            .cache_bytes_used
+            .insert(cache_key!(Blob, 41), 1234);
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar)]))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(41), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
+            BuildBottomFsLayer(
+                digest!(41),
+                path_buf!("/b"),
+                ArtifactType::Tar,
+                path_buf!("/a"),
+            ),
+        ]);
+        assert_eq!(fixture.test_state.borrow().cache_reservations, vec![1234]);
+    }
+
+    script_test! {
+        manifest_reads_start_before_artifact_fetches_when_fetches_are_limited,
+        Fixture::new(1, [
+            (cache_key!(Blob, 41), GetArtifact::Get(path_buf!("/a"))),
+            (cache_key!(Blob, 42), GetArtifact::Get(path_buf!("/b"))),
+            (cache_key!(Blob, 43), GetArtifact::Success(path_buf!("/c"))),
+            (cache_key!(BottomFsLayer, 41), GetArtifact::Get(path_buf!("/d"))),
+            (cache_key!(BottomFsLayer, 43), GetArtifact::Get(path_buf!("/e"))),
+        ], [
+            (cache_key!(Blob, 41), (path_buf!("/a"), vec![jid!(1)])),
+        ], []).max_outstanding_fetches(1),
+        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar)]))) => {
+            CacheGetArtifact(Blob, digest!(41), jid!(1)),
+            StartArtifactFetch(digest!(41), path_buf!("/a")),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, [(42, Tar)]))) => {
+            CacheGetArtifact(Blob, digest!(42), jid!(2)),
+        };
+        Broker(EnqueueJob(jid!(3), spec!(3, [(43, Manifest)]))) => {
+            CacheGetArtifact(Blob, digest!(43), jid!(3)),
+        };
+        ArtifactFetchCompleted(digest!(41), Ok(100)) => {
+            CacheGotArtifactSuccess(Blob, digest!(41), 100),
+            CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
+            BuildBottomFsLayer(
+                digest!(41), path_buf!("/d"), ArtifactType::Tar, path_buf!("/a")
+            ),
+            TestMessage::ReadManifestDigests(digest!(43), path_buf!("/c"), jid!(3)),
+        };
+        Message::ReadManifestDigests(digest!(43), jid!(3), Ok(HashSet::default())) => {
+            CacheGetArtifact(BottomFsLayer, digest!(43), jid!(3)),
+            BuildBottomFsLayer(
+                digest!(43), path_buf!("/e"), ArtifactType::Manifest, path_buf!("/c")
+            ),
+            StartArtifactFetch(digest!(42), path_buf!("/b")),
+        };
+    }
+
+    script_test! {
+        layer_builds_beyond_limit_are_queued,
+        Fixture::new(3, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+            (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/2"))),
+            (cache_key!(Blob, 3), GetArtifact::Success(path_buf!("/3"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Get(path_buf!("/b1"))),
+            (cache_key!(BottomFsLayer, 2), GetArtifact::Get(path_buf!("/b2"))),
+            (cache_key!(BottomFsLayer, 3), GetArtifact::Get(path_buf!("/b3"))),
+        ], [
+            (cache_key!(BottomFsLayer, 1), (path_buf!("/b1"), vec![jid!(1)])),
+            (cache_key!(BottomFsLayer, 2), (path_buf!("/b2"), vec![jid!(2)])),
+            (cache_key!(BottomFsLayer, 3), (path_buf!("/b3"), vec![jid!(3)])),
+        ], []).max_outstanding_layer_builds(1),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            BuildBottomFsLayer(digest!(1), path_buf!("/b1"), ArtifactType::Tar, path_buf!("/1")),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+        };
+        Broker(EnqueueJob(jid!(3), spec!(3, Tar))) => {
+            CacheGetArtifact(Blob, digest!(3), jid!(3)),
+            CacheGetArtifact(BottomFsLayer, digest!(3), jid!(3)),
+        };
+        BuiltBottomFsLayer(digest!(1), Ok(100)) => {
+            CacheGotArtifactSuccess(BottomFsLayer, digest!(1), 100),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/b1")),
+            BuildBottomFsLayer(digest!(2), path_buf!("/b2"), ArtifactType::Tar, path_buf!("/2")),
+        };
+        BuiltBottomFsLayer(digest!(2), Ok(100)) => {
+            CacheGotArtifactSuccess(BottomFsLayer, digest!(2), 100),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/b2")),
+            BuildBottomFsLayer(digest!(3), path_buf!("/b3"), ArtifactType::Tar, path_buf!("/3")),
+        };
+        BuiltBottomFsLayer(digest!(3), Ok(100)) => {
+            CacheGotArtifactSuccess(BottomFsLayer, digest!(3), 100),
+            StartJob(jid!(3), spec!(3, Tar), path_buf!("/b3")),
+        };
+    }
+
+    #[test]
+    fn shutdown() {
+        let mut fixture = Fixture::new(
+            1,
+            [
+                (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+                (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/2"))),
+                (
+                    cache_key!(BottomFsLayer, 1),
+                    GetArtifact::Success(path_buf!("/1")),
+                ),
+                (
+                    cache_key!(BottomFsLayer, 2),
+                    GetArtifact::Success(path_buf!("/2")),
+                ),
+            ],
+            [],
+            [],
+        );
+
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/1")),
+        ]);
+
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(2), spec!(2, Tar))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+        ]);
+
+        fixture
+            .dispatcher
+            .receive_message(Shutdown(anyhow!("test error")));
+        fixture.expect_messages_in_any_order(vec![JobHandleDropped(jid!(1))]);
+
+        assert_eq!(fixture.dispatcher.num_executing(), 1);
+        assert!(fixture.test_state.borrow().closed);
+
+        fixture
+            .dispatcher
+            .receive_message(JobCompleted(jid!(1), Ok(completed!(3))));
+
+        assert_eq!(fixture.dispatcher.num_executing(), 0);
+    }
+
+    #[test]
+    fn drain_lets_executing_jobs_finish() {
+        let mut fixture = Fixture::new(
+            1,
+            [
+                (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
+                (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/2"))),
+                (
+                    cache_key!(BottomFsLayer, 1),
+                    GetArtifact::Success(path_buf!("/1")),
+                ),
+                (
+                    cache_key!(BottomFsLayer, 2),
+                    GetArtifact::Success(path_buf!("/2")),
+                ),
+            ],
+            [],
+            [],
+        );
+
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/1")),
+        ]);
+
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(2), spec!(2, Tar))));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+        ]);
+
+        // The job that hasn't started yet is dropped, but the executing job is left alone.
+        fixture.dispatcher.receive_message(Drain);
+        fixture.expect_messages_in_any_order(vec![
+            CacheDecrementRefCount(Blob, digest!(2)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(2)),
+        ]);
+        assert!(fixture.dispatcher.is_draining());
+        assert_eq!(fixture.dispatcher.num_executing(), 1);
+        assert!(!fixture.test_state.borrow().closed);
+
+        // New jobs aren't accepted.
+        fixture
+            .dispatcher
+            .receive_message(Broker(EnqueueJob(jid!(3), spec!(1, Tar))));
+        fixture.expect_messages_in_any_order(vec![]);
+
+        fixture
+            .dispatcher
+            .receive_message(JobCompleted(jid!(1), Ok(completed!(1))));
+        fixture.expect_messages_in_any_order(vec![
+            SendMessageToBroker(WorkerToBroker::JobResponse {
+                jid: jid!(1),
+                result: Ok(outcome!(1)),
+            }),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        ]);
+        assert_eq!(fixture.dispatcher.num_executing(), 0);
+        assert!(!fixture.test_state.borrow().closed);
+    }
+
+    #[test]
+    fn estimated_remaining_work() {
+        let mut fixture = Fixture::new(
+            1,
+            [
+                (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+                (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/b"))),
+                (cache_key!(Blob, 3), GetArtifact::Success(path_buf!("/c"))),
+                (cache_key!(Blob, 4), GetArtifact::Wait),
+                (
+                    cache_key!(BottomFsLayer, 1),
+                    GetArtifact::Success(path_buf!("/a")),
+                ),
+                (
+                    cache_key!(BottomFsLayer, 2),
+                    GetArtifact::Success(path_buf!("/b")),
+                ),
+                (
+                    cache_key!(BottomFsLayer, 3),
+                    GetArtifact::Success(path_buf!("/c")),
+                ),
+            ],
+            [],
+            [],
+        )
+        .default_estimated_duration(millis!(7));
+        assert_eq!(
+            fixture.dispatcher.estimated_remaining_work(),
+            Duration::ZERO
+        );
+
+        for (jid, spec) in [
+            (
+                jid!(1),
+                spec!(1, Tar).estimated_duration(Some(millis!(100))),
+            ),
+            (jid!(2), spec!(2, Tar).estimated_duration(Some(millis!(20)))),
+            (jid!(3), spec!(3, Tar)),
+            (
+                jid!(4),
+                spec!(4, Tar).estimated_duration(Some(millis!(1000))),
+            ),
+        ] {
+            fixture
+                .dispatcher
+                .receive_message(Broker(EnqueueJob(jid, spec)));
+        }
+
+        // Job 1 is executing, jobs 2 and 3 are available, and job 4 is awaiting its layers.
+        assert_eq!(fixture.dispatcher.num_executing(), 1);
+        assert_eq!(fixture.dispatcher.estimated_remaining_work(), millis!(127));
+    }
+
+    #[test]
+    fn config_snapshot_reflects_construction() {
+        let fixture = Fixture::new(3, [], [], []);
+        fixture.test_state.borrow_mut().cache_config_snapshot = cache::CacheConfigSnapshot {
+            bytes_used: 10,
+            bytes_used_target: 1000,
+            removal_grace_period: Duration::from_secs(5),
+        };
+        assert_eq!(
+            fixture.dispatcher.config_snapshot(),
+            WorkerConfigSnapshot {
+                slots: 3,
+                scheduling_policy: SchedulingPolicy::Lpt,
+                max_outstanding_fetches: None,
+                max_outstanding_layer_builds: None,
+                max_system_error_retries: 0,
+                default_estimated_duration: Duration::ZERO,
+                cache: cache::CacheConfigSnapshot {
+                    bytes_used: 10,
+                    bytes_used_target: 1000,
+                    removal_grace_period: Duration::from_secs(5),
+                },
+            }
+        );
+
+        let fixture = Fixture::new(1, [], [], [])
+            .max_outstanding_fetches(2)
+            .max_outstanding_layer_builds(4);
+        let snapshot = fixture.dispatcher.config_snapshot();
+        assert_eq!(snapshot.max_outstanding_fetches, Some(2));
+        assert_eq!(snapshot.max_outstanding_layer_builds, Some(4));
+
+        let fixture =
+            Fixture::with_scheduling_policy(SchedulingPolicy::FairAcrossClients, 1, [], [], []);
+        assert_eq!(
+            fixture.dispatcher.config_snapshot().scheduling_policy,
+            SchedulingPolicy::FairAcrossClients
+        );
+    }
+
+    #[test]
+    fn awaiting_layers_detail_reports_pending_digests() {
+        let mut fixture = Fixture::new(
+            1,
+            [
+                (cache_key!(Blob, 41), GetArtifact::Success(path_buf!("/a"))),
+                (
+                    cache_key!(BottomFsLayer, 41),
+                    GetArtifact::Success(path_buf!("/a")),
+                ),
+                (cache_key!(Blob, 42), GetArtifact::Wait),
+            ],
+            [],
+            [],
+        );
+        assert_eq!(fixture.dispatcher.awaiting_layers_detail(), vec![]);
+
+        fixture.dispatcher.receive_message(Broker(EnqueueJob(
+            jid!(1),
+            spec!(1, [(41, Tar), (42, Tar)]),
+        )));
+        fixture.expect_messages_in_any_order(vec![
+            CacheGetArtifact(Blob, digest!(41), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
+            CacheGetArtifact(Blob, digest!(42), jid!(1)),
+        ]);
+        assert_eq!(
+            fixture.dispatcher.awaiting_layers_detail(),
+            vec![(jid!(1), vec![digest!(42)])]
+        );
+
+        fixture
+            .dispatcher
+            .receive_message(Broker(CancelJob(jid!(1))));
+        assert_eq!(fixture.dispatcher.awaiting_layers_detail(), vec![]);
+    }
+
     #[test]
-    fn shutdown() {
+    fn debug_snapshot_reports_jobs_in_each_state() {
         let mut fixture = Fixture::new(
-            1,
+            2,
             [
-                (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/1"))),
-                (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/2"))),
+                (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+                (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/b"))),
+                (cache_key!(Blob, 3), GetArtifact::Success(path_buf!("/c"))),
+                (cache_key!(Blob, 4), GetArtifact::Success(path_buf!("/d"))),
+                (cache_key!(Blob, 44), GetArtifact::Get(path_buf!("/e"))),
                 (
                     cache_key!(BottomFsLayer, 1),
-                    GetArtifact::Success(path_buf!("/1")),
+                    GetArtifact::Success(path_buf!("/a")),
                 ),
                 (
                     cache_key!(BottomFsLayer, 2),
-                    GetArtifact::Success(path_buf!("/2")),
+                    GetArtifact::Success(path_buf!("/b")),
+                ),
+                (
+                    cache_key!(BottomFsLayer, 3),
+                    GetArtifact::Success(path_buf!("/c")),
                 ),
             ],
             [],
             [],
         );
+        assert_eq!(
+            fixture.dispatcher.debug_snapshot(),
+            DispatcherSnapshot::default()
+        );
 
+        for (jid, spec) in [
+            (jid!(1), spec!(1, Tar)),
+            (jid!(2), spec!(2, Tar)),
+            (jid!(3), spec!(3, Tar)),
+            (jid!(4), spec!(4, [(44, Tar)])),
+        ] {
+            fixture
+                .dispatcher
+                .receive_message(Broker(EnqueueJob(jid, spec)));
+        }
+        fixture.dispatcher.receive_message(JobTimer(jid!(1)));
         fixture
             .dispatcher
-            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
-        fixture.expect_messages_in_any_order(vec![
-            CacheGetArtifact(Blob, digest!(1), jid!(1)),
-            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
-            StartJob(jid!(1), spec!(1, Tar), path_buf!("/1")),
-        ]);
+            .receive_message(Broker(CancelJob(jid!(2))));
+
+        assert_eq!(
+            fixture.dispatcher.debug_snapshot(),
+            DispatcherSnapshot {
+                awaiting_layers: vec![(jid!(4), vec![digest!(44)])],
+                available: vec![jid!(3)],
+                executing: vec![
+                    (jid!(1), ExecutingJobStatus::TimedOut),
+                    (jid!(2), ExecutingJobStatus::Canceled),
+                ],
+                outstanding_fetches: 1,
+                queued_manifest_reads: 0,
+                queued_artifact_fetches: 0,
+                artifact_fetches_awaiting_cache_space: 0,
+                outstanding_layer_builds: 0,
+                queued_layer_builds: 0,
+            }
+        );
+    }
 
-        fixture
-            .dispatcher
-            .receive_message(Broker(EnqueueJob(jid!(2), spec!(2, Tar))));
-        fixture.expect_messages_in_any_order(vec![
+    #[test]
+    fn queued_jobs_snapshot_can_be_requeued_in_another_dispatcher() {
+        let fixture = || {
+            Fixture::new(
+                1,
+                [
+                    (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+                    (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/b"))),
+                    (cache_key!(Blob, 3), GetArtifact::Get(path_buf!("/c"))),
+                    (
+                        cache_key!(BottomFsLayer, 1),
+                        GetArtifact::Success(path_buf!("/a")),
+                    ),
+                    (
+                        cache_key!(BottomFsLayer, 2),
+                        GetArtifact::Success(path_buf!("/b")),
+                    ),
+                ],
+                [],
+                [],
+            )
+        };
+
+        let mut old = fixture();
+        for (jid, spec) in [
+            (jid!(1), spec!(1, Tar)),
+            (jid!(2), spec!(2, Tar)),
+            (jid!(3), spec!(3, Tar)),
+        ] {
+            old.dispatcher
+                .receive_message(Broker(EnqueueJob(jid, spec)));
+        }
+        let snapshot = old.dispatcher.queued_jobs_snapshot();
+        assert_eq!(
+            snapshot,
+            QueuedJobsSnapshot {
+                jobs: vec![(jid!(2), spec!(2, Tar)), (jid!(3), spec!(3, Tar))],
+            }
+        );
+
+        let mut new = fixture();
+        new.dispatcher.requeue_jobs(snapshot);
+        new.expect_messages_in_any_order(vec![
             CacheGetArtifact(Blob, digest!(2), jid!(2)),
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/b")),
+            CacheGetArtifact(Blob, digest!(3), jid!(3)),
+            StartArtifactFetch(digest!(3), path_buf!("/c")),
         ]);
-
-        fixture
-            .dispatcher
-            .receive_message(Shutdown(anyhow!("test error")));
-        fixture.expect_messages_in_any_order(vec![JobHandleDropped(jid!(1))]);
-
-        assert_eq!(fixture.dispatcher.num_executing(), 1);
-        assert!(fixture.test_state.borrow().closed);
-
-        fixture
-            .dispatcher
-            .receive_message(JobCompleted(jid!(1), Ok(completed!(3))));
-
-        assert_eq!(fixture.dispatcher.num_executing(), 0);
+        assert_eq!(
+            new.dispatcher.debug_snapshot(),
+            DispatcherSnapshot {
+                awaiting_layers: vec![(jid!(3), vec![digest!(3)])],
+                available: vec![],
+                executing: vec![(jid!(2), ExecutingJobStatus::Nominal)],
+                outstanding_fetches: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            new.dispatcher.queued_jobs_snapshot(),
+            QueuedJobsSnapshot {
+                jobs: vec![(jid!(3), spec!(3, Tar))],
+            }
+        );
     }
 
     script_test! {
@@ -1326,7 +3225,7 @@ mod tests {
             CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
         };
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
-            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(outcome!(1)))),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             JobHandleDropped(jid!(1)),
@@ -1334,6 +3233,93 @@ mod tests {
         };
     }
 
+    script_test! {
+        system_error_is_retried_until_job_succeeds,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+        ], [], []).max_system_error_retries(2),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+        };
+        Message::JobCompleted(jid!(1), Err(JobError::System(string!("system error")))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        };
+        Message::JobCompleted(jid!(1), Err(JobError::System(string!("system error")))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        };
+        Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        };
+    }
+
+    script_test! {
+        system_error_is_reported_once_retries_are_exhausted,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+        ], [], []).max_system_error_retries(1),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+        };
+        Message::JobCompleted(jid!(1), Err(JobError::System(string!("system error")))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        };
+        Message::JobCompleted(jid!(1), Err(JobError::System(string!("system error")))) => {
+            SendMessageToBroker(WorkerToBroker::system_error(jid!(1), "system error")),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        };
+    }
+
+    script_test! {
+        execution_error_is_never_retried,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+        ], [], []).max_system_error_retries(2),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+        };
+        Message::JobCompleted(jid!(1), Err(JobError::Execution(string!("execution error")))) => {
+            SendMessageToBroker(WorkerToBroker::execution_error(jid!(1), "execution error")),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+        };
+    }
+
     script_test! {
         receive_error_job_completed_executing,
         Fixture::new(1, [
@@ -1358,16 +3344,14 @@ mod tests {
             CacheGetArtifact(BottomFsLayer, digest!(3), jid!(3)),
         };
         Message::JobCompleted(jid!(1), Err(JobError::System(string!("system error")))) => {
-            SendMessageToBroker(WorkerToBroker(
-                jid!(1), Err(JobError::System(string!("system error"))))),
+            SendMessageToBroker(WorkerToBroker::system_error(jid!(1), "system error")),
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             JobHandleDropped(jid!(1)),
             StartJob(jid!(2), spec!(2, Tar), path_buf!("/b")),
         };
         Message::JobCompleted(jid!(2), Err(JobError::Execution(string!("execution error")))) => {
-            SendMessageToBroker(WorkerToBroker(
-                jid!(2), Err(JobError::Execution(string!("execution error"))))),
+            SendMessageToBroker(WorkerToBroker::execution_error(jid!(2), "execution error")),
             CacheDecrementRefCount(Blob, digest!(2)),
             CacheDecrementRefCount(BottomFsLayer, digest!(2)),
             JobHandleDropped(jid!(2)),
@@ -1375,6 +3359,58 @@ mod tests {
         };
     }
 
+    script_test! {
+        job_progress_executing,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+        };
+        JobProgress(jid!(1), 42, Some(100)) => {
+            SendMessageToBroker(WorkerToBroker::JobProgress(jid!(1), 42, Some(100))),
+        };
+        JobProgress(jid!(1), 43, None) => {
+            SendMessageToBroker(WorkerToBroker::JobProgress(jid!(1), 43, None)),
+        };
+    }
+
+    script_test! {
+        job_progress_unknown_or_completed_dropped,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/b"))),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 2), GetArtifact::Success(path_buf!("/b"))),
+        ], [], []),
+        JobProgress(jid!(1), 1, None) => {};
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+        };
+        JobProgress(jid!(2), 1, None) => {};
+        Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/b")),
+        };
+        JobProgress(jid!(1), 2, None) => {};
+        Broker(CancelJob(jid!(2))) => {
+            JobHandleDropped(jid!(2)),
+        };
+        JobProgress(jid!(2), 2, None) => {};
+    }
+
     script_test! {
         receive_job_completed_canceled,
         Fixture::new(1, [
@@ -1426,7 +3462,7 @@ mod tests {
             StartTimer(jid!(1), Duration::from_secs(33)),
         };
         Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
-            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(outcome!(1)))),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             TimerHandleDropped(jid!(1)),
@@ -1480,15 +3516,17 @@ mod tests {
                 stdout: JobOutputResult::Inline(boxed_u8!(b"stdout")),
                 stderr: JobOutputResult::Inline(boxed_u8!(b"stderr")),
                 duration: std::time::Duration::from_secs(1),
+                worker: None,
             }
         })) => {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
-            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(JobOutcome::TimedOut(JobEffects {
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(JobOutcome::TimedOut(JobEffects {
                 stdout: JobOutputResult::Inline(boxed_u8!(b"stdout")),
                 stderr: JobOutputResult::Inline(boxed_u8!(b"stderr")),
                 duration: std::time::Duration::from_secs(1),
-            })))),
+                worker: None,
+            })) }),
             StartJob(jid!(2), spec!(2, Tar), path_buf!("/2")),
         };
     }
@@ -1515,7 +3553,7 @@ mod tests {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             TimerHandleDropped(jid!(1)),
-            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(outcome!(1)))),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
             JobHandleDropped(jid!(1)),
             StartJob(jid!(2), spec!(2, Tar), path_buf!("/2")),
         };
@@ -1580,8 +3618,8 @@ mod tests {
         };
         ArtifactFetchCompleted(digest!(42), Err(anyhow!("foo"))) => {
             CacheGotArtifactFailure(Blob, digest!(42)),
-            SendMessageToBroker(WorkerToBroker(jid!(1), Err(JobError::System(
-                string!("Failed to download and extract layer artifact 000000000000000000000000000000000000000000000000000000000000002a: foo"))))),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Err(JobError::System(
+                string!("Failed to download and extract layer artifact 000000000000000000000000000000000000000000000000000000000000002a: foo"))) }),
             CacheDecrementRefCount(Blob, digest!(41))
         };
         ArtifactFetchCompleted(digest!(43), Ok(103)) => {
@@ -1593,32 +3631,67 @@ mod tests {
         };
     }
 
-    #[test]
-    #[should_panic(expected = "assertion failed: self.is_none()")]
-    fn duplicate_ids_from_broker_panics() {
-        let mut fixture = Fixture::new(
-            2,
-            [
-                (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
-                (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/b"))),
-                (
-                    cache_key!(BottomFsLayer, 1),
-                    GetArtifact::Success(path_buf!("/a")),
-                ),
-                (
-                    cache_key!(BottomFsLayer, 2),
-                    GetArtifact::Success(path_buf!("/b")),
-                ),
-            ],
-            [],
-            [],
-        );
-        fixture
-            .dispatcher
-            .receive_message(Broker(EnqueueJob(jid!(1), spec!(1, Tar))));
-        fixture
-            .dispatcher
-            .receive_message(Broker(EnqueueJob(jid!(1), spec!(2, Tar))));
+    script_test! {
+        artifact_digest_mismatch_fails_waiting_jobs,
+        Fixture::new(2, [
+            (cache_key!(Blob, 41), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 42), GetArtifact::Get(path_buf!("/b"))),
+            (cache_key!(Blob, 42), GetArtifact::Wait),
+            (cache_key!(BottomFsLayer, 41), GetArtifact::Success(path_buf!("/a"))),
+        ], [], [
+            (cache_key!(Blob, 42), vec![jid!(1), jid!(2)]),
+        ]),
+        Broker(EnqueueJob(jid!(1), spec!(1, [(41, Tar), (42, Tar)]))) => {
+            CacheGetArtifact(Blob, digest!(41), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(41), jid!(1)),
+            CacheGetArtifact(Blob, digest!(42), jid!(1)),
+            StartArtifactFetch(digest!(42), path_buf!("/b")),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, [(42, Tar)]))) => {
+            CacheGetArtifact(Blob, digest!(42), jid!(2)),
+        };
+        ArtifactFetchCompleted(digest!(42), Err(digest!(43).verify(&digest!(42)).unwrap_err().into())) => {
+            CacheGotArtifactFailure(Blob, digest!(42)),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Err(JobError::System(
+                string!("Failed to download and extract layer artifact 000000000000000000000000000000000000000000000000000000000000002a: mismatched SHA-256 digest (expected 000000000000000000000000000000000000000000000000000000000000002a, found 000000000000000000000000000000000000000000000000000000000000002b)"))) }),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(2), result: Err(JobError::System(
+                string!("Failed to download and extract layer artifact 000000000000000000000000000000000000000000000000000000000000002a: mismatched SHA-256 digest (expected 000000000000000000000000000000000000000000000000000000000000002a, found 000000000000000000000000000000000000000000000000000000000000002b)"))) }),
+            CacheDecrementRefCount(Blob, digest!(41)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(41)),
+        };
+    }
+
+    script_test! {
+        duplicate_ids_from_broker_are_ignored,
+        Fixture::new(1, [
+            (cache_key!(Blob, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(Blob, 2), GetArtifact::Success(path_buf!("/b"))),
+            (cache_key!(Blob, 3), GetArtifact::Wait),
+            (cache_key!(BottomFsLayer, 1), GetArtifact::Success(path_buf!("/a"))),
+            (cache_key!(BottomFsLayer, 2), GetArtifact::Success(path_buf!("/b"))),
+        ], [], []),
+        Broker(EnqueueJob(jid!(1), spec!(1, Tar))) => {
+            CacheGetArtifact(Blob, digest!(1), jid!(1)),
+            CacheGetArtifact(BottomFsLayer, digest!(1), jid!(1)),
+            StartJob(jid!(1), spec!(1, Tar), path_buf!("/a")),
+        };
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {
+            CacheGetArtifact(Blob, digest!(2), jid!(2)),
+            CacheGetArtifact(BottomFsLayer, digest!(2), jid!(2)),
+        };
+        Broker(EnqueueJob(jid!(3), spec!(3, Tar))) => {
+            CacheGetArtifact(Blob, digest!(3), jid!(3)),
+        };
+        Broker(EnqueueJob(jid!(1), spec!(2, Tar))) => {};
+        Broker(EnqueueJob(jid!(2), spec!(2, Tar))) => {};
+        Broker(EnqueueJob(jid!(3), spec!(3, Tar))) => {};
+        Message::JobCompleted(jid!(1), Ok(completed!(1))) => {
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
+            CacheDecrementRefCount(Blob, digest!(1)),
+            CacheDecrementRefCount(BottomFsLayer, digest!(1)),
+            JobHandleDropped(jid!(1)),
+            StartJob(jid!(2), spec!(2, Tar), path_buf!("/b")),
+        };
     }
 
     script_test! {
@@ -1638,7 +3711,7 @@ mod tests {
             CacheDecrementRefCount(Blob, digest!(1)),
             CacheDecrementRefCount(BottomFsLayer, digest!(1)),
             CacheDecrementRefCount(UpperFsLayer, upper_digest!(1, 1)),
-            SendMessageToBroker(WorkerToBroker(jid!(1), Ok(outcome!(1)))),
+            SendMessageToBroker(WorkerToBroker::JobResponse { jid: jid!(1), result: Ok(outcome!(1)) }),
             JobHandleDropped(jid!(1)),
         };
     }
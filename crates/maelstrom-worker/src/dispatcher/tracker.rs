@@ -352,6 +352,39 @@ impl LayerTracker {
         ) || (self.bottom_layers_all_ready() && self.layers.len() < 2)
     }
 
+    /// The digests of the layers that are still outstanding. These are the bottom layers that
+    /// aren't ready yet, in the order they appear in the job, followed by any artifacts
+    /// referenced by manifests that haven't been gotten yet. Once all bottom layers are ready,
+    /// these are the layers that still need to be stacked on top.
+    pub fn pending_digests(&self) -> Vec<Sha256Digest> {
+        let mut pending = vec![];
+        for digest in &self.layers {
+            let ready = matches!(
+                self.bottom_layers.get(digest),
+                Some(PendingBottomLayer::Ready { .. })
+            );
+            if !ready && !pending.contains(digest) {
+                pending.push(digest.clone());
+            }
+        }
+        let mut manifest_dependencies = Vec::from_iter(
+            self.pending_manifest_dependencies
+                .keys()
+                .filter(|digest| !pending.contains(digest))
+                .cloned(),
+        );
+        manifest_dependencies.sort();
+        pending.extend(manifest_dependencies);
+        if pending.is_empty() && !self.is_complete() {
+            let stacked = match self.top_fs_layer {
+                PendingTopLayer::NoStackedUpperLayers => 1,
+                PendingTopLayer::StackedUpperLayers { index, .. } => index,
+            };
+            pending.extend(self.layers.iter().skip(stacked).cloned());
+        }
+        pending
+    }
+
     pub fn into_cache_keys(self) -> HashSet<Key> {
         self.cache_keys
     }
@@ -719,6 +752,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pending_digests_includes_manifest_dependencies() {
+        let layers = nonempty![
+            (digest!(2), ArtifactType::Manifest),
+            (digest!(1), ArtifactType::Tar)
+        ];
+        let mut fetcher = TestFetcher::new(
+            [
+                (digest!(1), FetcherResult::Pending),
+                (digest!(2), FetcherResult::Got(path_buf!("/blob/2"))),
+                (digest!(3), FetcherResult::Got(path_buf!("/blob/3"))),
+                (digest!(4), FetcherResult::Pending),
+            ],
+            [],
+            [],
+            [(digest!(2), path_buf!("/blob/2"))],
+        );
+        let mut tracker = LayerTracker::new(&layers, &mut fetcher);
+        assert_eq!(tracker.pending_digests(), vec![digest!(2), digest!(1)]);
+
+        tracker.got_manifest_digests(
+            &digest!(2),
+            hashset! { digest!(3), digest!(4) },
+            &mut fetcher,
+        );
+        assert_eq!(
+            tracker.pending_digests(),
+            vec![digest!(2), digest!(1), digest!(4)]
+        );
+    }
+
+    #[test]
+    fn pending_digests_includes_unstacked_upper_layers() {
+        let layers = nonempty![
+            (digest!(1), ArtifactType::Tar),
+            (digest!(2), ArtifactType::Tar)
+        ];
+        let mut fetcher = TestFetcher::new(
+            [
+                (digest!(1), FetcherResult::Got(path_buf!("/blob/1"))),
+                (digest!(2), FetcherResult::Got(path_buf!("/blob/2"))),
+            ],
+            [
+                (digest!(1), FetcherResult::Got(path_buf!("/fs_b/1"))),
+                (digest!(2), FetcherResult::Got(path_buf!("/fs_b/2"))),
+            ],
+            [(upper_digest!(2, 1), FetcherResult::Pending)],
+            [],
+        );
+        let tracker = LayerTracker::new(&layers, &mut fetcher);
+
+        assert!(!tracker.is_complete());
+        assert_eq!(tracker.pending_digests(), vec![digest!(2)]);
+    }
+
     #[test]
     fn two_layers_one_artifact_gotten_one_pending_then_got_into_cache_keys() {
         let layers = nonempty![
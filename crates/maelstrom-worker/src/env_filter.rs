@@ -0,0 +1,193 @@
+//! Filter the environment of every job according to the worker's configuration, regardless of
+//! what the job asked for.
+
+use serde::{Deserialize, Deserializer};
+use std::{
+    convert::Infallible,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+/// A list of environment variable name patterns. In a pattern, `*` matches any sequence of
+/// characters, and every other character matches itself. On the command line or in an environment
+/// variable, the patterns are separated by commas. In a config file, they may also be given as an
+/// array of strings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EnvPatterns(Vec<String>);
+
+impl EnvPatterns {
+    pub fn new(patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self(patterns.into_iter().map(Into::into).collect())
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.0.iter().any(|pattern| wildcard_match(pattern, name))
+    }
+}
+
+impl FromStr for EnvPatterns {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(
+            s.split(',').map(str::trim).filter(|p| !p.is_empty()),
+        ))
+    }
+}
+
+impl Display for EnvPatterns {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.join(","))
+    }
+}
+
+impl<'de> Deserialize<'de> for EnvPatterns {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum StringOrList {
+            String(String),
+            List(Vec<String>),
+        }
+        Ok(match StringOrList::deserialize(deserializer)? {
+            StringOrList::String(s) => s.parse().unwrap(),
+            StringOrList::List(patterns) => Self(patterns),
+        })
+    }
+}
+
+/// Return true if `name` matches `pattern`, where `*` in `pattern` matches any sequence of
+/// characters.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let Some(last) = parts.next_back() else {
+        // There were no wildcards.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Decides which of a job's environment variables are passed to it. A variable is passed only if
+/// its name matches the allow list, if there is one, and doesn't match the deny list.
+#[derive(Clone, Debug, Default)]
+pub struct EnvFilter {
+    allow: Option<EnvPatterns>,
+    deny: Option<EnvPatterns>,
+}
+
+impl EnvFilter {
+    pub fn new(allow: Option<EnvPatterns>, deny: Option<EnvPatterns>) -> Self {
+        Self { allow, deny }
+    }
+
+    pub fn is_allowed(&self, name: &str) -> bool {
+        self.allow
+            .as_ref()
+            .map_or(true, |allow| allow.matches(name))
+            && !self.deny.as_ref().is_some_and(|deny| deny.matches(name))
+    }
+
+    /// Remove the variables that aren't allowed from `environment`, which is a list of
+    /// `NAME=VALUE` strings, as found in [`maelstrom_base::JobSpec::environment`].
+    pub fn filter(&self, mut environment: Vec<String>) -> Vec<String> {
+        environment.retain(|var| {
+            let name = var.split_once('=').map_or(var.as_str(), |(name, _)| name);
+            self.is_allowed(name)
+        });
+        environment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_environment() -> Vec<String> {
+        [
+            "PATH=/bin",
+            "HOME=/root",
+            "AWS_SECRET_KEY=x",
+            "MY_TOKEN=y",
+            "RUST_LOG=debug",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect()
+    }
+
+    #[test]
+    fn no_patterns_allows_everything() {
+        assert_eq!(
+            EnvFilter::default().filter(sample_environment()),
+            sample_environment()
+        );
+    }
+
+    #[test]
+    fn allow_list_only() {
+        let filter = EnvFilter::new(Some(EnvPatterns::new(["PATH", "RUST_*"])), None);
+        assert_eq!(
+            filter.filter(sample_environment()),
+            vec!["PATH=/bin", "RUST_LOG=debug"]
+        );
+    }
+
+    #[test]
+    fn deny_list_only() {
+        let filter = EnvFilter::new(None, Some(EnvPatterns::new(["AWS_*", "*_TOKEN"])));
+        assert_eq!(
+            filter.filter(sample_environment()),
+            vec!["PATH=/bin", "HOME=/root", "RUST_LOG=debug"]
+        );
+    }
+
+    #[test]
+    fn deny_list_wins_over_allow_list() {
+        let filter = EnvFilter::new(
+            Some(EnvPatterns::new(["*A*"])),
+            Some(EnvPatterns::new(["*SECRET*"])),
+        );
+        assert_eq!(filter.filter(sample_environment()), vec!["PATH=/bin"]);
+    }
+
+    #[test]
+    fn wildcard_matching() {
+        assert!(wildcard_match("PATH", "PATH"));
+        assert!(!wildcard_match("PATH", "PATHS"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("A*B*C", "ABC"));
+        assert!(wildcard_match("A*B*C", "AxxBxxC"));
+        assert!(!wildcard_match("A*B*C", "AxxCxxB"));
+        assert!(!wildcard_match("AB*BA", "ABA"));
+    }
+
+    #[test]
+    fn env_patterns_parsing() {
+        assert_eq!(
+            " FOO, BAR_* ,,".parse::<EnvPatterns>().unwrap(),
+            EnvPatterns::new(["FOO", "BAR_*"])
+        );
+        assert_eq!(
+            toml::Value::from(vec!["FOO", "BAR_*"])
+                .try_into::<EnvPatterns>()
+                .unwrap(),
+            EnvPatterns::new(["FOO", "BAR_*"])
+        );
+        assert_eq!(
+            toml::Value::from("FOO,BAR_*")
+                .try_into::<EnvPatterns>()
+                .unwrap(),
+            EnvPatterns::new(["FOO", "BAR_*"])
+        );
+    }
+}
@@ -7,19 +7,22 @@ use bumpalo::{
 };
 use maelstrom_base::{
     tty::{self, DecodeInputChunk, DecodeInputRemainder},
-    GroupId, JobCompleted, JobDevice, JobEffects, JobError, JobMount, JobNetwork, JobOutputResult,
-    JobResult, JobRootOverlay, JobStatus, JobTty, UserId, Utf8PathBuf, WindowSize,
+    CompressionAlgorithm, GroupId, JobCompleted, JobDevice, JobEffects, JobError, JobMount,
+    JobNetwork, JobOutputResult, JobResult, JobRootOverlay, JobSpecValidationError, JobStatus,
+    JobTty, TtyOutputLimit, TtyOutputOverflow, TtySocket, UserId, Utf8PathBuf, WindowSize,
+    MAX_CPUS, OOM_SCORE_ADJ_RANGE,
 };
 use maelstrom_linux::{
-    self as linux, CloneArgs, CloneFlags, CloseRangeFirst, CloseRangeFlags, CloseRangeLast, Errno,
-    Fd, FileMode, FsconfigCommand, FsmountFlags, FsopenFlags, Gid, MountAttrs, MountFlags,
+    self as linux, CloneArgs, CloneFlags, CloseRangeFirst, CloseRangeFlags, CloseRangeLast, CpuSet,
+    Errno, Fd, FileMode, FsconfigCommand, FsmountFlags, FsopenFlags, Gid, MountAttrs, MountFlags,
     MoveMountFlags, OpenFlags, OpenTreeFlags, OwnedFd, Signal, SockaddrNetlink, SockaddrUnStorage,
     SocketDomain, SocketProtocol, SocketType, Uid, UmountFlags, WaitStatus,
 };
 use maelstrom_util::{
+    async_fs,
     config::common::InlineLimit,
-    io::AsyncFile,
-    root::RootBuf,
+    io::{AsyncFile, Sha256Stream},
+    root::{Root, RootBuf},
     sync::EventReceiver,
     time::{Clock, ClockInstant as _},
     tty::open_pseudoterminal,
@@ -36,10 +39,12 @@ use std::{
     result,
 };
 use tokio::{
-    io::{self, unix::AsyncFd, AsyncReadExt as _, AsyncWriteExt as _, Interest},
+    io::{
+        self, unix::AsyncFd, AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _, Interest,
+    },
     net::UnixStream,
     runtime, select,
-    sync::oneshot,
+    sync::{mpsc, oneshot},
     task::JoinSet,
 };
 
@@ -60,10 +65,13 @@ pub struct JobSpec {
     pub mounts: Vec<JobMount>,
     pub network: JobNetwork,
     pub root_overlay: JobRootOverlay,
+    pub readonly_root: bool,
     pub working_directory: Option<Utf8PathBuf>,
     pub user: Option<UserId>,
     pub group: Option<GroupId>,
     pub allocate_tty: Option<JobTty>,
+    pub oom_score_adj: Option<i16>,
+    pub cpu_set: Option<Vec<u16>>,
 }
 
 impl JobSpec {
@@ -76,11 +84,14 @@ impl JobSpec {
             mounts,
             network,
             root_overlay,
+            readonly_root,
             working_directory,
             user,
             group,
             estimated_duration: _,
             allocate_tty,
+            oom_score_adj,
+            cpu_set,
             ..
         } = spec;
         JobSpec {
@@ -90,16 +101,20 @@ impl JobSpec {
             mounts,
             network,
             root_overlay,
+            readonly_root,
             working_directory,
             user,
             group,
             allocate_tty,
+            oom_score_adj,
+            cpu_set,
         }
     }
 }
 
 pub struct MountDir;
 pub struct TmpfsDir;
+pub struct ExternalOutputDir;
 
 pub struct Executor<'clock, ClockT> {
     user: UserId,
@@ -112,6 +127,7 @@ pub struct Executor<'clock, ClockT> {
     netlink_socket_addr: SockaddrNetlink,
     netlink_message: Box<[u8]>,
     clock: &'clock ClockT,
+    external_output_dir: Option<RootBuf<ExternalOutputDir>>,
 }
 
 impl<'clock, ClockT> Executor<'clock, ClockT> {
@@ -175,8 +191,17 @@ impl<'clock, ClockT> Executor<'clock, ClockT> {
             netlink_socket_addr,
             netlink_message: buffer,
             clock,
+            external_output_dir: None,
         })
     }
+
+    /// Store stdout or stderr that exceeds the inline limit in `external_output_dir` instead of
+    /// truncating it. The whole output is written to a file named after its digest, and
+    /// [`JobOutputResult::External`] is returned. Stored files are never removed by the executor.
+    pub fn external_output_dir(mut self, external_output_dir: RootBuf<ExternalOutputDir>) -> Self {
+        self.external_output_dir = Some(external_output_dir);
+        self
+    }
 }
 
 impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
@@ -193,6 +218,9 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
     /// The `kill_event_receiver` is used to kill the child process. If the attached sender is ever
     /// closed, the child will be immediately killed with a SIGTERM.
     ///
+    /// Window sizes received on `resize_receiver` are applied to the job's pty. They are ignored if
+    /// the job doesn't have a TTY.
+    ///
     /// This function should be run in a `spawn_blocking` context. Ideally, this function would be
     /// async, but that doesn't work because we rely on [`bumpalo::Bump`] as a fast arena
     /// allocator, and it's not `Sync`.
@@ -201,10 +229,18 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         spec: &JobSpec,
         inline_limit: InlineLimit,
         kill_event_receiver: EventReceiver,
+        resize_receiver: mpsc::UnboundedReceiver<WindowSize>,
         fuse_spawn: impl FnOnce(OwnedFd),
         runtime: runtime::Handle,
     ) -> JobResult<JobCompleted, Error> {
-        self.run_job_inner(spec, inline_limit, kill_event_receiver, fuse_spawn, runtime)
+        self.run_job_inner(
+            spec,
+            inline_limit,
+            kill_event_receiver,
+            resize_receiver,
+            fuse_spawn,
+            runtime,
+        )
     }
 }
 
@@ -241,8 +277,14 @@ async fn wait_for_child(
     })
 }
 
-/// Read all of the contents of `stream` and return the appropriate [`JobOutputResult`].
-async fn output_reader(fd: OwnedFd, inline_limit: InlineLimit) -> Result<JobOutputResult> {
+/// Read all of the contents of `stream` and return the appropriate [`JobOutputResult`]. If there
+/// is more than `inline_limit` bytes of output, and `external_output_dir` is provided, all of the
+/// output is stored in that directory, and the first `inline_limit` bytes are also returned inline.
+async fn output_reader(
+    fd: OwnedFd,
+    inline_limit: InlineLimit,
+    external_output_dir: Option<RootBuf<ExternalOutputDir>>,
+) -> Result<JobOutputResult> {
     let mut buf = Vec::<u8>::new();
     // Make the read side of the pipe non-blocking so that we can use it with Tokio.
     linux::fcntl_setfl(&fd, OpenFlags::NONBLOCK).map_err(Error::from)?;
@@ -250,24 +292,223 @@ async fn output_reader(fd: OwnedFd, inline_limit: InlineLimit) -> Result<JobOutp
     let mut take = stream.take(inline_limit.as_bytes());
     take.read_to_end(&mut buf).await?;
     let buf = buf.into_boxed_slice();
-    let truncated = io::copy(&mut take.into_inner(), &mut io::sink()).await?;
-    match truncated {
-        0 if buf.is_empty() => Ok(JobOutputResult::None),
-        0 => Ok(JobOutputResult::Inline(buf)),
-        _ => Ok(JobOutputResult::Truncated {
-            first: buf,
-            truncated,
-        }),
+    let mut stream = take.into_inner();
+    let mut next = [0u8; 1];
+    if stream.read(&mut next).await? == 0 {
+        return Ok(if buf.is_empty() {
+            JobOutputResult::None
+        } else {
+            inline_output(buf)
+        });
     }
+    match external_output_dir {
+        Some(external_output_dir) => {
+            write_external_output(&external_output_dir, buf, &next, stream).await
+        }
+        None => {
+            let truncated = io::copy(&mut stream, &mut io::sink()).await? + 1;
+            Ok(JobOutputResult::Truncated {
+                first: buf,
+                truncated,
+            })
+        }
+    }
+}
+
+/// Inline output at least this large is compressed, as long as compression makes it smaller.
+const INLINE_COMPRESSION_THRESHOLD: usize = 4096;
+
+/// Return `buf` as inline output, compressing it if it's large enough to be worth it.
+fn inline_output(buf: Box<[u8]>) -> JobOutputResult {
+    if buf.len() >= INLINE_COMPRESSION_THRESHOLD {
+        let compressed = JobOutputResult::inline_compressed(CompressionAlgorithm::Deflate, &buf);
+        if matches!(&compressed, JobOutputResult::InlineCompressed { data, .. } if data.len() < buf.len())
+        {
+            return compressed;
+        }
+    }
+    JobOutputResult::Inline(buf)
+}
+
+/// Write `first` and `next` followed by the rest of `stream` to a file in `external_output_dir`
+/// named after the digest of its contents, and return the corresponding
+/// [`JobOutputResult::External`], which keeps `first` inline.
+async fn write_external_output(
+    external_output_dir: &Root<ExternalOutputDir>,
+    first: Box<[u8]>,
+    next: &[u8],
+    mut stream: impl io::AsyncRead + Unpin,
+) -> Result<JobOutputResult> {
+    let fs = async_fs::Fs::new();
+    let temp_path = external_output_dir.join::<()>(format!(".{:016x}", rand::random::<u64>()));
+    let result = async {
+        let mut writer = Sha256Stream::new(fs.create_file(&temp_path).await?);
+        writer.write_all(&first).await?;
+        writer.write_all(next).await?;
+        let size = (first.len() + next.len()) as u64 + io::copy(&mut stream, &mut writer).await?;
+        writer.flush().await?;
+        let (_, digest) = writer.finalize();
+        fs.rename(
+            &temp_path,
+            external_output_dir.join::<()>(digest.to_string()),
+        )
+        .await?;
+        Ok(JobOutputResult::External {
+            first,
+            digest,
+            size,
+        })
+    }
+    .await;
+    if result.is_err() {
+        let _ = fs.remove_file(&temp_path).await;
+    }
+    result
 }
 
 /// Task main for the output reader: Read the output and then call the callback.
 async fn output_reader_task_main(
     fd: OwnedFd,
     inline_limit: InlineLimit,
+    external_output_dir: Option<RootBuf<ExternalOutputDir>>,
     sender: oneshot::Sender<Result<JobOutputResult>>,
 ) {
-    let _ = sender.send(output_reader(fd, inline_limit).await);
+    let _ = sender.send(output_reader(fd, inline_limit, external_output_dir).await);
+}
+
+/// Output read from a TTY job's pseudoterminal that hasn't been sent over the socket yet. This
+/// decides when to stop reading from the pseudoterminal, and what to do with output that doesn't
+/// fit, according to the job's [`TtyOutputLimit`]. If `framed` is set, the output is encoded like
+/// input is, so that window size acknowledgements can be mixed in. The limit applies to the output
+/// before it is encoded.
+struct TtyOutputBuffer {
+    limit: TtyOutputLimit,
+    framed: bool,
+    buffer: Vec<u8>,
+    dropped: u64,
+}
+
+impl TtyOutputBuffer {
+    fn new(limit: TtyOutputLimit, framed: bool) -> Self {
+        Self {
+            limit,
+            framed,
+            buffer: vec![],
+            dropped: 0,
+        }
+    }
+
+    /// Whether more output should be read from the pseudoterminal. We always read when the buffer
+    /// is empty, so that a high-water mark of zero still makes progress.
+    fn should_read(&self) -> bool {
+        match self.limit.overflow {
+            TtyOutputOverflow::Backpressure => {
+                self.buffer.is_empty() || (self.buffer.len() as u64) < self.limit.high_water_mark
+            }
+            TtyOutputOverflow::Drop => true,
+        }
+    }
+
+    /// Add output read from the pseudoterminal. With [`TtyOutputOverflow::Drop`], whatever doesn't
+    /// fit under the high-water mark is counted and thrown away, and a marker is put in its place
+    /// once there is room again.
+    fn push(&mut self, output: &[u8]) {
+        match self.limit.overflow {
+            TtyOutputOverflow::Backpressure => self.extend(output),
+            TtyOutputOverflow::Drop => {
+                let room = self
+                    .limit
+                    .high_water_mark
+                    .saturating_sub(self.buffer.len() as u64);
+                let kept = output.len().min(room.try_into().unwrap_or(usize::MAX));
+                if kept > 0 {
+                    self.push_dropped_marker();
+                    self.extend(&output[..kept]);
+                }
+                self.dropped += (output.len() - kept) as u64;
+            }
+        }
+    }
+
+    /// Acknowledge that the pseudoterminal was resized. Only framed output has a way to do this.
+    /// The acknowledgement is never dropped, and doesn't count against the limit.
+    fn push_resized(&mut self, window_size: WindowSize) {
+        assert!(self.framed);
+        self.push_dropped_marker();
+        self.buffer
+            .extend(tty::encode_window_size_change(window_size));
+    }
+
+    fn extend(&mut self, output: &[u8]) {
+        if self.framed {
+            for chunk in tty::encode_input(output) {
+                self.buffer.extend_from_slice(chunk);
+            }
+        } else {
+            self.buffer.extend_from_slice(output);
+        }
+    }
+
+    /// Called when there is no more output to read.
+    fn finish(&mut self) {
+        self.push_dropped_marker();
+    }
+
+    fn push_dropped_marker(&mut self) {
+        if self.dropped > 0 {
+            self.buffer
+                .extend(tty::encode_dropped_output(mem::take(&mut self.dropped)));
+        }
+    }
+
+    fn pending(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.buffer.drain(..n);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+}
+
+/// Copy a TTY job's output from the pseudoterminal's master to the socket, buffering according to
+/// `limit` when the socket can't keep up. If `resizes` is given, the output is framed, and every
+/// window size received from `resizes` is acknowledged in the output.
+async fn copy_tty_output(
+    mut master: impl AsyncRead + Unpin,
+    mut socket: impl AsyncWrite + Unpin,
+    limit: TtyOutputLimit,
+    mut resizes: Option<mpsc::UnboundedReceiver<WindowSize>>,
+) -> io::Result<()> {
+    let mut output = TtyOutputBuffer::new(limit, resizes.is_some());
+    let mut buf = [0u8; 1024];
+    let mut master_open = true;
+    while master_open || !output.is_empty() {
+        select! {
+            res = master.read(&mut buf), if master_open && output.should_read() => match res {
+                // Reading from the master fails with EIO once the job closes the slave.
+                Ok(0) | Err(_) => {
+                    master_open = false;
+                    output.finish();
+                }
+                Ok(n) => output.push(&buf[..n]),
+            },
+            res = socket.write(output.pending()), if !output.is_empty() => match res? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => output.consume(n),
+            },
+            res = async { resizes.as_mut().unwrap().recv().await }, if resizes.is_some() => {
+                match res {
+                    Some(window_size) => output.push_resized(window_size),
+                    None => resizes = None,
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
 struct ScriptBuilder<'a> {
@@ -405,6 +646,8 @@ enum Stdio {
         master: OwnedFd,
         slave: OwnedFd,
         socket: OwnedFd,
+        output_limit: Option<TtyOutputLimit>,
+        framed_output: bool,
     },
 }
 
@@ -626,6 +869,87 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         Ok(())
     }
 
+    fn set_up_oom_score_adj<'bump>(
+        &'bump self,
+        spec: &JobSpec,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> JobResult<(), Error> {
+        let Some(oom_score_adj) = spec.oom_score_adj else {
+            return Ok(());
+        };
+        if !OOM_SCORE_ADJ_RANGE.contains(&oom_score_adj) {
+            return Err(execerr(JobSpecValidationError::OomScoreAdjOutOfRange(
+                oom_score_adj,
+            )));
+        }
+
+        let fd = new_fd_slot(bump);
+        let mut contents = BumpString::with_capacity_in(8, bump);
+        writeln!(contents, "{oom_score_adj}").map_err(syserr)?;
+        builder.push(
+            Syscall::Open {
+                path: c"/proc/self/oom_score_adj",
+                flags: OpenFlags::WRONLY | OpenFlags::TRUNC,
+                mode: FileMode::default(),
+                out: fd,
+            },
+            &|err| {
+                syserr(anyhow!(
+                    "opening /proc/self/oom_score_adj for writing: {err}"
+                ))
+            },
+        );
+        builder.push(
+            Syscall::Write {
+                fd,
+                buf: contents.into_bump_str().as_bytes(),
+            },
+            // The kernel only lets a process lower its OOM score adjustment below the minimum it
+            // inherited if it has CAP_SYS_RESOURCE in the initial user namespace. The job's user
+            // namespace doesn't help, so this is something the job asked for that we can't do.
+            bump.alloc(move |err| {
+                if Errno::EACCES.desc() == Some(err) {
+                    execerr(anyhow!(
+                        "setting oom_score_adj to {oom_score_adj}: {err}; lowering it below the \
+                        worker's own value requires the worker to have CAP_SYS_RESOURCE"
+                    ))
+                } else {
+                    syserr(anyhow!("writing to /proc/self/oom_score_adj: {err}"))
+                }
+            }),
+        );
+
+        Ok(())
+    }
+
+    fn set_up_cpu_set<'bump>(
+        &'bump self,
+        spec: &JobSpec,
+        bump: &'bump Bump,
+        builder: &mut ScriptBuilder<'bump>,
+    ) -> JobResult<(), Error> {
+        let Some(cpus) = &spec.cpu_set else {
+            return Ok(());
+        };
+        if cpus.is_empty() {
+            return Err(execerr(JobSpecValidationError::EmptyCpuSet));
+        }
+
+        let cpu_set = bump.alloc(CpuSet::empty());
+        for &cpu in cpus {
+            if cpu >= MAX_CPUS {
+                return Err(execerr(JobSpecValidationError::CpuIndexOutOfRange(cpu)));
+            }
+            cpu_set.insert(cpu.into());
+        }
+        builder.push(Syscall::SchedSetaffinity { cpu_set }, &|err| {
+            syserr(anyhow!("sched_setaffinity: {err}"))
+        });
+
+        Ok(())
+    }
+
     fn set_up_fuse_root<'bump>(
         &'bump self,
         spec: &JobSpec,
@@ -682,6 +1006,9 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         bump: &'bump Bump,
         builder: &mut ScriptBuilder<'bump>,
     ) -> JobResult<(), Error> {
+        if spec.readonly_root && spec.root_overlay != JobRootOverlay::None {
+            return Err(execerr(JobSpecValidationError::ReadonlyRootWithOverlay));
+        }
         let (upper, work) = match spec.root_overlay {
             JobRootOverlay::None => {
                 // There is nothing to do. We're just going to have a read-only root without an
@@ -953,6 +1280,66 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 JobMount::Mqueue { .. } => {
                     normal_mount(bump, builder, mount_fds, c"mqueue", "mqueue")?;
                 }
+                JobMount::Overlay {
+                    lower, upper, work, ..
+                } => {
+                    // Like with bind mounts, the directories are local paths, so we have to
+                    // configure the overlay file system before we pivot_root. The configured mount
+                    // is attached at the mount point later.
+                    let fd = new_fd_slot(bump);
+                    mount_fds.push(fd);
+                    builder.push(
+                        Syscall::Fsopen {
+                            fsname: c"overlay",
+                            flags: FsopenFlags::default(),
+                            out: fd,
+                        },
+                        &|err| syserr(anyhow!("fsopen for overlay mount: {err}")),
+                    );
+                    for (key, key_str, value) in [
+                        (c"lowerdir", "lowerdir", lower),
+                        (c"upperdir", "upperdir", upper),
+                        (c"workdir", "workdir", work),
+                    ] {
+                        builder.push(
+                            Syscall::Fsconfig {
+                                fd,
+                                command: FsconfigCommand::SET_STRING,
+                                key: Some(key),
+                                value: Some(
+                                    &bump_c_str(bump, value.as_str())
+                                        .map_err(syserr)?
+                                        .to_bytes_with_nul()[0],
+                                ),
+                                aux: None,
+                            },
+                            bump.alloc(move |err| {
+                                execerr(anyhow!(
+                                    "fsconfig of {key_str} {value} for overlay mount: {err}"
+                                ))
+                            }),
+                        );
+                    }
+                    builder.push(
+                        Syscall::Fsconfig {
+                            fd,
+                            command: FsconfigCommand::CMD_CREATE,
+                            key: None,
+                            value: None,
+                            aux: None,
+                        },
+                        &|err| execerr(anyhow!("fsconfig(CMD_CREATE) for overlay mount: {err}")),
+                    );
+                    builder.push(
+                        Syscall::Fsmount {
+                            fd,
+                            flags: FsmountFlags::default(),
+                            mount_attrs: MountAttrs::default(),
+                            out: fd,
+                        },
+                        &|err| syserr(anyhow!("fsmount for overlay mount: {err}")),
+                    );
+                }
                 JobMount::Proc { .. } => {
                     normal_mount(bump, builder, mount_fds, c"proc", "proc")?;
                 }
@@ -1010,6 +1397,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                     mount_point,
                     local_path,
                     read_only,
+                    security_label,
                 } => {
                     let mount_local_path_fd = mount_fds.next().unwrap();
                     let mount_point_cstr =
@@ -1044,6 +1432,21 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                             }),
                         );
                     }
+                    if let Some(security_label) = security_label {
+                        builder.push(
+                            Syscall::Setxattr {
+                                path: mount_point_cstr,
+                                name: c"security.selinux",
+                                value: bump.alloc_slice_copy(security_label.as_bytes()),
+                            },
+                            bump.alloc(move |err| {
+                                execerr(anyhow!(
+                                    "setting security label of bind mount of {mount_point} \
+                                    to {security_label}: {err}",
+                                ))
+                            }),
+                        );
+                    }
                 }
                 JobMount::Devices { devices } => {
                     for device in devices.iter() {
@@ -1088,6 +1491,13 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                     mount_fds.next().unwrap(),
                     mount_point,
                 )?,
+                JobMount::Overlay { mount_point, .. } => normal_mount(
+                    bump,
+                    builder,
+                    "overlay",
+                    mount_fds.next().unwrap(),
+                    mount_point,
+                )?,
                 JobMount::Proc { mount_point } => normal_mount(
                     bump,
                     builder,
@@ -1246,6 +1656,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         spec: &JobSpec,
         inline_limit: InlineLimit,
         kill_event_receiver: EventReceiver,
+        mut resize_receiver: mpsc::UnboundedReceiver<WindowSize>,
         fuse_spawn: impl FnOnce(OwnedFd),
         runtime: runtime::Handle,
     ) -> JobResult<JobCompleted, Error> {
@@ -1258,7 +1669,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         // unnecessary ends in the parent and child. Alternatively, we could creates the pipes in
         // the child and then send the read ends back over the socket, but that seems unnecessarily
         // complex.
-        let stdio = match spec.allocate_tty {
+        let stdio = match &spec.allocate_tty {
             None => {
                 let (stdout_read, stdout_write) = linux::pipe().map_err(syserr)?;
                 let (stderr_read, stderr_write) = linux::pipe().map_err(syserr)?;
@@ -1270,8 +1681,10 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 }
             }
             Some(JobTty {
-                socket_address,
+                socket: tty_socket,
                 window_size,
+                output_limit,
+                framed_output,
             }) => {
                 // Open and connect the socket.
                 let socket = linux::socket(
@@ -1280,17 +1693,24 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                     Default::default(),
                 )
                 .map_err(syserr)?;
-                let sockaddr = SockaddrUnStorage::new(socket_address.as_slice()).map_err(syserr)?;
+                let sockaddr = match tty_socket {
+                    TtySocket::Abstract(address) => SockaddrUnStorage::new(address.as_slice()),
+                    TtySocket::Path(path) => SockaddrUnStorage::new(path.as_str().as_bytes()),
+                }
+                .map_err(syserr)?;
                 linux::connect(&socket, &sockaddr).map_err(syserr)?;
 
                 // Open pseudoterminal device
                 let (master, slave) =
-                    open_pseudoterminal(window_size, true /* master nonblock */).map_err(syserr)?;
+                    open_pseudoterminal(*window_size, true /* master nonblock */)
+                        .map_err(syserr)?;
 
                 Stdio::Pty {
                     master,
                     slave,
                     socket,
+                    output_limit: *output_limit,
+                    framed_output: *framed_output,
                 }
             }
         };
@@ -1346,6 +1766,12 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
         let newnet = self.set_up_network(spec, &bump, &mut builder);
         self.set_up_user_namespace(spec, &bump, &mut builder)?;
 
+        // Adjust the OOM score while we can still reach the worker's /proc.
+        self.set_up_oom_score_adj(spec, &bump, &mut builder)?;
+
+        // Restrict the job to its CPU set, if it has one. The affinity is inherited across execve.
+        self.set_up_cpu_set(spec, &bump, &mut builder)?;
+
         // Set up the fuse mount and send back the open fuse fd.
         let new_root_path = self.mount_dir.as_c_str();
         self.set_up_fuse_root(spec, new_root_path, &bump, &mut builder);
@@ -1491,11 +1917,21 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 drop(stdout_write);
                 drop(stderr_write);
                 joinset.spawn_on(
-                    output_reader_task_main(stdout_read, inline_limit, stdout_sender),
+                    output_reader_task_main(
+                        stdout_read,
+                        inline_limit,
+                        self.external_output_dir.clone(),
+                        stdout_sender,
+                    ),
                     &runtime,
                 );
                 joinset.spawn_on(
-                    output_reader_task_main(stderr_read, inline_limit, stderr_sender),
+                    output_reader_task_main(
+                        stderr_read,
+                        inline_limit,
+                        self.external_output_dir.clone(),
+                        stderr_sender,
+                    ),
                     &runtime,
                 );
             }
@@ -1503,6 +1939,8 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 master,
                 slave,
                 socket,
+                output_limit,
+                framed_output,
             } => {
                 // There is no output sent back in the JobResult if there is a pty allocated.
                 let _ = stdout_sender.send(Ok(JobOutputResult::None));
@@ -1519,37 +1957,77 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 let (mut socket_read, mut socket_write) =
                     UnixStream::try_from(socket).map_err(syserr)?.into_split();
 
+                // With framed output, the task reading from the socket tells the task writing to the
+                // socket about each window size change, so it can be acknowledged.
+                let (resized_sender, resized_receiver) =
+                    framed_output.then(mpsc::unbounded_channel).unzip();
+
                 // Spawn two tasks to proxy between the master and the socket.
                 let master_to_socket_handle = joinset.spawn_on(
                     async move {
-                        let _ = io::copy(&mut master_read, &mut socket_write).await;
+                        let _ = match (output_limit, resized_receiver) {
+                            (None, None) => io::copy(&mut master_read, &mut socket_write)
+                                .await
+                                .map(drop),
+                            (limit, resizes) => {
+                                // Without a limit, output is sent as it's read, like io::copy.
+                                let limit = limit.unwrap_or(TtyOutputLimit {
+                                    high_water_mark: 0,
+                                    overflow: TtyOutputOverflow::Backpressure,
+                                });
+                                copy_tty_output(&mut master_read, &mut socket_write, limit, resizes)
+                                    .await
+                            }
+                        };
                     },
                     &runtime,
                 );
                 joinset.spawn_on(
                     async move {
+                        let resize = |window_size: WindowSize| {
+                            let _ = linux::ioctl_tiocswinsz(
+                                &master_fd,
+                                window_size.rows,
+                                window_size.columns,
+                            );
+                            if let Some(resized_sender) = &resized_sender {
+                                let _ = resized_sender.send(window_size);
+                            }
+                        };
                         let mut buf = [0u8; 1024];
                         let mut remainder = DecodeInputRemainder::default();
+                        let mut resize_receiver_open = true;
+                        let mut offset = 0;
                         'outer: loop {
-                            let offset = remainder.move_to_slice(&mut buf[..]);
-                            let Ok(n) = socket_read.read(&mut buf[offset..]).await else {
+                            offset += remainder.move_to_slice(&mut buf[offset..]);
+                            // Resizes may also come from the broker, instead of in-band on the
+                            // socket. We handle them here so that the master is still open.
+                            let n = select! {
+                                window_size = resize_receiver.recv(), if resize_receiver_open => {
+                                    match window_size {
+                                        Some(window_size) => resize(window_size),
+                                        None => resize_receiver_open = false,
+                                    }
+                                    continue;
+                                }
+                                res = socket_read.read(&mut buf[offset..]) => res,
+                            };
+                            let Ok(n) = n else {
                                 break;
                             };
                             if n == 0 {
                                 break;
                             }
-                            for chunk in tty::decode_input(&buf[..offset + n]) {
+                            let length = mem::take(&mut offset) + n;
+                            for chunk in tty::decode_input(&buf[..length]) {
                                 match chunk {
                                     DecodeInputChunk::Input(buf) => {
                                         if master_write.write_all(buf).await.is_err() {
                                             break 'outer;
                                         }
                                     }
-                                    DecodeInputChunk::WindowSizeChange(WindowSize {
-                                        rows,
-                                        columns,
-                                    }) => {
-                                        let _ = linux::ioctl_tiocswinsz(&master_fd, rows, columns);
+                                    DecodeInputChunk::WindowSizeChange(window_size) => {
+                                        resize(window_size)
                                     }
                                     DecodeInputChunk::Remainder(new_remainder) => {
                                         remainder = new_remainder;
@@ -1595,6 +2073,7 @@ impl<'clock, ClockT: Clock> Executor<'clock, ClockT> {
                 stdout: read_from_receiver(stdout_receiver)?,
                 stderr: read_from_receiver(stderr_receiver)?,
                 duration,
+                worker: None,
             },
         })
     }
@@ -1616,13 +2095,20 @@ mod tests {
     use bytesize::ByteSize;
     use indoc::indoc;
     use maelstrom_base::{
-        enum_set, nonempty, ArtifactType, EnumSet, JobStatus, Utf8Path, WindowSize,
+        enum_set, nonempty, ArtifactType, EnumSet, JobStatus, Sha256Digest, Utf8Path, WindowSize,
     };
     use maelstrom_layer_fs::{BlobDir, BottomLayerBuilder, LayerFs, ReaderCache};
     use maelstrom_test::{boxed_u8, digest, utf8_path_buf};
     use maelstrom_util::{async_fs, log::test_logger, sync, time::TickingClock};
+    use sha2::{Digest as _, Sha256};
     use std::{
-        ascii, collections::HashSet, env, fs, path::PathBuf, str, sync::Arc, time::Duration,
+        ascii,
+        collections::HashSet,
+        env, fs,
+        path::{Path, PathBuf},
+        str,
+        sync::Arc,
+        time::Duration,
     };
     use tempfile::{NamedTempFile, TempDir};
     use tokio::{
@@ -1683,22 +2169,37 @@ mod tests {
     async fn run(
         spec: maelstrom_base::JobSpec,
         inline_limit: InlineLimit,
+        external_output_dir: Option<RootBuf<ExternalOutputDir>>,
+    ) -> JobResult<JobCompleted, Error> {
+        let (_, resize_receiver) = mpsc::unbounded_channel();
+        run_with_resizes(spec, inline_limit, external_output_dir, resize_receiver).await
+    }
+
+    async fn run_with_resizes(
+        spec: maelstrom_base::JobSpec,
+        inline_limit: InlineLimit,
+        external_output_dir: Option<RootBuf<ExternalOutputDir>>,
+        resize_receiver: mpsc::UnboundedReceiver<WindowSize>,
     ) -> JobResult<JobCompleted, Error> {
         let clock = TickingClock::new();
         let mount = TarMount::new().await;
         let spec = JobSpec::from_spec(spec);
         let (_kill_event_sender, kill_event_receiver) = sync::event();
         task::spawn_blocking(move || {
-            Executor::new(
+            let mut executor = Executor::new(
                 RootBuf::new(tempfile::tempdir().unwrap().into_path()),
                 RootBuf::new(tempfile::tempdir().unwrap().into_path()),
                 &clock,
             )
-            .unwrap()
-            .run_job(
+            .unwrap();
+            if let Some(external_output_dir) = external_output_dir {
+                executor = executor.external_output_dir(external_output_dir);
+            }
+            executor.run_job(
                 &spec,
                 inline_limit,
                 kill_event_receiver,
+                resize_receiver,
                 |fd| mount.spawn(fd),
                 runtime::Handle::current(),
             )
@@ -1710,6 +2211,7 @@ mod tests {
     struct Test {
         spec: maelstrom_base::JobSpec,
         inline_limit: InlineLimit,
+        external_output_dir: Option<RootBuf<ExternalOutputDir>>,
         expected_status: JobStatus,
         expected_stdout: JobOutputResult,
         expected_stderr: JobOutputResult,
@@ -1721,6 +2223,7 @@ mod tests {
             Test {
                 spec,
                 inline_limit: InlineLimit::from(ByteSize::b(1000)),
+                external_output_dir: None,
                 expected_status: JobStatus::Exited(0),
                 expected_stdout: JobOutputResult::None,
                 expected_stderr: JobOutputResult::None,
@@ -1733,6 +2236,11 @@ mod tests {
             self
         }
 
+        fn external_output_dir(mut self, external_output_dir: &Path) -> Self {
+            self.external_output_dir = Some(RootBuf::new(external_output_dir.to_owned()));
+            self
+        }
+
         fn expected_status(mut self, expected_status: JobStatus) -> Self {
             self.expected_status = expected_status;
             self
@@ -1756,8 +2264,11 @@ mod tests {
                         stdout,
                         stderr,
                         duration,
+                        worker: _,
                     },
-            } = run(self.spec, self.inline_limit).await.unwrap();
+            } = run(self.spec, self.inline_limit, self.external_output_dir)
+                .await
+                .unwrap();
 
             assert_eq!(stderr, self.expected_stderr);
             assert_eq!(status, self.expected_status);
@@ -1854,6 +2365,75 @@ mod tests {
             .await;
     }
 
+    #[tokio::test]
+    async fn large_inline_stdout_is_compressed() {
+        Test::new(python_spec("print('a' * 5000, end='')"))
+            .inline_limit(ByteSize::kb(10))
+            .expected_stdout(JobOutputResult::inline_compressed(
+                CompressionAlgorithm::Deflate,
+                &[b'a'; 5000],
+            ))
+            .run()
+            .await;
+    }
+
+    #[test]
+    fn inline_output_only_compresses_large_compressible_output() {
+        let small = boxed_u8!(b"hello\n");
+        assert_eq!(inline_output(small.clone()), JobOutputResult::Inline(small));
+
+        let large = Box::<[u8]>::from(vec![b'a'; INLINE_COMPRESSION_THRESHOLD]);
+        let result = inline_output(large.clone());
+        assert_eq!(
+            result,
+            JobOutputResult::inline_compressed(CompressionAlgorithm::Deflate, &large)
+        );
+        assert_eq!(result.inline_bytes().unwrap(), &large[..]);
+
+        // Bytes from a simple LCG don't compress, so they're left alone.
+        let mut state = 1u32;
+        let incompressible = Box::<[u8]>::from_iter((0..INLINE_COMPRESSION_THRESHOLD).map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        }));
+        assert_eq!(
+            inline_output(incompressible.clone()),
+            JobOutputResult::Inline(incompressible)
+        );
+    }
+
+    #[tokio::test]
+    async fn stdout_over_inline_limit_stored_externally() {
+        let temp_dir = TempDir::new().unwrap();
+        let digest = Sha256Digest::new(Sha256::digest(b"a\nb\n").into());
+        Test::new(bash_spec("echo a; echo b"))
+            .inline_limit(ByteSize::b(1))
+            .external_output_dir(temp_dir.path())
+            .expected_stdout(JobOutputResult::External {
+                first: boxed_u8!(b"a"),
+                digest: digest.clone(),
+                size: 4,
+            })
+            .run()
+            .await;
+        assert_eq!(
+            fs::read(temp_dir.path().join(digest.to_string())).unwrap(),
+            b"a\nb\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn stdout_within_inline_limit_not_stored_externally() {
+        let temp_dir = TempDir::new().unwrap();
+        Test::new(bash_spec("echo a"))
+            .inline_limit(ByteSize::b(2))
+            .external_output_dir(temp_dir.path())
+            .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"a\n")))
+            .run()
+            .await;
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+    }
+
     #[tokio::test]
     async fn stderr_inline_limit_0() {
         Test::new(bash_spec("echo a >&2"))
@@ -1949,6 +2529,20 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn disabled_network_has_no_interfaces_up() {
+        Test::new(
+            bash_spec("ls /sys/class/net && cat /sys/class/net/lo/operstate")
+                .mounts([JobMount::Sys {
+                    mount_point: utf8_path_buf!("/sys"),
+                }])
+                .network(JobNetwork::Disabled),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"lo\ndown\n")))
+        .run()
+        .await;
+    }
+
     #[tokio::test]
     async fn loopback() {
         Test::new(
@@ -2049,6 +2643,7 @@ mod tests {
                     mount_point: utf8_path_buf!("/mnt"),
                     local_path: utf8_path_buf!("/"),
                     read_only: false,
+                    security_label: None,
                 },
                 JobMount::Devices {
                     devices: enum_set!(JobDevice::Null),
@@ -2057,7 +2652,7 @@ mod tests {
         let JobCompleted {
             status,
             effects: JobEffects { stdout, stderr, .. },
-        } = run(spec, "100".parse().unwrap()).await.unwrap();
+        } = run(spec, "100".parse().unwrap(), None).await.unwrap();
         assert_eq!(stderr, JobOutputResult::None);
         assert_eq!(status, JobStatus::Exited(0));
         let JobOutputResult::Inline(contents) = stdout else {
@@ -2641,6 +3236,7 @@ mod tests {
                     .unwrap()
                     .to_owned(),
                 read_only: false,
+                security_label: None,
             }]),
         )
         .run()
@@ -2666,6 +3262,7 @@ mod tests {
                         .unwrap()
                         .to_owned(),
                     read_only: true,
+                    security_label: None,
                 },
                 JobMount::Devices {
                     devices: enum_set!(JobDevice::Null),
@@ -2679,6 +3276,149 @@ mod tests {
         assert_eq!(contents, "hello\n");
     }
 
+    fn tty_output_buffer(high_water_mark: u64, overflow: TtyOutputOverflow) -> TtyOutputBuffer {
+        TtyOutputBuffer::new(
+            TtyOutputLimit {
+                high_water_mark,
+                overflow,
+            },
+            false, // framed
+        )
+    }
+
+    #[test]
+    fn tty_output_backpressure_stops_reading_at_high_water_mark() {
+        let mut output = tty_output_buffer(8, TtyOutputOverflow::Backpressure);
+        assert!(output.should_read());
+        output.push(b"12345");
+        assert!(output.should_read());
+        output.push(b"67890");
+        assert!(!output.should_read());
+        assert_eq!(output.pending(), b"1234567890");
+
+        output.consume(2);
+        assert!(!output.should_read());
+        output.consume(1);
+        assert!(output.should_read());
+        assert_eq!(output.pending(), b"4567890");
+    }
+
+    #[test]
+    fn tty_output_backpressure_zero_high_water_mark_reads_when_empty() {
+        let mut output = tty_output_buffer(0, TtyOutputOverflow::Backpressure);
+        assert!(output.should_read());
+        output.push(b"abc");
+        assert!(!output.should_read());
+        output.consume(3);
+        assert!(output.should_read());
+    }
+
+    #[test]
+    fn tty_output_drop_discards_overflow_and_marks_gap() {
+        let mut output = tty_output_buffer(8, TtyOutputOverflow::Drop);
+        output.push(b"12345");
+        output.push(b"67890");
+        assert!(output.should_read());
+        assert_eq!(output.pending(), b"12345678");
+
+        output.push(b"abc");
+        assert_eq!(output.pending(), b"12345678");
+
+        output.consume(8);
+        output.push(b"def");
+        let mut expected = tty::encode_dropped_output(5);
+        expected.extend(b"def");
+        assert_eq!(output.pending(), expected);
+    }
+
+    #[test]
+    fn tty_output_drop_marks_gap_on_finish() {
+        let mut output = tty_output_buffer(4, TtyOutputOverflow::Drop);
+        output.push(b"123456");
+        output.finish();
+        let mut expected = b"1234".to_vec();
+        expected.extend(tty::encode_dropped_output(2));
+        assert_eq!(output.pending(), expected);
+
+        let mut output = tty_output_buffer(4, TtyOutputOverflow::Drop);
+        output.push(b"1234");
+        output.finish();
+        assert_eq!(output.pending(), b"1234");
+    }
+
+    #[tokio::test]
+    async fn copy_tty_output_copies_everything_with_backpressure() {
+        let input = Vec::from_iter((0..10_000).map(|i| (i % 251) as u8));
+        let mut socket = vec![];
+        copy_tty_output(
+            input.as_slice(),
+            &mut socket,
+            TtyOutputLimit {
+                high_water_mark: 100,
+                overflow: TtyOutputOverflow::Backpressure,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(socket, input);
+    }
+
+    #[test]
+    fn tty_output_framed_escapes_output_and_acknowledges_resizes() {
+        let mut output = TtyOutputBuffer::new(
+            TtyOutputLimit {
+                high_water_mark: 4,
+                overflow: TtyOutputOverflow::Drop,
+            },
+            true, // framed
+        );
+        output.push(b"a\xffbcd");
+        output.push_resized(WindowSize::new(40, 100));
+
+        let mut expected = b"a\xff\xffbc".to_vec();
+        expected.extend(tty::encode_dropped_output(1));
+        expected.extend(tty::encode_window_size_change(WindowSize::new(40, 100)));
+        assert_eq!(output.pending(), expected);
+    }
+
+    #[tokio::test]
+    async fn overlay_mount_is_writable_and_leaves_lower_unchanged() {
+        let temp_dir = TempDir::new().unwrap();
+        let temp_path = temp_dir.path();
+        let lower_path = temp_path.join("lower");
+        let upper_path = temp_path.join("upper");
+        let work_path = temp_path.join("work");
+        let fs = async_fs::Fs::new();
+        fs.create_dir(&lower_path).await.unwrap();
+        fs.create_dir(&upper_path).await.unwrap();
+        fs.create_dir(&work_path).await.unwrap();
+        fs.write(lower_path.join("foo"), b"foo\n").await.unwrap();
+
+        Test::new(
+            bash_spec("cat /mnt/foo && echo bar > /mnt/foo && cat /mnt/foo").mounts([
+                JobMount::Overlay {
+                    mount_point: utf8_path_buf!("/mnt"),
+                    lower: lower_path.clone().try_into().unwrap(),
+                    upper: upper_path.clone().try_into().unwrap(),
+                    work: work_path.clone().try_into().unwrap(),
+                },
+            ]),
+        )
+        .expected_stdout(JobOutputResult::Inline(boxed_u8!(b"foo\nbar\n")))
+        .run()
+        .await;
+
+        assert_eq!(
+            fs.read_to_string(lower_path.join("foo")).await.unwrap(),
+            "foo\n"
+        );
+        assert_eq!(
+            fs.read_to_string(upper_path.join("foo")).await.unwrap(),
+            "bar\n"
+        );
+    }
+
     #[tokio::test]
     async fn bind_mount_path_is_relative_to_pwd() {
         let temp_file = NamedTempFile::new().unwrap();
@@ -2698,6 +3438,7 @@ mod tests {
                     mount_point: utf8_path_buf!("/mnt"),
                     local_path: temp_dir_relative_path,
                     read_only: true,
+                    security_label: None,
                 }]),
         )
         .expected_status(JobStatus::Exited(0))
@@ -2738,7 +3479,7 @@ mod tests {
 
     async fn assert_execution_error(spec: maelstrom_base::JobSpec) {
         assert_matches!(
-            run(spec, "0".parse().unwrap()).await,
+            run(spec, "0".parse().unwrap(), None).await,
             Err(JobError::Execution(_))
         );
     }
@@ -2753,6 +3494,46 @@ mod tests {
         assert_execution_error(test_spec("/bin/cat").working_directory(Some("/dev/null"))).await;
     }
 
+    #[tokio::test]
+    async fn readonly_root_with_root_overlay_is_an_execution_error() {
+        assert_execution_error(
+            bash_spec("exit 0")
+                .root_overlay(JobRootOverlay::Tmp)
+                .readonly_root(true),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn empty_cpu_set_is_an_execution_error() {
+        assert_execution_error(bash_spec("exit 0").cpu_set(Some([]))).await;
+    }
+
+    #[tokio::test]
+    async fn cpu_set_out_of_range_is_an_execution_error() {
+        assert_execution_error(bash_spec("exit 0").cpu_set(Some([0, MAX_CPUS]))).await;
+    }
+
+    #[tokio::test]
+    async fn cpu_set() {
+        let allowed = linux::sched_getaffinity().unwrap();
+        let cpu = (0..MAX_CPUS)
+            .find(|&cpu| allowed.contains(cpu.into()))
+            .unwrap();
+        Test::new(
+            bash_spec("awk '/Cpus_allowed_list/ { print $2 }' /proc/self/status")
+                .mounts([JobMount::Proc {
+                    mount_point: utf8_path_buf!("/proc"),
+                }])
+                .cpu_set(Some([cpu])),
+        )
+        .expected_stdout(JobOutputResult::Inline(
+            format!("{cpu}\n").into_bytes().into_boxed_slice(),
+        ))
+        .run()
+        .await;
+    }
+
     async fn expect(mut socket: impl AsyncRead + Unpin, expected: &[u8]) {
         fn escaped_string(bytes: &[u8]) -> String {
             bytes
@@ -2812,13 +3593,32 @@ mod tests {
     async fn start_tty_job(
         program: &str,
         window_size: WindowSize,
+    ) -> (JoinHandle<JobCompleted>, UnixStream) {
+        start_tty_job_with(program, |path| JobTty::new(path, window_size)).await
+    }
+
+    async fn start_tty_job_with(
+        program: &str,
+        job_tty: impl FnOnce(&[u8; 6]) -> JobTty,
+    ) -> (JoinHandle<JobCompleted>, UnixStream) {
+        let (_, resize_receiver) = mpsc::unbounded_channel();
+        start_tty_job_with_resizes(program, job_tty, resize_receiver).await
+    }
+
+    async fn start_tty_job_with_resizes(
+        program: &str,
+        job_tty: impl FnOnce(&[u8; 6]) -> JobTty,
+        resize_receiver: mpsc::UnboundedReceiver<WindowSize>,
     ) -> (JoinHandle<JobCompleted>, UnixStream) {
         let (listener, path) = unix_listener();
         let program_string = program.to_string();
+        let job_tty = job_tty(&path);
         let job_handle = task::spawn(async move {
-            run(
-                test_spec(&program_string).allocate_tty(Some(JobTty::new(&path, window_size))),
+            run_with_resizes(
+                test_spec(&program_string).allocate_tty(Some(job_tty)),
                 InlineLimit::from_bytes(0),
+                None,
+                resize_receiver,
             )
             .await
             .unwrap()
@@ -2919,4 +3719,82 @@ mod tests {
         drop(socket);
         assert_job_exit(job_handle.await.unwrap(), 0);
     }
+
+    #[tokio::test]
+    async fn tty_winsize_from_resize_receiver() {
+        let (resize_sender, resize_receiver) = mpsc::unbounded_channel();
+        let (job_handle, mut socket) = start_tty_job_with_resizes(
+            "/winsize",
+            |path| JobTty::new(path, WindowSize::new(30, 90)),
+            resize_receiver,
+        )
+        .await;
+
+        expect(&mut socket, b"30 90\r\n").await;
+
+        resize_sender.send(WindowSize::new(40, 100)).unwrap();
+        expect(&mut socket, b"40 100\r\n").await;
+
+        // Resizes on the socket still work once the receiver is closed.
+        drop(resize_sender);
+        socket
+            .write_all(tty::encode_window_size_change(WindowSize::new(50, 120)).as_slice())
+            .await
+            .unwrap();
+        expect(&mut socket, b"50 120\r\n").await;
+
+        // exitsig catches the sighup and exits.
+        drop(socket);
+        assert_job_exit(job_handle.await.unwrap(), 0);
+    }
+
+    /// Read framed output from `socket` until exactly `expected` has been output, and, if given,
+    /// `resized` has been acknowledged. The job's output and the acknowledgement race, so they may
+    /// come in either order.
+    async fn expect_framed(socket: &mut UnixStream, expected: &[u8], resized: Option<WindowSize>) {
+        let mut bytes = vec![];
+        loop {
+            let mut output = vec![];
+            let mut acknowledged = vec![];
+            for chunk in tty::decode_input(&bytes) {
+                match chunk {
+                    DecodeInputChunk::Input(input) => output.extend_from_slice(input),
+                    DecodeInputChunk::WindowSizeChange(window_size) => {
+                        acknowledged.push(window_size)
+                    }
+                    DecodeInputChunk::Remainder(_) => {}
+                }
+            }
+            assert!(expected.starts_with(&output), "{output:?}");
+            assert!(acknowledged.len() <= 1, "{acknowledged:?}");
+            if output == expected && acknowledged.first() == resized.as_ref() {
+                break;
+            }
+            let mut buf = [0u8; 100];
+            let n = socket.read(&mut buf).await.unwrap();
+            assert_ne!(n, 0, "socket closed");
+            bytes.extend_from_slice(&buf[..n]);
+        }
+    }
+
+    #[tokio::test]
+    async fn tty_winsize_framed_output() {
+        let (job_handle, mut socket) = start_tty_job_with("/winsize", |path| {
+            JobTty::new(path, WindowSize::new(30, 90)).framed_output(true)
+        })
+        .await;
+
+        expect_framed(&mut socket, b"30 90\r\n", None).await;
+
+        let window_size = WindowSize::new(40, 100);
+        socket
+            .write_all(tty::encode_window_size_change(window_size).as_slice())
+            .await
+            .unwrap();
+        expect_framed(&mut socket, b"40 100\r\n", Some(window_size)).await;
+
+        // exitsig catches the sighup and exits.
+        drop(socket);
+        assert_job_exit(job_handle.await.unwrap(), 0);
+    }
 }
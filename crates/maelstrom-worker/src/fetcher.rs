@@ -7,12 +7,22 @@ use maelstrom_linux as linux;
 use maelstrom_util::{config::common::BrokerAddr, fs::Fs, io, net};
 use slog::{debug, Logger};
 use std::os::fd::AsRawFd as _;
-use std::{net::TcpStream, path::PathBuf};
+use std::{
+    net::TcpStream,
+    path::{Path, PathBuf},
+};
 
+/// Download the artifact with the given digest from the broker into `path`, returning its size. If
+/// `verify` is set, also check that the downloaded contents match `digest`.
+///
+/// Once the size is known, but before anything is written, `reserve_space` is called with it. If
+/// it returns an error, the download is abandoned.
 pub fn main(
     digest: &Sha256Digest,
     path: PathBuf,
     broker_addr: BrokerAddr,
+    verify: bool,
+    reserve_space: impl FnOnce(u64) -> Result<()>,
     log: &mut Logger,
 ) -> Result<u64> {
     let mut stream = TcpStream::connect(broker_addr.inner())?;
@@ -27,9 +37,10 @@ pub fn main(
     let expected_size = msg
         .0
         .map_err(|e| anyhow!("Broker error reading artifact: {e}"))?;
+    reserve_space(expected_size)?;
 
     let fs = Fs::new();
-    let file = fs.create_file(path)?;
+    let file = fs.create_file(&path)?;
 
     let mut writer = io::MaybeFastWriter::new(log.clone());
 
@@ -49,5 +60,162 @@ pub fn main(
         file_offset += written as u64;
     }
 
+    if verify {
+        verify_file(&path, digest)?;
+    }
+
     Ok(expected_size)
 }
+
+/// Check that the contents of the file at `path` have the SHA-256 digest `expected`.
+fn verify_file(path: &Path, expected: &Sha256Digest) -> Result<()> {
+    let fs = Fs::new();
+    let mut reader = io::Sha256Stream::new(fs.open_file(path)?);
+    std::io::copy(&mut reader, &mut std::io::sink())?;
+    reader.finalize().1.verify(expected)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slog::{o, Discard};
+    use std::{io::Write as _, net::TcpListener, thread};
+    use tempfile::tempdir;
+
+    fn digest_of(data: &[u8]) -> Sha256Digest {
+        let mut reader = io::Sha256Stream::new(data);
+        std::io::copy(&mut reader, &mut std::io::sink()).unwrap();
+        reader.finalize().1
+    }
+
+    /// Start a fake broker that answers one artifact fetcher with `contents`.
+    fn serve_artifact(contents: &'static [u8]) -> (BrokerAddr, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let broker_addr = BrokerAddr::new(listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _: Hello = net::read_message_from_socket(&mut stream).unwrap();
+            let _: ArtifactFetcherToBroker = net::read_message_from_socket(&mut stream).unwrap();
+            let msg = BrokerToArtifactFetcher(Ok(contents.len() as u64));
+            net::write_message_to_socket(&mut stream, msg).unwrap();
+            // The fetcher may hang up without reading the contents.
+            stream.write_all(contents).ok();
+        });
+        (broker_addr, handle)
+    }
+
+    #[test]
+    fn main_verified_matching_digest() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("artifact");
+        let (broker_addr, handle) = serve_artifact(b"hello");
+        let mut log = Logger::root(Discard, o!());
+        let size = main(
+            &digest_of(b"hello"),
+            path.clone(),
+            broker_addr,
+            true,
+            |_| Ok(()),
+            &mut log,
+        )
+        .unwrap();
+        handle.join().unwrap();
+        assert_eq!(size, 5);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn main_verified_mismatched_digest() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("artifact");
+        let (broker_addr, handle) = serve_artifact(b"hello");
+        let mut log = Logger::root(Discard, o!());
+        let err = main(
+            &digest_of(b"goodbye"),
+            path,
+            broker_addr,
+            true,
+            |_| Ok(()),
+            &mut log,
+        )
+        .unwrap_err();
+        handle.join().unwrap();
+        assert!(
+            err.to_string().starts_with("mismatched SHA-256 digest"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn main_unverified_mismatched_digest() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("artifact");
+        let (broker_addr, handle) = serve_artifact(b"hello");
+        let mut log = Logger::root(Discard, o!());
+        let size = main(
+            &digest_of(b"goodbye"),
+            path,
+            broker_addr,
+            false,
+            |_| Ok(()),
+            &mut log,
+        )
+        .unwrap();
+        handle.join().unwrap();
+        assert_eq!(size, 5);
+    }
+
+    #[test]
+    fn main_writes_nothing_without_space() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("artifact");
+        let (broker_addr, handle) = serve_artifact(b"hello");
+        let mut log = Logger::root(Discard, o!());
+        let mut requested = None;
+        let err = main(
+            &digest_of(b"hello"),
+            path.clone(),
+            broker_addr,
+            false,
+            |bytes| {
+                requested = Some(bytes);
+                Err(anyhow!("cache full"))
+            },
+            &mut log,
+        )
+        .unwrap_err();
+        handle.join().unwrap();
+        assert_eq!(err.to_string(), "cache full");
+        assert_eq!(requested, Some(5));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn verify_file_matching_digest() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("artifact");
+        std::fs::write(&path, b"hello").unwrap();
+        let digest = digest_of(b"hello");
+        verify_file(&path, &digest).unwrap();
+    }
+
+    #[test]
+    fn verify_file_mismatched_digest() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("artifact");
+        std::fs::write(&path, b"hello").unwrap();
+        let err = verify_file(&path, &digest_of(b"goodbye")).unwrap_err();
+        assert!(
+            err.to_string().starts_with("mismatched SHA-256 digest"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn verify_file_missing_file_is_an_error() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("artifact");
+        assert!(verify_file(&path, &digest_of(b"hello")).is_err());
+    }
+}
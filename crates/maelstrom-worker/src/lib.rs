@@ -3,6 +3,7 @@
 mod cache;
 pub mod config;
 mod dispatcher;
+pub mod env_filter;
 mod executor;
 mod fetcher;
 mod layer_fs;
@@ -13,13 +14,13 @@ use anyhow::{anyhow, Context as _, Result};
 use cache::{Cache, CacheDir, StdFs};
 use config::Config;
 use dispatcher::{Deps, Dispatcher, Message};
-use executor::{Executor, MountDir, TmpfsDir};
+use env_filter::EnvFilter;
+use executor::{Executor, ExternalOutputDir, MountDir, TmpfsDir};
 use futures::StreamExt as _;
 use lru::LruCache;
 use maelstrom_base::{
-    manifest::{ManifestEntryData, ManifestFileData},
     proto::{BrokerToWorker, Hello, WorkerToBroker},
-    ArtifactType, JobError, JobId, JobSpec, Sha256Digest,
+    ArtifactType, JobError, JobId, JobSpec, Sha256Digest, WindowSize,
 };
 use maelstrom_layer_fs::{BlobDir, LayerFs, ReaderCache};
 use maelstrom_linux::{
@@ -49,7 +50,10 @@ use std::{
 use tokio::{
     io::BufReader,
     net::TcpStream,
-    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
     task::{self, JoinHandle},
     time,
 };
@@ -58,14 +62,8 @@ pub struct WorkerCacheDir;
 
 async fn read_manifest(path: &Path) -> Result<HashSet<Sha256Digest>> {
     let fs = async_fs::Fs::new();
-    let mut reader = AsyncManifestReader::new(fs.open_file(path).await?).await?;
-    let mut digests = HashSet::new();
-    while let Some(entry) = reader.next().await? {
-        if let ManifestEntryData::File(ManifestFileData::Digest(digest)) = entry.data {
-            digests.insert(digest);
-        }
-    }
-    Ok(digests)
+    let reader = AsyncManifestReader::new(fs.open_file(path).await?).await?;
+    Ok(reader.referenced_digests().await?)
 }
 
 struct ManifestDigestCacheInner {
@@ -158,6 +156,7 @@ type BrokerSocketIncomingReceiver = UnboundedReceiver<BrokerToWorker>;
 pub struct DispatcherAdapter {
     dispatcher_sender: DispatcherSender,
     inline_limit: InlineLimit,
+    env_filter: EnvFilter,
     log: Logger,
     executor: Arc<Executor<'static, SystemMonotonicClock>>,
     blob_dir: RootBuf<BlobDir>,
@@ -174,13 +173,20 @@ impl DispatcherAdapter {
         mount_dir: RootBuf<MountDir>,
         tmpfs_dir: RootBuf<TmpfsDir>,
         blob_dir: RootBuf<BlobDir>,
+        external_output_dir: Option<RootBuf<ExternalOutputDir>>,
     ) -> Result<Self> {
         let fs = Fs::new();
         fs.create_dir_all(&mount_dir)?;
         fs.create_dir_all(&tmpfs_dir)?;
+        let mut executor = Executor::new(mount_dir, tmpfs_dir, &SystemMonotonicClock)?;
+        if let Some(external_output_dir) = external_output_dir {
+            fs.create_dir_all(&external_output_dir)?;
+            executor = executor.external_output_dir(external_output_dir);
+        }
         Ok(DispatcherAdapter {
             inline_limit,
-            executor: Arc::new(Executor::new(mount_dir, tmpfs_dir, &SystemMonotonicClock)?),
+            env_filter: EnvFilter::default(),
+            executor: Arc::new(executor),
             blob_dir,
             layer_fs_cache: Arc::new(tokio::sync::Mutex::new(ReaderCache::new())),
             manifest_digest_cache: ManifestDigestCache::new(
@@ -193,12 +199,20 @@ impl DispatcherAdapter {
         })
     }
 
+    /// Remove the environment variables that `env_filter` doesn't allow from every job before
+    /// starting it. By default, all variables are passed through.
+    pub fn env_filter(mut self, env_filter: EnvFilter) -> Self {
+        self.env_filter = env_filter;
+        self
+    }
+
     fn start_job_inner(
         &mut self,
         jid: JobId,
         spec: JobSpec,
         layer_fs_path: PathBuf,
         kill_event_receiver: EventReceiver,
+        resize_receiver: UnboundedReceiver<WindowSize>,
     ) -> Result<()> {
         let log = self
             .log
@@ -216,7 +230,8 @@ impl DispatcherAdapter {
         };
 
         let executor = self.executor.clone();
-        let spec = executor::JobSpec::from_spec(spec);
+        let mut spec = executor::JobSpec::from_spec(spec);
+        spec.environment = self.env_filter.filter(spec.environment);
         let inline_limit = self.inline_limit;
         let dispatcher_sender = self.dispatcher_sender.clone();
         let runtime = tokio::runtime::Handle::current();
@@ -229,6 +244,7 @@ impl DispatcherAdapter {
                             &spec,
                             inline_limit,
                             kill_event_receiver,
+                            resize_receiver,
                             fuse_spawn,
                             runtime,
                         )
@@ -240,6 +256,12 @@ impl DispatcherAdapter {
     }
 }
 
+/// The job is killed when this is dropped.
+pub struct JobHandle {
+    _kill_event_sender: EventSender,
+    resize_sender: UnboundedSender<WindowSize>,
+}
+
 pub struct TimerHandle(JoinHandle<()>);
 
 impl Drop for TimerHandle {
@@ -249,17 +271,31 @@ impl Drop for TimerHandle {
 }
 
 impl Deps for DispatcherAdapter {
-    type JobHandle = EventSender;
+    type JobHandle = JobHandle;
 
     fn start_job(&mut self, jid: JobId, spec: JobSpec, layer_fs_path: PathBuf) -> Self::JobHandle {
         let (kill_event_sender, kill_event_receiver) = sync::event();
-        if let Err(e) = self.start_job_inner(jid, spec, layer_fs_path, kill_event_receiver) {
+        let (resize_sender, resize_receiver) = mpsc::unbounded_channel();
+        if let Err(e) = self.start_job_inner(
+            jid,
+            spec,
+            layer_fs_path,
+            kill_event_receiver,
+            resize_receiver,
+        ) {
             let _ = self.dispatcher_sender.send(Message::JobCompleted(
                 jid,
                 Err(JobError::System(e.to_string())),
             ));
         }
-        kill_event_sender
+        JobHandle {
+            _kill_event_sender: kill_event_sender,
+            resize_sender,
+        }
+    }
+
+    fn resize_job_tty(&mut self, handle: &Self::JobHandle, window_size: WindowSize) {
+        handle.resize_sender.send(window_size).ok();
     }
 
     type TimerHandle = TimerHandle;
@@ -333,14 +369,24 @@ impl Deps for DispatcherAdapter {
 struct ArtifactFetcher {
     dispatcher_sender: DispatcherSender,
     broker_addr: BrokerAddr,
+    verify: bool,
+    /// How to answer each started fetch once it asks for cache space.
+    space_reserved_senders: HashMap<Sha256Digest, oneshot::Sender<Result<(), cache::CacheFull>>>,
     log: Logger,
 }
 
 impl ArtifactFetcher {
-    fn new(dispatcher_sender: DispatcherSender, broker_addr: BrokerAddr, log: Logger) -> Self {
+    fn new(
+        dispatcher_sender: DispatcherSender,
+        broker_addr: BrokerAddr,
+        verify: bool,
+        log: Logger,
+    ) -> Self {
         ArtifactFetcher {
             broker_addr,
             dispatcher_sender,
+            verify,
+            space_reserved_senders: HashMap::default(),
             log,
         }
     }
@@ -350,19 +396,41 @@ impl dispatcher::ArtifactFetcher for ArtifactFetcher {
     fn start_artifact_fetch(&mut self, digest: Sha256Digest, path: PathBuf) {
         let sender = self.dispatcher_sender.clone();
         let broker_addr = self.broker_addr;
+        let verify = self.verify;
         let mut log = self.log.new(o!(
             "digest" => digest.to_string(),
             "broker_addr" => broker_addr.inner().to_string()
         ));
+        let (space_reserved_sender, space_reserved_receiver) = oneshot::channel();
+        self.space_reserved_senders
+            .insert(digest.clone(), space_reserved_sender);
         debug!(log, "artifact fetcher starting");
         thread::spawn(move || {
-            let result = fetcher::main(&digest, path, broker_addr, &mut log);
+            let reserve_space = |bytes| {
+                sender
+                    .send(Message::ReserveArtifactSpace(digest.clone(), bytes))
+                    .map_err(|_| anyhow!("dispatcher shut down"))?;
+                Ok(space_reserved_receiver
+                    .blocking_recv()
+                    .map_err(|_| anyhow!("dispatcher shut down"))??)
+            };
+            let result = fetcher::main(&digest, path, broker_addr, verify, reserve_space, &mut log);
             debug!(log, "artifact fetcher completed"; "result" => ?result);
             sender
                 .send(Message::ArtifactFetchCompleted(digest, result))
                 .ok();
         });
     }
+
+    fn artifact_space_reserved(
+        &mut self,
+        digest: Sha256Digest,
+        result: Result<(), cache::CacheFull>,
+    ) {
+        if let Some(sender) = self.space_reserved_senders.remove(&digest) {
+            sender.send(result).ok();
+        }
+    }
 }
 
 struct BrokerSender {
@@ -389,13 +457,17 @@ impl dispatcher::BrokerSender for BrokerSender {
     }
 }
 
-/// Returns error from shutdown message, or delivers message to dispatcher.
+/// Returns error from shutdown message, or delivers message to dispatcher. Once a drain has
+/// finished, returns an error so that the worker shuts down.
 fn handle_dispatcher_message(msg: Message, dispatcher: &mut DefaultDispatcher) -> Result<()> {
     if let Message::Shutdown(error) = msg {
         return Err(error);
     }
 
     dispatcher.receive_message(msg);
+    if dispatcher.is_draining() && dispatcher.num_executing() == 0 {
+        return Err(anyhow!("drain complete"));
+    }
     Ok(())
 }
 
@@ -449,11 +521,27 @@ async fn dispatcher_main(
     let tmpfs_dir = config.cache_root.join::<TmpfsDir>("upper");
     let cache_root = config.cache_root.join::<CacheDir>("artifacts");
     let blob_dir = cache_root.join::<BlobDir>("blob/sha256");
+    let external_output_dir = config
+        .external_output
+        .then(|| RootBuf::new(Cache::<StdFs>::external_output_dir(&cache_root)));
 
     let broker_sender = BrokerSender::new(broker_socket_outgoing_sender);
-    let cache = Cache::new(StdFs, cache_root, config.cache_size, log.clone());
-    let artifact_fetcher =
-        ArtifactFetcher::new(dispatcher_sender.clone(), config.broker, log.clone());
+    let removal_grace_period = Duration::from_secs(config.cache_removal_grace_period);
+    let cache = Cache::new(StdFs, cache_root, config.cache_size, log.clone())
+        .removal_grace_period(removal_grace_period);
+    if !removal_grace_period.is_zero() {
+        tokio::task::spawn(remove_expired_cache_entries_periodically(
+            removal_grace_period,
+            dispatcher_sender.clone(),
+        ));
+    }
+    let artifact_fetcher = ArtifactFetcher::new(
+        dispatcher_sender.clone(),
+        config.broker,
+        config.verify_artifacts,
+        log.clone(),
+    );
+    let env_filter = EnvFilter::new(config.environment_allow, config.environment_deny);
     match DispatcherAdapter::new(
         dispatcher_sender,
         config.inline_limit,
@@ -461,18 +549,30 @@ async fn dispatcher_main(
         mount_dir,
         tmpfs_dir,
         blob_dir,
-    ) {
+        external_output_dir,
+    )
+    .map(|adapter| adapter.env_filter(env_filter))
+    {
         Err(err) => {
             error!(log, "could not start executor"; "err" => ?err);
         }
         Ok(adapter) => {
-            let dispatcher = Dispatcher::new(
+            let mut dispatcher = Dispatcher::new(
                 adapter,
                 artifact_fetcher,
                 broker_sender,
                 cache,
                 config.slots,
-            );
+                config.scheduling_policy,
+            )
+            .max_system_error_retries(config.max_system_error_retries);
+            if let Some(max_outstanding_fetches) = config.max_outstanding_fetches {
+                dispatcher = dispatcher.max_outstanding_fetches(max_outstanding_fetches);
+            }
+            if let Some(max_outstanding_layer_builds) = config.max_outstanding_layer_builds {
+                dispatcher = dispatcher.max_outstanding_layer_builds(max_outstanding_layer_builds);
+            }
+            info!(log, "dispatcher started"; "config" => ?dispatcher.config_snapshot());
             handle_incoming_messages(
                 log,
                 dispatcher_receiver,
@@ -484,6 +584,24 @@ async fn dispatcher_main(
     }
 }
 
+/// Tell the dispatcher to remove expired cache entries every `period`, until the dispatcher goes
+/// away. Otherwise, they would only be removed when the cache is next used.
+async fn remove_expired_cache_entries_periodically(
+    period: Duration,
+    dispatcher_sender: DispatcherSender,
+) {
+    let mut interval = time::interval(period);
+    loop {
+        interval.tick().await;
+        if dispatcher_sender
+            .send(Message::RemoveExpiredCacheEntries)
+            .is_err()
+        {
+            break;
+        }
+    }
+}
+
 async fn shutdown_on_error(
     fut: impl Future<Output = Result<()>>,
     dispatcher_sender: DispatcherSender,
@@ -493,8 +611,15 @@ async fn shutdown_on_error(
     }
 }
 
-async fn wait_for_signal(log: Logger) -> Result<()> {
-    let signal = signals::wait_for_signal(log).await;
+/// Drain on the first SIGTERM, so that executing jobs can finish before the worker exits. Jobs that
+/// haven't started are dropped, and the broker gives them to other workers once this worker
+/// disconnects. Any other exit signal, including a second SIGTERM, shuts down right away.
+async fn wait_for_signal(log: Logger, dispatcher_sender: DispatcherSender) -> Result<()> {
+    let signal = signals::wait_for_signal_draining_on_term(log.clone(), || {
+        info!(log, "draining");
+        let _ = dispatcher_sender.send(Message::Drain);
+    })
+    .await;
     Err(anyhow!("signal {signal}"))
 }
 
@@ -557,7 +682,7 @@ pub async fn main_inner(config: Config, log: Logger) -> Result<()> {
     ));
 
     tokio::task::spawn(shutdown_on_error(
-        wait_for_signal(log.clone()),
+        wait_for_signal(log.clone(), dispatcher_sender.clone()),
         dispatcher_sender.clone(),
     ));
 
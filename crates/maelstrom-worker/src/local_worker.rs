@@ -1,6 +1,6 @@
 pub use crate::{
-    cache::{Cache, CacheDir, StdFs},
-    dispatcher::{ArtifactFetcher, BrokerSender, Dispatcher, Message},
+    cache::{Cache, CacheDir, CacheFull, EntryKind, EvictionListener, StdFs},
+    dispatcher::{ArtifactFetcher, BrokerSender, Dispatcher, Message, SchedulingPolicy},
     executor::MountDir,
     executor::TmpfsDir,
     DispatcherAdapter, WorkerCacheDir,
@@ -69,7 +69,7 @@ fn default_signal_waiter(log: Logger) -> SignalWaiter {
     w
 }
 
-async fn default_signal_handler(mut w: SignalWaiter) -> Signal {
+async fn default_signal_handler(w: &mut SignalWaiter) -> Signal {
     let exit: BTreeSet<_> = EXIT.into_iter().collect();
     let ignore: BTreeSet<_> = IGNORE.into_iter().collect();
     loop {
@@ -85,8 +85,20 @@ async fn default_signal_handler(mut w: SignalWaiter) -> Signal {
 }
 
 pub async fn wait_for_signal(log: Logger) -> Signal {
-    let w = default_signal_waiter(log);
-    default_signal_handler(w).await
+    let mut w = default_signal_waiter(log);
+    default_signal_handler(&mut w).await
+}
+
+/// Like [`wait_for_signal`], but the first SIGTERM calls `drain` instead of returning. The handlers
+/// stay registered while draining, so a second SIGTERM can't slip by unnoticed.
+pub async fn wait_for_signal_draining_on_term(log: Logger, drain: impl FnOnce()) -> Signal {
+    let mut w = default_signal_waiter(log);
+    let signal = default_signal_handler(&mut w).await;
+    if signal != Signal::TERM {
+        return signal;
+    }
+    drain();
+    default_signal_handler(&mut w).await
 }
 
 #[cfg(test)]
@@ -96,10 +108,10 @@ async fn test_wait_for_signal(sock: std::os::unix::net::UnixStream) {
     let mut sock = tokio::net::UnixStream::from_std(sock).unwrap();
 
     let log = maelstrom_util::log::test_logger();
-    let w = default_signal_waiter(log);
+    let mut w = default_signal_waiter(log);
     sock.write_all(&[12]).await.unwrap();
 
-    default_signal_handler(w).await;
+    default_signal_handler(&mut w).await;
 }
 
 #[cfg(test)]